@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // A protoc and the well-known types come with the build, so nothing has
+    // to be installed; PROTOC and PROTOC_INCLUDE still pick others.
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+    if std::env::var_os("PROTOC_INCLUDE").is_none() {
+        std::env::set_var("PROTOC_INCLUDE", protoc_bin_vendored::include_path()?);
+    }
+    tonic_prost_build::compile_protos("proto/tictoc.proto")?;
+    Ok(())
+}
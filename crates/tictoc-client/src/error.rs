@@ -0,0 +1,113 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::fmt;
+use tictoc_types::{
+    BodyErrorResponse, EntriesErrorResponse, ErrorResponse, TimesheetLockedResponse, TwoFactorChallenge,
+    ValidationErrors,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server couldn't be reached, or what it sent couldn't be read.
+    Http(reqwest::Error),
+    /// The server answered with an error.
+    Api { status: StatusCode, body: ErrorBody },
+    /// The password was right, and the account wants a second factor too.
+    TwoFactorRequired(TwoFactorChallenge),
+}
+
+impl Error {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Http(err) => err.status(),
+            Error::Api { status, .. } => Some(*status),
+            Error::TwoFactorRequired(_) => None,
+        }
+    }
+
+    /// The `error` the server answered with, like `timer_already_running`.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Api { body, .. } => body.code(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(err) => write!(f, "{}", err),
+            Error::Api { status, body } => match body.code() {
+                Some(code) => write!(f, "{}: {}", status, code),
+                None => write!(f, "{}", status),
+            },
+            Error::TwoFactorRequired(_) => write!(f, "two-factor authentication required"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+/// An error body, by its shape.
+#[derive(Debug, PartialEq)]
+pub enum ErrorBody {
+    /// `422`: what was wrong with each field.
+    Validation(ValidationErrors),
+    /// A body or query that couldn't be read, and where.
+    InvalidInput(BodyErrorResponse),
+    TimesheetLocked(TimesheetLockedResponse),
+    /// Overlaps, and bulk changes naming entries that aren't the user's.
+    Entries(EntriesErrorResponse),
+    Other(ErrorResponse),
+    /// Not one of the API's, like a proxy's error page.
+    Unknown(String),
+}
+
+impl ErrorBody {
+    pub(crate) fn parse(body: &str) -> ErrorBody {
+        let Ok(value) = serde_json::from_str::<Value>(body) else {
+            return ErrorBody::Unknown(body.to_string());
+        };
+
+        // The shapes all carry `error` but validation's, so they're told
+        // apart by what else they have.
+        let has = |field: &str| value.get(field).is_some();
+        let parsed = if has("errors") {
+            serde_json::from_value(value).map(ErrorBody::Validation)
+        } else if has("detail") {
+            serde_json::from_value(value).map(ErrorBody::InvalidInput)
+        } else if has("timesheet") {
+            serde_json::from_value(value).map(ErrorBody::TimesheetLocked)
+        } else if has("entry_ids") {
+            serde_json::from_value(value).map(ErrorBody::Entries)
+        } else {
+            serde_json::from_value(value).map(ErrorBody::Other)
+        };
+        parsed.unwrap_or_else(|_| ErrorBody::Unknown(body.to_string()))
+    }
+
+    /// `error`, which every shape but validation's has.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ErrorBody::Validation(_) | ErrorBody::Unknown(_) => None,
+            ErrorBody::InvalidInput(body) => Some(&body.error),
+            ErrorBody::TimesheetLocked(body) => Some(&body.error),
+            ErrorBody::Entries(body) => Some(&body.error),
+            ErrorBody::Other(body) => Some(&body.error),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BodyErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub detail: String,
+}
+
+/// An error over some of the user's time entries: `entries_overlap` when
+/// an entry would overlap them, `entries_not_found` when a bulk change
+/// names ones that aren't the user's, or `timer_already_running`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EntriesErrorResponse {
+    pub error: String,
+    /// The entries concerned, except for `timer_already_running`. Empty for
+    /// an overlap when another request wrote one of them at the same moment.
+    #[serde(default)]
+    pub entry_ids: Vec<i32>,
+}
+
+/// `timesheet_locked`: the entries are in a submitted or approved week.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TimesheetLockedResponse {
+    pub error: String,
+    pub entry_ids: Vec<i32>,
+    /// Where the week's timesheet is.
+    pub timesheet: String,
+}
+
+/// Field-level validation failures, serialized as
+/// `{"errors":{"field":["reason", ...]}}`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ValidationErrors {
+    pub errors: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, reason: &str) {
+        self.errors
+            .entry(field.to_string())
+            .or_default()
+            .push(reason.to_string());
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
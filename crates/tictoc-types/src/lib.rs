@@ -0,0 +1,259 @@
+//! The requests and responses of the tictoc API, as the server reads and
+//! writes them, so a client can't drift from it. The server turns on the
+//! `sqlx`, `openapi` and `graphql` features; a client needs none.
+
+mod error;
+
+pub use error::{BodyErrorResponse, EntriesErrorResponse, ErrorResponse, TimesheetLockedResponse, ValidationErrors};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateUserRequest {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateUserResponse {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Goes up by one with every edit; send it back to update.
+    pub version: i32,
+}
+
+/// A user as read back, with what only the server knows about them.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject), graphql(name = "User"))]
+pub struct UserResponse {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub version: i32,
+    /// `None` until the first successful login.
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Pass as `after_id` to fetch the next page; `None` once exhausted.
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LoginUserRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(type_name = "text", rename_all = "lowercase"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LoginUserResponse {
+    pub token: String,
+    pub refresh_token: String,
+    pub verified: bool,
+}
+
+/// Handed out by login instead of tokens when the account has two-factor
+/// authentication on.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Tokens(LoginUserResponse),
+    TwoFactor(TwoFactorChallenge),
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TimeEntry {
+    pub id: i32,
+    pub user_id: i32,
+    pub project_id: Option<i32>,
+    pub description: String,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the entry is still running.
+    pub ended_at: Option<DateTime<Utc>>,
+    /// From `started_at` to `ended_at`; `None` while running.
+    pub duration_seconds: Option<i64>,
+    /// Sorted by name, ignoring case.
+    pub tags: Vec<String>,
+    /// A pomodoro that ran all the way to its target.
+    pub pomodoro: bool,
+    pub billable: bool,
+    /// Cents an hour, from the entry's project or else the user's default,
+    /// as it was when the entry was made or moved to its project.
+    pub rate_cents: Option<i32>,
+    pub currency: Option<String>,
+    /// The invoice the entry was billed on, after which it can't change.
+    pub invoice_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TimeEntryRequest {
+    /// One of the user's own projects, archived or not.
+    pub project_id: Option<i32>,
+    #[serde(default)]
+    pub description: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Instead of `ended_at`, how long the entry ran for.
+    pub duration_seconds: Option<DurationInput>,
+    /// Replaces the entry's tags. Ones the user hasn't used yet are created,
+    /// and a name differing only in case from an existing tag renames it.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub billable: bool,
+}
+
+/// Seconds, or text like `1h 30m`, `90m` or `45s`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(untagged)]
+pub enum DurationInput {
+    Seconds(i64),
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct EntryQuery {
+    pub project_id: Option<i32>,
+    /// Only entries with every one of these tags; repeat to add more.
+    #[serde(default)]
+    pub tag: Vec<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "lowercase")]
+pub enum TimerMode {
+    /// Runs until stopped.
+    #[default]
+    Normal,
+    /// Stops itself after the user's `pomodoro_work_secs`.
+    Pomodoro,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StartTimerRequest {
+    pub project_id: Option<i32>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub billable: bool,
+}
+
+/// The color projects get when none is given.
+pub const DEFAULT_PROJECT_COLOR: &str = "#808080";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Project {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    /// `#rrggbb`.
+    pub color: String,
+    /// Left out of listings, but still there for the entries on it.
+    pub archived: bool,
+    /// Cents an hour. Entries take the rate they're made under, so changing
+    /// it leaves earlier ones as they were.
+    pub hourly_rate_cents: Option<i32>,
+    /// Given with `hourly_rate_cents`, and only then.
+    pub currency: Option<String>,
+    /// Set for a project shared with an organization's members.
+    pub organization_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProjectRequest {
+    pub name: String,
+    /// `#rrggbb`; grey when left out.
+    #[serde(default = "default_project_color")]
+    pub color: String,
+    #[serde(default)]
+    pub archived: bool,
+    /// Left out, entries on the project use the user's default rate.
+    pub hourly_rate_cents: Option<i32>,
+    pub currency: Option<String>,
+    /// Shares the project with the organization; takes being one of its
+    /// admins or owners.
+    pub organization_id: Option<i32>,
+}
+
+fn default_project_color() -> String {
+    DEFAULT_PROJECT_COLOR.to_string()
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct ProjectQuery {
+    #[serde(default)]
+    pub include_archived: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// One of the user's tags, with how many entries have it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    pub entries: i64,
+}
@@ -0,0 +1,14 @@
+-- Responses to requests sent with an Idempotency-Key, replayed when the
+-- same request is retried. `scope` keeps keys for different endpoints apart.
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    scope TEXT NOT NULL,
+    key TEXT NOT NULL,
+    request_hash BYTEA NOT NULL,
+    status SMALLINT,
+    location TEXT,
+    body JSONB,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (scope, key)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_keys_expires_at_idx ON idempotency_keys (expires_at);
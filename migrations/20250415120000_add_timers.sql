@@ -0,0 +1,7 @@
+-- At most one running entry per user, however the requests to start them
+-- interleave.
+CREATE UNIQUE INDEX IF NOT EXISTS time_entries_one_running_idx ON time_entries (user_id) WHERE ended_at IS NULL;
+
+-- Whether starting a timer stops the running one, rather than being turned
+-- away.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS auto_stop_timer BOOLEAN NOT NULL DEFAULT FALSE;
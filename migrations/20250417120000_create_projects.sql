@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS projects (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    color VARCHAR(7) NOT NULL DEFAULT '#808080',
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Lets entries require their project to have the same owner.
+    UNIQUE (id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS projects_user_id_idx ON projects (user_id);
+
+-- Deleting a project that still has entries is refused, not cascaded.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS project_id INTEGER;
+ALTER TABLE time_entries ADD CONSTRAINT time_entries_project_fkey
+    FOREIGN KEY (project_id, user_id) REFERENCES projects (id, user_id);
+
+CREATE INDEX IF NOT EXISTS time_entries_project_id_idx ON time_entries (project_id);
@@ -0,0 +1,14 @@
+-- Lets the exclusion constraint compare user ids with a GiST index.
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
+-- Set on entries written with overlaps allowed, which the constraint then
+-- leaves out.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS parallel BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- A running entry's range has no upper bound, so it overlaps everything
+-- after its start. Ranges are half-open, so entries may touch.
+ALTER TABLE time_entries ADD CONSTRAINT time_entries_no_overlap
+    EXCLUDE USING gist (user_id WITH =, tstzrange(started_at, ended_at) WITH &&)
+    WHERE (NOT parallel);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS allow_overlap BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- An IANA time zone name, checked by the app against its tz database.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone TEXT NOT NULL DEFAULT 'UTC';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS week_start TEXT NOT NULL DEFAULT 'monday'
+    CHECK (week_start IN ('monday', 'sunday'));
@@ -0,0 +1,3 @@
+-- Lets calendar apps, which can't send an Authorization header, read the
+-- user's entries feed. Only the hash is kept, as with API keys.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS calendar_token_hash VARCHAR(64) UNIQUE;
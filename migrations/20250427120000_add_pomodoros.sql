@@ -0,0 +1,11 @@
+-- A timer started as a pomodoro stops itself this long after it started.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS pomodoro_target_secs INTEGER CHECK (pomodoro_target_secs > 0);
+
+-- Set when a pomodoro runs all the way to its target; stopping one early
+-- leaves it an ordinary entry.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS pomodoro BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS time_entries_pomodoro_idx ON time_entries (user_id, started_at) WHERE pomodoro;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pomodoro_work_secs INTEGER NOT NULL DEFAULT 1500;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pomodoro_break_secs INTEGER NOT NULL DEFAULT 300;
@@ -0,0 +1,37 @@
+-- Rates are in cents an hour. A project's rate, when it has one, comes
+-- with its own currency; the user's is the fallback for everything else.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS hourly_rate_cents INTEGER CHECK (hourly_rate_cents >= 0);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS currency CHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS hourly_rate_cents INTEGER CHECK (hourly_rate_cents >= 0);
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS currency CHAR(3);
+ALTER TABLE projects ADD CONSTRAINT projects_rate_currency CHECK ((hourly_rate_cents IS NULL) = (currency IS NULL));
+
+-- The rate as it was when the entry was made, so changing a rate later
+-- doesn't rewrite what past work was worth.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS billable BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS rate_cents INTEGER;
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS currency CHAR(3);
+
+-- Taken on insert, and again when the entry moves to another project, so
+-- every way of writing entries gets it.
+CREATE OR REPLACE FUNCTION time_entries_snapshot_rate() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND NEW.project_id IS NOT DISTINCT FROM OLD.project_id THEN
+        RETURN NEW;
+    END IF;
+
+    SELECT COALESCE(p.hourly_rate_cents, u.hourly_rate_cents),
+        CASE WHEN p.hourly_rate_cents IS NOT NULL THEN p.currency ELSE u.currency END
+    INTO NEW.rate_cents, NEW.currency
+    FROM users u
+    LEFT JOIN projects p ON p.id = NEW.project_id
+    WHERE u.id = NEW.user_id;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER time_entries_snapshot_rate
+    BEFORE INSERT OR UPDATE OF project_id ON time_entries
+    FOR EACH ROW EXECUTE FUNCTION time_entries_snapshot_rate();
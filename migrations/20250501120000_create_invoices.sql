@@ -0,0 +1,44 @@
+-- Basis points, so 2000 is 20%.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tax_rate_bps INTEGER NOT NULL DEFAULT 0
+    CHECK (tax_rate_bps BETWEEN 0 AND 10000);
+-- Invoice numbers run per user, without gaps; taking the next one locks
+-- the user's row until the invoice is in.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_invoice_number INTEGER NOT NULL DEFAULT 0;
+
+-- Everything an invoice says is copied onto it, so it reads the same
+-- however the project and entries change afterwards.
+CREATE TABLE IF NOT EXISTS invoices (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    number INTEGER NOT NULL,
+    project_id INTEGER,
+    project_name VARCHAR(100) NOT NULL,
+    period_from DATE NOT NULL,
+    period_to DATE NOT NULL,
+    group_by TEXT NOT NULL CHECK (group_by IN ('day', 'entry')),
+    currency CHAR(3) NOT NULL,
+    tax_rate_bps INTEGER NOT NULL,
+    subtotal_cents BIGINT NOT NULL,
+    tax_cents BIGINT NOT NULL,
+    total_cents BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, number),
+    FOREIGN KEY (project_id, user_id) REFERENCES projects (id, user_id) ON DELETE SET NULL (project_id)
+);
+
+CREATE TABLE IF NOT EXISTS invoice_lines (
+    id SERIAL PRIMARY KEY,
+    invoice_id INTEGER NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    date DATE NOT NULL,
+    description TEXT NOT NULL,
+    seconds BIGINT NOT NULL,
+    rate_cents INTEGER NOT NULL,
+    amount_cents BIGINT NOT NULL,
+    UNIQUE (invoice_id, position)
+);
+
+-- Set once an entry is billed; such entries can't be changed or deleted.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS invoice_id INTEGER REFERENCES invoices(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS time_entries_invoice_id_idx ON time_entries (invoice_id);
@@ -0,0 +1,76 @@
+CREATE TABLE IF NOT EXISTS organizations (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS organization_members (
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'admin', 'member')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (organization_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS organization_members_user_id_idx ON organization_members (user_id);
+
+-- Only a hash of the token is kept, as for email verifications.
+CREATE TABLE IF NOT EXISTS organization_invitations (
+    id SERIAL PRIMARY KEY,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'admin', 'member')),
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    invited_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    accepted_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS organization_invitations_organization_id_idx ON organization_invitations (organization_id);
+
+-- An organization's projects are shared by its members; the user who made
+-- one still owns it.
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS organization_id INTEGER REFERENCES organizations(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS projects_organization_id_idx ON projects (organization_id);
+
+-- Entries may now be on projects someone else owns, so the project's user
+-- no longer has to be the entry's. Deleting a project with entries is
+-- still refused.
+ALTER TABLE time_entries DROP CONSTRAINT IF EXISTS time_entries_project_fkey;
+ALTER TABLE time_entries ADD CONSTRAINT time_entries_project_id_fkey
+    FOREIGN KEY (project_id) REFERENCES projects (id);
+
+-- Checked when an entry is put on a project, not afterwards: leaving an
+-- organization keeps the entries already made on its projects.
+CREATE OR REPLACE FUNCTION time_entries_check_project() RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.project_id IS NULL
+        OR (TG_OP = 'UPDATE' AND NEW.project_id IS NOT DISTINCT FROM OLD.project_id) THEN
+        RETURN NEW;
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT 1 FROM projects p
+        WHERE p.id = NEW.project_id AND (
+            p.user_id = NEW.user_id
+            OR EXISTS (
+                SELECT 1 FROM organization_members m
+                WHERE m.organization_id = p.organization_id AND m.user_id = NEW.user_id
+            )
+        )
+    ) THEN
+        -- Named as the constraint this replaces, which callers look for.
+        RAISE foreign_key_violation USING
+            MESSAGE = 'project not available to the entry''s user',
+            CONSTRAINT = 'time_entries_project_fkey';
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER time_entries_check_project
+    BEFORE INSERT OR UPDATE OF project_id ON time_entries
+    FOR EACH ROW EXECUTE FUNCTION time_entries_check_project();
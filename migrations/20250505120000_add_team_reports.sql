@@ -0,0 +1,11 @@
+-- Days in team reports are in this time zone, or UTC without one.
+ALTER TABLE organizations ADD COLUMN IF NOT EXISTS timezone TEXT;
+
+-- When each former member last left. Their entries on the organization's
+-- projects count in its reports up to then, and not after.
+CREATE TABLE IF NOT EXISTS organization_former_members (
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    left_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (organization_id, user_id)
+);
@@ -0,0 +1,25 @@
+-- A user's week, from the first day of it in their settings. Weeks never
+-- submitted have no row and read as drafts.
+CREATE TABLE IF NOT EXISTS timesheets (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    week_start DATE NOT NULL,
+    state TEXT NOT NULL DEFAULT 'draft' CHECK (state IN ('draft', 'submitted', 'approved', 'rejected')),
+    -- As of the last submission.
+    entries BIGINT NOT NULL DEFAULT 0,
+    tracked_secs BIGINT NOT NULL DEFAULT 0,
+    submitted_at TIMESTAMPTZ,
+    reviewed_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    comment TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, week_start)
+);
+
+CREATE INDEX IF NOT EXISTS timesheets_state_idx ON timesheets (state);
+
+-- Set while the entry's week is submitted or approved; such entries can't
+-- be changed or deleted.
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS timesheet_id INTEGER REFERENCES timesheets(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS time_entries_timesheet_id_idx ON time_entries (timesheet_id);
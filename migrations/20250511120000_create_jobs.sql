@@ -0,0 +1,18 @@
+-- Work to run later, outside the request that asked for it. A job is
+-- deleted once it succeeds; one that ran out of attempts keeps failed_at
+-- and its last error until retried by hand.
+CREATE TABLE IF NOT EXISTS jobs (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    locked_by TEXT,
+    locked_at TIMESTAMPTZ,
+    failed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- What workers claim from.
+CREATE INDEX IF NOT EXISTS jobs_run_at_idx ON jobs (run_at) WHERE failed_at IS NULL;
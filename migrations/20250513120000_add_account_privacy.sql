@@ -0,0 +1,17 @@
+-- Set when the account was erased at its owner's request. The row stays,
+-- nameless, so what was invoiced or approved keeps a user; it is never
+-- restored or purged.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS erased_at TIMESTAMPTZ;
+
+-- Exports too big to build within a request. A job fills in `data`; the
+-- row goes once it expires.
+CREATE TABLE IF NOT EXISTS account_exports (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    data JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    completed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS account_exports_user_id_idx ON account_exports (user_id);
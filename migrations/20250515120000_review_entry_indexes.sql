@@ -0,0 +1,10 @@
+-- Listings page through a user's entries newest first, ties broken by id;
+-- with the id in it the index gives that order as it is, so a page is read
+-- off it and nothing more.
+CREATE INDEX IF NOT EXISTS time_entries_user_id_started_at_id_idx ON time_entries (user_id, started_at DESC, id DESC);
+DROP INDEX IF EXISTS time_entries_user_id_started_at_idx;
+
+-- The primary key finds an entry's tags; this finds a tag's entries, and
+-- answers from the index alone.
+CREATE INDEX IF NOT EXISTS time_entry_tags_tag_id_entry_id_idx ON time_entry_tags (tag_id, entry_id);
+DROP INDEX IF EXISTS time_entry_tags_tag_id_idx;
@@ -0,0 +1,39 @@
+use serde_json::Value;
+use sqlx::PgExecutor;
+use std::net::IpAddr;
+
+use crate::models::AuditEventType;
+use crate::AppError;
+
+/// Something worth a line in `audit_events`.
+pub struct Event<'a> {
+    pub event_type: AuditEventType,
+    pub actor: Option<i32>,
+    /// What was acted on, as a kind (`"user"`, `"api_key"`) and id.
+    pub target: Option<(&'static str, i32)>,
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<&'a str>,
+    pub details: Value,
+}
+
+/// Writes the event through `executor`. Where the action runs in a
+/// transaction, pass that, so the action and its record commit together.
+pub async fn record<'e>(executor: impl PgExecutor<'e>, event: Event<'_>) -> Result<(), AppError> {
+    let (target_type, target_id) = event.target.unzip();
+
+    sqlx::query!(
+        "INSERT INTO audit_events (actor_user_id, event_type, target_type, target_id, ip, user_agent, details)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        event.actor,
+        event.event_type as _,
+        target_type,
+        target_id,
+        event.ip.map(|ip| ip.to_string()),
+        event.user_agent,
+        event.details
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+pub mod breach;
+pub mod jwt;
+pub mod password;
+pub mod tokens;
+pub mod totp;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use jsonwebtoken::errors::ErrorKind;
+
+use tokens::hash_token;
+use crate::error::AppError;
+use crate::models::{Claims, Role};
+use crate::state::AppState;
+
+pub const API_KEY_PREFIX: &str = "tt_";
+
+/// The user behind a valid `Authorization: Bearer <token>` header, where the
+/// token is either a JWT or an API key.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: i32,
+    pub email: String,
+    pub jti: String,
+    pub exp: u64,
+    pub role: Role,
+    /// Set when authenticated with an API key, which has no jti or expiry.
+    pub api_key_id: Option<i32>,
+    /// The login session the access token belongs to.
+    pub session_id: Option<i32>,
+}
+
+impl AuthUser {
+    /// Regular users may only act on their own account.
+    pub fn require_self_or_admin(&self, id: i32) -> Result<(), AppError> {
+        if self.id == id || self.role == Role::Admin {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden("forbidden"))
+        }
+    }
+}
+
+/// An [`AuthUser`] whose token carries the admin role.
+pub struct AdminUser(pub AuthUser);
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let auth = AuthUser::from_request_parts(parts, state).await?;
+
+        if auth.role != Role::Admin {
+            return Err(AppError::Forbidden("forbidden"));
+        }
+
+        Ok(AdminUser(auth))
+    }
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .ok_or(AppError::InvalidToken("missing_token"))?;
+
+        let token = header
+            .to_str()
+            .ok()
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or(AppError::InvalidToken("malformed_token"))?;
+
+        authenticate(state, token).await
+    }
+}
+
+/// The user behind a JWT or API key, however it was passed.
+pub(crate) async fn authenticate(state: &AppState, token: &str) -> Result<AuthUser, AppError> {
+    if token.starts_with(API_KEY_PREFIX) {
+        return api_key_user(state, token).await;
+    }
+
+    let claims = state.jwt.decode::<Claims>(token)
+        .map_err(|err| match err.kind() {
+            ErrorKind::ExpiredSignature => AppError::InvalidToken("token_expired"),
+            _ => AppError::InvalidToken("invalid_token"),
+        })?
+        .claims;
+
+    let id = claims
+        .sub
+        .parse()
+        .map_err(|_| AppError::InvalidToken("invalid_token"))?;
+
+    let revoked = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = $1) AS \"revoked!\"",
+        claims.jti
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    if revoked {
+        return Err(AppError::InvalidToken("token_revoked"));
+    }
+
+    Ok(AuthUser {
+        id,
+        email: claims.email,
+        jti: claims.jti,
+        exp: claims.exp,
+        role: claims.role,
+        api_key_id: None,
+        session_id: claims.sid,
+    })
+}
+
+async fn api_key_user(state: &AppState, key: &str) -> Result<AuthUser, AppError> {
+    let key = sqlx::query!(
+        r#"SELECT api_keys.id, users.id AS user_id, users.email, users.role AS "role: Role",
+            last_used_at IS NULL OR last_used_at < now() - interval '1 minute' AS "stale!"
+        FROM api_keys JOIN users ON users.id = api_keys.user_id
+        WHERE key_hash = $1 AND revoked_at IS NULL AND users.deleted_at IS NULL"#,
+        hash_token(key)
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::InvalidToken("invalid_api_key"))?;
+
+    // Keys used in a tight loop would otherwise write on every request.
+    if key.stale {
+        sqlx::query!("UPDATE api_keys SET last_used_at = now() WHERE id = $1", key.id)
+            .execute(&state.pool)
+            .await?;
+    }
+
+    Ok(AuthUser {
+        id: key.user_id,
+        email: key.email,
+        jti: String::new(),
+        exp: 0,
+        role: key.role,
+        api_key_id: Some(key.id),
+        session_id: None,
+    })
+}
@@ -0,0 +1,108 @@
+use futures::future::BoxFuture;
+use sha1::{Digest, Sha1};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::BoxError;
+
+use crate::validation::ValidationErrors;
+
+pub const DEFAULT_RANGE_URL: &str = "https://api.pwnedpasswords.com/range/";
+
+/// How long a signup waits on the range API before going ahead without it.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Looks up the hashes in a breach corpus that start with a prefix, as
+/// Have I Been Pwned's range API does: one `SUFFIX:COUNT` line for each,
+/// suffixes being the rest of an uppercase hex SHA-1.
+pub trait RangeApi: Send + Sync {
+    fn range<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<String, BoxError>>;
+}
+
+/// The range API over HTTP.
+pub struct HttpRangeApi {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HttpRangeApi {
+    /// `url` is what the prefix is appended to.
+    pub fn new(url: &str) -> HttpRangeApi {
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .user_agent(concat!("tictoc/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("the HTTP client's TLS backend initializes");
+
+        HttpRangeApi { client, url: url.to_string() }
+    }
+}
+
+impl RangeApi for HttpRangeApi {
+    fn range<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<String, BoxError>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .get(format!("{}{}", self.url, prefix))
+                // Pads the answer so its size doesn't give the prefix away.
+                .header("Add-Padding", "true")
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(response.text().await?)
+        })
+    }
+}
+
+/// Refuses new passwords that turn up in breaches. Only the first five hex
+/// digits of the password's SHA-1 leave the server, which hundreds of
+/// breached hashes share, so the API never learns which one was asked about.
+#[derive(Clone)]
+pub struct BreachCheck {
+    api: Arc<dyn RangeApi>,
+    /// Seen this many times or fewer is still allowed.
+    pub threshold: u64,
+}
+
+impl BreachCheck {
+    pub fn new(api: Arc<dyn RangeApi>, threshold: u64) -> BreachCheck {
+        BreachCheck { api, threshold }
+    }
+
+    /// How many times `password` has been seen in breaches, or `None` when
+    /// the API couldn't say in time.
+    pub async fn times_seen(&self, password: &str) -> Option<u64> {
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        match tokio::time::timeout(TIMEOUT, self.api.range(prefix)).await {
+            Ok(Ok(body)) => Some(
+                body.lines()
+                    .filter_map(|line| line.trim().split_once(':'))
+                    .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+                    .and_then(|(_, count)| count.trim().parse().ok())
+                    .unwrap_or(0),
+            ),
+            Ok(Err(err)) => {
+                tracing::warn!(error = %err, "could not check a password against breaches, allowing it");
+                None
+            }
+            Err(_) => {
+                tracing::warn!("breached password check timed out, allowing the password");
+                None
+            }
+        }
+    }
+
+    /// Refuses `password`, as `field`, when it has been seen more than
+    /// `threshold` times. Lets it through when the API is down, so signups
+    /// don't break with it.
+    pub async fn check(&self, field: &str, password: &str) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(seen) = self.times_seen(password).await {
+            if seen > self.threshold {
+                errors.add(field, &format!("has appeared in {} known data breaches; choose another", seen));
+            }
+        }
+        errors.into_result()
+    }
+}
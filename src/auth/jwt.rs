@@ -0,0 +1,226 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::errors::{Error, ErrorKind};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use rsa::pkcs8::der::{Decode, Document};
+use rsa::pkcs8::spki::SubjectPublicKeyInfoRef;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPublicKey;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, RwLock};
+
+/// The keys tokens are signed and verified with: one signing key, plus any
+/// older keys still accepted for verification while their tokens run out.
+/// HMAC keys stay private; asymmetric ones also publish their public half as
+/// a JWK so other services can verify tokens themselves.
+///
+/// Clones share the same set, so [`JwtKeys::replace`] rotates keys for every
+/// holder at once.
+#[derive(Clone)]
+pub struct JwtKeys {
+    set: Arc<RwLock<KeySet>>,
+}
+
+struct KeySet {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    encoding: EncodingKey,
+    /// The signing key's own verifier comes first.
+    verifying: Vec<VerifyingKey>,
+}
+
+struct VerifyingKey {
+    kid: Option<String>,
+    decoding: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+impl JwtKeys {
+    fn new(set: KeySet) -> Self {
+        JwtKeys {
+            set: Arc::new(RwLock::new(set)),
+        }
+    }
+
+    /// A single HMAC secret. Its tokens carry no kid, as they always have.
+    pub fn from_secret(secret: &[u8]) -> Self {
+        JwtKeys::new(KeySet {
+            algorithm: Algorithm::HS256,
+            kid: None,
+            encoding: EncodingKey::from_secret(secret),
+            verifying: vec![VerifyingKey {
+                kid: None,
+                decoding: DecodingKey::from_secret(secret),
+                jwk: None,
+            }],
+        })
+    }
+
+    /// HMAC secrets, newest first. The first one signs; the rest only
+    /// verify. Each is told apart by a kid derived from it.
+    pub fn from_secrets(secrets: &[Vec<u8>]) -> Result<Self, String> {
+        let primary = secrets.first().ok_or("at least one JWT secret is required")?;
+
+        Ok(JwtKeys::new(KeySet {
+            algorithm: Algorithm::HS256,
+            kid: Some(key_id(primary)),
+            encoding: EncodingKey::from_secret(primary),
+            verifying: secrets
+                .iter()
+                .map(|secret| VerifyingKey {
+                    kid: Some(key_id(secret)),
+                    decoding: DecodingKey::from_secret(secret),
+                    jwk: None,
+                })
+                .collect(),
+        }))
+    }
+
+    pub fn from_rsa_pem(private_pem: &[u8], public_pem: &[u8]) -> Result<Self, String> {
+        let public_pem = std::str::from_utf8(public_pem).map_err(|err| err.to_string())?;
+        let public_key = RsaPublicKey::from_public_key_pem(public_pem).map_err(|err| err.to_string())?;
+        let kid = public_key_id(public_pem)?;
+
+        let jwk = Jwk {
+            common: common_parameters(KeyAlgorithm::RS256, &kid),
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+                e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+            }),
+        };
+
+        Ok(JwtKeys::new(KeySet {
+            algorithm: Algorithm::RS256,
+            kid: Some(kid.clone()),
+            encoding: EncodingKey::from_rsa_pem(private_pem).map_err(|err| err.to_string())?,
+            verifying: vec![VerifyingKey {
+                kid: Some(kid),
+                decoding: DecodingKey::from_rsa_pem(public_pem.as_bytes()).map_err(|err| err.to_string())?,
+                jwk: Some(jwk),
+            }],
+        }))
+    }
+
+    pub fn from_ed_pem(private_pem: &[u8], public_pem: &[u8]) -> Result<Self, String> {
+        let public_pem = std::str::from_utf8(public_pem).map_err(|err| err.to_string())?;
+        let (_, document) = Document::from_pem(public_pem).map_err(|err| err.to_string())?;
+        let info = SubjectPublicKeyInfoRef::from_der(document.as_bytes()).map_err(|err| err.to_string())?;
+        let kid = public_key_id(public_pem)?;
+
+        let jwk = Jwk {
+            common: common_parameters(KeyAlgorithm::EdDSA, &kid),
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(info.subject_public_key.raw_bytes()),
+            }),
+        };
+
+        Ok(JwtKeys::new(KeySet {
+            algorithm: Algorithm::EdDSA,
+            kid: Some(kid.clone()),
+            encoding: EncodingKey::from_ed_pem(private_pem).map_err(|err| err.to_string())?,
+            verifying: vec![VerifyingKey {
+                kid: Some(kid),
+                decoding: DecodingKey::from_ed_pem(public_pem.as_bytes()).map_err(|err| err.to_string())?,
+                jwk: Some(jwk),
+            }],
+        }))
+    }
+
+    /// Swaps in the keys of `keys` for every clone of `self`.
+    pub fn replace(&self, keys: JwtKeys) {
+        if Arc::ptr_eq(&self.set, &keys.set) {
+            return;
+        }
+
+        std::mem::swap(&mut *self.set.write().unwrap(), &mut *keys.set.write().unwrap());
+    }
+
+    pub fn signing_kid(&self) -> Option<String> {
+        self.set.read().unwrap().kid.clone()
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let set = self.set.read().unwrap();
+        let mut header = Header::new(set.algorithm);
+        header.kid = set.kid.clone();
+
+        jsonwebtoken::encode(&header, claims, &set.encoding)
+    }
+
+    /// Verifies against the key named by the token's kid. Tokens without one
+    /// predate kids and are tried against every key.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, Error> {
+        let kid = jsonwebtoken::decode_header(token)?.kid;
+        let set = self.set.read().unwrap();
+        let validation = Validation::new(set.algorithm);
+
+        if let Some(kid) = kid {
+            let key = set
+                .verifying
+                .iter()
+                .find(|key| key.kid.as_deref() == Some(kid.as_str()))
+                .ok_or(ErrorKind::InvalidSignature)?;
+
+            return jsonwebtoken::decode(token, &key.decoding, &validation);
+        }
+
+        let mut last_err = Error::from(ErrorKind::InvalidSignature);
+
+        for key in &set.verifying {
+            match jsonwebtoken::decode(token, &key.decoding, &validation) {
+                Ok(data) => return Ok(data),
+                // Anything else means the signature matched, so this was the
+                // right key and there's no point trying the rest.
+                Err(err) if *err.kind() != ErrorKind::InvalidSignature => return Err(err),
+                Err(err) => last_err = err,
+            }
+        }
+
+        Err(last_err)
+    }
+
+    /// The public keys, empty when signing with shared secrets.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .set
+                .read()
+                .unwrap()
+                .verifying
+                .iter()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+fn common_parameters(algorithm: KeyAlgorithm, kid: &str) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Derived from the key itself, so it stays the same across restarts and
+/// changes whenever the key does.
+fn key_id(key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(key)[..8])
+}
+
+fn public_key_id(public_pem: &str) -> Result<String, String> {
+    let (_, document) = Document::from_pem(public_pem).map_err(|err| err.to_string())?;
+
+    Ok(key_id(document.as_bytes()))
+}
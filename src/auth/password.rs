@@ -0,0 +1,203 @@
+use argon2::password_hash::{self, PasswordHash, SaltString};
+use argon2::{Argon2, Params, PasswordVerifier};
+use rand::rngs::OsRng;
+use std::fmt;
+use std::sync::Arc;
+use tokio::task::spawn_blocking;
+
+use crate::config::{Config, Hasher};
+use crate::AppError;
+
+pub const DEFAULT_BCRYPT_COST: u32 = 12;
+
+#[derive(Debug)]
+pub enum PasswordError {
+    Bcrypt(bcrypt::BcryptError),
+    Argon2(password_hash::Error),
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordError::Bcrypt(err) => write!(f, "bcrypt: {}", err),
+            PasswordError::Argon2(err) => write!(f, "argon2: {}", err),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for PasswordError {
+    fn from(err: bcrypt::BcryptError) -> Self {
+        PasswordError::Bcrypt(err)
+    }
+}
+
+impl From<password_hash::Error> for PasswordError {
+    fn from(err: password_hash::Error) -> Self {
+        PasswordError::Argon2(err)
+    }
+}
+
+/// A password hashing backend. Hashes are self-describing strings, so any
+/// backend can tell whether a stored hash is one of its own.
+pub trait PasswordHasher: Send + Sync {
+    fn hash(&self, password: &str) -> Result<String, PasswordError>;
+
+    fn verify(&self, password: &str, password_hash: &str) -> Result<bool, PasswordError>;
+
+    /// Whether `password_hash` was produced by this backend with its current
+    /// parameters.
+    fn is_current(&self, password_hash: &str) -> bool;
+}
+
+pub struct Bcrypt {
+    pub cost: u32,
+}
+
+impl PasswordHasher for Bcrypt {
+    fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify(&self, password: &str, password_hash: &str) -> Result<bool, PasswordError> {
+        Ok(bcrypt::verify(password, password_hash)?)
+    }
+
+    fn is_current(&self, password_hash: &str) -> bool {
+        // $2b$<cost>$<salt and hash>
+        match password_hash.split('$').collect::<Vec<_>>()[..] {
+            ["", "2a" | "2b" | "2x" | "2y", cost, _] => cost.parse() == Ok(self.cost),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Argon2id {
+    pub params: Params,
+}
+
+impl Argon2id {
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, self.params.clone())
+    }
+}
+
+impl PasswordHasher for Argon2id {
+    fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash =
+            argon2::PasswordHasher::hash_password(&self.argon2(), password.as_bytes(), &salt)?;
+
+        Ok(password_hash.to_string())
+    }
+
+    fn verify(&self, password: &str, password_hash: &str) -> Result<bool, PasswordError> {
+        let password_hash = PasswordHash::new(password_hash)?;
+
+        match self.argon2().verify_password(password.as_bytes(), &password_hash) {
+            Ok(()) => Ok(true),
+            Err(password_hash::Error::Password) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn is_current(&self, password_hash: &str) -> bool {
+        match PasswordHash::new(password_hash) {
+            Ok(parsed) => {
+                parsed.algorithm == argon2::Algorithm::Argon2id.ident()
+                    && Params::try_from(&parsed).is_ok_and(|params| {
+                        params.m_cost() == self.params.m_cost()
+                            && params.t_cost() == self.params.t_cost()
+                            && params.p_cost() == self.params.p_cost()
+                    })
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Picks the backend able to verify `password_hash` from its prefix.
+fn backend_for(password_hash: &str) -> Box<dyn PasswordHasher> {
+    if password_hash.starts_with("$argon2") {
+        Box::new(Argon2id::default())
+    } else {
+        // bcrypt reads the cost from the hash itself.
+        Box::new(Bcrypt { cost: DEFAULT_BCRYPT_COST })
+    }
+}
+
+/// Hashes new passwords with the configured backend and verifies stored ones
+/// with whichever backend produced them.
+#[derive(Clone)]
+pub struct Passwords {
+    hasher: Arc<dyn PasswordHasher>,
+    /// Verified against when the email is unknown, so both login failures
+    /// cost the same hashing round.
+    dummy_hash: Arc<str>,
+}
+
+impl Passwords {
+    /// With whichever backend the config picks.
+    pub fn from_config(config: &Config) -> Result<Self, PasswordError> {
+        match config.password_hasher {
+            Hasher::Bcrypt => Passwords::new(Bcrypt { cost: config.bcrypt_cost }),
+            Hasher::Argon2 => Passwords::new(Argon2id::default()),
+        }
+    }
+
+    pub fn new(hasher: impl PasswordHasher + 'static) -> Result<Self, PasswordError> {
+        let dummy_hash = hasher.hash("dummy-password")?;
+
+        Ok(Passwords {
+            hasher: Arc::new(hasher),
+            dummy_hash: dummy_hash.into(),
+        })
+    }
+
+    // Hashing is deliberately slow, so all of these run on the blocking pool
+    // to keep it from stalling the async workers.
+
+    pub async fn hash(&self, password: String) -> Result<String, AppError> {
+        let hasher = self.hasher.clone();
+        Ok(spawn_blocking(move || hasher.hash(&password)).await??)
+    }
+
+    pub async fn verify(&self, password: String, password_hash: String) -> Result<bool, AppError> {
+        Ok(spawn_blocking(move || backend_for(&password_hash).verify(&password, &password_hash)).await??)
+    }
+
+    pub async fn verify_dummy(&self, password: String) -> Result<(), AppError> {
+        let hasher = self.hasher.clone();
+        let dummy_hash = self.dummy_hash.clone();
+        spawn_blocking(move || hasher.verify(&password, &dummy_hash)).await??;
+        Ok(())
+    }
+
+    pub fn needs_rehash(&self, password_hash: &str) -> bool {
+        !self.hasher.is_current(password_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_password_round_trip() {
+        let bcrypt = Passwords::new(Bcrypt { cost: 4 }).unwrap();
+        let argon2 = Passwords::new(Argon2id::default()).unwrap();
+
+        for passwords in [&bcrypt, &argon2] {
+            let password_hash = passwords.hash("password".to_string()).await.unwrap();
+
+            assert!(passwords.verify("password".to_string(), password_hash.clone()).await.unwrap());
+            assert!(!passwords.verify("wrong password".to_string(), password_hash.clone()).await.unwrap());
+            assert!(!passwords.needs_rehash(&password_hash));
+        }
+
+        let password_hash = bcrypt.hash("password".to_string()).await.unwrap();
+        assert!(argon2.verify("password".to_string(), password_hash.clone()).await.unwrap());
+        assert!(argon2.needs_rehash(&password_hash));
+        assert!(Passwords::new(Bcrypt { cost: 5 }).unwrap().needs_rehash(&password_hash));
+    }
+}
@@ -0,0 +1,112 @@
+use jsonwebtoken::get_current_timestamp;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+use crate::error::AppError;
+use crate::models::{ChallengeClaims, Claims, LoginUserResponse, Role};
+use crate::state::AppState;
+
+pub(crate) const TWO_FACTOR_PURPOSE: &str = "2fa";
+pub(crate) const TWO_FACTOR_CHALLENGE_TTL_SECS: u64 = 5 * 60;
+
+/// A random 32-byte token, hex encoded.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Where a token pair belongs: a login starts a session, and a refresh
+/// carries on the one its token was issued in.
+pub(crate) enum Session<'a> {
+    New { ip: Option<IpAddr>, user_agent: Option<&'a str> },
+    Existing(i32),
+}
+
+fn access_claims(state: &AppState, user_id: i32, email: String, role: Role, session_id: i32) -> Claims {
+    let now = get_current_timestamp();
+    Claims {
+        sub: user_id.to_string(),
+        email,
+        jti: uuid::Uuid::new_v4().to_string(),
+        iat: now,
+        exp: now + state.token_ttl_secs,
+        role,
+        sid: Some(session_id),
+    }
+}
+
+/// Issues an access token together with a new opaque refresh token, of
+/// which only the hash is stored. The session remembers the access token,
+/// so revoking it can cut that off too.
+pub(crate) async fn issue_tokens(
+    state: &AppState,
+    session: Session<'_>,
+    user_id: i32,
+    email: String,
+    role: Role,
+    verified: bool,
+) -> Result<LoginUserResponse, AppError> {
+    let refresh_token = random_token();
+    let mut tx = state.pool.begin().await?;
+
+    let session_id = match session {
+        Session::New { ip, user_agent } => {
+            sqlx::query_scalar!(
+                "INSERT INTO sessions (user_id, user_agent, ip) VALUES ($1, $2, $3) RETURNING id",
+                user_id,
+                user_agent,
+                ip.map(|ip| ip.to_string())
+            )
+            .fetch_one(&mut *tx)
+            .await?
+        }
+        Session::Existing(id) => id,
+    };
+    let claims = access_claims(state, user_id, email, role, session_id);
+
+    sqlx::query!(
+        "UPDATE sessions SET last_used_at = now(), access_jti = $2, access_expires_at = to_timestamp($3) WHERE id = $1",
+        session_id,
+        claims.jti,
+        claims.exp as f64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO refresh_tokens (token_hash, user_id, session_id, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(secs => $4))",
+        hash_token(&refresh_token),
+        user_id,
+        session_id,
+        state.refresh_ttl_secs as f64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(LoginUserResponse {
+        token: state.jwt.encode(&claims)?,
+        refresh_token,
+        verified,
+    })
+}
+
+pub(crate) fn challenge_token(state: &AppState, user_id: i32) -> Result<String, AppError> {
+    let now = get_current_timestamp();
+    let claims = ChallengeClaims {
+        sub: user_id.to_string(),
+        purpose: TWO_FACTOR_PURPOSE.to_string(),
+        iat: now,
+        exp: now + TWO_FACTOR_CHALLENGE_TTL_SECS,
+    };
+
+    Ok(state.jwt.encode(&claims)?)
+}
@@ -0,0 +1,197 @@
+use axum::body::Bytes;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use moka::sync::Cache as MokaCache;
+use moka::Expiry;
+use serde::Serialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::error::AppError;
+
+/// Entries the in-memory cache holds before evicting the least used.
+pub const MEMORY_CAPACITY: u64 = 10_000;
+
+// What's cached is response bodies, as the endpoint serializes them, so a
+// hit is sent as it is. Whatever changes what a key holds deletes it after
+// committing; a read racing that delete can still put back what it read
+// before, which the TTL bounds. So do writes that only touch a key in
+// passing: `/me` isn't forgotten for bookkeeping like failed logins, which
+// moves nothing but `updated_at`, nor the timer for a renamed tag.
+
+/// Where cached responses are kept. Backends deal with their own errors: a
+/// cache that can't be reached misses, and writes to it are dropped.
+pub trait Cache: Send + Sync {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<Bytes>>;
+    fn set<'a>(&'a self, key: &'a str, value: Bytes, ttl: Duration) -> BoxFuture<'a, ()>;
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()>;
+}
+
+/// `GET /me`.
+pub fn me_key(user_id: i32) -> String {
+    format!("me:{}", user_id)
+}
+
+/// `GET /timer/current`.
+pub fn timer_key(user_id: i32) -> String {
+    format!("timer:{}", user_id)
+}
+
+/// The first page of `GET /projects`, as the apps ask for it.
+pub fn projects_key(user_id: i32, include_archived: bool) -> String {
+    match include_archived {
+        true => format!("projects:{}:archived", user_id),
+        false => format!("projects:{}", user_id),
+    }
+}
+
+/// Forgets every cached list of the users' projects.
+pub async fn forget_projects(cache: &dyn Cache, user_ids: impl IntoIterator<Item = i32>) {
+    for user_id in user_ids {
+        cache.delete(&projects_key(user_id, false)).await;
+        cache.delete(&projects_key(user_id, true)).await;
+    }
+}
+
+/// A cached body, sent as JSON.
+pub fn json_response(body: Bytes) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Serves `key` from the cache, or else what `load` returns, caching that
+/// for `ttl`. Errors aren't cached.
+pub async fn cached_json<T, F>(cache: &dyn Cache, key: &str, ttl: Duration, load: F) -> Result<Response, AppError>
+where
+    T: Serialize,
+    F: Future<Output = Result<T, AppError>>,
+{
+    if let Some(body) = cache.get(key).await {
+        return Ok(json_response(body));
+    }
+
+    let value = load.await?;
+    let body = Bytes::from(serde_json::to_vec(&value).unwrap_or_default());
+    if !ttl.is_zero() {
+        cache.set(key, body.clone(), ttl).await;
+    }
+
+    Ok(json_response(body))
+}
+
+/// The in-process cache, for a single instance or when Redis isn't set up.
+pub struct MemoryCache {
+    entries: MokaCache<String, (Bytes, Duration)>,
+}
+
+/// Each entry lives as long as it was set for.
+struct PerEntryTtl;
+
+impl Expiry<String, (Bytes, Duration)> for PerEntryTtl {
+    fn expire_after_create(&self, _key: &String, value: &(Bytes, Duration), _created_at: Instant) -> Option<Duration> {
+        Some(value.1)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        value: &(Bytes, Duration),
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(value.1)
+    }
+}
+
+impl MemoryCache {
+    pub fn new(capacity: u64) -> MemoryCache {
+        MemoryCache {
+            entries: MokaCache::builder().max_capacity(capacity).expire_after(PerEntryTtl).build(),
+        }
+    }
+}
+
+impl Default for MemoryCache {
+    fn default() -> MemoryCache {
+        MemoryCache::new(MEMORY_CAPACITY)
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<Bytes>> {
+        let value = self.entries.get(key).map(|(value, _)| value);
+        Box::pin(async move { value })
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Bytes, ttl: Duration) -> BoxFuture<'a, ()> {
+        self.entries.insert(key.to_string(), (value, ttl));
+        Box::pin(async {})
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+        self.entries.invalidate(key);
+        Box::pin(async {})
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisCache;
+
+#[cfg(feature = "redis")]
+mod redis {
+    use axum::body::Bytes;
+    use futures::future::BoxFuture;
+    use redis::aio::ConnectionManager;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+
+    use super::Cache;
+
+    /// Keys are prefixed so the cache can share a database with other uses.
+    const PREFIX: &str = "tictoc:cache:";
+
+    /// Shared by every instance, so an invalidation on one is seen by all.
+    pub struct RedisCache {
+        connection: ConnectionManager,
+    }
+
+    impl RedisCache {
+        pub fn new(connection: ConnectionManager) -> RedisCache {
+            RedisCache { connection }
+        }
+    }
+
+    impl Cache for RedisCache {
+        fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<Bytes>> {
+            Box::pin(async move {
+                let mut connection = self.connection.clone();
+                match connection.get::<_, Option<Vec<u8>>>(format!("{}{}", PREFIX, key)).await {
+                    Ok(value) => value.map(Bytes::from),
+                    Err(err) => {
+                        tracing::warn!(key, error = %err, "could not read from the cache");
+                        None
+                    }
+                }
+            })
+        }
+
+        fn set<'a>(&'a self, key: &'a str, value: Bytes, ttl: Duration) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                let mut connection = self.connection.clone();
+                let millis = ttl.as_millis().max(1) as u64;
+                if let Err(err) = connection.pset_ex::<_, _, ()>(format!("{}{}", PREFIX, key), value.as_ref(), millis).await {
+                    tracing::warn!(key, error = %err, "could not write to the cache");
+                }
+            })
+        }
+
+        fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                let mut connection = self.connection.clone();
+                if let Err(err) = connection.del::<_, ()>(format!("{}{}", PREFIX, key)).await {
+                    tracing::error!(key, error = %err, "could not invalidate the cache");
+                }
+            })
+        }
+    }
+}
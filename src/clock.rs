@@ -0,0 +1,45 @@
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Where the current time comes from, so time-based rules can be tested
+/// without waiting.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// When `date` starts in `tz`. A day that starts in a gap, its clocks going
+/// forward at midnight, starts when they do.
+pub fn local_midnight(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+
+    match tz.from_local_datetime(&midnight).earliest() {
+        Some(start) => start.to_utc(),
+        None => {
+            let before = tz.offset_from_utc_datetime(&(midnight - Days::new(1))).fix();
+            (midnight - before).and_utc()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_midnight() {
+        let date = NaiveDate::from_ymd_opt(2025, 4, 7).unwrap();
+        assert_eq!(local_midnight(date, Tz::America__Sao_Paulo).to_rfc3339(), "2025-04-07T03:00:00+00:00");
+
+        // São Paulo's clocks went from midnight straight to one, that day.
+        let date = NaiveDate::from_ymd_opt(2018, 11, 4).unwrap();
+        assert_eq!(local_midnight(date, Tz::America__Sao_Paulo).to_rfc3339(), "2018-11-04T03:00:00+00:00");
+    }
+}
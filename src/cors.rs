@@ -0,0 +1,39 @@
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// How long browsers may reuse a preflight answer.
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Lets browsers on `origins` call the API. With no origins there is no
+/// layer at all, and cross-origin calls stay blocked.
+///
+/// `origins` are expected to have been checked by the config already.
+pub fn layer(origins: &[String], allow_credentials: bool) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().map(|origin| HeaderValue::from_str(origin).unwrap()))
+    };
+    let request_id = HeaderName::from_static("x-request-id");
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+            .allow_headers([
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                header::IF_MATCH,
+                header::IF_NONE_MATCH,
+                request_id.clone(),
+            ])
+            .expose_headers([request_id, header::ETAG, header::LOCATION, header::RETRY_AFTER])
+            .allow_credentials(allow_credentials)
+            .max_age(MAX_AGE),
+    )
+}
@@ -0,0 +1,354 @@
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, PgPool};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::log::LevelFilter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::config::Config;
+use crate::metrics::Metrics;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The migrations built into the binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Debug)]
+pub enum DbError {
+    Connect(sqlx::Error),
+    Migrate(MigrateError),
+    /// Versions that have not been applied yet.
+    Pending(Vec<i64>),
+    /// Versions applied from a different file than the one built in.
+    Changed(Vec<i64>),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Connect(err) => write!(f, "could not connect: {}", err),
+            DbError::Migrate(err) => write!(f, "could not migrate: {}", err),
+            DbError::Pending(versions) => write!(f, "migrations pending: {}", versions_list(versions)),
+            DbError::Changed(versions) => write!(f, "migrations changed since applied: {}", versions_list(versions)),
+        }
+    }
+}
+
+fn versions_list(versions: &[i64]) -> String {
+    versions.iter().map(i64::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// How long to keep trying to reach the database before giving up.
+pub struct Retry {
+    pub attempts: u32,
+    /// Gives up once this much time has passed, even with attempts left.
+    pub max_elapsed: Duration,
+    pub initial_backoff: Duration,
+}
+
+impl Retry {
+    pub fn from_config(config: &Config) -> Retry {
+        Retry {
+            attempts: config.db_connect_attempts,
+            max_elapsed: Duration::from_secs(config.db_connect_timeout_secs),
+            initial_backoff: INITIAL_BACKOFF,
+        }
+    }
+}
+
+pub fn pool_options(config: &Config) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.db_max_connections)
+        .min_connections(config.db_min_connections)
+        .acquire_timeout(Duration::from_secs(config.db_acquire_timeout_secs))
+        // Zero keeps idle connections open for good.
+        .idle_timeout(Some(Duration::from_secs(config.db_idle_timeout_secs)).filter(|timeout| !timeout.is_zero()))
+}
+
+/// The database at `url`, logging its statements as
+/// [`log_statements`] does with `SLOW_QUERY_MS`.
+pub fn connect_options(config: &Config, url: &str) -> Result<PgConnectOptions, DbError> {
+    let options = url.parse().map_err(DbError::Connect)?;
+    Ok(log_statements(options, Duration::from_millis(config.slow_query_ms)))
+}
+
+/// Logs every statement to `sqlx::query` at DEBUG, and ones taking `slow` or
+/// longer at WARN, with the SQL and how long it took. Logged inside the
+/// request's span, so they carry its `request_id`. Only the statement's text
+/// is ever logged, never what's bound to it, so passwords and tokens stay
+/// out of the logs.
+pub fn log_statements(options: PgConnectOptions, slow: Duration) -> PgConnectOptions {
+    options
+        .log_statements(LevelFilter::Debug)
+        .log_slow_statements(LevelFilter::Warn, slow)
+}
+
+/// Connects, and runs the migrations when `migrate` is set, retrying both
+/// with exponential backoff so the server can start before Postgres is
+/// ready.
+pub async fn connect(
+    options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+    retry: &Retry,
+    migrate: bool,
+) -> Result<PgPool, DbError> {
+    let started = Instant::now();
+    let mut backoff = retry.initial_backoff;
+    let mut attempt = 1;
+
+    loop {
+        let err = match options.clone().connect_with(connect_options.clone()).await {
+            Ok(pool) if !migrate => return Ok(pool),
+            Ok(pool) => match MIGRATOR.run(&pool).await {
+                Ok(()) => return Ok(pool),
+                Err(err) => DbError::Migrate(err),
+            },
+            Err(err) => DbError::Connect(err),
+        };
+
+        if attempt >= retry.attempts || started.elapsed() + backoff > retry.max_elapsed {
+            return Err(err);
+        }
+
+        tracing::warn!(
+            attempt,
+            max_attempts = retry.attempts,
+            retry_in_ms = backoff.as_millis() as u64,
+            error = %err,
+            "database not ready"
+        );
+
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Which pool a query goes to, from [`AppState::db`](crate::AppState::db).
+/// Reads that can be a moment behind go to the replica when there is one;
+/// writes, and reads that have to see them, go to the primary.
+pub struct Db<'a> {
+    primary: &'a PgPool,
+    replica: Option<&'a PgPool>,
+    metrics: &'a Metrics,
+}
+
+impl<'a> Db<'a> {
+    pub fn new(primary: &'a PgPool, replica: Option<&'a PgPool>, metrics: &'a Metrics) -> Db<'a> {
+        Db { primary, replica, metrics }
+    }
+
+    /// The replica, or the primary without one. Each call counts towards
+    /// `db_reads_total`.
+    pub fn reader(&self) -> &'a PgPool {
+        match self.replica {
+            Some(replica) => {
+                self.metrics.record_db_read("replica");
+                replica
+            }
+            None => {
+                self.metrics.record_db_read("primary");
+                self.primary
+            }
+        }
+    }
+
+    pub fn writer(&self) -> &'a PgPool {
+        self.primary
+    }
+}
+
+/// Counts the slow statements sqlx logs towards `db_slow_queries_total`, by
+/// [`fingerprint`]. Filter it to WARN on `sqlx::query` when adding it, so
+/// statements that aren't slow don't have their log lines built for it.
+pub struct SlowQueries;
+
+impl<S: tracing::Subscriber> Layer<S> for SlowQueries {
+    fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+        let mut statement = Statement::default();
+        event.record(&mut statement);
+        if !statement.slow {
+            return;
+        }
+
+        // The summary is the whole statement when it's short enough.
+        let sql = match statement.sql.trim() {
+            "" => statement.summary.as_str(),
+            sql => sql,
+        };
+        crate::metrics::record_slow_query(&fingerprint(sql));
+    }
+}
+
+#[derive(Default)]
+struct Statement {
+    summary: String,
+    sql: String,
+    slow: bool,
+}
+
+impl Visit for Statement {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_string(),
+            "db.statement" => self.sql = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, _: &dyn fmt::Debug) {
+        if field.name() == "slow_threshold" {
+            self.slow = true;
+        }
+    }
+}
+
+/// `sql` with its literals taken out and its whitespace collapsed, so the
+/// same statement counts as one whatever it was run with.
+pub fn fingerprint(sql: &str) -> String {
+    let mut fingerprint = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // A doubled quote is one inside the string.
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                fingerprint.push('?');
+            }
+            // Digits on the end of a name or a `$1` placeholder are kept.
+            '0'..='9' if !fingerprint.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$') => {
+                while chars.next_if(|c| c.is_ascii_digit() || *c == '.').is_some() {}
+                fingerprint.push('?');
+            }
+            c if c.is_whitespace() => {
+                if !fingerprint.is_empty() && !fingerprint.ends_with(' ') {
+                    fingerprint.push(' ');
+                }
+            }
+            c => fingerprint.push(c),
+        }
+    }
+
+    fingerprint.truncate(fingerprint.trim_end().len());
+    fingerprint
+}
+
+/// The built-in migrations the database has not seen yet, without creating
+/// the bookkeeping table when there is none.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<&'static Migration>, DbError> {
+    let applied = applied_migrations(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .collect())
+}
+
+async fn applied_migrations(pool: &PgPool) -> Result<HashMap<i64, Vec<u8>>, DbError> {
+    let exists = sqlx::query_scalar!(r#"SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "exists!""#)
+        .fetch_one(pool)
+        .await
+        .map_err(DbError::Connect)?;
+    if !exists {
+        return Ok(HashMap::new());
+    }
+
+    let mut conn = pool.acquire().await.map_err(DbError::Connect)?;
+    let applied = conn.list_applied_migrations().await.map_err(DbError::Migrate)?;
+
+    Ok(applied
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum.into_owned()))
+        .collect())
+}
+
+/// Applies whatever is pending and returns what that was.
+pub async fn migrate(pool: &PgPool) -> Result<Vec<&'static Migration>, DbError> {
+    let pending = pending_migrations(pool).await?;
+    MIGRATOR.run(pool).await.map_err(DbError::Migrate)?;
+    Ok(pending)
+}
+
+/// Passes when every built-in migration has been applied, unchanged.
+pub async fn check(pool: &PgPool) -> Result<(), DbError> {
+    let applied = applied_migrations(pool).await?;
+    let mut pending = Vec::new();
+    let mut changed = Vec::new();
+
+    for migration in MIGRATOR.iter().filter(|migration| !migration.migration_type.is_down_migration()) {
+        match applied.get(&migration.version) {
+            None => pending.push(migration.version),
+            Some(checksum) if *checksum != *migration.checksum => changed.push(migration.version),
+            Some(_) => {}
+        }
+    }
+
+    if !changed.is_empty() {
+        Err(DbError::Changed(changed))
+    } else if !pending.is_empty() {
+        Err(DbError::Pending(pending))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_connect_retries_then_gives_up() {
+        // Accepts connections and hangs up straight away, like a database
+        // that is still starting.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("postgres://postgres@{}/tictoc", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicU32::new(0));
+
+        tokio::spawn({
+            let connections = connections.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    drop(stream);
+                }
+            }
+        });
+
+        let retry = Retry {
+            attempts: 4,
+            max_elapsed: Duration::from_secs(10),
+            initial_backoff: Duration::from_millis(20),
+        };
+        let options = PgPoolOptions::new().acquire_timeout(Duration::from_secs(1));
+
+        let started = Instant::now();
+        let result = connect(options, url.parse().unwrap(), &retry, true).await;
+
+        assert!(matches!(result, Err(DbError::Connect(_))));
+        assert_eq!(connections.load(Ordering::SeqCst), 4);
+        // 20ms + 40ms + 80ms of backoff between the four attempts.
+        assert!(started.elapsed() >= Duration::from_millis(140));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(fingerprint("SELECT pg_sleep(0.25)"), "SELECT pg_sleep(?)");
+        assert_eq!(
+            fingerprint("SELECT id FROM users\n    WHERE email = 'o''brien@gmail.com' AND id > 10 LIMIT $1\n"),
+            "SELECT id FROM users WHERE email = ? AND id > ? LIMIT $1"
+        );
+        assert_eq!(fingerprint("SELECT t1.id FROM time_entries t1"), "SELECT t1.id FROM time_entries t1");
+    }
+}
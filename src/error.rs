@@ -0,0 +1,254 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::time::Duration;
+
+use crate::validation::ValidationErrors;
+
+pub use tictoc_types::{BodyErrorResponse, EntriesErrorResponse, ErrorResponse, TimesheetLockedResponse};
+
+#[derive(Debug)]
+pub enum AppError {
+    Database(sqlx::Error),
+    Hashing(crate::auth::password::PasswordError),
+    Token(jsonwebtoken::errors::Error),
+    Task(tokio::task::JoinError),
+    Template(askama::Error),
+    Config(String),
+    BadRequest(&'static str),
+    NotFound(&'static str),
+    Conflict(&'static str),
+    /// The ids of the entries a time entry would overlap.
+    Overlap(Vec<i32>),
+    /// Ids in a bulk change that aren't the user's entries.
+    EntriesNotFound(Vec<i32>),
+    /// Entries that can't change, having been billed.
+    EntriesInvoiced(Vec<i32>),
+    /// Entries in the user's week that starts on `week`, which is waiting
+    /// for approval or has it.
+    TimesheetLocked { entry_ids: Vec<i32>, week: chrono::NaiveDate },
+    /// Well-formed, but not something that can be done.
+    Unprocessable(&'static str),
+    Unauthorized,
+    /// An update that didn't say which version it was made against.
+    PreconditionRequired,
+    /// The resource changed since the client read it; `current` is what it
+    /// looks like now, so the client can merge.
+    PreconditionFailed { etag: String, current: serde_json::Value },
+    Forbidden(&'static str),
+    InvalidToken(&'static str),
+    TooManyRequests { retry_after_secs: u64 },
+    Locked,
+    Validation(ValidationErrors),
+    InvalidInput {
+        error: &'static str,
+        field: Option<String>,
+        detail: String,
+    },
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    Timeout,
+    Overloaded,
+    Middleware(tower::BoxError),
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => AppError::Conflict("conflict"),
+            _ => AppError::Database(err),
+        }
+    }
+}
+
+impl From<crate::auth::password::PasswordError> for AppError {
+    fn from(err: crate::auth::password::PasswordError) -> Self {
+        AppError::Hashing(err)
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(err: ValidationErrors) -> Self {
+        AppError::Validation(err)
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        AppError::Task(err)
+    }
+}
+
+impl From<askama::Error> for AppError {
+    fn from(err: askama::Error) -> Self {
+        AppError::Template(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        AppError::Token(err)
+    }
+}
+
+const UNIQUE_VIOLATION: &str = "23505";
+
+pub(crate) fn email_conflict(err: sqlx::Error) -> AppError {
+    match &err {
+        sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+            AppError::Conflict("email_already_registered")
+        }
+        _ => AppError::from(err),
+    }
+}
+
+/// Writes to a time entry that the request is to blame for: a second
+/// running entry, one overlapping another, or a project that isn't the
+/// user's.
+pub(crate) fn entry_error(err: sqlx::Error) -> AppError {
+    let constraint = match &err {
+        sqlx::Error::Database(db_err) => db_err.constraint(),
+        _ => None,
+    };
+
+    match constraint {
+        Some("time_entries_one_running_idx") => AppError::Conflict("timer_already_running"),
+        // Only reached when the overlapping entry was written after the
+        // check for one, so there's no id to give.
+        Some("time_entries_no_overlap") => AppError::Overlap(Vec::new()),
+        Some("time_entries_project_fkey") => {
+            let mut errors = ValidationErrors::default();
+            errors.add("project_id", "not found");
+            AppError::Validation(errors)
+        }
+        _ => AppError::from(err),
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            AppError::Validation(errors) => {
+                return (StatusCode::UNPROCESSABLE_ENTITY, Json(errors)).into_response();
+            }
+            AppError::InvalidInput { error, field, detail } => {
+                let status = match error {
+                    "invalid_field" => StatusCode::UNPROCESSABLE_ENTITY,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                let body = BodyErrorResponse {
+                    error: error.to_string(),
+                    field,
+                    detail,
+                };
+
+                return (status, Json(body)).into_response();
+            }
+            AppError::TooManyRequests { retry_after_secs } => {
+                let body = ErrorResponse {
+                    error: "too_many_requests".to_string(),
+                };
+
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                    Json(body),
+                )
+                    .into_response();
+            }
+            AppError::PreconditionFailed { etag, current } => {
+                let body = serde_json::json!({ "error": "version_mismatch", "current": current });
+
+                return (StatusCode::PRECONDITION_FAILED, [(header::ETAG, etag)], Json(body)).into_response();
+            }
+            AppError::Overlap(entry_ids) => {
+                let body = EntriesErrorResponse {
+                    error: "entries_overlap".to_string(),
+                    entry_ids,
+                };
+
+                return (StatusCode::CONFLICT, Json(body)).into_response();
+            }
+            AppError::EntriesNotFound(entry_ids) => {
+                let body = EntriesErrorResponse {
+                    error: "entries_not_found".to_string(),
+                    entry_ids,
+                };
+
+                return (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response();
+            }
+            AppError::EntriesInvoiced(entry_ids) => {
+                let body = EntriesErrorResponse {
+                    error: "entries_invoiced".to_string(),
+                    entry_ids,
+                };
+
+                return (StatusCode::CONFLICT, Json(body)).into_response();
+            }
+            AppError::TimesheetLocked { entry_ids, week } => {
+                let body = TimesheetLockedResponse {
+                    error: "timesheet_locked".to_string(),
+                    entry_ids,
+                    timesheet: format!("/timesheets/{}", week),
+                };
+
+                return (StatusCode::LOCKED, Json(body)).into_response();
+            }
+            AppError::PreconditionRequired => (StatusCode::PRECONDITION_REQUIRED, "precondition_required"),
+            AppError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large"),
+            AppError::UnsupportedMediaType => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type"),
+            AppError::Timeout => (StatusCode::REQUEST_TIMEOUT, "request_timeout"),
+            AppError::Overloaded => (StatusCode::SERVICE_UNAVAILABLE, "overloaded"),
+            AppError::Middleware(err) => {
+                tracing::error!(error = %err, "middleware error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+            }
+            AppError::Database(err) => {
+                tracing::error!(error = %err, "database error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "database_error")
+            }
+            AppError::Hashing(err) => {
+                tracing::error!(error = %err, "hashing error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "hashing_error")
+            }
+            AppError::Token(err) => {
+                tracing::error!(error = %err, "token error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "token_error")
+            }
+            AppError::Task(err) => {
+                tracing::error!(error = %err, "task error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+            }
+            AppError::Template(err) => {
+                tracing::error!(error = %err, "template error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "template_error")
+            }
+            AppError::Config(err) => {
+                tracing::error!(error = %err, "config error");
+                (StatusCode::INTERNAL_SERVER_ERROR, "config_error")
+            }
+            AppError::BadRequest(error) => (StatusCode::BAD_REQUEST, error),
+            AppError::NotFound(error) => (StatusCode::NOT_FOUND, error),
+            AppError::Conflict(error) => (StatusCode::CONFLICT, error),
+            AppError::Unprocessable(error) => (StatusCode::UNPROCESSABLE_ENTITY, error),
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "invalid_credentials"),
+            AppError::Forbidden(error) => (StatusCode::FORBIDDEN, error),
+            AppError::InvalidToken(error) => (StatusCode::UNAUTHORIZED, error),
+            AppError::Locked => (StatusCode::LOCKED, "account_locked"),
+        };
+
+        let body = ErrorResponse {
+            error: error.to_string(),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+pub(crate) fn rate_limited(retry_after: Duration) -> AppError {
+    AppError::TooManyRequests {
+        retry_after_secs: retry_after.as_secs_f64().ceil() as u64,
+    }
+}
@@ -0,0 +1,218 @@
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::models::{ExportFormat, UserResponse};
+
+/// Rows encoded ahead of the client. Past this the query waits for the
+/// client to catch up, so memory stays flat however many rows there are.
+const BUFFERED_ROWS: usize = 64;
+
+/// In the order [`UserResponse`] serializes its fields.
+const CSV_COLUMNS: [&str; 7] = ["id", "name", "email", "created_at", "updated_at", "version", "last_login_at"];
+
+/// In the order [`TimesheetRow`] serializes its fields.
+const TIMESHEET_COLUMNS: [&str; 7] = ["date", "start", "end", "duration", "project", "tags", "description"];
+
+/// Which of a user's entries go in a timesheet: the finished ones started
+/// in `[start, end)`.
+pub struct TimesheetFilter {
+    pub user_id: i32,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub project_id: Option<i32>,
+}
+
+/// An entry as a timesheet shows it, its times local to the user.
+#[derive(Serialize)]
+struct TimesheetRow {
+    date: String,
+    start: String,
+    end: String,
+    /// `HH:MM:SS`, hours going past 24 if they have to.
+    duration: String,
+    project: Option<String>,
+    /// Joined with `;`.
+    tags: String,
+    description: String,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Every live user, oldest first, streamed straight from the query as it
+/// is read. A failure part way through cuts the body short, which clients
+/// see as the connection dropping rather than the body ending: a JSON
+/// array never gets its closing `]`, so what did arrive doesn't parse.
+pub fn users(pool: PgPool, format: ExportFormat) -> Body {
+    let (mut sender, receiver) = mpsc::channel::<Result<Bytes, sqlx::Error>>(BUFFERED_ROWS);
+
+    tokio::spawn(async move {
+        let mut encoder = Encoder::new(format);
+        if sender.send(Ok(encoder.start(&CSV_COLUMNS))).await.is_err() {
+            return;
+        }
+
+        let mut users = sqlx::query_as!(
+            UserResponse,
+            "SELECT id, name, email, created_at, updated_at, version, last_login_at FROM users
+            WHERE deleted_at IS NULL ORDER BY id"
+        )
+        .fetch(&pool);
+
+        while let Some(user) = users.next().await {
+            let chunk = user.map(|user| encoder.row(&user));
+            if let Err(err) = &chunk {
+                tracing::error!(error = %err, "user export failed");
+            }
+
+            let failed = chunk.is_err();
+            // The client went away.
+            if sender.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+
+        let _ = sender.send(Ok(encoder.end())).await;
+    });
+
+    Body::from_stream(receiver)
+}
+
+/// The user's entries in the filter's range, oldest first, streamed the
+/// same way as [`users`].
+pub fn timesheet(pool: PgPool, filter: TimesheetFilter, tz: Tz, format: ExportFormat) -> Body {
+    let (mut sender, receiver) = mpsc::channel::<Result<Bytes, sqlx::Error>>(BUFFERED_ROWS);
+
+    tokio::spawn(async move {
+        let mut encoder = Encoder::new(format);
+        if sender.send(Ok(encoder.start(&TIMESHEET_COLUMNS))).await.is_err() {
+            return;
+        }
+
+        let mut entries = sqlx::query!(
+            r#"SELECT e.started_at, e.ended_at AS "ended_at!", e.description, p.name AS "project?",
+                COALESCE(array_agg(t.name ORDER BY lower(t.name)) FILTER (WHERE t.id IS NOT NULL), '{}') AS "tags!"
+            FROM time_entries e
+            LEFT JOIN projects p ON p.id = e.project_id
+            LEFT JOIN time_entry_tags et ON et.entry_id = e.id
+            LEFT JOIN tags t ON t.id = et.tag_id
+            WHERE e.user_id = $1 AND e.started_at >= $2 AND e.started_at < $3 AND e.ended_at IS NOT NULL
+                AND ($4::int IS NULL OR e.project_id = $4)
+            GROUP BY e.id, p.name
+            ORDER BY e.started_at, e.id"#,
+            filter.user_id,
+            filter.start,
+            filter.end,
+            filter.project_id
+        )
+        .fetch(&pool);
+
+        while let Some(entry) = entries.next().await {
+            let chunk = entry.map(|entry| {
+                let (start, end) = (entry.started_at.with_timezone(&tz), entry.ended_at.with_timezone(&tz));
+                let secs = (entry.ended_at - entry.started_at).num_seconds();
+                let row = TimesheetRow {
+                    date: start.format("%Y-%m-%d").to_string(),
+                    start: start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    end: end.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    duration: format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+                    project: entry.project,
+                    tags: entry.tags.join(";"),
+                    description: entry.description,
+                };
+                encoder.row(&row)
+            });
+            if let Err(err) = &chunk {
+                tracing::error!(error = %err, "timesheet export failed");
+            }
+
+            let failed = chunk.is_err();
+            if sender.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+
+        let _ = sender.send(Ok(encoder.end())).await;
+    });
+
+    Body::from_stream(receiver)
+}
+
+/// Frames rows one at a time: CSV under a header, NDJSON a line each, or
+/// JSON between brackets and commas.
+struct Encoder {
+    format: ExportFormat,
+    rows: usize,
+}
+
+impl Encoder {
+    fn new(format: ExportFormat) -> Encoder {
+        Encoder { format, rows: 0 }
+    }
+
+    /// What goes before the first row; CSV's header names `columns`.
+    fn start(&self, columns: &[&str]) -> Bytes {
+        match self.format {
+            ExportFormat::Csv => csv_row(columns),
+            ExportFormat::Ndjson => Bytes::new(),
+            ExportFormat::Json => Bytes::from_static(b"["),
+        }
+    }
+
+    // Writing to a Vec can't fail, and nothing exported fails to serialize.
+    fn row<T: Serialize>(&mut self, row: &T) -> Bytes {
+        self.rows += 1;
+
+        match self.format {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+                writer.serialize(row).unwrap();
+                Bytes::from(writer.into_inner().unwrap())
+            }
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_vec(row).unwrap();
+                line.push(b'\n');
+                Bytes::from(line)
+            }
+            ExportFormat::Json => {
+                let mut item = if self.rows == 1 { Vec::new() } else { b",".to_vec() };
+                serde_json::to_writer(&mut item, row).unwrap();
+                Bytes::from(item)
+            }
+        }
+    }
+
+    /// What goes after the last row, once every one was sent.
+    fn end(&self) -> Bytes {
+        match self.format {
+            ExportFormat::Csv | ExportFormat::Ndjson => Bytes::new(),
+            ExportFormat::Json => Bytes::from_static(b"]"),
+        }
+    }
+}
+
+fn csv_row(fields: &[&str]) -> Bytes {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields).unwrap();
+    Bytes::from(writer.into_inner().unwrap())
+}
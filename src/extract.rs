@@ -0,0 +1,336 @@
+use axum::body::Bytes;
+use axum::extract::{ConnectInfo, FromRequest, FromRequestParts, OptionalFromRequest, Request};
+use axum::http::{header, request::Parts, Extensions, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use ipnet::IpNet;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::error::Category;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{AppError, AppState};
+
+/// Largest request body accepted, enforced through `DefaultBodyLimit`.
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// User agents are cut to this many characters before being stored.
+const MAX_USER_AGENT_LEN: usize = 512;
+
+const MSGPACK: &str = "application/msgpack";
+
+/// Like `axum::Json`, but rejections come back in the crate's JSON error
+/// shape, naming the offending field when serde reports one.
+pub(crate) struct JsonBody<T>(pub T);
+
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_json(req.headers()) {
+            return Err(AppError::UnsupportedMediaType);
+        }
+
+        let bytes = read_body(req, state).await?;
+
+        decode_json(&bytes).map(JsonBody)
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, AppError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        let inner = err.into_inner();
+        let detail = inner.to_string();
+
+        match inner.classify() {
+            Category::Data => AppError::InvalidInput {
+                error: "invalid_field",
+                field: field_name(&path, &detail),
+                detail,
+            },
+            _ => AppError::InvalidInput {
+                error: "malformed_json",
+                field: None,
+                detail,
+            },
+        }
+    })
+}
+
+fn decode_msgpack<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, AppError> {
+    let deserializer = &mut rmp_serde::Deserializer::new(bytes);
+
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        let inner = err.into_inner();
+        let detail = inner.to_string();
+
+        match inner {
+            // A value of the wrong type or out of range, as opposed to bytes
+            // that aren't MessagePack at all.
+            rmp_serde::decode::Error::TypeMismatch(marker) if marker != rmp::Marker::Reserved => AppError::InvalidInput {
+                error: "invalid_field",
+                field: field_name(&path, &detail),
+                detail,
+            },
+            rmp_serde::decode::Error::OutOfRange
+            | rmp_serde::decode::Error::Syntax(_) => AppError::InvalidInput {
+                error: "invalid_field",
+                field: field_name(&path, &detail),
+                detail,
+            },
+            _ => AppError::InvalidInput {
+                error: "malformed_msgpack",
+                field: None,
+                detail,
+            },
+        }
+    })
+}
+
+/// `Option<JsonBody<T>>` is `None` for a request with no body at all, for
+/// endpoints where the body is optional.
+impl<T, S> OptionalFromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Option<Self>, Self::Rejection> {
+        let headers = req.headers();
+        let empty = headers
+            .get(header::CONTENT_LENGTH)
+            .is_none_or(|length| length.as_bytes() == b"0");
+        if empty && !headers.contains_key(header::CONTENT_TYPE) {
+            return Ok(None);
+        }
+
+        <JsonBody<T> as FromRequest<S>>::from_request(req, state).await.map(Some)
+    }
+}
+
+/// The wire format a client asked for with `Accept`. Anything it doesn't
+/// name, or no header at all, gets JSON rather than a 406.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Format {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Format {
+    pub(crate) fn from_accept(headers: &HeaderMap) -> Format {
+        // The best quality wins, the first listed on a tie.
+        let mut best: Option<(f32, Format)> = None;
+        let ranges = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or_default().split(','));
+
+        for range in ranges {
+            let mut params = range.split(';');
+            let format = match params.next().unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+                "application/json" => Format::Json,
+                "application/msgpack" | "application/x-msgpack" => Format::MessagePack,
+                _ => continue,
+            };
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+                best = Some((quality, format));
+            }
+        }
+
+        best.map_or(Format::Json, |(_, format)| format)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Format::from_accept(&parts.headers))
+    }
+}
+
+/// A body in whichever of JSON or MessagePack its `Content-Type` says,
+/// answered in the format `Accept` asks for. Errors stay JSON either way.
+pub(crate) struct Negotiated<T>(pub Format, pub T);
+
+impl<T, S> FromRequest<S> for Negotiated<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = Format::from_accept(req.headers());
+        let msgpack = mime(req.headers()).is_some_and(|mime| is_msgpack(&mime));
+        if !msgpack && !is_json(req.headers()) {
+            return Err(AppError::UnsupportedMediaType);
+        }
+
+        let bytes = read_body(req, state).await?;
+        let value = if msgpack { decode_msgpack(&bytes)? } else { decode_json(&bytes)? };
+
+        Ok(Negotiated(format, value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let Negotiated(format, value) = self;
+        let mut response = match format {
+            Format::Json => Json(value).into_response(),
+            // Named fields, so it decodes to the same shape as the JSON.
+            Format::MessagePack => match rmp_serde::to_vec_named(&value) {
+                Ok(body) => ([(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK))], body).into_response(),
+                Err(err) => {
+                    tracing::error!(error = %err, "could not encode a response as MessagePack");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            },
+        };
+        response.headers_mut().append(header::VARY, HeaderValue::from_static("accept"));
+
+        response
+    }
+}
+
+fn is_msgpack(mime: &str) -> bool {
+    mime == MSGPACK || mime == "application/x-msgpack"
+}
+
+/// The whole body, with the body limit and read failures reported the same
+/// way for every extractor.
+pub(crate) async fn read_body<S: Send + Sync>(req: Request, state: &S) -> Result<Bytes, AppError> {
+    Bytes::from_request(req, state)
+        .await
+        .map_err(|rejection| match rejection.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+            _ => AppError::InvalidInput {
+                error: "invalid_body",
+                field: None,
+                detail: rejection.body_text(),
+            },
+        })
+}
+
+/// The `Content-Type` without its parameters, lowercased.
+pub(crate) fn mime(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+
+    Some(content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase())
+}
+
+pub(crate) fn is_json(headers: &HeaderMap) -> bool {
+    let Some(mime) = mime(headers) else {
+        return false;
+    };
+
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// serde reports a missing field against its parent, so the name has to be
+/// recovered from the message.
+fn field_name(path: &str, detail: &str) -> Option<String> {
+    if let Some(rest) = detail.strip_prefix("missing field `") {
+        let missing = rest.split('`').next()?;
+
+        return Some(match path {
+            "." => missing.to_string(),
+            parent => format!("{}.{}", parent, missing),
+        });
+    }
+
+    match path {
+        "." => None,
+        path => Some(path.to_string()),
+    }
+}
+
+/// The address the request came from, if it can be told. From a trusted
+/// proxy it's read off `X-Forwarded-For`, otherwise that header is ignored
+/// and it's the peer's own.
+pub(crate) struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let peer = peer_ip(&parts.extensions);
+        Ok(ClientIp(peer.map(|peer| client_ip(peer, &parts.headers, &state.trusted_proxies))))
+    }
+}
+
+fn peer_ip(extensions: &Extensions) -> Option<IpAddr> {
+    extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// Whether the request came straight from one of `trusted`, so what it says
+/// it forwarded can be believed.
+pub(crate) fn from_trusted_proxy(extensions: &Extensions, trusted: &[IpNet]) -> bool {
+    peer_ip(extensions).is_some_and(|peer| is_trusted(peer, trusted))
+}
+
+fn is_trusted(ip: IpAddr, trusted: &[IpNet]) -> bool {
+    // A v4 client of a dual-stack listener shows up mapped into v6.
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        ip => ip,
+    };
+    trusted.iter().any(|net| net.contains(&ip))
+}
+
+/// Each proxy appends the peer it saw, so walking `X-Forwarded-For` back
+/// from `peer`, the first hop that isn't a trusted proxy is the client;
+/// anything left of it could have been forged. An entry that doesn't parse
+/// stops the walk at the proxy it came through.
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNet]) -> IpAddr {
+    let mut client = peer;
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .rev()
+        .flat_map(|value| value.to_str().unwrap_or_default().rsplit(','));
+
+    for hop in hops {
+        if !is_trusted(client, trusted) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+
+    client
+}
+
+/// The `User-Agent` header, if there is a readable one.
+pub(crate) struct UserAgent(pub Option<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for UserAgent {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect());
+
+        Ok(UserAgent(user_agent))
+    }
+}
@@ -0,0 +1,403 @@
+pub mod loaders;
+
+use async_graphql::dataloader::DataLoader;
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptySubscription, ErrorExtensions, InputObject, Object, OutputType, SimpleObject};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::body::to_bytes;
+use axum::extract::{FromRequestParts, State};
+use axum::http::{header, request::Parts, HeaderValue};
+use axum::response::{Html, IntoResponse};
+use axum::routing::{get, post};
+use axum::{Extension, Router};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+
+use crate::auth::AuthUser;
+use crate::models::{
+    DurationInput, EntryQuery, Pagination, Project, ProjectQuery, ReportGroup, ReportQuery, Role, StartTimerRequest,
+    SummaryReport, Tag, TimeEntry, TimeEntryRequest, TimerMode, UserResponse,
+};
+use crate::routes::{entries, projects, reports, timer, users};
+use crate::state::AppState;
+use crate::validation::Validate;
+use crate::AppError;
+use loaders::{ProjectLoader, TagLoader};
+
+// The same data as the REST endpoints, through the same functions, so a
+// rule only ever lives in one place. Field errors carry what the endpoint
+// would have answered with: `code` is its `error`, `status` its status and
+// `body` the rest.
+
+/// Deeper than any query the dashboard needs, and shallow enough that one
+/// can't fan out into the whole database.
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 500;
+
+/// GraphiQL comes off unpkg and boots from an inline script, neither of
+/// which the API's own policy lets through.
+const PLAYGROUND_POLICY: &str = "default-src 'self'; script-src 'unsafe-inline' https://unpkg.com; \
+    style-src 'unsafe-inline' https://unpkg.com; img-src 'self' data:; frame-ancestors 'none'";
+
+pub type Schema = async_graphql::Schema<Query, Mutation, EmptySubscription>;
+
+pub fn schema(state: AppState) -> Schema {
+    Schema::build(Query, Mutation, EmptySubscription)
+        .data(state)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// `request` made by `auth`, with loaders of its own: what they cache is
+/// only ever what that user may see.
+pub fn request(request: impl Into<async_graphql::Request>, state: &AppState, auth: Option<AuthUser>) -> async_graphql::Request {
+    let mut request = request.into();
+    if let Some(auth) = auth {
+        request = request
+            .data(DataLoader::new(ProjectLoader::new(state.pool.clone(), auth.id), tokio::spawn))
+            .data(DataLoader::new(TagLoader::new(state.pool.clone(), auth.id), tokio::spawn))
+            .data(auth);
+    }
+    request
+}
+
+pub fn router(state: &AppState) -> Router<AppState> {
+    let mut router = Router::new()
+        .route("/graphql", post(execute))
+        .layer(Extension(schema(state.clone())));
+
+    if state.graphql_playground {
+        router = router.route("/graphql/playground", get(playground));
+    }
+
+    router
+}
+
+/// The caller, when they sent a token at all. Without one only what needs
+/// no user, like introspection, resolves; a bad one is refused before the
+/// query runs, as anywhere else.
+struct Caller(Option<AuthUser>);
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if !parts.headers.contains_key(header::AUTHORIZATION) {
+            return Ok(Caller(None));
+        }
+
+        AuthUser::from_request_parts(parts, state).await.map(|auth| Caller(Some(auth)))
+    }
+}
+
+async fn execute(
+    State(state): State<AppState>,
+    Extension(schema): Extension<Schema>,
+    Caller(auth): Caller,
+    query: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(request(query.into_inner(), &state, auth)).await.into()
+}
+
+async fn playground() -> impl IntoResponse {
+    (
+        [(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static(PLAYGROUND_POLICY))],
+        Html(GraphiQLSource::build().endpoint("/graphql").finish()),
+    )
+}
+
+/// The error as the REST endpoint would have answered with it.
+async fn field_error(err: AppError) -> async_graphql::Error {
+    let response = err.into_response();
+    let status = response.status().as_u16();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+    let body: Value = serde_json::from_slice(&body).unwrap_or_default();
+    // Validation errors are the only ones without an `error`.
+    let code = body["error"].as_str().unwrap_or("validation_failed").to_string();
+
+    async_graphql::Error::new(code.clone()).extend_with(|_, extensions| {
+        extensions.set("code", code);
+        extensions.set("status", status);
+        if let Ok(body) = async_graphql::Value::from_json(body) {
+            extensions.set("body", body);
+        }
+    })
+}
+
+async fn resolve<T>(result: Result<T, AppError>) -> async_graphql::Result<T> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(err) => Err(field_error(err).await),
+    }
+}
+
+/// The state and the signed in user, or the error a REST endpoint gives
+/// without a token.
+async fn caller<'a>(ctx: &Context<'a>) -> async_graphql::Result<(&'a AppState, &'a AuthUser)> {
+    let state = ctx.data_unchecked::<AppState>();
+    match ctx.data_opt::<AuthUser>() {
+        Some(auth) => Ok((state, auth)),
+        None => Err(field_error(AppError::InvalidToken("missing_token")).await),
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(
+    concrete(name = "UserPage", params(UserResponse)),
+    concrete(name = "ProjectPage", params(Project)),
+    concrete(name = "EntryPage", params(Entry))
+)]
+pub struct Page<T: OutputType> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Pass as `afterId` to fetch the next page; `null` once exhausted.
+    pub next_cursor: Option<i32>,
+}
+
+impl<T, U: OutputType + From<T>> From<crate::models::Page<T>> for Page<U> {
+    fn from(page: crate::models::Page<T>) -> Self {
+        Page {
+            items: page.items.into_iter().map(U::from).collect(),
+            total: page.total,
+            limit: page.limit,
+            offset: page.offset,
+            next_cursor: page.next_cursor,
+        }
+    }
+}
+
+/// A time entry, with its project and tags resolved through the request's
+/// loaders.
+pub struct Entry(pub TimeEntry);
+
+impl From<TimeEntry> for Entry {
+    fn from(entry: TimeEntry) -> Self {
+        Entry(entry)
+    }
+}
+
+#[Object]
+impl Entry {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn user_id(&self) -> i32 {
+        self.0.user_id
+    }
+
+    async fn project_id(&self) -> Option<i32> {
+        self.0.project_id
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    async fn started_at(&self) -> DateTime<Utc> {
+        self.0.started_at
+    }
+
+    /// `null` while the entry is still running.
+    async fn ended_at(&self) -> Option<DateTime<Utc>> {
+        self.0.ended_at
+    }
+
+    /// From `startedAt` to `endedAt`; `null` while running.
+    async fn duration_seconds(&self) -> Option<i64> {
+        self.0.duration_seconds
+    }
+
+    /// A pomodoro that ran all the way to its target.
+    async fn pomodoro(&self) -> bool {
+        self.0.pomodoro
+    }
+
+    async fn billable(&self) -> bool {
+        self.0.billable
+    }
+
+    /// Cents an hour, from the entry's project or else the user's default,
+    /// as it was when the entry was made or moved to its project.
+    async fn rate_cents(&self) -> Option<i32> {
+        self.0.rate_cents
+    }
+
+    async fn currency(&self) -> Option<&str> {
+        self.0.currency.as_deref()
+    }
+
+    /// The invoice the entry was billed on, after which it can't change.
+    async fn invoice_id(&self) -> Option<i32> {
+        self.0.invoice_id
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn project(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Project>> {
+        let Some(project_id) = self.0.project_id else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DataLoader<ProjectLoader>>();
+        loader.load_one(project_id).await.map_err(loaders::error)
+    }
+
+    /// Sorted by name, ignoring case.
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Tag>> {
+        if self.0.tags.is_empty() {
+            return Ok(Vec::new());
+        }
+        let loader = ctx.data_unchecked::<DataLoader<TagLoader>>();
+        Ok(loader.load_one(self.0.id).await.map_err(loaders::error)?.unwrap_or_default())
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The signed in user.
+    async fn me(&self, ctx: &Context<'_>) -> async_graphql::Result<UserResponse> {
+        let (state, auth) = caller(ctx).await?;
+        resolve(users::load_user(&state.pool, auth.id).await).await
+    }
+
+    /// Every account not deleted, by id; only admins may list them.
+    async fn users(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        after_id: Option<i32>,
+        #[graphql(desc = "Part of a name or email.")] q: Option<String>,
+    ) -> async_graphql::Result<Page<UserResponse>> {
+        let (state, auth) = caller(ctx).await?;
+        if auth.role != Role::Admin {
+            return Err(field_error(AppError::Forbidden("forbidden")).await);
+        }
+
+        let pagination = Pagination {
+            limit,
+            offset,
+            after_id,
+            q,
+            sort: None,
+            order: None,
+            inactive_since: None,
+        };
+        resolve(users::list_users(state, &pagination).await).await.map(Page::from)
+    }
+
+    /// The user's projects and their organizations', by name.
+    async fn projects(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] include_archived: bool,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> async_graphql::Result<Page<Project>> {
+        let (state, auth) = caller(ctx).await?;
+        let query = ProjectQuery { include_archived, limit, offset };
+        resolve(projects::list_projects(state, auth.id, &query).await).await.map(Page::from)
+    }
+
+    /// The user's entries, latest first.
+    async fn entries(
+        &self,
+        ctx: &Context<'_>,
+        project_id: Option<i32>,
+        #[graphql(default, desc = "Only entries with every one of these tags.")] tags: Vec<String>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> async_graphql::Result<Page<Entry>> {
+        let (state, auth) = caller(ctx).await?;
+        let query = EntryQuery { project_id, tag: tags, limit, offset };
+        resolve(entries::list_entries(state, auth.id, &query).await).await.map(Page::from)
+    }
+
+    /// Time tracked between two of the user's days, both included; the
+    /// current week when left out.
+    async fn report_summary(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        #[graphql(default)] group_by: ReportGroup,
+    ) -> async_graphql::Result<SummaryReport> {
+        let (state, auth) = caller(ctx).await?;
+        let query = ReportQuery { from, to, group_by };
+        resolve(reports::summary(state, auth.id, &query).await).await
+    }
+}
+
+#[derive(InputObject)]
+pub struct EntryInput {
+    /// One of the user's own projects, archived or not.
+    pub project_id: Option<i32>,
+    #[graphql(default)]
+    pub description: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Instead of `endedAt`, how long the entry ran for.
+    pub duration_seconds: Option<i64>,
+    /// Ones the user hasn't used yet are created.
+    #[graphql(default)]
+    pub tags: Vec<String>,
+    #[graphql(default)]
+    pub billable: bool,
+}
+
+impl From<EntryInput> for TimeEntryRequest {
+    fn from(input: EntryInput) -> Self {
+        TimeEntryRequest {
+            project_id: input.project_id,
+            description: input.description,
+            started_at: input.started_at,
+            ended_at: input.ended_at,
+            duration_seconds: input.duration_seconds.map(DurationInput::Seconds),
+            tags: input.tags,
+            billable: input.billable,
+        }
+    }
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn create_entry(
+        &self,
+        ctx: &Context<'_>,
+        input: EntryInput,
+        #[graphql(default, desc = "Skip the check for overlapping entries, this once.")] allow_overlap: bool,
+    ) -> async_graphql::Result<Entry> {
+        let (state, auth) = caller(ctx).await?;
+        let mut payload = TimeEntryRequest::from(input);
+        resolve(payload.validate().map_err(AppError::from)).await?;
+        resolve(entries::create_entry_for(state, auth.id, allow_overlap, payload).await).await.map(Entry)
+    }
+
+    async fn start_timer(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] mode: TimerMode,
+        project_id: Option<i32>,
+        #[graphql(default)] description: String,
+        #[graphql(default)] billable: bool,
+    ) -> async_graphql::Result<Entry> {
+        let (state, auth) = caller(ctx).await?;
+        let mut payload = StartTimerRequest { project_id, description, billable };
+        resolve(payload.validate().map_err(AppError::from)).await?;
+        resolve(timer::start_timer_for(state, auth.id, mode, payload).await).await.map(Entry)
+    }
+
+    async fn stop_timer(&self, ctx: &Context<'_>) -> async_graphql::Result<Entry> {
+        let (state, auth) = caller(ctx).await?;
+        resolve(timer::stop_timer_for(state, auth.id).await).await.map(Entry)
+    }
+}
@@ -0,0 +1,93 @@
+use async_graphql::dataloader::Loader;
+use async_graphql::ErrorExtensions;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::{Project, Tag};
+
+// Entries resolve their project and tags through these, so a page of them
+// costs one query for each rather than one per entry. A loader belongs to
+// one request and one user, and only finds what that user can see.
+
+/// Shared, since the one failed load answers every field waiting on it.
+pub type LoadError = Arc<sqlx::Error>;
+
+/// Projects by id.
+pub struct ProjectLoader {
+    pool: PgPool,
+    user_id: i32,
+}
+
+impl ProjectLoader {
+    pub fn new(pool: PgPool, user_id: i32) -> ProjectLoader {
+        ProjectLoader { pool, user_id }
+    }
+}
+
+impl Loader<i32> for ProjectLoader {
+    type Value = Project;
+    type Error = LoadError;
+
+    async fn load(&self, ids: &[i32]) -> Result<HashMap<i32, Project>, LoadError> {
+        let projects = sqlx::query_as!(
+            Project,
+            "SELECT id, user_id, name, color, archived, hourly_rate_cents, currency, organization_id, created_at FROM projects
+            WHERE id = ANY($1)
+                AND (organization_id IS NULL AND user_id = $2
+                    OR organization_id IN (SELECT organization_id FROM organization_members WHERE user_id = $2))",
+            ids,
+            self.user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(projects.into_iter().map(|project| (project.id, project)).collect())
+    }
+}
+
+/// Each entry's tags, by the entry's id.
+pub struct TagLoader {
+    pool: PgPool,
+    user_id: i32,
+}
+
+impl TagLoader {
+    pub fn new(pool: PgPool, user_id: i32) -> TagLoader {
+        TagLoader { pool, user_id }
+    }
+}
+
+impl Loader<i32> for TagLoader {
+    type Value = Vec<Tag>;
+    type Error = LoadError;
+
+    async fn load(&self, entry_ids: &[i32]) -> Result<HashMap<i32, Vec<Tag>>, LoadError> {
+        let rows = sqlx::query!(
+            r#"SELECT et.entry_id, t.id, t.name,
+                (SELECT count(*) FROM time_entry_tags c WHERE c.tag_id = t.id) AS "entries!"
+            FROM time_entry_tags et JOIN tags t ON t.id = et.tag_id
+            WHERE et.entry_id = ANY($1) AND t.user_id = $2
+            ORDER BY lower(t.name)"#,
+            entry_ids,
+            self.user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags: HashMap<i32, Vec<Tag>> = HashMap::new();
+        for row in rows {
+            tags.entry(row.entry_id).or_default().push(Tag { id: row.id, name: row.name, entries: row.entries });
+        }
+        Ok(tags)
+    }
+}
+
+/// A failed load, reported the way a database error is anywhere else.
+pub(crate) fn error(err: LoadError) -> async_graphql::Error {
+    tracing::error!(error = %err, "database error");
+    async_graphql::Error::new("database_error").extend_with(|_, extensions| {
+        extensions.set("code", "database_error");
+        extensions.set("status", 500);
+    })
+}
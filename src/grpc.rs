@@ -0,0 +1,255 @@
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::time::{Duration, SystemTime};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+
+use crate::auth::{self, tokens::hash_token};
+use crate::models::{self, EntryQuery, TimeEntry, UserResponse};
+use crate::routes::{entries, timer, users};
+use crate::state::AppState;
+use crate::validation::Validate;
+use crate::AppError;
+use proto::tictoc_server::{Tictoc, TictocServer};
+
+// For other services rather than people: callers show the service token,
+// and name the user they act for. Everything goes through the functions
+// the REST handlers use, so both answer alike.
+
+pub mod proto {
+    tonic::include_proto!("tictoc.v1");
+}
+
+/// The API over `state`, refusing calls without its service token.
+pub struct Service {
+    state: AppState,
+}
+
+impl Service {
+    pub fn new(state: AppState) -> Service {
+        Service { state }
+    }
+}
+
+/// Serves until `shutdown` fires, then stops accepting calls and gives the
+/// ones in flight up to `grace` to finish, like [`crate::serve`].
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    state: AppState,
+    shutdown: CancellationToken,
+    grace: Duration,
+) -> Result<(), tonic::transport::Error> {
+    let token = state.grpc_token.as_deref().map(hash_token);
+    let service = TictocServer::with_interceptor(Service::new(state), move |request: Request<()>| {
+        check_token(token.as_deref(), request)
+    });
+
+    let server = Server::builder()
+        .add_service(service)
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), shutdown.clone().cancelled_owned());
+    let mut server = std::pin::pin!(server);
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = shutdown.cancelled() => {}
+    }
+
+    match tokio::time::timeout(grace, server).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!("grace period over, dropping gRPC calls still in flight");
+            Ok(())
+        }
+    }
+}
+
+/// Lets the call through if its `authorization` metadata is the service
+/// token, whose hash is `expected`; compared as hashes so the check takes
+/// the same time however much of it matches.
+fn check_token(expected: Option<&str>, request: Request<()>) -> Result<Request<()>, Status> {
+    let presented = request
+        .metadata()
+        .get("authorization")
+        .ok_or_else(|| Status::unauthenticated("missing_token"))?
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(hash_token);
+
+    match expected {
+        Some(expected) if presented.as_deref() == Some(expected) => Ok(request),
+        _ => Err(Status::unauthenticated("invalid_token")),
+    }
+}
+
+/// The error as a status: its code is the nearest to the HTTP one, its
+/// message the `error` the endpoint would have answered with, and its
+/// details that answer's JSON body.
+async fn status(err: AppError) -> Status {
+    let response = err.into_response();
+    let code = match response.status() {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT | StatusCode::LOCKED | StatusCode::PRECONDITION_FAILED => Code::FailedPrecondition,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::REQUEST_TIMEOUT => Code::DeadlineExceeded,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+    // Validation errors are the only ones without an `error`.
+    let message = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| "validation_failed".to_string());
+
+    Status::with_details(code, message, body)
+}
+
+async fn respond<T, M: From<T>>(result: Result<T, AppError>) -> Result<Response<M>, Status> {
+    match result {
+        Ok(value) => Ok(Response::new(M::from(value))),
+        Err(err) => Err(status(err).await),
+    }
+}
+
+fn timestamp(at: DateTime<Utc>) -> prost_types::Timestamp {
+    SystemTime::from(at).into()
+}
+
+impl From<UserResponse> for proto::User {
+    fn from(user: UserResponse) -> Self {
+        proto::User {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            created_at: Some(timestamp(user.created_at)),
+            updated_at: Some(timestamp(user.updated_at)),
+            last_login_at: user.last_login_at.map(timestamp),
+        }
+    }
+}
+
+impl From<TimeEntry> for proto::Entry {
+    fn from(entry: TimeEntry) -> Self {
+        proto::Entry {
+            id: entry.id,
+            user_id: entry.user_id,
+            project_id: entry.project_id,
+            description: entry.description,
+            started_at: Some(timestamp(entry.started_at)),
+            ended_at: entry.ended_at.map(timestamp),
+            duration_seconds: entry.duration_seconds,
+            tags: entry.tags,
+            pomodoro: entry.pomodoro,
+            billable: entry.billable,
+            created_at: Some(timestamp(entry.created_at)),
+        }
+    }
+}
+
+impl From<models::Page<TimeEntry>> for proto::ListEntriesResponse {
+    fn from(page: models::Page<TimeEntry>) -> Self {
+        proto::ListEntriesResponse {
+            entries: page.items.into_iter().map(proto::Entry::from).collect(),
+            total: page.total,
+        }
+    }
+}
+
+impl From<models::Role> for proto::Role {
+    fn from(role: models::Role) -> Self {
+        match role {
+            models::Role::User => proto::Role::User,
+            models::Role::Admin => proto::Role::Admin,
+        }
+    }
+}
+
+impl From<proto::TimerMode> for models::TimerMode {
+    fn from(mode: proto::TimerMode) -> Self {
+        match mode {
+            proto::TimerMode::Normal => models::TimerMode::Normal,
+            proto::TimerMode::Pomodoro => models::TimerMode::Pomodoro,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Tictoc for Service {
+    async fn get_user(&self, request: Request<proto::GetUserRequest>) -> Result<Response<proto::User>, Status> {
+        respond(users::load_user(&self.state.pool, request.into_inner().id).await).await
+    }
+
+    async fn validate_token(
+        &self,
+        request: Request<proto::ValidateTokenRequest>,
+    ) -> Result<Response<proto::ValidateTokenResponse>, Status> {
+        let auth = match auth::authenticate(&self.state, &request.into_inner().token).await {
+            Ok(auth) => auth,
+            // A bad token is an answer, not a failed call.
+            Err(AppError::InvalidToken(reason)) => {
+                return Ok(Response::new(proto::ValidateTokenResponse {
+                    reason: reason.to_string(),
+                    ..Default::default()
+                }));
+            }
+            Err(err) => return Err(status(err).await),
+        };
+
+        let expires_at = match auth.api_key_id {
+            Some(_) => None,
+            None => DateTime::from_timestamp(auth.exp as i64, 0).map(timestamp),
+        };
+        Ok(Response::new(proto::ValidateTokenResponse {
+            valid: true,
+            reason: String::new(),
+            user_id: auth.id,
+            email: auth.email,
+            role: proto::Role::from(auth.role).into(),
+            api_key_id: auth.api_key_id,
+            expires_at,
+        }))
+    }
+
+    async fn start_timer(&self, request: Request<proto::StartTimerRequest>) -> Result<Response<proto::Entry>, Status> {
+        let request = request.into_inner();
+        let mode = proto::TimerMode::try_from(request.mode)
+            .map_err(|_| Status::invalid_argument("invalid_timer_mode"))?;
+
+        let mut payload = models::StartTimerRequest {
+            project_id: request.project_id,
+            description: request.description,
+            billable: request.billable,
+        };
+        if let Err(errors) = payload.validate() {
+            return Err(status(AppError::from(errors)).await);
+        }
+        respond(timer::start_timer_for(&self.state, request.user_id, mode.into(), payload).await).await
+    }
+
+    async fn stop_timer(&self, request: Request<proto::StopTimerRequest>) -> Result<Response<proto::Entry>, Status> {
+        respond(timer::stop_timer_for(&self.state, request.into_inner().user_id).await).await
+    }
+
+    async fn list_entries(
+        &self,
+        request: Request<proto::ListEntriesRequest>,
+    ) -> Result<Response<proto::ListEntriesResponse>, Status> {
+        let request = request.into_inner();
+        let query = EntryQuery {
+            project_id: request.project_id,
+            tag: request.tags,
+            limit: request.limit,
+            offset: request.offset,
+        };
+        respond(entries::list_entries(&self.state, request.user_id, &query).await).await
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+
+/// Longest a line may be, in octets, before it has to be folded.
+const MAX_LINE_OCTETS: usize = 75;
+
+/// One entry as a calendar shows it, times in UTC.
+pub struct Event {
+    pub uid: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub summary: String,
+    pub categories: Vec<String>,
+}
+
+/// A VCALENDAR of `events`, stamped with `now`.
+pub fn calendar(events: &[Event], now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    line(&mut out, "BEGIN", "VCALENDAR");
+    line(&mut out, "VERSION", "2.0");
+    line(&mut out, "PRODID", "-//tictoc//Time entries//EN");
+    line(&mut out, "CALSCALE", "GREGORIAN");
+    line(&mut out, "X-WR-CALNAME", "tictoc");
+
+    for event in events {
+        line(&mut out, "BEGIN", "VEVENT");
+        line(&mut out, "UID", &escape(&event.uid));
+        line(&mut out, "DTSTAMP", &timestamp(now));
+        line(&mut out, "DTSTART", &timestamp(event.start));
+        line(&mut out, "DTEND", &timestamp(event.end));
+        line(&mut out, "SUMMARY", &escape(&event.summary));
+        if !event.categories.is_empty() {
+            let categories: Vec<String> = event.categories.iter().map(|category| escape(category)).collect();
+            line(&mut out, "CATEGORIES", &categories.join(","));
+        }
+        line(&mut out, "END", "VEVENT");
+    }
+
+    line(&mut out, "END", "VCALENDAR");
+    out
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes a TEXT value, which can't otherwise hold commas, semicolons or
+/// line breaks.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes `name:value`, folded so no line runs past 75 octets. Folds
+/// only fall between characters, so multi-byte ones stay whole.
+fn line(out: &mut String, name: &str, value: &str) {
+    let mut octets = 0;
+    for c in name.chars().chain([':']).chain(value.chars()) {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            // The leading space counts toward the next line.
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a, b; c\\d\r\ne"), "a\\, b\\; c\\\\d\\ne");
+    }
+
+    #[test]
+    fn test_line_folding() {
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", &"é".repeat(40));
+
+        let lines: Vec<&str> = out.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        // "SUMMARY:" and 33 two-octet characters, the next of which won't fit.
+        assert_eq!(lines[0].len(), 74);
+        assert_eq!(lines[1], format!(" {}", "é".repeat(7)));
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_OCTETS));
+    }
+}
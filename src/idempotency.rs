@@ -0,0 +1,145 @@
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+
+use crate::extract::{Format, Negotiated};
+use crate::AppError;
+
+/// How long a response is kept for replay.
+pub const IDEMPOTENCY_TTL_SECS: i64 = 24 * 60 * 60;
+pub const MAX_KEY_LEN: usize = 255;
+
+/// The `Idempotency-Key` header, when the client sent one.
+pub struct IdempotencyKey(pub Option<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for IdempotencyKey {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get("idempotency-key") else {
+            return Ok(IdempotencyKey(None));
+        };
+
+        let key = value
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+            .ok_or(AppError::BadRequest("invalid_idempotency_key"))?;
+
+        Ok(IdempotencyKey(Some(key.to_string())))
+    }
+}
+
+/// Tells retries of a request from a different request under the same key.
+pub fn fingerprint(request: &impl Serialize) -> Vec<u8> {
+    Sha256::digest(serde_json::to_vec(request).unwrap_or_default()).to_vec()
+}
+
+/// A response as first sent, replayed with `Idempotent-Replay: true`.
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub location: Option<String>,
+    pub body: serde_json::Value,
+}
+
+impl StoredResponse {
+    /// The replay in the wire format the retry asked for, whichever the
+    /// first request got.
+    pub(crate) fn replay(self, format: Format) -> Response {
+        let mut response = (self.status, Negotiated(format, self.body)).into_response();
+        let headers = response.headers_mut();
+
+        if let Some(location) = self.location.and_then(|location| HeaderValue::try_from(location).ok()) {
+            headers.insert(header::LOCATION, location);
+        }
+        headers.insert("idempotent-replay", HeaderValue::from_static("true"));
+
+        response
+    }
+}
+
+impl IntoResponse for StoredResponse {
+    fn into_response(self) -> Response {
+        self.replay(Format::Json)
+    }
+}
+
+pub enum Claim {
+    /// The key is this request's; go ahead and store the response in the same
+    /// transaction.
+    Claimed,
+    Replay(StoredResponse),
+}
+
+/// Takes `key` for this request inside `tx`. A request racing on the same key
+/// waits on the unique index until the first commits, then replays its
+/// response, or takes the key itself if the first rolled back.
+pub async fn claim(
+    tx: &mut Transaction<'_, Postgres>,
+    scope: &str,
+    key: &str,
+    fingerprint: &[u8],
+) -> Result<Claim, AppError> {
+    let claimed = sqlx::query_scalar!(
+        r#"INSERT INTO idempotency_keys (scope, key, request_hash, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(secs => $4))
+        ON CONFLICT (scope, key) DO UPDATE
+            SET request_hash = EXCLUDED.request_hash, status = NULL, location = NULL, body = NULL,
+                expires_at = EXCLUDED.expires_at
+            WHERE idempotency_keys.expires_at <= now()
+        RETURNING TRUE AS "claimed!""#,
+        scope,
+        key,
+        fingerprint,
+        IDEMPOTENCY_TTL_SECS as f64
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if claimed.is_some() {
+        return Ok(Claim::Claimed);
+    }
+
+    let stored = sqlx::query!(
+        r#"SELECT request_hash, status AS "status!", location, body AS "body!" FROM idempotency_keys
+        WHERE scope = $1 AND key = $2"#,
+        scope,
+        key
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if stored.request_hash != fingerprint {
+        return Err(AppError::Unprocessable("idempotency_key_reused"));
+    }
+
+    Ok(Claim::Replay(StoredResponse {
+        status: StatusCode::from_u16(stored.status as u16).unwrap_or(StatusCode::OK),
+        location: stored.location,
+        body: stored.body,
+    }))
+}
+
+/// Saves the response for `key`, claimed earlier in the same transaction.
+pub async fn store(
+    tx: &mut Transaction<'_, Postgres>,
+    scope: &str,
+    key: &str,
+    response: &StoredResponse,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "UPDATE idempotency_keys SET status = $3, location = $4, body = $5 WHERE scope = $1 AND key = $2",
+        scope,
+        key,
+        response.status.as_u16() as i16,
+        response.location,
+        response.body
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
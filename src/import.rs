@@ -0,0 +1,209 @@
+use axum::extract::{FromRequest, Request};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde_json::json;
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use crate::audit::{self, Event};
+use crate::extract::{is_json, mime, read_body};
+use crate::models::{AuditEventType, CreateUserRequest, ImportReport, ImportRow};
+use crate::validation::Validate;
+use crate::{AppError, AppState};
+
+/// Largest import body accepted, in place of the usual body limit.
+pub const MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+
+/// Rows written per transaction, so a big import never holds one open for
+/// long.
+const BATCH_SIZE: usize = 500;
+
+/// Passwords hashed at once, each on its own blocking thread.
+const HASH_CONCURRENCY: usize = 4;
+
+/// The rows of a `POST /users/import` body, a JSON array or CSV with a
+/// header row. Rows that can't be read are kept, with why, so the report
+/// can point at them.
+pub(crate) struct ImportRows(pub Vec<Result<CreateUserRequest, String>>);
+
+impl FromRequest<AppState> for ImportRows {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let csv = mime(req.headers()).as_deref() == Some("text/csv");
+        if !csv && !is_json(req.headers()) {
+            return Err(AppError::UnsupportedMediaType);
+        }
+
+        let body = read_body(req, state).await?;
+        let rows = if csv { parse_csv(&body)? } else { parse_json(&body)? };
+
+        if rows.len() > state.import_max_rows {
+            return Err(AppError::InvalidInput {
+                error: "too_many_rows",
+                field: None,
+                detail: format!("at most {} rows per import", state.import_max_rows),
+            });
+        }
+
+        Ok(ImportRows(rows))
+    }
+}
+
+fn parse_json(body: &[u8]) -> Result<Vec<Result<CreateUserRequest, String>>, AppError> {
+    let rows: Vec<serde_json::Value> = serde_json::from_slice(body).map_err(|err| AppError::InvalidInput {
+        error: "malformed_json",
+        field: None,
+        detail: err.to_string(),
+    })?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| serde_json::from_value(row).map_err(|err| err.to_string()))
+        .collect())
+}
+
+/// The header names the columns, `name`, `email` and `password`, in any
+/// order.
+fn parse_csv(body: &[u8]) -> Result<Vec<Result<CreateUserRequest, String>>, AppError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::Headers).from_reader(body);
+    let headers = reader.headers().map_err(|err| AppError::InvalidInput {
+        error: "malformed_csv",
+        field: None,
+        detail: err.to_string(),
+    })?;
+
+    for column in ["name", "email", "password"] {
+        if !headers.iter().any(|header| header == column) {
+            return Err(AppError::InvalidInput {
+                error: "malformed_csv",
+                field: Some(column.to_string()),
+                detail: "missing column".to_string(),
+            });
+        }
+    }
+
+    Ok(reader
+        .deserialize()
+        .map(|row| row.map_err(|err: csv::Error| err.to_string()))
+        .collect())
+}
+
+/// Who is importing, for the audit log.
+pub struct Importer<'a> {
+    pub id: i32,
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<&'a str>,
+}
+
+/// Creates every row that is valid and whose email is free, and reports on
+/// each. A dry run does all the same checks and writes nothing.
+///
+/// Imported users start unverified, and no verification emails are sent;
+/// they can ask for one when they first sign in.
+pub async fn import(
+    state: &AppState,
+    importer: Importer<'_>,
+    rows: Vec<Result<CreateUserRequest, String>>,
+    dry_run: bool,
+) -> Result<ImportReport, AppError> {
+    let mut report: Vec<ImportRow> = (1..=rows.len()).map(ImportRow::new).collect();
+    let mut valid = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (i, row) in rows.into_iter().enumerate() {
+        match row {
+            Err(detail) => report[i].fail("malformed_row").detail = Some(detail),
+            Ok(mut user) => match user.validate() {
+                Err(errors) => report[i].fail("invalid_fields").fields = Some(errors.errors),
+                Ok(()) if !seen.insert(user.email.clone()) => {
+                    report[i].fail("duplicate_in_import");
+                }
+                Ok(()) => valid.push((i, user)),
+            },
+        }
+    }
+
+    let emails: Vec<String> = valid.iter().map(|(_, user)| user.email.clone()).collect();
+    let taken: HashSet<String> =
+        sqlx::query_scalar!("SELECT email FROM users WHERE email = ANY($1) AND deleted_at IS NULL", &emails)
+            .fetch_all(&state.pool)
+            .await?
+            .into_iter()
+            .collect();
+
+    valid.retain(|(i, user)| {
+        let free = !taken.contains(&user.email);
+        if !free {
+            report[*i].fail("email_already_registered");
+        }
+        free
+    });
+
+    if !dry_run {
+        for batch in valid.chunks(BATCH_SIZE) {
+            let passwords: Vec<String> = batch.iter().map(|(_, user)| user.password.clone()).collect();
+            let password_hashes: Vec<String> = stream::iter(passwords)
+                .map(|password| {
+                    let passwords = state.passwords.clone();
+                    async move { passwords.hash(password).await }
+                })
+                .buffered(HASH_CONCURRENCY)
+                .try_collect()
+                .await?;
+
+            let mut tx = state.pool.begin().await?;
+
+            for ((i, user), password_hash) in batch.iter().zip(password_hashes) {
+                // Someone may have signed up with the email since the check.
+                let id = sqlx::query_scalar!(
+                    "INSERT INTO users (name, email, password_hash) VALUES ($1, $2, $3)
+                    ON CONFLICT (email) WHERE deleted_at IS NULL DO NOTHING
+                    RETURNING id",
+                    user.name,
+                    user.email,
+                    password_hash
+                )
+                .fetch_optional(&mut *tx)
+                .await?;
+
+                let Some(id) = id else {
+                    report[*i].fail("email_already_registered");
+                    continue;
+                };
+                report[*i].id = Some(id);
+
+                audit::record(&mut *tx, Event {
+                    event_type: AuditEventType::UserCreated,
+                    actor: Some(importer.id),
+                    target: Some(("user", id)),
+                    ip: importer.ip,
+                    user_agent: importer.user_agent,
+                    details: json!({ "email": user.email, "via": "import" }),
+                })
+                .await?;
+            }
+
+            tx.commit().await?;
+        }
+    }
+
+    let failed = report.iter().filter(|row| row.error.is_some()).count();
+
+    Ok(ImportReport {
+        dry_run,
+        created: report.len() - failed,
+        failed,
+        rows: report,
+    })
+}
+
+impl ImportRow {
+    fn new(row: usize) -> ImportRow {
+        ImportRow { row, id: None, error: None, fields: None, detail: None }
+    }
+
+    fn fail(&mut self, error: &str) -> &mut ImportRow {
+        self.error = Some(error.to_string());
+        self
+    }
+}
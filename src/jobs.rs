@@ -0,0 +1,214 @@
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgExecutor;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::state::AppState;
+
+/// How often an idle worker looks for jobs.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A job locked this long is taken to belong to a worker that died, and is
+/// claimed again.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Jobs are rows in `jobs`, so they survive restarts and can be enqueued in
+// the same transaction as whatever they follow up on. Workers claim them
+// with `FOR UPDATE SKIP LOCKED`, so any number can run side by side, and
+// a job may run more than once: after a crash, or a release at shutdown.
+// Handlers have to be safe to repeat.
+
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+type Handler = Arc<dyn Fn(AppState, Value) -> BoxFuture<'static, Result<(), JobError>> + Send + Sync>;
+
+/// The handlers by kind, and how failed jobs are retried.
+#[derive(Clone)]
+pub struct Jobs {
+    handlers: Arc<RwLock<HashMap<&'static str, Handler>>>,
+    /// Set by the first [`spawn_worker`].
+    started: Arc<AtomicBool>,
+    /// Attempts at each job, the first included, before it's dead-lettered.
+    pub max_attempts: u32,
+    /// The wait before the first retry; each one after waits twice as long.
+    pub retry_base: Duration,
+    pub poll_interval: Duration,
+    /// How long a running job gets to finish once shutdown starts before
+    /// it's released for another worker.
+    pub shutdown_grace: Duration,
+}
+
+impl Jobs {
+    pub fn new(max_attempts: u32, retry_base: Duration, shutdown_grace: Duration) -> Jobs {
+        Jobs {
+            handlers: Arc::default(),
+            started: Arc::default(),
+            max_attempts,
+            retry_base,
+            poll_interval: POLL_INTERVAL,
+            shutdown_grace,
+        }
+    }
+
+    /// Runs jobs of `kind` through `handler`. An error, or a panic, counts
+    /// as a failed attempt.
+    pub fn register<F, Fut>(&self, kind: &'static str, handler: F)
+    where
+        F: Fn(AppState, Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), JobError>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |state, payload| Box::pin(handler(state, payload)));
+        self.handlers.write().unwrap().insert(kind, handler);
+    }
+
+    fn handler(&self, kind: &str) -> Option<Handler> {
+        self.handlers.read().unwrap().get(kind).cloned()
+    }
+}
+
+/// Adds a job to run as soon as a worker gets to it. Pass the transaction
+/// the job follows up on, so it's only enqueued if that commits.
+pub async fn enqueue<'e>(executor: impl PgExecutor<'e>, kind: &str, payload: impl Serialize) -> Result<i64, sqlx::Error> {
+    let payload = serde_json::to_value(payload).unwrap_or_default();
+
+    sqlx::query_scalar!("INSERT INTO jobs (kind, payload) VALUES ($1, $2) RETURNING id", kind, payload)
+        .fetch_one(executor)
+        .await
+}
+
+/// Starts running jobs, until shutdown. Only the first call for a state
+/// starts a worker; later ones return `None`. The worker has finished or
+/// released the job it was running by the time it ends.
+pub fn spawn_worker(state: &AppState) -> Option<JoinHandle<()>> {
+    if state.jobs.started.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    let state = state.clone();
+    let worker_id = uuid::Uuid::new_v4().to_string();
+
+    Some(tokio::spawn(async move {
+        while !state.shutdown.is_cancelled() {
+            let ran = match claim(&state, &worker_id).await {
+                Ok(Some(job)) => {
+                    run(&state, job).await;
+                    true
+                }
+                Ok(None) => false,
+                Err(err) => {
+                    tracing::error!(error = %err, "could not claim a job");
+                    false
+                }
+            };
+
+            // Straight on to the next one while there's work.
+            if !ran {
+                state.shutdown.run_until_cancelled(tokio::time::sleep(state.jobs.poll_interval)).await;
+            }
+        }
+    }))
+}
+
+struct Claimed {
+    id: i64,
+    kind: String,
+    payload: Value,
+    attempts: i32,
+}
+
+async fn claim(state: &AppState, worker_id: &str) -> Result<Option<Claimed>, sqlx::Error> {
+    sqlx::query_as!(
+        Claimed,
+        "UPDATE jobs SET locked_by = $1, locked_at = now(), attempts = attempts + 1
+        WHERE id = (
+            SELECT id FROM jobs
+            WHERE failed_at IS NULL AND run_at <= now()
+                AND (locked_at IS NULL OR locked_at < now() - make_interval(secs => $2))
+            ORDER BY run_at, id
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, kind, payload, attempts",
+        worker_id,
+        LOCK_TIMEOUT.as_secs_f64()
+    )
+    .fetch_optional(&state.pool)
+    .await
+}
+
+async fn run(state: &AppState, job: Claimed) {
+    let Some(handler) = state.jobs.handler(&job.kind) else {
+        // Retrying won't make a handler appear.
+        return finish(state, &job, Err(format!("no handler for {:?}", job.kind)), true).await;
+    };
+
+    // Spawned, so a panic is caught as an error.
+    let mut task = tokio::spawn(handler(state.clone(), job.payload.clone()));
+    let result = tokio::select! {
+        result = &mut task => Some(result),
+        _ = state.shutdown.cancelled() => tokio::time::timeout(state.jobs.shutdown_grace, &mut task).await.ok(),
+    };
+
+    match result {
+        Some(Ok(Ok(()))) => finish(state, &job, Ok(()), false).await,
+        Some(Ok(Err(err))) => finish(state, &job, Err(err.to_string()), false).await,
+        Some(Err(err)) => finish(state, &job, Err(format!("panicked: {}", err)), false).await,
+        None => {
+            task.abort();
+            release(state, &job).await;
+        }
+    }
+}
+
+/// Records how an attempt went: a job that worked is done with, one that
+/// didn't is retried later or, out of attempts, dead-lettered.
+async fn finish(state: &AppState, job: &Claimed, result: Result<(), String>, give_up: bool) {
+    let recorded = match result {
+        Ok(()) => sqlx::query!("DELETE FROM jobs WHERE id = $1", job.id).execute(&state.pool).await,
+        Err(error) => {
+            let give_up = give_up || job.attempts as u32 >= state.jobs.max_attempts;
+            if give_up {
+                tracing::error!(job_id = job.id, kind = job.kind, attempts = job.attempts, error, "job failed for good");
+            } else {
+                tracing::warn!(job_id = job.id, kind = job.kind, attempts = job.attempts, error, "job failed, retrying");
+            }
+
+            let backoff = state.jobs.retry_base.saturating_mul(2u32.saturating_pow(job.attempts.max(1) as u32 - 1));
+            sqlx::query!(
+                "UPDATE jobs SET locked_by = NULL, locked_at = NULL, last_error = $2,
+                    failed_at = CASE WHEN $3 THEN now() END,
+                    run_at = CASE WHEN $3 THEN run_at ELSE now() + make_interval(secs => $4) END
+                WHERE id = $1",
+                job.id,
+                error,
+                give_up,
+                backoff.as_secs_f64()
+            )
+            .execute(&state.pool)
+            .await
+        }
+    };
+
+    if let Err(err) = recorded {
+        tracing::error!(job_id = job.id, error = %err, "could not record how a job went");
+    }
+}
+
+/// Hands an unfinished job back, without counting the attempt.
+async fn release(state: &AppState, job: &Claimed) {
+    tracing::warn!(job_id = job.id, kind = job.kind, "releasing a job at shutdown");
+
+    let released = sqlx::query!(
+        "UPDATE jobs SET locked_by = NULL, locked_at = NULL, attempts = attempts - 1 WHERE id = $1",
+        job.id
+    )
+    .execute(&state.pool)
+    .await;
+    if let Err(err) = released {
+        tracing::error!(job_id = job.id, error = %err, "could not release a job");
+    }
+}
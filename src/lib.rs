@@ -0,0 +1,191 @@
+pub mod audit;
+pub mod auth;
+pub mod cache;
+pub mod clock;
+pub mod config;
+pub mod cors;
+pub mod db;
+pub mod error;
+pub mod etag;
+pub mod export;
+mod extract;
+pub mod graphql;
+pub mod grpc;
+pub mod ical;
+pub mod idempotency;
+pub mod import;
+pub mod jobs;
+pub mod limits;
+pub mod live;
+pub mod mail;
+pub mod maintenance;
+pub mod metrics;
+pub mod models;
+pub mod openapi;
+pub mod policy;
+pub mod privacy;
+pub mod rate_limit;
+pub mod routes;
+pub mod security_headers;
+pub mod seed;
+pub mod state;
+pub mod tls;
+pub mod validation;
+pub mod webhooks;
+
+use axum::{extract::DefaultBodyLimit, Router};
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tower::util::option_layer;
+use tower::ServiceBuilder;
+use tower_http::compression::predicate::{DefaultPredicate, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::Level;
+
+pub use error::AppError;
+pub use state::AppState;
+
+/// Responses smaller than this go out as they are; compressing them saves
+/// next to nothing.
+const MIN_COMPRESSED_BYTES: u16 = 1024;
+
+/// The whole API, ready to serve.
+pub fn app(state: AppState) -> Router {
+    let metrics = state.metrics.clone();
+    let cors = cors::layer(&state.allowed_origins, state.cors_allow_credentials);
+
+    let api = Router::new()
+        .merge(routes::users::router())
+        .merge(routes::privacy::router())
+        .merge(routes::avatars::router())
+        .merge(routes::entries::router())
+        .merge(routes::timer::router())
+        .merge(routes::events::router())
+        .merge(routes::ws::router())
+        .merge(routes::projects::router())
+        .merge(routes::tags::router())
+        .merge(routes::reports::router())
+        .merge(routes::calendar::router())
+        .merge(routes::invoices::router())
+        .merge(routes::organizations::router())
+        .merge(routes::timesheets::router())
+        .merge(routes::webhooks::router())
+        .merge(routes::auth::router())
+        .merge(routes::sessions::router())
+        .merge(routes::keys::router())
+        .merge(routes::admin::router())
+        .merge(graphql::router(&state))
+        // Inside compression, so the tag is over the body as the handler
+        // wrote it.
+        .layer(axum::middleware::from_fn(etag::conditional_get));
+    let api = limits::apply(
+        api,
+        Duration::from_secs(state.request_timeout_secs),
+        state.max_in_flight_requests,
+    )
+    .layer(RequestDecompressionLayer::new())
+    .layer(CompressionLayer::new().compress_when(DefaultPredicate::new().and(SizeAbove::new(MIN_COMPRESSED_BYTES))));
+
+    // Probes stay outside the limits so a busy server isn't mistaken for a
+    // dead one, and outside compression since Prometheus negotiates its own.
+    let mut router = api.merge(routes::health::router());
+
+    if state.api_docs {
+        router = router.merge(openapi::router());
+    }
+
+    router
+        .route_layer(axum::middleware::from_fn_with_state(metrics, metrics::track))
+        .layer(DefaultBodyLimit::max(extract::MAX_BODY_BYTES))
+        // Outside the routes, so preflights are answered before any handler
+        // or extractor sees them.
+        .layer(option_layer(cors))
+        // Outside the rest, so errors, timeouts and preflights get them too.
+        .layer(axum::middleware::from_fn_with_state(state.clone(), security_headers::apply))
+        .layer(
+            // The id is set before the span opens so everything logged for
+            // the request, errors included, carries it.
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(|request: &axum::http::Request<_>| {
+                            let request_id = request
+                                .headers()
+                                .get("x-request-id")
+                                .and_then(|id| id.to_str().ok())
+                                .unwrap_or_default();
+
+                            tracing::info_span!(
+                                "request",
+                                method = %request.method(),
+                                path = %request.uri().path(),
+                                request_id,
+                            )
+                        })
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
+        .with_state(state)
+}
+
+/// Serves until `shutdown` fires, then stops accepting connections and gives
+/// requests in flight up to `grace` to finish.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    shutdown: CancellationToken,
+    grace: Duration,
+) -> std::io::Result<()> {
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+    let mut server = std::pin::pin!(server.into_future());
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = shutdown.cancelled() => {}
+    }
+
+    tracing::info!(grace_secs = grace.as_secs(), "shutting down, draining requests");
+
+    match tokio::time::timeout(grace, server).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!("grace period over, dropping requests still in flight");
+            Ok(())
+        }
+    }
+}
+
+/// [`serve`], but over TLS.
+pub async fn serve_tls(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls: RustlsConfig,
+    shutdown: CancellationToken,
+    grace: Duration,
+) -> std::io::Result<()> {
+    let handle = axum_server::Handle::<SocketAddr>::new();
+
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.cancelled().await;
+            tracing::info!(grace_secs = grace.as_secs(), "shutting down, draining requests");
+            handle.graceful_shutdown(Some(grace));
+        }
+    });
+
+    axum_server::Server::from_listener(listener)
+        .acceptor(RustlsAcceptor::new(tls))
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+}
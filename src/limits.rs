@@ -0,0 +1,33 @@
+use axum::error_handling::HandleErrorLayer;
+use axum::Router;
+use std::time::Duration;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::error::Overloaded;
+use tower::timeout::error::Elapsed;
+use tower::{BoxError, ServiceBuilder};
+
+use crate::AppError;
+
+/// Fails requests still running after `timeout`, and turns new ones away
+/// straight away while `max_in_flight` are already being handled, rather
+/// than letting them queue for the pool.
+pub fn apply<S: Clone + Send + Sync + 'static>(router: Router<S>, timeout: Duration, max_in_flight: usize) -> Router<S> {
+    // The limit is shared by every route the router has, not per route.
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(middleware_error))
+            .load_shed()
+            .layer(GlobalConcurrencyLimitLayer::new(max_in_flight))
+            .timeout(timeout),
+    )
+}
+
+async fn middleware_error(err: BoxError) -> AppError {
+    if err.is::<Elapsed>() {
+        AppError::Timeout
+    } else if err.is::<Overloaded>() {
+        AppError::Overloaded
+    } else {
+        AppError::Middleware(err)
+    }
+}
@@ -0,0 +1,69 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Events a slow client hasn't read past this many are dropped; it's told
+/// so and re-fetches.
+pub const CHANNEL_CAPACITY: usize = 64;
+
+/// What `GET /events` tells a user about their own entries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    TimerStarted,
+    TimerStopped,
+    EntryCreated,
+    EntryUpdated,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::TimerStarted => "timer.started",
+            EventKind::TimerStopped => "timer.stopped",
+            EventKind::EntryCreated => "entry.created",
+            EventKind::EntryUpdated => "entry.updated",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveEvent {
+    pub kind: EventKind,
+    pub data: Value,
+}
+
+/// A broadcast channel for each user with someone listening. Nothing is
+/// kept for later: whoever connects afterwards fetches the current state.
+#[derive(Clone, Default)]
+pub struct Live {
+    channels: Arc<Mutex<HashMap<i32, broadcast::Sender<LiveEvent>>>>,
+}
+
+impl Live {
+    pub fn subscribe(&self, user_id: i32) -> broadcast::Receiver<LiveEvent> {
+        let mut channels = self.channels.lock().unwrap();
+        channels
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Sends the event to whoever of the user's is listening. A channel
+    /// everyone has left is dropped here.
+    pub fn publish(&self, user_id: i32, kind: EventKind, data: impl Serialize) {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(sender) = channels.get(&user_id) else {
+            return;
+        };
+
+        let event = LiveEvent {
+            kind,
+            data: serde_json::to_value(data).unwrap_or_default(),
+        };
+        if sender.send(event).is_err() {
+            channels.remove(&user_id);
+        }
+    }
+}
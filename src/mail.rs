@@ -0,0 +1,206 @@
+use lettre::message::{Mailbox, Message, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::{MailBackend, SmtpSettings};
+
+pub mod templates;
+
+/// Emails waiting for the worker past this many are dropped.
+pub const QUEUE_LEN: usize = 256;
+/// Attempts at each email, the first included.
+pub const MAX_ATTEMPTS: u32 = 3;
+/// The wait before the first retry; the next one waits twice as long.
+pub const RETRY_BASE: Duration = Duration::from_secs(5);
+
+/// Delivers emails to users. Sending never fails from the caller's point of
+/// view; backends deal with their own errors.
+pub trait Mailer: Send + Sync {
+    fn send(&self, to: &str, subject: &str, html_body: &str, text_body: &str);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+/// Where queued emails end up.
+pub enum Transport {
+    /// Prints them instead, for local development.
+    Console,
+    Smtp {
+        transport: AsyncSmtpTransport<Tokio1Executor>,
+        from: Mailbox,
+    },
+}
+
+impl Transport {
+    pub fn from_config(backend: &MailBackend) -> Result<Transport, lettre::transport::smtp::Error> {
+        match backend {
+            MailBackend::Console => Ok(Transport::Console),
+            MailBackend::Smtp(settings) => Ok(Transport::Smtp {
+                transport: smtp_transport(settings)?,
+                from: settings.from.clone(),
+            }),
+        }
+    }
+
+    async fn deliver(&self, email: &Email) -> Result<(), DeliveryError> {
+        match self {
+            Transport::Console => {
+                println!("To: {}\nSubject: {}\n\n{}\n", email.to, email.subject, email.text_body);
+                Ok(())
+            }
+            Transport::Smtp { transport, from } => {
+                let message = message(from, email)?;
+                transport.send(message).await.map_err(DeliveryError::Smtp)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn smtp_transport(settings: &SmtpSettings) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
+    // Without STARTTLS everything, credentials included, goes in the clear;
+    // that's only for a local catcher like MailHog.
+    let builder = if settings.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+    };
+    let builder = builder.port(settings.port);
+
+    Ok(match &settings.credentials {
+        Some((username, password)) => builder.credentials(Credentials::new(username.clone(), password.clone())),
+        None => builder,
+    }
+    .build())
+}
+
+/// Both bodies, for the mail client to pick from.
+fn message(from: &Mailbox, email: &Email) -> Result<Message, DeliveryError> {
+    let to = email.to.parse().map_err(DeliveryError::Address)?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(&email.subject)
+        .multipart(MultiPart::alternative_plain_html(email.text_body.clone(), email.html_body.clone()))
+        .map_err(DeliveryError::Message)
+}
+
+#[derive(Debug)]
+enum DeliveryError {
+    Address(lettre::address::AddressError),
+    Message(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+}
+
+impl DeliveryError {
+    /// Whether trying again could go differently. A server that turned the
+    /// recipient down will do so again.
+    fn is_transient(&self) -> bool {
+        match self {
+            DeliveryError::Address(_) | DeliveryError::Message(_) => false,
+            DeliveryError::Smtp(err) => !err.is_permanent(),
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryError::Address(err) => write!(f, "invalid recipient: {}", err),
+            DeliveryError::Message(err) => write!(f, "could not build the message: {}", err),
+            DeliveryError::Smtp(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Queues emails for a worker to deliver, so handlers never wait on the
+/// mail server.
+pub struct QueuedMailer {
+    sender: mpsc::Sender<Email>,
+}
+
+impl QueuedMailer {
+    /// Starts the worker, which runs until the mailer is dropped. Emails
+    /// still queued when the process exits are lost.
+    pub fn start(transport: Transport) -> (QueuedMailer, JoinHandle<()>) {
+        let (sender, mut receiver) = mpsc::channel::<Email>(QUEUE_LEN);
+
+        // One at a time: when the server is down, every email would wait out
+        // the same retries anyway, and the queue stays bounded.
+        let worker = tokio::spawn(async move {
+            while let Some(email) = receiver.recv().await {
+                deliver(&transport, &email).await;
+            }
+        });
+
+        (QueuedMailer { sender }, worker)
+    }
+}
+
+impl Mailer for QueuedMailer {
+    fn send(&self, to: &str, subject: &str, html_body: &str, text_body: &str) {
+        let email = Email {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            html_body: html_body.to_string(),
+            text_body: text_body.to_string(),
+        };
+
+        if let Err(err) = self.sender.try_send(email) {
+            tracing::warn!(subject, error = %err, "dropping email");
+        }
+    }
+}
+
+async fn deliver(transport: &Transport, email: &Email) {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let err = match transport.deliver(email).await {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+
+        if attempt == MAX_ATTEMPTS || !err.is_transient() {
+            tracing::error!(subject = %email.subject, attempt, error = %err, "could not send email");
+            return;
+        }
+        tracing::warn!(subject = %email.subject, attempt, error = %err, "could not send email, retrying");
+        tokio::time::sleep(RETRY_BASE * 2u32.pow(attempt - 1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(to: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Verify your email".to_string(),
+            html_body: "<p>Hi</p>".to_string(),
+            text_body: "Hi".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let from: Mailbox = "tictoc <noreply@example.com>".parse().unwrap();
+
+        let formatted = String::from_utf8(message(&from, &email("chad@gmail.com")).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("To: chad@gmail.com"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("text/plain"), "{}", formatted);
+        assert!(formatted.contains("text/html"), "{}", formatted);
+
+        assert!(message(&from, &email("not an address")).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use askama::Template;
+
+use crate::mail::Mailer;
+
+// Each email is a context struct, rendered through an HTML and a text
+// template per locale under `templates/email/`. Askama compiles them with
+// the crate, so a broken template fails the build.
+
+/// The languages emails are written in. Another one is a directory of
+/// templates next to `en/` and an arm in each `render`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    En,
+}
+
+/// A rendered email, both alternatives of it.
+#[derive(Debug, PartialEq)]
+pub struct Rendered {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+pub trait EmailTemplate {
+    fn render(&self, locale: Locale) -> Result<Rendered, askama::Error>;
+}
+
+/// Renders the email and queues it. It can only fail to render through a
+/// bug, which is logged rather than failing a request that already did
+/// what it was asked.
+pub fn send(mailer: &dyn Mailer, to: &str, locale: Locale, email: &impl EmailTemplate) {
+    match email.render(locale) {
+        Ok(rendered) => mailer.send(to, &rendered.subject, &rendered.html, &rendered.text),
+        Err(err) => tracing::error!(error = %err, "could not render email"),
+    }
+}
+
+pub struct Verification {
+    pub name: String,
+    pub link: String,
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+pub struct PasswordReset {
+    pub name: String,
+    pub link: String,
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+pub struct Invitation {
+    /// Who it's to; they may not have an account, so no name.
+    pub email: String,
+    pub organization: String,
+    pub inviter: String,
+    pub link: String,
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+/// The HTML and text templates of one email in one locale, given the
+/// email and how long until it expires, in words.
+macro_rules! templates {
+    ($email:ty, $html:ident = $html_path:literal, $text:ident = $text_path:literal) => {
+        #[derive(Template)]
+        #[template(path = $html_path)]
+        struct $html<'a> {
+            email: &'a $email,
+            expires_in: &'a str,
+        }
+
+        #[derive(Template)]
+        #[template(path = $text_path)]
+        struct $text<'a> {
+            email: &'a $email,
+            expires_in: &'a str,
+        }
+    };
+}
+
+templates!(Verification, VerificationEnHtml = "email/en/verification.html", VerificationEnText = "email/en/verification.txt");
+templates!(PasswordReset, PasswordResetEnHtml = "email/en/password_reset.html", PasswordResetEnText = "email/en/password_reset.txt");
+templates!(Invitation, InvitationEnHtml = "email/en/invitation.html", InvitationEnText = "email/en/invitation.txt");
+
+impl EmailTemplate for Verification {
+    fn render(&self, locale: Locale) -> Result<Rendered, askama::Error> {
+        match locale {
+            Locale::En => {
+                let expires_in = &english_duration(self.expires_in_secs);
+                Ok(Rendered {
+                    subject: "Verify your email".to_string(),
+                    html: VerificationEnHtml { email: self, expires_in }.render()?,
+                    text: VerificationEnText { email: self, expires_in }.render()?,
+                })
+            }
+        }
+    }
+}
+
+impl EmailTemplate for PasswordReset {
+    fn render(&self, locale: Locale) -> Result<Rendered, askama::Error> {
+        match locale {
+            Locale::En => {
+                let expires_in = &english_duration(self.expires_in_secs);
+                Ok(Rendered {
+                    subject: "Reset your password".to_string(),
+                    html: PasswordResetEnHtml { email: self, expires_in }.render()?,
+                    text: PasswordResetEnText { email: self, expires_in }.render()?,
+                })
+            }
+        }
+    }
+}
+
+impl EmailTemplate for Invitation {
+    fn render(&self, locale: Locale) -> Result<Rendered, askama::Error> {
+        match locale {
+            Locale::En => {
+                let expires_in = &english_duration(self.expires_in_secs);
+                Ok(Rendered {
+                    subject: format!("Join {} on tictoc", self.organization),
+                    html: InvitationEnHtml { email: self, expires_in }.render()?,
+                    text: InvitationEnText { email: self, expires_in }.render()?,
+                })
+            }
+        }
+    }
+}
+
+/// `1 hour`, `7 days`, `90 minutes`: the largest unit that divides evenly.
+fn english_duration(secs: u64) -> String {
+    let (count, unit) = match secs {
+        secs if secs >= 86_400 && secs % 86_400 == 0 => (secs / 86_400, "day"),
+        secs if secs >= 3_600 && secs % 3_600 == 0 => (secs / 3_600, "hour"),
+        secs => (secs.div_ceil(60).max(1), "minute"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn assert_in_both(rendered: &Rendered, needles: &[&str]) {
+        for needle in needles {
+            assert!(rendered.html.contains(needle), "{:?} not in the HTML:\n{}", needle, rendered.html);
+            assert!(rendered.text.contains(needle), "{:?} not in the text:\n{}", needle, rendered.text);
+        }
+    }
+
+    #[test]
+    fn test_verification() {
+        let link = format!("https://app.example.com/verify-email?token={}", TOKEN);
+        let email = Verification { name: "Chad".to_string(), link: link.clone(), token: TOKEN.to_string(), expires_in_secs: 86_400 };
+
+        let rendered = email.render(Locale::En).unwrap();
+        assert_eq!(rendered.subject, "Verify your email");
+        assert_in_both(&rendered, &["Chad", &link, "1 day"]);
+        assert!(rendered.html.contains(&format!("href=\"{}\"", link)));
+    }
+
+    #[test]
+    fn test_password_reset() {
+        let link = format!("https://app.example.com/reset-password?token={}", TOKEN);
+        let email = PasswordReset { name: "Chad".to_string(), link: link.clone(), token: TOKEN.to_string(), expires_in_secs: 3_600 };
+
+        let rendered = email.render(Locale::En).unwrap();
+        assert_eq!(rendered.subject, "Reset your password");
+        assert_in_both(&rendered, &["Chad", &link, "1 hour"]);
+    }
+
+    #[test]
+    fn test_invitation() {
+        let link = format!("https://app.example.com/invitations/accept?token={}", TOKEN);
+        let email = Invitation {
+            email: "chad@gmail.com".to_string(),
+            organization: "Tom & Jerry <Ltd>".to_string(),
+            inviter: "Chad".to_string(),
+            link: link.clone(),
+            token: TOKEN.to_string(),
+            expires_in_secs: 7 * 86_400,
+        };
+
+        let rendered = email.render(Locale::En).unwrap();
+        assert_eq!(rendered.subject, "Join Tom & Jerry <Ltd> on tictoc");
+        assert_in_both(&rendered, &["chad@gmail.com", "Chad", &link, "7 days"]);
+        // Only the HTML is escaped.
+        assert!(rendered.html.contains("Tom &#38; Jerry &#60;Ltd&#62;"), "{}", rendered.html);
+        assert!(rendered.text.contains("Tom & Jerry <Ltd>"));
+    }
+
+    #[test]
+    fn test_english_duration() {
+        assert_eq!(english_duration(60), "1 minute");
+        assert_eq!(english_duration(90 * 60), "90 minutes");
+        assert_eq!(english_duration(30), "1 minute");
+        assert_eq!(english_duration(2 * 3_600), "2 hours");
+        assert_eq!(english_duration(86_400), "1 day");
+    }
+}
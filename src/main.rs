@@ -1,265 +1,444 @@
-use axum::{
-    routing::{get, post},
-    Router,
-    extract::{State, Json},
-};
-use serde::{Deserialize, Serialize};
-use jsonwebtoken::{encode, Header, EncodingKey};
-use bcrypt::{hash, verify};
-use sqlx::PgPool;
 use dotenv::dotenv;
+use serde_json::json;
+use sqlx::PgPool;
 use std::env;
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-struct User {
-    id: i32,
-    name: String,
-    email: String,
-    password_hash: String,
+use std::io::{BufRead, IsTerminal};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tictoc::audit::{self, Event};
+use tictoc::auth::password::Passwords;
+use tictoc::config::{load_secrets_file, Config};
+use tictoc::mail::{QueuedMailer, Transport};
+use tictoc::models::{AuditEventType, CreateUserRequest, Role};
+use tictoc::seed::{seed_admin, Seeded};
+use tictoc::{app, db, grpc, jobs, maintenance, serve, serve_tls, tls, webhooks, AppError, AppState};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
 
-#[derive(Clone)]
-struct AppState {
-    pool: PgPool,
+const USAGE: &str = "usage: tictoc [serve] [--insecure-dev-secret]
+       tictoc migrate
+       tictoc db-check
+       tictoc seed-admin --email <email> --name <name>";
+
+enum Command {
+    Serve { insecure_dev_secret: bool },
+    Migrate,
+    /// Fails unless the database is reachable and fully migrated.
+    DbCheck,
+    /// The password comes from `ADMIN_PASSWORD`, or else stdin.
+    SeedAdmin { email: String, name: String },
 }
 
-#[derive(Deserialize)]
-struct CreateUserRequest {
-    name: String,
-    email: String,
-    password: String,
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args: Vec<String> = args.collect();
+    // Serving is the default, so a bare flag still means `serve`.
+    let command = match args.first() {
+        Some(arg) if !arg.starts_with("--") => args.remove(0),
+        _ => "serve".to_string(),
+    };
+
+    let mut take = |flag: &str| match args.iter().position(|arg| arg == flag) {
+        Some(i) if i + 1 < args.len() => Ok(args.drain(i..=i + 1).nth(1)),
+        Some(_) => Err(format!("{} needs a value", flag)),
+        None => Ok(None),
+    };
+
+    let parsed = match command.as_str() {
+        "serve" => {
+            let insecure_dev_secret = args.iter().any(|arg| arg == "--insecure-dev-secret");
+            args.retain(|arg| arg != "--insecure-dev-secret");
+            Command::Serve { insecure_dev_secret }
+        }
+        "migrate" => Command::Migrate,
+        "db-check" => Command::DbCheck,
+        "seed-admin" => Command::SeedAdmin {
+            email: take("--email")?.ok_or("--email is required")?,
+            name: take("--name")?.ok_or("--name is required")?,
+        },
+        other => return Err(format!("unknown command {}", other)),
+    };
+
+    match args.first() {
+        Some(arg) => Err(format!("unexpected argument {}", arg)),
+        None => Ok(parsed),
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-struct CreateUserResponse {
-    id: i32,
-    name: String,
-    email: String,
-}
+fn read_admin_password() -> String {
+    if let Ok(password) = env::var("ADMIN_PASSWORD") {
+        return password;
+    }
 
-#[derive(Deserialize)]
-struct LoginUserRequest {
-    email: String,
-    password: String,
-}
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("password: ");
+    }
+    let mut password = String::new();
+    stdin.lock().read_line(&mut password).unwrap_or_else(|err| {
+        eprintln!("could not read the password from stdin: {}", err);
+        std::process::exit(1);
+    });
 
-#[derive(Serialize, Deserialize)]
-struct LoginUserResponse {
-    token: String,
+    password.trim_end_matches(['\r', '\n']).to_string()
 }
 
-async fn read_user(State(state): State<AppState>) -> String {
-    let users = sqlx::query_as!(
-        CreateUserResponse,
-        "SELECT id, name, email FROM users ORDER BY id"
+/// Gives `ADMIN_EMAIL` the admin role, if that account exists and lacks it.
+async fn promote_admin(pool: &PgPool, email: &str) {
+    let email = email.trim().to_lowercase();
+    let user = sqlx::query!(
+        r#"SELECT id, role AS "role: Role" FROM users WHERE email = $1 AND deleted_at IS NULL"#,
+        email
     )
-    .fetch_all(&state.pool)
+    .fetch_optional(pool)
     .await
     .unwrap();
 
-    serde_json::to_string(&users).unwrap()
-}
+    let Some(user) = user else {
+        tracing::warn!(%email, "ADMIN_EMAIL does not match any account");
+        return;
+    };
+    if user.role == Role::Admin {
+        return;
+    }
 
-async fn create_user(
-    State(state): State<AppState>,
-    Json(payload): Json<CreateUserRequest>,
-) -> String {
-    let password_hash = hash(payload.password, 10).unwrap();
-
-    let user = sqlx::query_as!(
-        CreateUserResponse,
-        "INSERT INTO users (name, email, password_hash) VALUES ($1, $2, $3) RETURNING id, name, email",
-        payload.name,
-        payload.email,
-        password_hash
-    )
-    .fetch_one(&state.pool)
+    let mut tx = pool.begin().await.unwrap();
+    sqlx::query!("UPDATE users SET role = 'admin' WHERE id = $1", user.id)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::RoleChanged,
+        actor: None,
+        target: Some(("user", user.id)),
+        ip: None,
+        user_agent: None,
+        details: json!({ "from": user.role, "to": Role::Admin, "via": "ADMIN_EMAIL" }),
+    })
     .await
     .unwrap();
+    tx.commit().await.unwrap();
 
-    serde_json::to_string(&user).unwrap()
+    tracing::info!(%email, "promoted ADMIN_EMAIL to admin");
 }
 
-async fn login(
-    State(state): State<AppState>,
-    Json(payload): Json<LoginUserRequest>,
-) -> String {
-    let user = sqlx::query!(
-        "SELECT id, name, email, password_hash FROM users WHERE email = $1",
-        payload.email
-    )
-    .fetch_optional(&state.pool)
-    .await
-    .unwrap();
-
-    match user {
-        Some(user) => {
-            if !verify(&payload.password, &user.password_hash).unwrap() {
-                return "Invalid password".to_string();
-            }
+async fn run_migrate(pool: PgPool) {
+    let applied = db::migrate(&pool).await.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    pool.close().await;
 
-            let user_data = CreateUserResponse {
-                id: user.id,
-                name: user.name,
-                email: user.email,
-            };
+    if applied.is_empty() {
+        println!("nothing to migrate");
+    }
+    for migration in applied {
+        println!("applied {} {}", migration.version, migration.description);
+    }
+}
 
-            let token = LoginUserResponse {
-                token: encode(
-                    &Header::default(),
-                    &user_data,
-                    &EncodingKey::from_secret("secret".as_ref()),
-                ).unwrap(),
-            };
+async fn run_db_check(pool: PgPool) {
+    let checked = db::check(&pool).await;
+    pool.close().await;
 
-            serde_json::to_string(&token).unwrap()
+    match checked {
+        Ok(()) => println!("ok, {} migrations applied", db::MIGRATOR.iter().count()),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
         }
-        _ => "User not found".to_string(),
     }
 }
 
-#[tokio::main]
-async fn main() {
-    dotenv().ok();
-    let pool = PgPool::connect(&env::var("DATABASE_URL").unwrap())
-        .await
-        .unwrap();
-
-    sqlx::migrate!()
-        .run(&pool)
-        .await
-        .unwrap();
+async fn run_seed_admin(config: &Config, pool: &PgPool, email: String, name: String) {
+    let passwords = Passwords::from_config(config).unwrap();
+    let admin = CreateUserRequest { name, email, password: read_admin_password() };
 
-    let state = AppState { pool };
+    match seed_admin(pool, &passwords, admin).await {
+        Ok(Seeded::Created { id }) => println!("created admin {}", id),
+        Ok(Seeded::Exists { id, role }) => {
+            println!("user {} already has that email (role {:?}), nothing changed", id, role)
+        }
+        Err(AppError::Validation(errors)) => {
+            for (field, reasons) in errors.errors {
+                eprintln!("{}: {}", field, reasons.join(", "));
+            }
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("could not create the admin: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let app = Router::new()
-        .route("/users", get(read_user))
-        .route("/users/create", post(create_user))
-        .route("/users/login", post(login))
-        .with_state(state);
+/// `RUST_LOG` picks what gets logged, `LOG_FORMAT` (`json` or `pretty`)
+/// how.
+/// Connects now, so a wrong URL shows at startup; the connection is re-made
+/// whenever it drops.
+#[cfg(feature = "redis")]
+async fn connect_redis(url: &str) -> redis::RedisResult<redis::aio::ConnectionManager> {
+    redis::aio::ConnectionManager::new(redis::Client::open(url)?).await
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
-        .await
-        .unwrap();
-    println!("Server running on http://0.0.0.0:3000");
-    axum::serve(listener, app).await.unwrap();
+fn init_tracing() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt = tracing_subscriber::fmt::layer();
+    let fmt = match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => fmt.json().boxed(),
+        Ok("pretty") => fmt.pretty().boxed(),
+        _ => fmt.boxed(),
+    };
+
+    // Slow statements are counted whatever RUST_LOG lets through.
+    let slow_queries = db::SlowQueries.with_filter(Targets::new().with_target("sqlx::query", tracing::Level::WARN));
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(filter))
+        .with(slow_queries)
+        .init();
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use jsonwebtoken::{decode, DecodingKey, Validation};
-    use std::collections::HashSet;
-    use uuid;
-
-    async fn setup_test_db(db_name: &str) -> PgPool {
-        dotenv().ok();
-        let base_url = env::var("DATABASE_URL").unwrap();
-        
-        let admin_pool = PgPool::connect(&base_url)
-            .await
-            .unwrap();
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    init_tracing();
+
+    let command = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        std::process::exit(2);
+    });
+
+    // Only serving signs tokens, so the other commands never need a real
+    // secret.
+    let insecure_dev_secret = match command {
+        Command::Serve { insecure_dev_secret } => insecure_dev_secret,
+        _ => true,
+    };
+    let config = Config::load(insecure_dev_secret).unwrap_or_else(|errors| {
+        eprintln!("{}", errors);
+        std::process::exit(1);
+    });
+
+    // migrate and db-check look at the schema before touching it.
+    let migrate = match command {
+        Command::Serve { .. } => config.migrate_on_start,
+        Command::Migrate | Command::DbCheck => false,
+        Command::SeedAdmin { .. } => true,
+    };
+    let connect_options = db::connect_options(&config, &config.database_url).unwrap_or_else(|err| {
+        eprintln!("DATABASE_URL is invalid, {}", err);
+        std::process::exit(1);
+    });
+    let pool = db::connect(db::pool_options(&config), connect_options, &db::Retry::from_config(&config), migrate)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("database at DATABASE_URL unavailable, {}", err);
+            std::process::exit(1);
+        });
+
+    match command {
+        Command::Serve { .. } => {}
+        Command::Migrate => return run_migrate(pool).await,
+        Command::DbCheck => return run_db_check(pool).await,
+        Command::SeedAdmin { email, name } => {
+            run_seed_admin(&config, &pool, email, name).await;
+            pool.close().await;
+            return;
+        }
+    }
+    if !migrate {
+        if let Err(err) = db::check(&pool).await {
+            eprintln!("{}, run tictoc migrate first", err);
+            std::process::exit(1);
+        }
+    }
+    if insecure_dev_secret {
+        tracing::warn!("running with an insecure development JWT secret");
+    }
 
-        sqlx::query(&format!("CREATE DATABASE {}", db_name))
-            .execute(&admin_pool)
-            .await
-            .unwrap();
+    // Existing accounts can only be made admin from here.
+    if let Some(email) = &config.admin_email {
+        promote_admin(&pool, email).await;
+    }
 
-        let test_url = base_url.replace("/tictoc", &format!("/{}", db_name));
-        let pool = PgPool::connect(&test_url)
+    let tls = config.tls.as_ref().map(|files| {
+        tls::load(files).unwrap_or_else(|err| {
+            eprintln!("TLS: {}", err);
+            std::process::exit(1);
+        })
+    });
+
+    let transport = Transport::from_config(&config.mail).unwrap_or_else(|err| {
+        eprintln!("SMTP: {}", err);
+        std::process::exit(1);
+    });
+    let (mailer, _) = QueuedMailer::start(transport);
+    let mut state = AppState::new(&config, pool, Arc::new(mailer)).unwrap();
+
+    // The replica is migrated through the primary.
+    if let Some(url) = &config.database_read_url {
+        let connect_options = db::connect_options(&config, url).unwrap_or_else(|err| {
+            eprintln!("DATABASE_READ_URL is invalid, {}", err);
+            std::process::exit(1);
+        });
+        let read_pool = db::connect(db::pool_options(&config), connect_options, &db::Retry::from_config(&config), false)
             .await
-            .unwrap();
+            .unwrap_or_else(|err| {
+                eprintln!("database at DATABASE_READ_URL unavailable, {}", err);
+                std::process::exit(1);
+            });
+        state.read_pool = Some(read_pool);
+    }
 
-        sqlx::migrate!()
-            .run(&pool)
-            .await
-            .unwrap();
+    #[cfg(feature = "redis")]
+    if let Some(url) = &config.redis_url {
+        let connection = connect_redis(url).await.unwrap_or_else(|err| {
+            eprintln!("Redis at REDIS_URL unavailable, {}", err);
+            std::process::exit(1);
+        });
+        state.cache = Arc::new(tictoc::cache::RedisCache::new(connection.clone()));
+        if config.rate_limit_backend == tictoc::config::RateLimitBackend::Redis {
+            state.login_limiter = Arc::new(tictoc::rate_limit::RedisRateLimiter::new(
+                connection,
+                tictoc::state::LOGIN_MAX_ATTEMPTS,
+                Duration::from_secs(tictoc::state::LOGIN_WINDOW_SECS),
+                state.metrics.clone(),
+            ));
+        }
+    }
 
-        pool
+    // SIGHUP does the same as POST /admin/keys/rotate.
+    #[cfg(unix)]
+    if let Some(path) = state.jwt_secrets_file.clone() {
+        let jwt = state.jwt.clone();
+        let shutdown = state.shutdown.clone();
+
+        tokio::spawn(async move {
+            let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).unwrap();
+
+            while shutdown.run_until_cancelled(hangups.recv()).await.flatten().is_some() {
+                match load_secrets_file(&path) {
+                    Ok(keys) => {
+                        jwt.replace(keys);
+                        tracing::info!(path = %path.display(), "reloaded JWT keys");
+                    }
+                    Err(err) => tracing::error!(error = %err, "could not reload JWT keys"),
+                }
+            }
+        });
     }
 
-    async fn cleanup_test_db(db_name: &str) {
-        let base_url = env::var("DATABASE_URL").unwrap();
-        let admin_pool = PgPool::connect(&base_url)
-            .await
-            .unwrap();
+    // Certificate renewals are picked up the same way.
+    #[cfg(unix)]
+    if let (Some(tls), Some(files)) = (tls.clone(), config.tls.clone()) {
+        let shutdown = state.shutdown.clone();
 
-        sqlx::query(&format!("DROP DATABASE IF EXISTS {}", db_name))
-            .execute(&admin_pool)
-            .await
-            .unwrap();
-    }
+        tokio::spawn(async move {
+            let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).unwrap();
 
-    #[tokio::test]
-    async fn test_create_user() {
-        let db_name = format!("test_{}", uuid::Uuid::new_v4().simple());
-        let pool = setup_test_db(&db_name).await;
-        let state = AppState { pool };
-
-        let user = CreateUserRequest {
-            name: "Chad".to_string(),
-            email: "chad1@gmail.com".to_string(),
-            password: "password".to_string()
-        };
-
-        let response = create_user(
-            State(state.clone()),
-            Json(user)
-        ).await;
-        assert_eq!(response, "{\"id\":1,\"name\":\"Chad\",\"email\":\"chad1@gmail.com\"}");
-
-        let user = CreateUserRequest {
-            name: "User".to_string(),
-            email: "user@gmail.com".to_string(),
-            password: "password".to_string()
-        };
-
-        let response = create_user(
-            State(state),
-            Json(user)
-        ).await;
-        assert_eq!(response, "{\"id\":2,\"name\":\"User\",\"email\":\"user@gmail.com\"}");
-
-        cleanup_test_db(&db_name).await;
+            while shutdown.run_until_cancelled(hangups.recv()).await.flatten().is_some() {
+                match tls::reload(&tls, &files) {
+                    Ok(()) => tracing::info!(path = %files.cert.display(), "reloaded TLS certificate"),
+                    Err(err) => tracing::error!(error = %err, "could not reload TLS certificate"),
+                }
+            }
+        });
     }
 
-    #[tokio::test]
-    async fn test_login() {
-        let db_name = format!("test_{}", uuid::Uuid::new_v4().simple());
-        let pool = setup_test_db(&db_name).await;
-        let state = AppState { pool };
-
-        let user = CreateUserRequest {
-            name: "Chad".to_string(),
-            email: "chad2@gmail.com".to_string(),
-            password: "password".to_string()
-        };
+    let shutdown = state.shutdown.clone();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            shutdown.cancel();
+        }
+    });
+
+    webhooks::spawn_worker(&state);
+    let jobs = jobs::spawn_worker(&state);
+    maintenance::spawn(&state);
+
+    let pool = state.pool.clone();
+    let read_pool = state.read_pool.clone();
+    let grace = Duration::from_secs(config.shutdown_grace_secs);
+
+    let grpc = match config.grpc_port {
+        Some(port) => {
+            let addr = SocketAddr::new(config.bind_addr, port);
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            tracing::info!("serving gRPC on {}", addr);
+            Some(tokio::spawn(grpc::serve(listener, state.clone(), shutdown.clone(), grace)))
+        }
+        None => None,
+    };
 
-        create_user(State(state.clone()), Json(user)).await;
+    let app = app(state);
+    let addr = SocketAddr::new(config.bind_addr, config.port);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .unwrap();
 
-        let login_user = LoginUserRequest {
-            email: "chad2@gmail.com".to_string(),
-            password: "password".to_string()
-        };
+    match tls {
+        Some(tls) => {
+            let redirect = match config.http_redirect_port {
+                Some(port) => {
+                    let addr = SocketAddr::new(config.bind_addr, port);
+                    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+                    tracing::info!("redirecting http://{} to https", addr);
+                    Some(tokio::spawn(serve(listener, tls::redirect_app(config.port), shutdown.clone(), grace)))
+                }
+                None => None,
+            };
 
-        let response = login(State(state), Json(login_user)).await;
+            tracing::info!("listening on https://{}", addr);
+            serve_tls(listener, app, tls, shutdown, grace).await.unwrap();
 
-        let token_response: LoginUserResponse = serde_json::from_str(&response).unwrap();
-        
-        let mut validation = Validation::default();
-        validation.validate_exp = false;
-        validation.required_spec_claims = HashSet::new();
+            if let Some(redirect) = redirect {
+                redirect.await.unwrap().unwrap();
+            }
+        }
+        None => {
+            tracing::info!("listening on http://{}", addr);
+            serve(listener, app, shutdown, grace).await.unwrap();
+        }
+    }
 
-        let token_data = decode::<CreateUserResponse>(
-            &token_response.token,
-            &DecodingKey::from_secret("secret".as_ref()),
-            &validation,
-        ).unwrap();
+    if let Some(grpc) = grpc {
+        grpc.await.unwrap().unwrap();
+    }
 
-        assert_eq!(token_data.claims.id, 1);
-        assert_eq!(token_data.claims.name, "Chad");
-        assert_eq!(token_data.claims.email, "chad2@gmail.com");
+    // The job it's running gets the same grace as requests.
+    if let Some(jobs) = jobs {
+        let _ = jobs.await;
+    }
 
-        cleanup_test_db(&db_name).await;
+    pool.close().await;
+    if let Some(read_pool) = read_pool {
+        read_pool.close().await;
     }
-}
\ No newline at end of file
+    tracing::info!("shut down");
+}
+
@@ -0,0 +1,150 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::models::PurgedRows;
+use crate::state::AppState;
+
+/// Rows deleted per statement, so no purge holds its locks for long.
+pub const BATCH_SIZE: i64 = 1000;
+
+/// A table and the rows in it that have outlived their use: `$1` is the
+/// retention in seconds, `$2` the batch size. Rows are picked by `ctid`
+/// since not every table has an id.
+struct Purge {
+    table: &'static str,
+    retention_secs: fn(&AppState) -> u64,
+    sql: &'static str,
+}
+
+const PURGES: &[Purge] = &[
+    Purge {
+        table: "revoked_tokens",
+        retention_secs: |_| 0,
+        sql: "DELETE FROM revoked_tokens WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM revoked_tokens WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "refresh_tokens",
+        retention_secs: |state| state.retention.refresh_tokens_secs,
+        sql: "DELETE FROM refresh_tokens WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM refresh_tokens WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    // Kept as long as their refresh tokens are, for what the last of those
+    // says about how the session ended.
+    Purge {
+        table: "sessions",
+        retention_secs: |state| state.retention.refresh_tokens_secs,
+        sql: "DELETE FROM sessions WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM sessions WHERE last_used_at < now() - make_interval(secs => $1)
+                AND NOT EXISTS (SELECT 1 FROM refresh_tokens WHERE session_id = sessions.id) LIMIT $2))",
+    },
+    Purge {
+        table: "password_resets",
+        retention_secs: |state| state.retention.password_resets_secs,
+        sql: "DELETE FROM password_resets WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM password_resets WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "email_verifications",
+        retention_secs: |state| state.retention.email_verifications_secs,
+        sql: "DELETE FROM email_verifications WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM email_verifications WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "organization_invitations",
+        retention_secs: |state| state.retention.invitations_secs,
+        sql: "DELETE FROM organization_invitations WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM organization_invitations WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "idempotency_keys",
+        retention_secs: |_| 0,
+        sql: "DELETE FROM idempotency_keys WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM idempotency_keys WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "login_attempts",
+        retention_secs: |state| state.retention.login_attempts_secs,
+        sql: "DELETE FROM login_attempts WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM login_attempts WHERE attempted_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "webhook_deliveries",
+        retention_secs: |state| state.retention.webhook_deliveries_secs,
+        sql: "DELETE FROM webhook_deliveries WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM webhook_deliveries WHERE created_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    Purge {
+        table: "account_exports",
+        retention_secs: |_| 0,
+        sql: "DELETE FROM account_exports WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM account_exports WHERE expires_at < now() - make_interval(secs => $1) LIMIT $2))",
+    },
+    // Everything of theirs goes with them, by cascade. Erased accounts stay
+    // for what was invoiced or approved.
+    Purge {
+        table: "users",
+        retention_secs: |state| state.restore_grace_secs,
+        sql: "DELETE FROM users WHERE ctid = ANY(ARRAY(
+            SELECT ctid FROM users WHERE deleted_at < now() - make_interval(secs => $1) AND erased_at IS NULL LIMIT $2))",
+    },
+];
+
+/// Purges every table once, a batch at a time, stopping early at shutdown.
+/// Returns how many rows went from each.
+pub async fn run(state: &AppState) -> Result<Vec<PurgedRows>, sqlx::Error> {
+    let mut report = Vec::with_capacity(PURGES.len());
+
+    for purge in PURGES {
+        let retention_secs = (purge.retention_secs)(state) as f64;
+        let mut rows = 0;
+
+        while !state.shutdown.is_cancelled() {
+            let deleted = sqlx::query(purge.sql)
+                .bind(retention_secs)
+                .bind(BATCH_SIZE)
+                .execute(&state.pool)
+                .await?
+                .rows_affected();
+            rows += deleted;
+
+            if deleted < BATCH_SIZE as u64 {
+                break;
+            }
+        }
+
+        state.metrics.record_purged(purge.table, rows);
+        report.push(PurgedRows {
+            table: purge.table.to_string(),
+            rows,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Runs [`run`] every `maintenance_interval_secs` until shutdown, starting
+/// one interval from now. `None` when the interval is zero.
+pub fn spawn(state: &AppState) -> Option<JoinHandle<()>> {
+    if state.maintenance_interval_secs == 0 {
+        return None;
+    }
+    let state = state.clone();
+    let period = Duration::from_secs(state.maintenance_interval_secs);
+
+    Some(tokio::spawn(async move {
+        let mut ticks = interval_at(Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        while state.shutdown.run_until_cancelled(ticks.tick()).await.is_some() {
+            match run(&state).await {
+                Ok(report) => {
+                    let rows: u64 = report.iter().map(|purged| purged.rows).sum();
+                    tracing::info!(rows, "purged expired rows");
+                }
+                Err(err) => tracing::error!(error = %err, "could not purge expired rows"),
+            }
+        }
+    }))
+}
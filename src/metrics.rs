@@ -0,0 +1,196 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::PgPool;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+/// Counted off the logs, which every instance in the process shares, so
+/// unlike the rest this one is too.
+static SLOW_QUERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    IntCounterVec::new(
+        Opts::new("db_slow_queries_total", "Statements slower than SLOW_QUERY_MS"),
+        &["fingerprint"],
+    )
+    .unwrap()
+});
+
+/// Counts a statement [`SlowQueries`](crate::db::SlowQueries) saw run slow.
+pub fn record_slow_query(fingerprint: &str) {
+    SLOW_QUERIES.with_label_values(&[fingerprint]).inc();
+}
+
+/// Everything exposed on `/metrics`. Each instance has its own registry, so
+/// tests don't see each other's numbers.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    in_flight: IntGauge,
+    pool_size: IntGauge,
+    pool_idle: IntGauge,
+    logins: IntCounterVec,
+    users_created: IntCounter,
+    purged_rows: IntCounterVec,
+    rate_limit_errors: IntCounter,
+    db_reads: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+            &["method", "route"],
+        )
+        .unwrap();
+        let in_flight = IntGauge::new("http_requests_in_flight", "HTTP requests being handled").unwrap();
+        let pool_size = IntGauge::new("db_pool_connections", "Open database connections").unwrap();
+        let pool_idle = IntGauge::new("db_pool_idle_connections", "Idle database connections").unwrap();
+        let logins = IntCounterVec::new(Opts::new("logins_total", "Login attempts"), &["outcome"]).unwrap();
+        let users_created = IntCounter::new("users_created_total", "Accounts created").unwrap();
+        let purged_rows = IntCounterVec::new(
+            Opts::new("maintenance_purged_rows_total", "Expired rows deleted by maintenance"),
+            &["table"],
+        )
+        .unwrap();
+        let rate_limit_errors = IntCounter::new(
+            "rate_limit_errors_total",
+            "Rate limit checks let through because the backend couldn't be reached",
+        )
+        .unwrap();
+
+        let db_reads = IntCounterVec::new(
+            Opts::new("db_reads_total", "Reads sent to the replica, or to the primary without one"),
+            &["pool"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+        registry.register(Box::new(pool_size.clone())).unwrap();
+        registry.register(Box::new(pool_idle.clone())).unwrap();
+        registry.register(Box::new(logins.clone())).unwrap();
+        registry.register(Box::new(users_created.clone())).unwrap();
+        registry.register(Box::new(purged_rows.clone())).unwrap();
+        registry.register(Box::new(rate_limit_errors.clone())).unwrap();
+        registry.register(Box::new(db_reads.clone())).unwrap();
+        registry.register(Box::new(SLOW_QUERIES.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            latency,
+            in_flight,
+            pool_size,
+            pool_idle,
+            logins,
+            users_created,
+            purged_rows,
+            rate_limit_errors,
+            db_reads,
+        }
+    }
+
+    pub fn record_purged(&self, table: &str, rows: u64) {
+        self.purged_rows.with_label_values(&[table]).inc_by(rows);
+    }
+
+    pub fn record_rate_limit_error(&self) {
+        self.rate_limit_errors.inc();
+    }
+
+    pub fn record_db_read(&self, pool: &str) {
+        self.db_reads.with_label_values(&[pool]).inc();
+    }
+
+    /// Renders the Prometheus text format, sampling the pool as it goes.
+    pub fn render(&self, pool: &PgPool) -> String {
+        self.pool_size.set(pool.size() as i64);
+        self.pool_idle.set(pool.num_idle() as i64);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn record(&self, method: &str, route: &str, status: u16, started: Instant) {
+        let class = format!("{}xx", status / 100);
+
+        self.requests.with_label_values(&[method, route, &class]).inc();
+        self.latency
+            .with_label_values(&[method, route])
+            .observe(started.elapsed().as_secs_f64());
+
+        // Account events are read off the responses, so handlers stay
+        // unaware of metrics.
+        match (method, route, status) {
+            ("POST", "/users/login" | "/users/login/2fa", 200) => {
+                self.logins.with_label_values(&["succeeded"]).inc()
+            }
+            ("POST", "/users/login" | "/users/login/2fa", 401 | 423) => {
+                self.logins.with_label_values(&["failed"]).inc()
+            }
+            ("POST", "/users/create", 201) => self.users_created.inc(),
+            _ => {}
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// Route layer recording every matched request. Labels use the route
+/// template rather than the raw path to keep cardinality down.
+pub async fn track(State(metrics): State<std::sync::Arc<Metrics>>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+
+    let started = Instant::now();
+    let response = {
+        let _in_flight = InFlight::new(&metrics.in_flight);
+        next.run(request).await
+    };
+
+    metrics.record(&method, &route, response.status().as_u16(), started);
+
+    response
+}
+
+/// Counts a request as in flight until dropped, even if the client goes away
+/// mid-request.
+struct InFlight<'a>(&'a IntGauge);
+
+impl<'a> InFlight<'a> {
+    fn new(gauge: &'a IntGauge) -> Self {
+        gauge.inc();
+        InFlight(gauge)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
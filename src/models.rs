@@ -0,0 +1,1215 @@
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+pub use tictoc_types::{
+    CreateUserRequest, CreateUserResponse, DurationInput, EntryQuery, LoginResponse, LoginUserRequest,
+    LoginUserResponse, Page, Project, ProjectQuery, ProjectRequest, RefreshTokenRequest, Role, StartTimerRequest, Tag,
+    TimeEntry, TimeEntryRequest, TimerMode, TwoFactorChallenge, UserResponse, DEFAULT_PROJECT_COLOR,
+};
+
+use crate::validation::{Validate, ValidationErrors};
+use crate::validation;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub password_hash: String,
+    pub verified: bool,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub role: Role,
+    pub two_factor: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub after_id: Option<i32>,
+    pub q: Option<String>,
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    /// Only accounts with no login since this day, UTC; those that never
+    /// logged in count from when they were created.
+    pub inactive_since: Option<NaiveDate>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateUserRequest {
+    pub name: String,
+    pub email: String,
+    /// The version being edited, for clients that can't send `If-Match`.
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PatchUserRequest {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// As for [`UpdateUserRequest::version`].
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+impl Validate for CreateUserRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::name(&mut errors, "name", &mut self.name);
+        validation::email(&mut errors, "email", &mut self.email);
+        validation::password(&mut errors, "password", &self.password);
+        errors.into_result()
+    }
+}
+
+impl Validate for UpdateUserRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::name(&mut errors, "name", &mut self.name);
+        validation::email(&mut errors, "email", &mut self.email);
+        errors.into_result()
+    }
+}
+
+impl Validate for PatchUserRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(name) = &mut self.name {
+            validation::name(&mut errors, "name", name);
+        }
+        if let Some(email) = &mut self.email {
+            validation::email(&mut errors, "email", email);
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for ChangePasswordRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::required(&mut errors, "current_password", &self.current_password);
+        validation::password(&mut errors, "new_password", &self.new_password);
+        if self.new_password == self.current_password {
+            errors.add("new_password", "must differ from current password");
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for PasswordResetRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::email(&mut errors, "email", &mut self.email);
+        errors.into_result()
+    }
+}
+
+impl Validate for PasswordResetConfirmRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::required(&mut errors, "token", &self.token);
+        validation::password(&mut errors, "new_password", &self.new_password);
+        errors.into_result()
+    }
+}
+
+impl Validate for LoginUserRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        // Only the shape is checked here; passwords set before the policy
+        // existed must still be able to log in.
+        let mut errors = ValidationErrors::default();
+        validation::email(&mut errors, "email", &mut self.email);
+        validation::required(&mut errors, "password", &self.password);
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Claims {
+    pub sub: String,
+    pub email: String,
+    pub jti: String,
+    pub iat: u64,
+    pub exp: u64,
+    /// Tokens from before roles existed carry none and count as `user`.
+    #[serde(default)]
+    pub role: Role,
+    /// The session the token was issued in; none on tokens from before
+    /// sessions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i32>,
+}
+
+/// Claims of a challenge token. It has no `jti` or `email`, so it can never
+/// pass for an access token.
+#[derive(Serialize, Deserialize)]
+pub struct ChallengeClaims {
+    pub sub: String,
+    pub purpose: String,
+    pub iat: u64,
+    pub exp: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct LoginAttempt {
+    pub ip: Option<String>,
+    pub succeeded: bool,
+    pub attempted_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    pub label: String,
+}
+
+impl Validate for CreateApiKeyRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::name(&mut errors, "label", &mut self.label);
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct ApiKey {
+    pub id: i32,
+    pub label: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A login on some device, live for as long as it can still refresh.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct LoginSession {
+    pub id: i32,
+    pub user_agent: Option<String>,
+    /// Read off the user agent, where it's one that can be told.
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// The session the request itself was made in.
+    pub current: bool,
+}
+
+/// Only ever sent once, when the key is created.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateApiKeyResponse {
+    pub id: i32,
+    pub label: String,
+    pub key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+    pub database: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RotateKeysResponse {
+    pub signing_kid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct StatsResponse {
+    pub users: UserCounts,
+    /// Successful password checks, two-factor logins included.
+    pub logins_last_24h: i64,
+    pub pool: PoolStats,
+}
+
+/// Live accounts only.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct UserCounts {
+    pub total: i64,
+    pub created_last_24h: i64,
+    pub created_last_7d: i64,
+    pub created_last_30d: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct PoolStats {
+    /// Connections open, busy or idle.
+    pub size: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditEventType {
+    UserCreated,
+    UserDeleted,
+    LoginSucceeded,
+    LoginFailed,
+    PasswordChanged,
+    RoleChanged,
+    TokenRevoked,
+    AccountErased,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct AuditEvent {
+    pub id: i64,
+    /// `None` when nobody was signed in, e.g. a failed login.
+    pub actor_user_id: Option<i32>,
+    pub event_type: AuditEventType,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub event_type: Option<AuditEventType>,
+    /// Acting user id.
+    pub actor: Option<i32>,
+    /// Inclusive.
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Where a background job is: waiting for its `run_at`, claimed by a
+/// worker, or out of attempts until retried.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Running,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub state: JobState,
+    pub run_at: DateTime<Utc>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub locked_by: Option<String>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub failed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobsQuery {
+    pub state: Option<JobState>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct PurgedRows {
+    pub table: String,
+    pub rows: u64,
+}
+
+/// Everything kept about an account, as stored: the rows of each table
+/// that are the user's, secrets such as password and token hashes left out.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    #[schema(value_type = Object)]
+    pub profile: serde_json::Value,
+    #[schema(value_type = Object)]
+    pub settings: serde_json::Value,
+    /// Rows by table name, each an object of its columns.
+    #[schema(value_type = Object)]
+    pub tables: BTreeMap<String, serde_json::Value>,
+}
+
+/// An export being built in the background. Once it's done,
+/// `download_url` serves it until `expires_at`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct AccountExportStatus {
+    pub id: i32,
+    pub download_url: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EraseAccountRequest {
+    /// The account's current password, confirming it's really its owner.
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct MaintenanceReport {
+    /// Every table maintenance looks at, purged from or not.
+    pub purged: Vec<PurgedRows>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Checks every row without creating anyone.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Rows that were, or on a dry run would have been, created.
+    pub created: usize,
+    pub failed: usize,
+    /// One per row sent, in the same order.
+    pub rows: Vec<ImportRow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct ImportRow {
+    /// Counted from 1, not counting a CSV header.
+    pub row: usize,
+    /// The new user's id. Dry runs create nobody, so have none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    /// Why the row was skipped: `malformed_row`, `invalid_fields`,
+    /// `duplicate_in_import` or `email_already_registered`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// With `invalid_fields`, what was wrong with each.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, Vec<String>>>,
+    /// With `malformed_row`, what could not be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+    /// One JSON array, sent an item at a time.
+    Json,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// The `multipart/form-data` body of an avatar upload.
+#[derive(ToSchema)]
+pub struct AvatarUpload {
+    /// PNG, JPEG or WebP, up to 2 MB.
+    #[schema(value_type = String, format = Binary)]
+    pub avatar: Vec<u8>,
+}
+
+impl Validate for TimeEntryRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::description(&mut errors, "description", &mut self.description);
+        validation::tags(&mut errors, "tags", &mut self.tags);
+        if let Some(duration) = self.duration_seconds.take() {
+            if self.ended_at.is_some() {
+                errors.add("duration_seconds", "give either ended_at or duration_seconds, not both");
+            } else {
+                let seconds = match duration {
+                    DurationInput::Seconds(seconds) => Ok(seconds),
+                    DurationInput::Text(text) => validation::parse_duration(&text),
+                };
+                match seconds {
+                    Ok(seconds) if seconds <= 0 => errors.add("duration_seconds", "must be positive"),
+                    Ok(seconds) => match TimeDelta::try_seconds(seconds).and_then(|d| self.started_at.checked_add_signed(d)) {
+                        Some(ended_at) => self.ended_at = Some(ended_at),
+                        None => errors.add("duration_seconds", "duration too long"),
+                    },
+                    Err(reason) => errors.add("duration_seconds", &reason),
+                }
+            }
+        }
+        validation::time_range(&mut errors, "ended_at", self.started_at, self.ended_at);
+        errors.into_result()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BulkUpdateRequest {
+    /// Up to 500 of the user's entries.
+    pub ids: Vec<i32>,
+    pub set: BulkChanges,
+}
+
+/// What to change on every entry; fields left out stay as they are.
+#[derive(Deserialize, ToSchema)]
+pub struct BulkChanges {
+    /// `null` takes the entries off their project.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub project_id: Option<Option<i32>>,
+    /// Replaces the entries' tags.
+    pub tags: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub billable: Option<bool>,
+}
+
+impl Validate for BulkUpdateRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::bulk_ids(&mut errors, "ids", &mut self.ids);
+        if let Some(tags) = &mut self.set.tags {
+            validation::tags(&mut errors, "set.tags", tags);
+        }
+        if let Some(description) = &mut self.set.description {
+            validation::description(&mut errors, "set.description", description);
+        }
+        errors.into_result()
+    }
+}
+
+/// Tells a field given as `null` apart from one left out.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BulkDeleteRequest {
+    /// Up to 500 of the user's entries.
+    pub ids: Vec<i32>,
+}
+
+impl Validate for BulkDeleteRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::bulk_ids(&mut errors, "ids", &mut self.ids);
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct BulkResult {
+    pub affected: u64,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EntryExportQuery {
+    /// First day included, in the user's time zone; the current month's
+    /// first when left out.
+    pub from: Option<NaiveDate>,
+    /// Last day included; the current month's last when left out.
+    pub to: Option<NaiveDate>,
+    pub project_id: Option<i32>,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CalendarQuery {
+    /// The feed token, for calendar apps that can't send `Authorization`.
+    pub token: Option<String>,
+    /// How many days back the feed goes; 90 when left out.
+    pub days: Option<i64>,
+}
+
+/// The user's calendar feed token, only shown when it's made.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CalendarToken {
+    pub token: String,
+    /// The feed, token included, to subscribe to.
+    pub url: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EntryWriteQuery {
+    /// Skip the check for overlapping entries, this once. Entries written
+    /// this way are never counted as overlapping later ones either.
+    #[serde(default)]
+    pub allow_overlap: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StartTimerQuery {
+    #[serde(default)]
+    pub mode: TimerMode,
+}
+
+impl Validate for StartTimerRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::description(&mut errors, "description", &mut self.description);
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct RunningTimer {
+    #[serde(flatten)]
+    pub entry: TimeEntry,
+    /// Seconds since `started_at`, as of the response.
+    pub elapsed_secs: i64,
+    /// Only for timers started as pomodoros.
+    pub countdown: Option<Countdown>,
+}
+
+/// Where a pomodoro is up to.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Countdown {
+    pub target_secs: i64,
+    pub remaining_seconds: i64,
+    /// The work is done and the entry stopped; the break is on until
+    /// `break_ends_at`.
+    pub elapsed: bool,
+    pub break_ends_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Preferences {
+    /// Starting a timer stops the one running, instead of failing with 409.
+    pub auto_stop_timer: bool,
+    /// Entries may overlap, for tracking parallel work, as if every write
+    /// passed `allow_overlap=true`.
+    pub allow_overlap: bool,
+    /// How long a pomodoro's work lasts; 25 minutes to begin with.
+    pub pomodoro_work_secs: i32,
+    /// How long the break after one lasts; 5 minutes to begin with.
+    pub pomodoro_break_secs: i32,
+    /// Cents an hour, for entries on projects without a rate of their own.
+    pub hourly_rate_cents: Option<i32>,
+    /// ISO 4217, like `USD`; the default rate's currency.
+    pub currency: String,
+    /// Tax added to invoices, in basis points: 2000 is 20%.
+    pub tax_rate_bps: i32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PreferencesPatch {
+    pub auto_stop_timer: Option<bool>,
+    pub allow_overlap: Option<bool>,
+    /// From a minute to four hours.
+    pub pomodoro_work_secs: Option<i32>,
+    /// From a minute to an hour.
+    pub pomodoro_break_secs: Option<i32>,
+    /// `null` leaves entries off projects without a rate unpriced.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub hourly_rate_cents: Option<Option<i32>>,
+    pub currency: Option<String>,
+    pub tax_rate_bps: Option<i32>,
+}
+
+impl Validate for PreferencesPatch {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if self.pomodoro_work_secs.is_some_and(|secs| !(60..=4 * 3600).contains(&secs)) {
+            errors.add("pomodoro_work_secs", "must be between 60 and 14400");
+        }
+        if self.pomodoro_break_secs.is_some_and(|secs| !(60..=3600).contains(&secs)) {
+            errors.add("pomodoro_break_secs", "must be between 60 and 3600");
+        }
+        if let Some(Some(rate)) = self.hourly_rate_cents {
+            validation::rate(&mut errors, "hourly_rate_cents", rate);
+        }
+        if let Some(currency) = &mut self.currency {
+            validation::currency(&mut errors, "currency", currency);
+        }
+        if let Some(bps) = self.tax_rate_bps {
+            validation::tax_rate(&mut errors, "tax_rate_bps", bps);
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl WeekStart {
+    pub fn weekday(self) -> Weekday {
+        match self {
+            WeekStart::Monday => Weekday::Mon,
+            WeekStart::Sunday => Weekday::Sun,
+        }
+    }
+}
+
+/// How the user's days and weeks are laid out, for reports.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct UserSettings {
+    /// An IANA time zone, like `America/Sao_Paulo`.
+    pub timezone: String,
+    pub week_start: WeekStart,
+}
+
+impl UserSettings {
+    /// Time zones are checked on the way in, so falling back to UTC only
+    /// covers one the tz database has since dropped.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+impl Validate for UserSettings {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::timezone(&mut errors, "timezone", &mut self.timezone);
+        errors.into_result()
+    }
+}
+
+impl Validate for ProjectRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::name(&mut errors, "name", &mut self.name);
+        validation::color(&mut errors, "color", &mut self.color);
+        match (self.hourly_rate_cents, &mut self.currency) {
+            (Some(rate), Some(currency)) => {
+                validation::rate(&mut errors, "hourly_rate_cents", rate);
+                validation::currency(&mut errors, "currency", currency);
+            }
+            (Some(_), None) => errors.add("currency", "required with hourly_rate_cents"),
+            (None, Some(_)) => errors.add("currency", "only given with hourly_rate_cents"),
+            (None, None) => {}
+        }
+        errors.into_result()
+    }
+}
+
+/// A member's role in an organization. Owners can do everything, admins
+/// everything but make or unmake owners, and members see what's shared.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum MemberRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Organization {
+    pub id: i32,
+    pub name: String,
+    /// An IANA name that team report days are in; UTC when `null`.
+    pub timezone: Option<String>,
+    /// The caller's role in it.
+    pub role: MemberRole,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct OrganizationRequest {
+    pub name: String,
+    pub timezone: Option<String>,
+}
+
+impl Validate for OrganizationRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::name(&mut errors, "name", &mut self.name);
+        if let Some(timezone) = &mut self.timezone {
+            validation::timezone(&mut errors, "timezone", timezone);
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Member {
+    pub user_id: i32,
+    pub name: String,
+    pub email: String,
+    pub role: MemberRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MemberRoleRequest {
+    pub role: MemberRole,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InvitationRequest {
+    pub email: String,
+    /// The role the invitee joins with.
+    #[serde(default = "default_invitation_role")]
+    pub role: MemberRole,
+}
+
+fn default_invitation_role() -> MemberRole {
+    MemberRole::Member
+}
+
+impl Validate for InvitationRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::email(&mut errors, "email", &mut self.email);
+        errors.into_result()
+    }
+}
+
+/// An invitation as the inviter sees it; the token only goes to the
+/// invitee, by email.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Invitation {
+    pub id: i32,
+    pub organization_id: i32,
+    pub email: String,
+    pub role: MemberRole,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AcceptInvitationRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema, Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportGroup {
+    #[default]
+    Day,
+    /// Starting on the user's `week_start`.
+    Week,
+    Project,
+    /// An entry with several tags counts toward each of them.
+    Tag,
+}
+
+/// Where a week is in approval. Submitting locks the week's entries;
+/// rejecting unlocks them, to be fixed and submitted again.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum TimesheetState {
+    Draft,
+    Submitted,
+    Approved,
+    Rejected,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Timesheet {
+    pub user_id: i32,
+    /// The week's first day, as the user's `week_start` has it.
+    pub week: NaiveDate,
+    pub state: TimesheetState,
+    /// The entries started in the week: as submitted, or for a draft, as
+    /// they are now.
+    pub entries: i64,
+    pub tracked_secs: i64,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    /// The reviewer's, required when rejecting.
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimesheetQuery {
+    pub state: Option<TimesheetState>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TimesheetReview {
+    pub comment: Option<String>,
+}
+
+impl Validate for TimesheetReview {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(comment) = &mut self.comment {
+            validation::description(&mut errors, "comment", comment);
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum InvoiceGrouping {
+    /// A line for each day, at each rate worked at that day.
+    #[default]
+    Day,
+    Entry,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct InvoiceRequest {
+    pub project_id: i32,
+    /// Days in the user's time zone; entries are billed on the day they
+    /// started.
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    #[serde(default)]
+    pub group_by: InvoiceGrouping,
+    /// Overrides the user's `tax_rate_bps`.
+    pub tax_rate_bps: Option<i32>,
+}
+
+impl Validate for InvoiceRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if self.to < self.from {
+            errors.add("to", "must not be before from");
+        }
+        if let Some(bps) = self.tax_rate_bps {
+            validation::tax_rate(&mut errors, "tax_rate_bps", bps);
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Invoice {
+    pub id: i32,
+    /// Counts up from 1 for each user.
+    pub number: i32,
+    /// `null` once the project is deleted.
+    pub project_id: Option<i32>,
+    pub project_name: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub group_by: InvoiceGrouping,
+    pub currency: String,
+    pub tax_rate_bps: i32,
+    pub subtotal_cents: i64,
+    /// Rounded to the cent, over the subtotal.
+    pub tax_cents: i64,
+    pub total_cents: i64,
+    pub created_at: DateTime<Utc>,
+    pub lines: Vec<InvoiceLine>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct InvoiceLine {
+    pub date: NaiveDate,
+    pub description: String,
+    pub seconds: i64,
+    /// Cents an hour.
+    pub rate_cents: i32,
+    pub amount_cents: i64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum InvoiceFormat {
+    #[default]
+    Json,
+    /// A printable page.
+    Html,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct InvoiceQuery {
+    #[serde(default)]
+    pub format: InvoiceFormat,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PomodoroQuery {
+    /// A day in the user's time zone; today when left out.
+    pub date: Option<NaiveDate>,
+}
+
+/// The pomodoros finished on one of the user's days.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct PomodoroReport {
+    pub date: NaiveDate,
+    pub timezone: String,
+    pub completed: i64,
+    pub tracked_secs: i64,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// First day included; the current week's first when left out.
+    pub from: Option<NaiveDate>,
+    /// Last day included; the current week's last when left out.
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub group_by: ReportGroup,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, SimpleObject)]
+pub struct SummaryReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// The user's, which days and weeks are in.
+    pub timezone: String,
+    pub group_by: ReportGroup,
+    /// Time tracked in the range, running entries counting up to now.
+    pub tracked_secs: i64,
+    pub entries: i64,
+    /// The billable part of `tracked_secs`.
+    pub billable_seconds: i64,
+    /// What the billable time is worth, when it's all in one currency.
+    pub amount_cents: Option<i64>,
+    pub currency: Option<String>,
+    /// What it's worth in each currency; billable time without a rate
+    /// isn't in any.
+    pub amounts: Vec<ReportAmount>,
+    pub buckets: Vec<ReportBucket>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TeamReportGroup {
+    #[default]
+    Member,
+    Project,
+    Day,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TeamReportQuery {
+    /// First day included; the current week's Monday when left out.
+    pub from: Option<NaiveDate>,
+    /// Last day included; the current week's Sunday when left out.
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub group_by: TeamReportGroup,
+}
+
+/// Time members tracked on an organization's projects. Entries on their
+/// own projects are never in it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct TeamReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// The organization's, which days are in.
+    pub timezone: String,
+    pub group_by: TeamReportGroup,
+    pub tracked_secs: i64,
+    pub entries: i64,
+    /// Keyed by user id, project id or day; labelled with the member's
+    /// name, the project's, or the day.
+    pub buckets: Vec<ReportBucket>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, SimpleObject)]
+pub struct ReportAmount {
+    pub currency: String,
+    pub billable_seconds: i64,
+    /// Rounded to the cent once, over the whole sum.
+    pub amount_cents: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, SimpleObject)]
+pub struct ReportBucket {
+    /// The day or week's first day as `YYYY-MM-DD`, the project id, or the
+    /// tag; `null` for entries without a project or tags.
+    pub key: Option<String>,
+    /// The key, or the project's name.
+    pub label: Option<String>,
+    /// Only the part of each entry that falls in this bucket and the range.
+    pub tracked_secs: i64,
+    /// Entries with any time in this bucket.
+    pub entries: i64,
+}
+
+/// What a webhook can be told about. `ping` is only ever sent by
+/// `POST /webhooks/{id}/test`, so it can't be subscribed to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "text")]
+pub enum WebhookEvent {
+    /// Someone registered; only admins' webhooks hear about it.
+    #[serde(rename = "user.created")]
+    #[sqlx(rename = "user.created")]
+    UserCreated,
+    #[serde(rename = "timer.started")]
+    #[sqlx(rename = "timer.started")]
+    TimerStarted,
+    #[serde(rename = "timer.stopped")]
+    #[sqlx(rename = "timer.stopped")]
+    TimerStopped,
+    #[serde(rename = "entry.deleted")]
+    #[sqlx(rename = "entry.deleted")]
+    EntryDeleted,
+    #[serde(rename = "ping")]
+    #[sqlx(rename = "ping")]
+    Ping,
+}
+
+impl WebhookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::UserCreated => "user.created",
+            WebhookEvent::TimerStarted => "timer.started",
+            WebhookEvent::TimerStopped => "timer.stopped",
+            WebhookEvent::EntryDeleted => "entry.deleted",
+            WebhookEvent::Ping => "ping",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    /// Inactive webhooks are kept but sent nothing, bar test pings.
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Only ever sent once, when the webhook is created.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateWebhookResponse {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub active: bool,
+    /// Signs every delivery: `X-Tictoc-Signature` is `sha256=` and the hex
+    /// HMAC-SHA256 of the body under it.
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookRequest {
+    /// Where events are POSTed, over http or https.
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    #[serde(default = "default_webhook_active")]
+    pub active: bool,
+}
+
+fn default_webhook_active() -> bool {
+    true
+}
+
+impl Validate for WebhookRequest {
+    fn validate(&mut self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        validation::url(&mut errors, "url", &mut self.url);
+
+        let mut events = Vec::new();
+        for event in &self.events {
+            if !events.contains(event) {
+                events.push(*event);
+            }
+        }
+        self.events = events;
+        if self.events.is_empty() {
+            errors.add("events", "required");
+        } else if self.events.contains(&WebhookEvent::Ping) {
+            errors.add("events", "ping can't be subscribed to");
+        }
+
+        errors.into_result()
+    }
+}
+
+/// One attempt at delivering an event.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    /// Shared by the attempts at the same event, and sent as
+    /// `X-Tictoc-Delivery`.
+    pub event_id: String,
+    pub event: WebhookEvent,
+    pub payload: serde_json::Value,
+    /// Starting at 1.
+    pub attempt: i32,
+    /// What the endpoint answered, if it did.
+    pub status_code: Option<i32>,
+    /// Why the attempt failed, when no answer came back.
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsQuery {
+    /// A JWT or API key. Browsers can't set headers on a WebSocket, so it
+    /// goes here, or else in the first message.
+    pub token: Option<String>,
+}
@@ -0,0 +1,146 @@
+use axum::Router;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::routes::{admin, auth, avatars, calendar, entries, events, health, invoices, keys, organizations, privacy, projects, reports, sessions, tags, timer, timesheets, users, webhooks, ws};
+use crate::state::AppState;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        users::read_user,
+        users::read_user_by_id,
+        users::update_user,
+        users::patch_user,
+        users::delete_user,
+        users::restore_user,
+        users::read_login_attempts,
+        users::create_user,
+        users::import_users,
+        users::export_users,
+        users::read_me,
+        users::change_password,
+        users::read_preferences,
+        users::update_preferences,
+        users::read_settings,
+        users::update_settings,
+        privacy::export_account,
+        privacy::read_account_export,
+        privacy::erase_account,
+        avatars::read_avatar,
+        avatars::upload_avatar,
+        avatars::delete_avatar,
+        entries::create_entry,
+        entries::read_entries,
+        entries::read_entry,
+        entries::update_entry,
+        entries::delete_entry,
+        entries::bulk_update_entries,
+        entries::bulk_delete_entries,
+        entries::export_entries,
+        calendar::read_calendar,
+        calendar::create_calendar_token,
+        calendar::revoke_calendar_token,
+        invoices::create_invoice,
+        invoices::read_invoice,
+        timer::start_timer,
+        timer::stop_timer,
+        timer::read_current_timer,
+        events::read_events,
+        ws::connect,
+        projects::create_project,
+        projects::read_projects,
+        projects::read_project,
+        projects::update_project,
+        projects::delete_project,
+        tags::read_tags,
+        organizations::create_organization,
+        organizations::read_organizations,
+        organizations::read_organization,
+        organizations::update_organization,
+        organizations::create_invitation,
+        organizations::accept_invitation,
+        organizations::read_members,
+        organizations::update_member,
+        organizations::remove_member,
+        reports::read_summary,
+        reports::read_pomodoros,
+        reports::read_team_report,
+        timesheets::read_timesheets,
+        timesheets::read_timesheet,
+        timesheets::submit_timesheet,
+        timesheets::approve_timesheet,
+        timesheets::reject_timesheet,
+        webhooks::create_webhook,
+        webhooks::read_webhooks,
+        webhooks::read_webhook,
+        webhooks::update_webhook,
+        webhooks::delete_webhook,
+        webhooks::read_deliveries,
+        webhooks::test_webhook,
+        auth::login,
+        auth::login_two_factor,
+        auth::logout,
+        auth::verify_email,
+        auth::resend_verification,
+        auth::request_password_reset,
+        auth::confirm_password_reset,
+        auth::refresh_token,
+        auth::setup_two_factor,
+        auth::enable_two_factor,
+        sessions::read_sessions,
+        sessions::revoke_session,
+        sessions::revoke_other_sessions,
+        keys::jwks,
+        keys::rotate_keys,
+        keys::read_api_keys,
+        keys::create_api_key,
+        keys::revoke_api_key,
+        admin::read_stats,
+        admin::read_audit_events,
+        admin::read_jobs,
+        admin::retry_job,
+        admin::run_maintenance,
+        health::read_metrics,
+        health::live,
+        health::ready,
+    ),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "users", description = "Accounts"),
+        (name = "entries", description = "Time tracking: entries, timers, projects and tags"),
+        (name = "reports", description = "Tracked time, summed up"),
+        (name = "invoices", description = "Billing tracked time"),
+        (name = "organizations", description = "Teams sharing projects"),
+        (name = "timesheets", description = "Weeks submitted for approval"),
+        (name = "webhooks", description = "Telling other services what happened"),
+        (name = "auth", description = "Logging in and out, sessions, verification and two-factor authentication"),
+        (name = "keys", description = "JWT signing keys and API keys"),
+        (name = "admin", description = "Operator tools"),
+        (name = "health", description = "Probes and metrics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Access tokens and API keys both go in `Authorization: Bearer`. Calendar
+/// feeds also take a token of their own in the query.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+        components.add_security_scheme("calendar_token", SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("token"))));
+    }
+}
+
+/// The spec at `/openapi.json` and Swagger UI over it at `/docs`.
+pub fn router() -> Router<AppState> {
+    SwaggerUi::new("/docs")
+        .url("/openapi.json", ApiDoc::openapi())
+        .into()
+}
@@ -0,0 +1,162 @@
+use sqlx::PgExecutor;
+
+use crate::error::AppError;
+use crate::models::MemberRole;
+
+// Every check of what someone may do in an organization, or with its
+// projects, comes through here. Handlers say what they're about to do and
+// leave the rules to `allows`.
+
+/// Something done in an organization.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// See it, its members and its projects.
+    View,
+    /// Rename it or change its settings.
+    Update,
+    /// Invite someone to join with the role.
+    Invite(MemberRole),
+    /// Take the role of a member from `from` to `to`.
+    ChangeRole { from: MemberRole, to: MemberRole },
+    /// Remove a member who has the role. Anyone may leave by themselves.
+    Remove(MemberRole),
+    /// Create, change and delete its projects.
+    ManageProjects,
+    /// See the time members tracked on its projects.
+    ViewReports,
+    /// Approve or reject members' timesheets.
+    ReviewTimesheets,
+}
+
+/// Whether a member with `role` may do `action`.
+pub fn allows(role: MemberRole, action: Action) -> bool {
+    let admin = matches!(role, MemberRole::Owner | MemberRole::Admin);
+    match action {
+        Action::View => true,
+        Action::Update | Action::ManageProjects | Action::ViewReports | Action::ReviewTimesheets => admin,
+        // Only owners make or unmake owners.
+        Action::Invite(target) | Action::Remove(target) => {
+            role == MemberRole::Owner || (admin && target != MemberRole::Owner)
+        }
+        Action::ChangeRole { from, to } => {
+            role == MemberRole::Owner || (admin && from != MemberRole::Owner && to != MemberRole::Owner)
+        }
+    }
+}
+
+/// The user's role in the organization, once `action` is allowed with it.
+/// Organizations the user isn't in are reported missing, so their ids
+/// don't leak.
+pub(crate) async fn authorize<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: i32,
+    organization_id: i32,
+    action: Action,
+) -> Result<MemberRole, AppError> {
+    let role = sqlx::query_scalar!(
+        r#"SELECT role AS "role: MemberRole" FROM organization_members WHERE organization_id = $1 AND user_id = $2"#,
+        organization_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .ok_or(AppError::NotFound("organization_not_found"))?;
+
+    if !allows(role, action) {
+        return Err(AppError::Forbidden("insufficient_role"));
+    }
+
+    Ok(role)
+}
+
+/// Checks `action` against a project: one of the user's own, which they
+/// may do anything with, or an organization's, which goes by their role
+/// there. Projects they can't see are reported missing.
+pub(crate) async fn authorize_project<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: i32,
+    project_id: i32,
+    action: Action,
+) -> Result<(), AppError> {
+    let project = sqlx::query!(
+        r#"SELECT p.user_id, p.organization_id, m.role AS "role?: MemberRole"
+        FROM projects p
+        LEFT JOIN organization_members m ON m.organization_id = p.organization_id AND m.user_id = $2
+        WHERE p.id = $1"#,
+        project_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    let allowed = project.and_then(|project| match project.organization_id {
+        None => (project.user_id == user_id).then_some(true),
+        Some(_) => project.role.map(|role| allows(role, action)),
+    });
+
+    match allowed {
+        None => Err(AppError::NotFound("project_not_found")),
+        Some(false) => Err(AppError::Forbidden("insufficient_role")),
+        Some(true) => Ok(()),
+    }
+}
+
+/// Checks the user may review `member_id`'s timesheets, which takes
+/// `ReviewTimesheets` in an organization they're both in. Nobody reviews
+/// their own. Members the user shares no organization with are reported
+/// missing.
+pub(crate) async fn authorize_review<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: i32,
+    member_id: i32,
+) -> Result<(), AppError> {
+    if user_id == member_id {
+        return Err(AppError::Forbidden("own_timesheet"));
+    }
+
+    let roles = sqlx::query_scalar!(
+        r#"SELECT mine.role AS "role: MemberRole"
+        FROM organization_members mine
+        JOIN organization_members theirs ON theirs.organization_id = mine.organization_id
+        WHERE mine.user_id = $1 AND theirs.user_id = $2"#,
+        user_id,
+        member_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    if roles.is_empty() {
+        return Err(AppError::NotFound("timesheet_not_found"));
+    }
+    if !roles.into_iter().any(|role| allows(role, Action::ReviewTimesheets)) {
+        return Err(AppError::Forbidden("insufficient_role"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() {
+        use MemberRole::*;
+
+        assert!([Owner, Admin, Member].into_iter().all(|role| allows(role, Action::View)));
+        assert!(allows(Admin, Action::ManageProjects));
+        assert!(!allows(Member, Action::ManageProjects));
+        assert!(!allows(Member, Action::ViewReports));
+        assert!(!allows(Member, Action::Invite(Member)));
+
+        assert!(allows(Admin, Action::Invite(Admin)));
+        assert!(!allows(Admin, Action::Invite(Owner)));
+        assert!(!allows(Admin, Action::Remove(Owner)));
+        assert!(allows(Owner, Action::Remove(Owner)));
+
+        assert!(allows(Admin, Action::ChangeRole { from: Member, to: Admin }));
+        assert!(!allows(Admin, Action::ChangeRole { from: Admin, to: Owner }));
+        assert!(!allows(Admin, Action::ChangeRole { from: Owner, to: Member }));
+        assert!(allows(Owner, Action::ChangeRole { from: Member, to: Owner }));
+    }
+}
@@ -0,0 +1,241 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::jobs::{JobError, Jobs};
+use crate::models::AccountExport;
+use crate::state::AppState;
+
+/// Accounts with more entries than this are exported in the background.
+pub const INLINE_EXPORT_MAX_ENTRIES: i64 = 10_000;
+/// How long a background export can be downloaded for.
+pub const EXPORT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+pub const EXPORT_JOB: &str = "account_export";
+
+// Query by query, `$1` is the user's id and the result one JSON value.
+
+const PROFILE_SQL: &str = "SELECT to_jsonb(u) FROM (
+    SELECT id, name, email, role, created_at, updated_at, verified_at, last_login_at, totp_enabled_at
+    FROM users WHERE id = $1 AND deleted_at IS NULL) u";
+
+const SETTINGS_SQL: &str = "SELECT to_jsonb(u) FROM (
+    SELECT timezone, week_start, auto_stop_timer, allow_overlap, pomodoro_work_secs, pomodoro_break_secs,
+        hourly_rate_cents, currency, tax_rate_bps, last_invoice_number
+    FROM users WHERE id = $1) u";
+
+/// The tables exported, and which of their rows are the user's.
+const EXPORTS: &[(&str, &str)] = &[
+    ("time_entries", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM time_entries t WHERE user_id = $1"),
+    ("time_entry_tags", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.entry_id, t.tag_id), '[]') FROM time_entry_tags t
+        JOIN time_entries e ON e.id = t.entry_id WHERE e.user_id = $1"),
+    ("tags", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM tags t WHERE user_id = $1"),
+    ("projects", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM projects t WHERE user_id = $1"),
+    ("timesheets", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM timesheets t WHERE user_id = $1"),
+    ("invoices", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM invoices t WHERE user_id = $1"),
+    ("invoice_lines", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM invoice_lines t
+        JOIN invoices i ON i.id = t.invoice_id WHERE i.user_id = $1"),
+    ("organization_members", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.organization_id), '[]')
+        FROM organization_members t WHERE user_id = $1"),
+    ("organization_former_members", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.organization_id), '[]')
+        FROM organization_former_members t WHERE user_id = $1"),
+    ("organization_invitations", "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'token_hash' ORDER BY t.id), '[]')
+        FROM organization_invitations t WHERE invited_by = $1"),
+    ("api_keys", "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'key_hash' ORDER BY t.id), '[]') FROM api_keys t WHERE user_id = $1"),
+    ("refresh_tokens", "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'token_hash' ORDER BY t.id), '[]') FROM refresh_tokens t WHERE user_id = $1"),
+    ("sessions", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM sessions t WHERE user_id = $1"),
+    ("webhooks", "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'secret' ORDER BY t.id), '[]') FROM webhooks t WHERE user_id = $1"),
+    ("webhook_deliveries", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM webhook_deliveries t
+        JOIN webhooks w ON w.id = t.webhook_id WHERE w.user_id = $1"),
+    ("login_attempts", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM login_attempts t WHERE user_id = $1"),
+    ("audit_events", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.id), '[]') FROM audit_events t
+        WHERE actor_user_id = $1 OR (target_type = 'user' AND target_id = $1)"),
+];
+
+/// One statement of an erasure: `$1` is the user's id, `$2` the email the
+/// account had.
+struct Erasure {
+    table: &'static str,
+    sql: &'static str,
+}
+
+// In order: entries that were invoiced or are on an approved timesheet stay,
+// since invoices and approvals count them, but lose what describes them.
+// Projects still under an entry stay too, nameless; nothing else of the
+// user's does. Audit events are append-only and stay as they are.
+const ERASURES: &[Erasure] = &[
+    Erasure {
+        table: "time_entries",
+        sql: "DELETE FROM time_entries WHERE user_id = $1 AND invoice_id IS NULL
+            AND (timesheet_id IS NULL OR timesheet_id NOT IN (SELECT id FROM timesheets WHERE state = 'approved'))",
+    },
+    Erasure {
+        table: "time_entries",
+        sql: "UPDATE time_entries SET description = '' WHERE user_id = $1",
+    },
+    Erasure {
+        table: "tags",
+        sql: "DELETE FROM tags WHERE user_id = $1",
+    },
+    Erasure {
+        table: "projects",
+        sql: "DELETE FROM projects WHERE user_id = $1 AND organization_id IS NULL
+            AND NOT EXISTS (SELECT 1 FROM time_entries WHERE project_id = projects.id)",
+    },
+    Erasure {
+        table: "projects",
+        sql: "UPDATE projects SET name = 'Erased project', archived = TRUE WHERE user_id = $1 AND organization_id IS NULL",
+    },
+    Erasure {
+        table: "timesheets",
+        sql: "DELETE FROM timesheets WHERE user_id = $1 AND state <> 'approved'",
+    },
+    Erasure {
+        table: "organization_members",
+        sql: "DELETE FROM organization_members WHERE user_id = $1",
+    },
+    Erasure {
+        table: "organization_former_members",
+        sql: "DELETE FROM organization_former_members WHERE user_id = $1",
+    },
+    // Invitations they sent are the organization's and stay open.
+    Erasure {
+        table: "organization_invitations",
+        sql: "DELETE FROM organization_invitations WHERE email = $2",
+    },
+    Erasure {
+        table: "api_keys",
+        sql: "DELETE FROM api_keys WHERE user_id = $1",
+    },
+    Erasure {
+        table: "refresh_tokens",
+        sql: "DELETE FROM refresh_tokens WHERE user_id = $1",
+    },
+    Erasure {
+        table: "sessions",
+        sql: "DELETE FROM sessions WHERE user_id = $1",
+    },
+    Erasure {
+        table: "password_resets",
+        sql: "DELETE FROM password_resets WHERE user_id = $1",
+    },
+    Erasure {
+        table: "email_verifications",
+        sql: "DELETE FROM email_verifications WHERE user_id = $1",
+    },
+    Erasure {
+        table: "recovery_codes",
+        sql: "DELETE FROM recovery_codes WHERE user_id = $1",
+    },
+    Erasure {
+        table: "avatars",
+        sql: "DELETE FROM avatars WHERE user_id = $1",
+    },
+    Erasure {
+        table: "webhooks",
+        sql: "DELETE FROM webhooks WHERE user_id = $1",
+    },
+    Erasure {
+        table: "login_attempts",
+        sql: "DELETE FROM login_attempts WHERE user_id = $1",
+    },
+    Erasure {
+        table: "account_exports",
+        sql: "DELETE FROM account_exports WHERE user_id = $1",
+    },
+    // Deleted too, so API keys and lookups by email pass it over, but marked
+    // erased so it's never restored or purged.
+    Erasure {
+        table: "users",
+        sql: "UPDATE users SET name = 'Erased user', email = 'erased-' || id || '@erased.invalid', password_hash = '',
+            verified_at = NULL, last_login_at = NULL, totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL,
+            calendar_token_hash = NULL, role = 'user', deleted_at = now(), erased_at = now()
+        WHERE id = $1",
+    },
+];
+
+/// Every table an export has rows from.
+pub fn exported_tables() -> impl Iterator<Item = &'static str> {
+    EXPORTS.iter().map(|(table, _)| *table)
+}
+
+/// Every table an erasure deletes or anonymizes rows in.
+pub fn erased_tables() -> impl Iterator<Item = &'static str> {
+    ERASURES.iter().map(|erasure| erasure.table)
+}
+
+/// Everything kept about a live user, read from one snapshot. `None` when
+/// there's no such user.
+pub async fn build(pool: &sqlx::PgPool, user_id: i32) -> Result<Option<AccountExport>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let query = |sql| sqlx::query_scalar::<_, Option<Value>>(sql).bind(user_id);
+    let Some(profile) = query(PROFILE_SQL).fetch_one(&mut *tx).await? else {
+        return Ok(None);
+    };
+    let settings = query(SETTINGS_SQL).fetch_one(&mut *tx).await?.unwrap_or_default();
+
+    let mut tables = BTreeMap::new();
+    for (table, sql) in EXPORTS {
+        let rows = query(sql).fetch_one(&mut *tx).await?.unwrap_or_default();
+        tables.insert(table.to_string(), rows);
+    }
+    tx.commit().await?;
+
+    Ok(Some(AccountExport {
+        exported_at: Utc::now(),
+        profile,
+        settings,
+        tables,
+    }))
+}
+
+/// Erases the user, whose email is still `email`, inside the caller's
+/// transaction.
+pub async fn erase(conn: &mut sqlx::PgConnection, user_id: i32, email: &str) -> Result<(), sqlx::Error> {
+    for erasure in ERASURES {
+        sqlx::query(erasure.sql).bind(user_id).bind(email).execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportJob {
+    pub export_id: i32,
+}
+
+/// Handles [`EXPORT_JOB`]s, which fill in the `account_exports` row they
+/// were enqueued for.
+pub fn register(jobs: &Jobs) {
+    jobs.register(EXPORT_JOB, |state: AppState, payload: Value| async move {
+        let job: ExportJob = serde_json::from_value(payload)?;
+
+        let user_id = sqlx::query_scalar!(
+            "SELECT user_id FROM account_exports WHERE id = $1 AND data IS NULL",
+            job.export_id
+        )
+        .fetch_optional(&state.pool)
+        .await?;
+        // Already built, or gone with the account.
+        let Some(user_id) = user_id else {
+            return Ok::<_, JobError>(());
+        };
+        let Some(export) = build(&state.pool, user_id).await? else {
+            return Ok(());
+        };
+
+        sqlx::query!(
+            "UPDATE account_exports SET data = $2, completed_at = now() WHERE id = $1",
+            job.export_id,
+            serde_json::to_value(export)?
+        )
+        .execute(&state.pool)
+        .await?;
+
+        Ok(())
+    });
+}
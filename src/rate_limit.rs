@@ -0,0 +1,186 @@
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Past this many tracked keys, expired windows are swept on the next hit.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Counts hits per key in fixed windows and refuses keys that go over the
+/// limit until their window ends. Backends deal with their own errors: one
+/// that can't be reached lets every hit through.
+pub trait RateLimiter: Send + Sync {
+    /// Records a hit for `key`. Over the limit, returns how long until the
+    /// key is allowed again.
+    fn hit<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Duration>>;
+    fn reset<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()>;
+}
+
+/// Limits kept by this instance alone, lost on restart.
+pub struct MemoryRateLimiter {
+    max_hits: u32,
+    window: Duration,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+struct Window {
+    started: Instant,
+    hits: u32,
+}
+
+impl MemoryRateLimiter {
+    pub fn new(max_hits: u32, window: Duration) -> Self {
+        MemoryRateLimiter {
+            max_hits,
+            window,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn hit_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut windows = self.windows.lock().unwrap();
+
+        if windows.len() > SWEEP_THRESHOLD {
+            windows.retain(|_, window| now < window.started + self.window);
+        }
+
+        let window = windows.entry(key.to_string()).or_insert(Window { started: now, hits: 0 });
+
+        if now >= window.started + self.window {
+            *window = Window { started: now, hits: 0 };
+        }
+
+        if window.hits >= self.max_hits {
+            return Err(window.started + self.window - now);
+        }
+
+        window.hits += 1;
+        Ok(())
+    }
+}
+
+impl RateLimiter for MemoryRateLimiter {
+    fn hit<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Duration>> {
+        let result = self.hit_at(key, Instant::now());
+        Box::pin(async move { result })
+    }
+
+    fn reset<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+        self.windows.lock().unwrap().remove(key);
+        Box::pin(async {})
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisRateLimiter;
+
+#[cfg(feature = "redis")]
+mod redis {
+    use futures::future::BoxFuture;
+    use redis::aio::ConnectionManager;
+    use redis::{AsyncCommands, Script};
+    use std::sync::{Arc, LazyLock};
+    use std::time::Duration;
+
+    use super::RateLimiter;
+    use crate::metrics::Metrics;
+
+    const PREFIX: &str = "tictoc:rate:";
+
+    // One round trip that counts the hit and reads the window together, so
+    // instances hitting the same key can't both be let through on the last
+    // hit. The window starts with its first hit; a key left without an
+    // expiry, say by a crash between the two calls, gets one here. Returns
+    // zero when allowed, or else the milliseconds left.
+    static HIT: LazyLock<Script> = LazyLock::new(|| {
+        Script::new(
+            r"
+            local hits = redis.call('INCR', KEYS[1])
+            local ttl = redis.call('PTTL', KEYS[1])
+            if ttl < 0 then
+                ttl = tonumber(ARGV[1])
+                redis.call('PEXPIRE', KEYS[1], ttl)
+            end
+            if hits > tonumber(ARGV[2]) then
+                return math.max(ttl, 1)
+            end
+            return 0
+            ",
+        )
+    });
+
+    /// Limits shared by every instance using the same Redis.
+    pub struct RedisRateLimiter {
+        connection: ConnectionManager,
+        max_hits: u32,
+        window: Duration,
+        metrics: Arc<Metrics>,
+    }
+
+    impl RedisRateLimiter {
+        /// Counts `rate_limit_errors_total` on `metrics` whenever Redis
+        /// can't be reached and a hit is let through.
+        pub fn new(connection: ConnectionManager, max_hits: u32, window: Duration, metrics: Arc<Metrics>) -> Self {
+            RedisRateLimiter { connection, max_hits, window, metrics }
+        }
+    }
+
+    impl RateLimiter for RedisRateLimiter {
+        fn hit<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Duration>> {
+            Box::pin(async move {
+                let mut connection = self.connection.clone();
+                let result: Result<u64, _> = HIT
+                    .key(format!("{}{}", PREFIX, key))
+                    .arg(self.window.as_millis().max(1) as u64)
+                    .arg(self.max_hits)
+                    .invoke_async(&mut connection)
+                    .await;
+
+                match result {
+                    Ok(0) => Ok(()),
+                    Ok(millis) => Err(Duration::from_millis(millis)),
+                    Err(err) => {
+                        self.metrics.record_rate_limit_error();
+                        tracing::error!(key, error = %err, "could not check a rate limit, letting it through");
+                        Ok(())
+                    }
+                }
+            })
+        }
+
+        fn reset<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+            Box::pin(async move {
+                let mut connection = self.connection.clone();
+                if let Err(err) = connection.del::<_, ()>(format!("{}{}", PREFIX, key)).await {
+                    self.metrics.record_rate_limit_error();
+                    tracing::error!(key, error = %err, "could not reset a rate limit");
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_decays() {
+        let limiter = MemoryRateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert_eq!(limiter.hit_at("key", start), Ok(()));
+        assert_eq!(limiter.hit_at("key", start), Ok(()));
+        assert_eq!(
+            limiter.hit_at("key", start + Duration::from_secs(15)),
+            Err(Duration::from_secs(45))
+        );
+        assert_eq!(limiter.hit_at("other", start), Ok(()));
+
+        assert_eq!(limiter.hit_at("key", start + Duration::from_secs(60)), Ok(()));
+
+        futures::executor::block_on(limiter.reset("other"));
+        assert_eq!(limiter.hit_at("other", start), Ok(()));
+        assert_eq!(limiter.hit_at("other", start), Ok(()));
+    }
+}
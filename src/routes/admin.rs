@@ -0,0 +1,284 @@
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+
+use crate::auth::AdminUser;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::maintenance;
+use crate::models::{AuditEvent, AuditQuery, Job, JobState, JobsQuery, MaintenanceReport, Page, PoolStats, StatsResponse, UserCounts};
+use crate::routes::{invalid_query, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/stats", get(read_stats))
+        .route("/admin/audit", get(read_audit_events))
+        .route("/admin/jobs", get(read_jobs))
+        .route("/admin/jobs/{id}/retry", post(retry_job))
+        .route("/admin/maintenance/run", post(run_maintenance))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    tag = "admin",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Totals for dashboards", body = StatsResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+    )
+)]
+async fn read_stats(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> Result<Json<StatsResponse>, AppError> {
+    let now = state.clock.now();
+
+    let counts = sqlx::query!(
+        r#"SELECT
+            count(*) AS "total!",
+            count(*) FILTER (WHERE created_at > $1::timestamptz - interval '1 day') AS "created_last_24h!",
+            count(*) FILTER (WHERE created_at > $1::timestamptz - interval '7 days') AS "created_last_7d!",
+            count(*) FILTER (WHERE created_at > $1::timestamptz - interval '30 days') AS "created_last_30d!",
+            (SELECT count(*) FROM login_attempts WHERE succeeded AND attempted_at > $1::timestamptz - interval '1 day') AS "logins_last_24h!"
+        FROM users WHERE deleted_at IS NULL"#,
+        now
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Json(StatsResponse {
+        users: UserCounts {
+            total: counts.total,
+            created_last_24h: counts.created_last_24h,
+            created_last_7d: counts.created_last_7d,
+            created_last_30d: counts.created_last_30d,
+        },
+        logins_last_24h: counts.logins_last_24h,
+        pool: PoolStats {
+            size: state.pool.size(),
+            idle: state.pool.num_idle() as u32,
+            max: state.pool.options().get_max_connections(),
+        },
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(AuditQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Matching events, newest first", body = Page<AuditEvent>),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+    )
+)]
+async fn read_audit_events(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    query: Result<Query<AuditQuery>, QueryRejection>,
+) -> Result<Json<Page<AuditEvent>>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(invalid_query("limit", &format!("must be between 1 and {}", MAX_PAGE_LIMIT)));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(invalid_query("offset", "must not be negative"));
+    }
+
+    let items = sqlx::query_as!(
+        AuditEvent,
+        r#"SELECT id, actor_user_id, event_type AS "event_type: _", target_type, target_id, ip, user_agent,
+            created_at, details
+        FROM audit_events
+        WHERE ($1::text IS NULL OR event_type = $1)
+            AND ($2::int IS NULL OR actor_user_id = $2)
+            AND ($3::timestamptz IS NULL OR created_at >= $3)
+            AND ($4::timestamptz IS NULL OR created_at < $4)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $5 OFFSET $6"#,
+        query.event_type as _,
+        query.actor,
+        query.from,
+        query.to,
+        limit,
+        offset
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "total!" FROM audit_events
+        WHERE ($1::text IS NULL OR event_type = $1)
+            AND ($2::int IS NULL OR actor_user_id = $2)
+            AND ($3::timestamptz IS NULL OR created_at >= $3)
+            AND ($4::timestamptz IS NULL OR created_at < $4)"#,
+        query.event_type as _,
+        query.actor,
+        query.from,
+        query.to
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    // Ids don't follow created_at closely enough to page by.
+    Ok(Json(Page {
+        items,
+        total,
+        limit,
+        offset,
+        next_cursor: None,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/jobs",
+    tag = "admin",
+    params(JobsQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Matching background jobs, oldest first. Jobs that succeeded are gone.",
+            body = Page<Job>),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+    )
+)]
+async fn read_jobs(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    query: Result<Query<JobsQuery>, QueryRejection>,
+) -> Result<Json<Page<Job>>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(invalid_query("limit", &format!("must be between 1 and {}", MAX_PAGE_LIMIT)));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(invalid_query("offset", "must not be negative"));
+    }
+
+    let items = sqlx::query_as!(
+        Job,
+        r#"SELECT id, kind, payload, state AS "state!: JobState", run_at, attempts, last_error, locked_by, locked_at,
+            failed_at, created_at
+        FROM (
+            SELECT *, CASE WHEN failed_at IS NOT NULL THEN 'failed' WHEN locked_at IS NOT NULL THEN 'running' ELSE 'pending' END
+                AS state
+            FROM jobs
+        ) jobs
+        WHERE $1::text IS NULL OR state = $1
+        ORDER BY run_at, id
+        LIMIT $2 OFFSET $3"#,
+        query.state as Option<JobState>,
+        limit,
+        offset
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "total!" FROM jobs
+        WHERE $1::text IS NULL
+            OR $1 = CASE WHEN failed_at IS NOT NULL THEN 'failed' WHEN locked_at IS NOT NULL THEN 'running' ELSE 'pending' END"#,
+        query.state as Option<JobState>
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Json(Page {
+        items,
+        total,
+        limit,
+        offset,
+        next_cursor: None,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/retry",
+    tag = "admin",
+    params(("id" = i64, Path, description = "Job id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The job, pending again with its attempts reset", body = Job),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 404, description = "No such job", body = ErrorResponse),
+        (status = 409, description = "The job hasn't failed", body = ErrorResponse),
+    )
+)]
+async fn retry_job(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    id: Result<Path<i64>, PathRejection>,
+) -> Result<Json<Job>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    // The last error stays until an attempt replaces it.
+    let job = sqlx::query_as!(
+        Job,
+        r#"UPDATE jobs SET failed_at = NULL, attempts = 0, run_at = now()
+        WHERE id = $1 AND failed_at IS NOT NULL
+        RETURNING id, kind, payload, 'pending' AS "state!: JobState", run_at, attempts, last_error, locked_by, locked_at,
+            failed_at, created_at"#,
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    match job {
+        Some(job) => Ok(Json(job)),
+        None => {
+            let exists = sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM jobs WHERE id = $1) AS "exists!""#, id)
+                .fetch_one(&state.pool)
+                .await?;
+            Err(if exists { AppError::Conflict("job_not_failed") } else { AppError::NotFound("job_not_found") })
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/maintenance/run",
+    tag = "admin",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Expired rows purged now rather than at the next scheduled run",
+            body = MaintenanceReport),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+    )
+)]
+async fn run_maintenance(State(state): State<AppState>, _admin: AdminUser) -> Result<Json<MaintenanceReport>, AppError> {
+    let purged = maintenance::run(&state).await?;
+
+    Ok(Json(MaintenanceReport { purged }))
+}
@@ -0,0 +1,793 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::post,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::Postgres;
+use std::net::IpAddr;
+
+use crate::audit::{self, Event};
+use crate::auth::tokens::{Session, TWO_FACTOR_PURPOSE, challenge_token, hash_token, issue_tokens, random_token};
+use crate::auth::{AuthUser, totp};
+use crate::cache;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse, rate_limited};
+use crate::extract::{ClientIp, JsonBody, UserAgent};
+use crate::mail::templates::{self, Locale, PasswordReset, Verification};
+use crate::models::{AuditEventType, ChallengeClaims, LoginResponse, LoginUserRequest, LoginUserResponse, PasswordResetConfirmRequest, PasswordResetRequest, RecoveryCodesResponse, RefreshTokenRequest, Role, TwoFactorChallenge, TwoFactorCodeRequest, TwoFactorLoginRequest, TwoFactorSetupResponse, User, VerifyEmailRequest};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+
+pub const MAX_FAILED_LOGINS: i32 = 10;
+pub const LOCKOUT_SECS: i64 = 15 * 60;
+pub const TWO_FACTOR_ISSUER: &str = "tictoc";
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/users/login", post(login))
+        .route("/users/login/2fa", post(login_two_factor))
+        .route("/users/logout", post(logout))
+        .route("/users/verify", post(verify_email))
+        .route("/users/verify/resend", post(resend_verification))
+        .route("/users/password-reset/request", post(request_password_reset))
+        .route("/users/password-reset/confirm", post(confirm_password_reset))
+        .route("/token/refresh", post(refresh_token))
+        .route("/me/2fa/setup", post(setup_two_factor))
+        .route("/me/2fa/enable", post(enable_two_factor))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/password-reset/request",
+    tag = "auth",
+    request_body = PasswordResetRequest,
+    responses(
+        (status = 202, description = "A reset token is emailed if the account exists"),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn request_password_reset(
+    State(state): State<AppState>,
+    JsonBody(mut payload): JsonBody<PasswordResetRequest>,
+) -> Result<StatusCode, AppError> {
+    payload.validate()?;
+
+    let user = sqlx::query!(
+        "SELECT id, name FROM users WHERE email = $1 AND deleted_at IS NULL",
+        payload.email
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    // The response is the same either way, so it can't be used to find out
+    // which emails have accounts.
+    if let Some(user) = user {
+        let token = random_token();
+
+        sqlx::query!(
+            "INSERT INTO password_resets (token_hash, user_id, expires_at) VALUES ($1, $2, now() + make_interval(secs => $3))",
+            hash_token(&token),
+            user.id,
+            state.reset_ttl_secs as f64
+        )
+        .execute(&state.pool)
+        .await?;
+
+        let email = PasswordReset {
+            name: user.name,
+            link: format!("{}/reset-password?token={}", state.app_url, token),
+            token,
+            expires_in_secs: state.reset_ttl_secs,
+        };
+        templates::send(&*state.mailer, &payload.email, Locale::En, &email);
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/password-reset/confirm",
+    tag = "auth",
+    request_body = PasswordResetConfirmRequest,
+    responses(
+        (status = 204, description = "Password reset; every session is signed out"),
+        (status = 400, description = "Invalid, used or expired token", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn confirm_password_reset(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(mut payload): JsonBody<PasswordResetConfirmRequest>,
+) -> Result<StatusCode, AppError> {
+    payload.validate()?;
+    state.check_breached("new_password", &payload.new_password).await?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let reset = sqlx::query!(
+        r#"SELECT password_resets.user_id, expires_at > now() AS "live!", used_at IS NOT NULL AS "used!"
+        FROM password_resets JOIN users ON users.id = password_resets.user_id
+        WHERE token_hash = $1 AND users.deleted_at IS NULL
+        FOR UPDATE OF password_resets"#,
+        hash_token(&payload.token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::BadRequest("invalid_reset_token"))?;
+
+    if reset.used {
+        return Err(AppError::BadRequest("reset_token_used"));
+    }
+    if !reset.live {
+        return Err(AppError::BadRequest("reset_token_expired"));
+    }
+
+    let password_hash = state.passwords.hash(payload.new_password).await?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        reset.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Every other reset link and session for the account dies with this one.
+    sqlx::query!(
+        "UPDATE password_resets SET used_at = now() WHERE user_id = $1 AND used_at IS NULL",
+        reset.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("UPDATE refresh_tokens SET revoked = TRUE WHERE user_id = $1", reset.user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::PasswordChanged,
+        actor: Some(reset.user_id),
+        target: Some(("user", reset.user_id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({ "via": "reset" }),
+    })
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stores a new email verification token for the user and returns it.
+pub(crate) async fn create_verification(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    state: &AppState,
+    user_id: i32,
+) -> Result<String, AppError> {
+    let token = random_token();
+
+    sqlx::query!(
+        "INSERT INTO email_verifications (token_hash, user_id, expires_at) VALUES ($1, $2, now() + make_interval(secs => $3))",
+        hash_token(&token),
+        user_id,
+        state.verification_ttl_secs as f64
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(token)
+}
+
+pub(crate) fn send_verification(state: &AppState, name: &str, email: &str, token: &str) {
+    let verification = Verification {
+        name: name.to_string(),
+        link: format!("{}/verify-email?token={}", state.app_url, token),
+        token: token.to_string(),
+        expires_in_secs: state.verification_ttl_secs,
+    };
+    templates::send(&*state.mailer, email, Locale::En, &verification);
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/verify",
+    tag = "auth",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 204, description = "Email verified"),
+        (status = 400, description = "Invalid, used or expired token", body = ErrorResponse),
+    )
+)]
+async fn verify_email(
+    State(state): State<AppState>,
+    JsonBody(payload): JsonBody<VerifyEmailRequest>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let verification = sqlx::query!(
+        r#"SELECT user_id, expires_at > now() AS "live!", used_at IS NOT NULL AS "used!"
+        FROM email_verifications WHERE token_hash = $1
+        FOR UPDATE"#,
+        hash_token(&payload.token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::BadRequest("invalid_verification_token"))?;
+
+    if verification.used {
+        return Err(AppError::BadRequest("verification_token_used"));
+    }
+    if !verification.live {
+        return Err(AppError::BadRequest("verification_token_expired"));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET verified_at = COALESCE(verified_at, now()) WHERE id = $1",
+        verification.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE email_verifications SET used_at = now() WHERE user_id = $1 AND used_at IS NULL",
+        verification.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/verify/resend",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 202, description = "A new verification token is emailed"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 409, description = "Email already verified", body = ErrorResponse),
+        (status = 429, description = "Sent too recently; see Retry-After", body = ErrorResponse),
+    )
+)]
+async fn resend_verification(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    // Locking the user row keeps concurrent resends from both getting past
+    // the cooldown check.
+    let user = sqlx::query!(
+        r#"SELECT name, email, verified_at IS NOT NULL AS "verified!",
+            (SELECT max(created_at) FROM email_verifications WHERE user_id = users.id) AS last_sent_at
+        FROM users WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE"#,
+        auth.id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+
+    if user.verified {
+        return Err(AppError::Conflict("email_already_verified"));
+    }
+
+    if let Some(last_sent_at) = user.last_sent_at {
+        let elapsed = (Utc::now() - last_sent_at).num_seconds().max(0) as u64;
+        if elapsed < state.verification_resend_secs {
+            return Err(AppError::TooManyRequests {
+                retry_after_secs: state.verification_resend_secs - elapsed,
+            });
+        }
+    }
+
+    let token = create_verification(&mut tx, &state, auth.id).await?;
+
+    tx.commit().await?;
+
+    send_verification(&state, &user.name, &user.email, &token);
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/login",
+    tag = "auth",
+    request_body = LoginUserRequest,
+    responses(
+        (status = 200, description = "Tokens, or a challenge when two-factor authentication is on", body = LoginResponse),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Wrong email or password", body = ErrorResponse),
+        (status = 403, description = "Email not verified", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+        (status = 423, description = "Account locked after too many failures", body = ErrorResponse),
+        (status = 429, description = "Too many attempts; see Retry-After", body = ErrorResponse),
+    )
+)]
+async fn login(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(mut payload): JsonBody<LoginUserRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    payload.validate()?;
+
+    let ip_key = match ip {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    };
+    let account_key = format!("email:{}", payload.email);
+
+    state.login_limiter.hit(&ip_key).await.map_err(rate_limited)?;
+    state.login_limiter.hit(&account_key).await.map_err(rate_limited)?;
+
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, name, email, password_hash, verified_at IS NOT NULL AS "verified!", locked_until,
+            role AS "role: Role", totp_enabled_at IS NOT NULL AS "two_factor!"
+        FROM users WHERE email = $1 AND deleted_at IS NULL"#,
+        payload.email
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    match user {
+        Some(user) => {
+            let now = state.clock.now();
+
+            // Even the right password is turned away until the lock runs out.
+            if user.locked_until.is_some_and(|until| until > now) {
+                record_login_attempt(&state, user.id, ip, false, now).await?;
+                audit_login(&state, Err("locked"), Some(user.id), ip, user_agent.as_deref()).await?;
+                return Err(AppError::Locked);
+            }
+
+            let verified = state
+                .passwords
+                .verify(payload.password.clone(), user.password_hash.clone())
+                .await?;
+            record_login_attempt(&state, user.id, ip, verified, now).await?;
+
+            if !verified {
+                sqlx::query!(
+                    "UPDATE users SET failed_logins = failed_logins + 1,
+                        locked_until = CASE WHEN failed_logins + 1 >= $2 THEN $3 ELSE locked_until END
+                    WHERE id = $1",
+                    user.id,
+                    MAX_FAILED_LOGINS,
+                    now + chrono::Duration::seconds(LOCKOUT_SECS)
+                )
+                .execute(&state.pool)
+                .await?;
+
+                audit_login(&state, Err("wrong_password"), Some(user.id), ip, user_agent.as_deref()).await?;
+                return Err(AppError::Unauthorized);
+            }
+
+            sqlx::query!(
+                "UPDATE users SET failed_logins = 0, locked_until = NULL, last_login_at = $2 WHERE id = $1",
+                user.id,
+                now
+            )
+            .execute(&state.pool)
+            .await?;
+            state.cache.delete(&cache::me_key(user.id)).await;
+
+            // Hashes made under older settings are upgraded while the
+            // plaintext is at hand.
+            if state.passwords.needs_rehash(&user.password_hash) {
+                let password_hash = state.passwords.hash(payload.password).await?;
+
+                sqlx::query!(
+                    "UPDATE users SET password_hash = $1 WHERE id = $2",
+                    password_hash,
+                    user.id
+                )
+                .execute(&state.pool)
+                .await?;
+            }
+
+            state.login_limiter.reset(&account_key).await;
+
+            if state.require_verified_email && !user.verified {
+                audit_login(&state, Err("email_not_verified"), Some(user.id), ip, user_agent.as_deref()).await?;
+                return Err(AppError::Forbidden("email_not_verified"));
+            }
+
+            if user.two_factor {
+                return Ok(Json(LoginResponse::TwoFactor(TwoFactorChallenge {
+                    two_factor_required: true,
+                    challenge_token: challenge_token(&state, user.id)?,
+                })));
+            }
+
+            let session = Session::New { ip, user_agent: user_agent.as_deref() };
+            let tokens = issue_tokens(&state, session, user.id, user.email, user.role, user.verified).await?;
+            audit_login(&state, Ok(()), Some(user.id), ip, user_agent.as_deref()).await?;
+
+            Ok(Json(LoginResponse::Tokens(tokens)))
+        }
+        None => {
+            state.passwords.verify_dummy(payload.password).await?;
+            audit::record(&state.pool, Event {
+                event_type: AuditEventType::LoginFailed,
+                actor: None,
+                target: None,
+                ip,
+                user_agent: user_agent.as_deref(),
+                details: json!({ "reason": "unknown_email", "email": payload.email }),
+            })
+            .await?;
+            Err(AppError::Unauthorized)
+        }
+    }
+}
+
+/// A finished login, `Ok` once tokens are handed out, or why it was turned
+/// away.
+async fn audit_login(
+    state: &AppState,
+    outcome: Result<(), &str>,
+    user_id: Option<i32>,
+    ip: Option<IpAddr>,
+    user_agent: Option<&str>,
+) -> Result<(), AppError> {
+    let (event_type, details) = match outcome {
+        Ok(()) => (AuditEventType::LoginSucceeded, json!({})),
+        Err(reason) => (AuditEventType::LoginFailed, json!({ "reason": reason })),
+    };
+
+    audit::record(&state.pool, Event {
+        event_type,
+        actor: user_id.filter(|_| outcome.is_ok()),
+        target: user_id.map(|id| ("user", id)),
+        ip,
+        user_agent,
+        details,
+    })
+    .await
+}
+
+async fn record_login_attempt(
+    state: &AppState,
+    user_id: i32,
+    ip: Option<IpAddr>,
+    succeeded: bool,
+    attempted_at: DateTime<Utc>,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "INSERT INTO login_attempts (user_id, ip, succeeded, attempted_at) VALUES ($1, $2, $3, $4)",
+        user_id,
+        ip.map(|ip| ip.to_string()),
+        succeeded,
+        attempted_at
+    )
+    .execute(&state.pool)
+    .await?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/login/2fa",
+    tag = "auth",
+    request_body = TwoFactorLoginRequest,
+    responses(
+        (status = 200, description = "Tokens", body = LoginUserResponse),
+        (status = 400, description = "Neither a code nor a recovery code given", body = ErrorResponse),
+        (status = 401, description = "Invalid challenge token or code", body = ErrorResponse),
+        (status = 429, description = "Too many attempts; see Retry-After", body = ErrorResponse),
+    )
+)]
+async fn login_two_factor(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(payload): JsonBody<TwoFactorLoginRequest>,
+) -> Result<Json<LoginUserResponse>, AppError> {
+    let claims = state.jwt.decode::<ChallengeClaims>(&payload.challenge_token)
+        .ok()
+        .filter(|data| data.claims.purpose == TWO_FACTOR_PURPOSE)
+        .ok_or(AppError::InvalidToken("invalid_challenge_token"))?
+        .claims;
+
+    let user_id: i32 = claims
+        .sub
+        .parse()
+        .map_err(|_| AppError::InvalidToken("invalid_challenge_token"))?;
+
+    // Six digits don't take long to brute force without this.
+    let limiter_key = format!("2fa:{}", user_id);
+    state.login_limiter.hit(&limiter_key).await.map_err(rate_limited)?;
+
+    let user = sqlx::query!(
+        r#"SELECT email, role AS "role: Role", verified_at IS NOT NULL AS "verified!", totp_secret, totp_last_step
+        FROM users WHERE id = $1 AND deleted_at IS NULL AND totp_enabled_at IS NOT NULL"#,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::InvalidToken("invalid_challenge_token"))?;
+
+    let accepted = match (payload.code, payload.recovery_code) {
+        (Some(code), _) => {
+            let secret = user.totp_secret.unwrap_or_default();
+            let now = state.clock.now().timestamp() as u64;
+            let last_step = user.totp_last_step.map(|step| step as u64);
+
+            match totp::verify(&secret, code.trim(), now, last_step) {
+                // Conditional, so two requests racing with the same code
+                // can't both get in.
+                Some(step) => sqlx::query!(
+                    "UPDATE users SET totp_last_step = $1
+                    WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)",
+                    step as i64,
+                    user_id
+                )
+                .execute(&state.pool)
+                .await?
+                .rows_affected() == 1,
+                None => false,
+            }
+        }
+        (None, Some(recovery_code)) => sqlx::query!(
+            "UPDATE recovery_codes SET used_at = now()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+            user_id,
+            hash_token(&recovery_code.trim().to_lowercase())
+        )
+        .execute(&state.pool)
+        .await?
+        .rows_affected() == 1,
+        (None, None) => return Err(AppError::BadRequest("code_required")),
+    };
+
+    if !accepted {
+        audit_login(&state, Err("invalid_two_factor_code"), Some(user_id), ip, user_agent.as_deref()).await?;
+        return Err(AppError::InvalidToken("invalid_two_factor_code"));
+    }
+
+    state.login_limiter.reset(&limiter_key).await;
+
+    let session = Session::New { ip, user_agent: user_agent.as_deref() };
+    let tokens = issue_tokens(&state, session, user_id, user.email, user.role, user.verified).await?;
+    audit_login(&state, Ok(()), Some(user_id), ip, user_agent.as_deref()).await?;
+
+    Ok(Json(tokens))
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/2fa/setup",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "A pending secret to confirm with /me/2fa/enable", body = TwoFactorSetupResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication already on", body = ErrorResponse),
+    )
+)]
+async fn setup_two_factor(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<TwoFactorSetupResponse>, AppError> {
+    let secret = totp::generate_secret();
+
+    // Until it's confirmed with a code, the secret just sits there pending
+    // and calling this again replaces it.
+    let user = sqlx::query!(
+        "UPDATE users SET totp_secret = $1, totp_last_step = NULL
+        WHERE id = $2 AND deleted_at IS NULL AND totp_enabled_at IS NULL
+        RETURNING email",
+        secret,
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::Conflict("two_factor_already_enabled"))?;
+
+    Ok(Json(TwoFactorSetupResponse {
+        secret: totp::base32(&secret),
+        otpauth_uri: totp::otpauth_uri(TWO_FACTOR_ISSUER, &user.email, &secret),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/2fa/enable",
+    tag = "auth",
+    request_body = TwoFactorCodeRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Two-factor authentication on; the recovery codes are only shown once", body = RecoveryCodesResponse),
+        (status = 400, description = "Not set up or wrong code", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication already on", body = ErrorResponse),
+    )
+)]
+async fn enable_two_factor(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(payload): JsonBody<TwoFactorCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let user = sqlx::query!(
+        r#"SELECT totp_secret, totp_enabled_at IS NOT NULL AS "enabled!"
+        FROM users WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE"#,
+        auth.id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+
+    if user.enabled {
+        return Err(AppError::Conflict("two_factor_already_enabled"));
+    }
+
+    let secret = user
+        .totp_secret
+        .ok_or(AppError::BadRequest("two_factor_not_set_up"))?;
+
+    let step = totp::verify(&secret, payload.code.trim(), state.clock.now().timestamp() as u64, None)
+        .ok_or(AppError::BadRequest("invalid_two_factor_code"))?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_enabled_at = now(), totp_last_step = $1 WHERE id = $2",
+        step as i64,
+        auth.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", auth.id)
+        .execute(&mut *tx)
+        .await?;
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| random_token()[..10].to_string())
+        .collect();
+
+    for code in &recovery_codes {
+        sqlx::query!(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)",
+            auth.id,
+            hash_token(code)
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/logout",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 400, description = "API keys are revoked through /api-keys instead", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn logout(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+) -> Result<StatusCode, AppError> {
+    if auth.api_key_id.is_some() {
+        return Err(AppError::BadRequest("api_key_not_a_session"));
+    }
+
+    // Entries are only needed until the token would have expired anyway.
+    sqlx::query!("DELETE FROM revoked_tokens WHERE expires_at < now()")
+        .execute(&state.pool)
+        .await?;
+
+    let mut tx = state.pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, to_timestamp($2)) ON CONFLICT (jti) DO NOTHING",
+        auth.jti,
+        auth.exp as f64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::TokenRevoked,
+        actor: Some(auth.id),
+        target: Some(("user", auth.id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({ "token": "access", "jti": auth.jti }),
+    })
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/token/refresh",
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "A new token pair; the old refresh token stops working", body = LoginUserResponse),
+        (status = 401, description = "Invalid, expired or reused refresh token", body = ErrorResponse),
+    )
+)]
+async fn refresh_token(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(payload): JsonBody<RefreshTokenRequest>,
+) -> Result<Json<LoginUserResponse>, AppError> {
+    let token_hash = hash_token(&payload.refresh_token);
+
+    let rotated = sqlx::query!(
+        r#"UPDATE refresh_tokens SET revoked = TRUE
+        FROM users
+        WHERE refresh_tokens.user_id = users.id AND users.deleted_at IS NULL
+            AND token_hash = $1 AND NOT revoked AND expires_at > now()
+        RETURNING users.id, users.email, users.verified_at IS NOT NULL AS "verified!",
+            users.role AS "role: Role", refresh_tokens.session_id"#,
+        token_hash
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    if let Some(user) = rotated {
+        let session = match user.session_id {
+            Some(id) => Session::Existing(id),
+            None => Session::New { ip, user_agent: user_agent.as_deref() },
+        };
+        return Ok(Json(issue_tokens(&state, session, user.id, user.email, user.role, user.verified).await?));
+    }
+
+    // A revoked token being presented again means it was rotated already and
+    // has leaked, so the whole chain for that user is cut off.
+    let reused = sqlx::query!(
+        "SELECT user_id FROM refresh_tokens WHERE token_hash = $1 AND revoked",
+        token_hash
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    match reused {
+        Some(token) => {
+            sqlx::query!(
+                "UPDATE refresh_tokens SET revoked = TRUE WHERE user_id = $1",
+                token.user_id
+            )
+            .execute(&state.pool)
+            .await?;
+
+            Err(AppError::InvalidToken("refresh_token_reused"))
+        }
+        None => Err(AppError::InvalidToken("invalid_refresh_token")),
+    }
+}
@@ -0,0 +1,217 @@
+use axum::{
+    body::Bytes,
+    extract::{multipart::MultipartRejection, rejection::PathRejection, DefaultBodyLimit, Multipart, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+
+use crate::auth::AuthUser;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::mime;
+use crate::models::AvatarUpload;
+use crate::state::AppState;
+
+/// Largest picture accepted.
+pub const MAX_AVATAR_BYTES: usize = 2 * 1024 * 1024;
+
+/// Room for the multipart boundaries and part headers around the picture.
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+/// Pictures change rarely, and once this runs out the ETag keeps checking
+/// for a new one cheap.
+const CACHE_CONTROL: &str = "private, max-age=3600";
+
+pub fn router() -> Router<AppState> {
+    Router::new().route(
+        "/users/{id}/avatar",
+        get(read_avatar)
+            .post(upload_avatar)
+            .delete(delete_avatar)
+            .layer(DefaultBodyLimit::max(MAX_AVATAR_BYTES + MULTIPART_OVERHEAD)),
+    )
+}
+
+/// What the bytes are, going by their signature rather than anything the
+/// client claims.
+fn image_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn multipart_error(status: StatusCode, detail: String) -> AppError {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+        _ => AppError::InvalidInput {
+            error: "invalid_body",
+            field: None,
+            detail,
+        },
+    }
+}
+
+/// The `avatar` part of the upload.
+async fn read_upload(headers: &HeaderMap, multipart: Result<Multipart, MultipartRejection>) -> Result<Bytes, AppError> {
+    if mime(headers).as_deref() != Some("multipart/form-data") {
+        return Err(AppError::UnsupportedMediaType);
+    }
+    let mut multipart = multipart.map_err(|rejection| multipart_error(rejection.status(), rejection.body_text()))?;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| multipart_error(err.status(), err.body_text()))?
+    {
+        if field.name() == Some("avatar") {
+            return field.bytes().await.map_err(|err| multipart_error(err.status(), err.body_text()));
+        }
+    }
+
+    Err(AppError::InvalidInput {
+        error: "invalid_field",
+        field: Some("avatar".to_string()),
+        detail: "missing field `avatar`".to_string(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/avatar",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The picture",
+            content(
+                (Vec<u8> = "image/png"),
+                (Vec<u8> = "image/jpeg"),
+                (Vec<u8> = "image/webp"),
+            ),
+            headers(("ETag" = String, description = "Send back in If-None-Match"))),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user, or no picture", body = ErrorResponse),
+    )
+)]
+async fn read_avatar(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let avatar = sqlx::query!(
+        "SELECT avatars.content_type, avatars.data FROM avatars
+        JOIN users ON users.id = avatars.user_id
+        WHERE avatars.user_id = $1 AND users.deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("avatar_not_found"))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, avatar.content_type),
+            (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        avatar.data,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{id}/avatar",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    request_body(content = AvatarUpload, content_type = "multipart/form-data"),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Picture stored, replacing any earlier one"),
+        (status = 400, description = "Invalid id or unreadable upload", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the account owner or an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 413, description = "Picture over 2 MB", body = ErrorResponse),
+        (status = 415, description = "Not multipart, or not a PNG, JPEG or WebP picture", body = ErrorResponse),
+    )
+)]
+async fn upload_avatar(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    headers: HeaderMap,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    auth.require_self_or_admin(id)?;
+
+    let data = read_upload(&headers, multipart).await?;
+    if data.len() > MAX_AVATAR_BYTES {
+        return Err(AppError::PayloadTooLarge);
+    }
+    let content_type = image_type(&data).ok_or(AppError::UnsupportedMediaType)?;
+
+    // One statement, so readers see either the old picture or the new one.
+    let result = sqlx::query!(
+        "INSERT INTO avatars (user_id, content_type, data, updated_at)
+        SELECT id, $2, $3, $4 FROM users WHERE id = $1 AND deleted_at IS NULL
+        ON CONFLICT (user_id) DO UPDATE
+        SET content_type = EXCLUDED.content_type, data = EXCLUDED.data, updated_at = EXCLUDED.updated_at",
+        id,
+        content_type,
+        &data[..],
+        state.clock.now()
+    )
+    .execute(&state.pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("user_not_found"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{id}/avatar",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Picture removed"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the account owner or an admin", body = ErrorResponse),
+        (status = 404, description = "No picture to remove", body = ErrorResponse),
+    )
+)]
+async fn delete_avatar(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    auth.require_self_or_admin(id)?;
+
+    let result = sqlx::query!("DELETE FROM avatars WHERE user_id = $1", id)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("avatar_not_found"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,154 @@
+use axum::{
+    extract::{rejection::QueryRejection, FromRequestParts, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Days;
+
+use crate::auth::tokens::{hash_token, random_token};
+use crate::auth::AuthUser;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::ical::{self, Event};
+use crate::models::{CalendarQuery, CalendarToken};
+use crate::routes::invalid_query;
+use crate::state::AppState;
+
+/// How far back the feed goes by default.
+const DEFAULT_CALENDAR_DAYS: i64 = 90;
+const MAX_CALENDAR_DAYS: i64 = 366;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/entries/calendar.ics", get(read_calendar))
+        .route("/me/calendar-token", post(create_calendar_token).delete(revoke_calendar_token))
+}
+
+#[utoipa::path(
+    get,
+    path = "/entries/calendar.ics",
+    tag = "entries",
+    params(CalendarQuery),
+    security(("bearer" = []), ("calendar_token" = [])),
+    responses(
+        (status = 200, description = "The user's finished entries in the window, as events",
+            content((String = "text/calendar"))),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing, invalid or revoked token", body = ErrorResponse),
+    )
+)]
+async fn read_calendar(
+    State(state): State<AppState>,
+    query: Result<Query<CalendarQuery>, QueryRejection>,
+    mut parts: Parts,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let user_id = match &query.token {
+        Some(token) => sqlx::query_scalar!(
+            "SELECT id FROM users WHERE calendar_token_hash = $1 AND deleted_at IS NULL",
+            hash_token(token)
+        )
+        .fetch_optional(&state.pool)
+        .await?
+        .ok_or(AppError::InvalidToken("invalid_calendar_token"))?,
+        None => AuthUser::from_request_parts(&mut parts, &state).await?.id,
+    };
+
+    let days = query.days.unwrap_or(DEFAULT_CALENDAR_DAYS);
+    if !(1..=MAX_CALENDAR_DAYS).contains(&days) {
+        return Err(invalid_query("days", &format!("must be between 1 and {}", MAX_CALENDAR_DAYS)));
+    }
+
+    let now = state.clock.now();
+    let since = now - Days::new(days as u64);
+
+    let events = sqlx::query!(
+        r#"SELECT e.id, e.started_at, e.ended_at AS "ended_at!", e.description, p.name AS "project?",
+            COALESCE(array_agg(t.name ORDER BY lower(t.name)) FILTER (WHERE t.id IS NOT NULL), '{}') AS "tags!"
+        FROM time_entries e
+        LEFT JOIN projects p ON p.id = e.project_id
+        LEFT JOIN time_entry_tags et ON et.entry_id = e.id
+        LEFT JOIN tags t ON t.id = et.tag_id
+        WHERE e.user_id = $1 AND e.ended_at > $2
+        GROUP BY e.id, p.name
+        ORDER BY e.started_at, e.id"#,
+        user_id,
+        since
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|entry| {
+        let summary = match (entry.project, entry.description.is_empty()) {
+            (Some(project), false) => format!("{}: {}", project, entry.description),
+            (Some(project), true) => project,
+            (None, false) => entry.description,
+            (None, true) => "Time entry".to_string(),
+        };
+        Event {
+            // Ids are never reused, so calendars can match events across
+            // refreshes.
+            uid: format!("entry-{}@tictoc", entry.id),
+            start: entry.started_at,
+            end: entry.ended_at,
+            summary,
+            categories: entry.tags,
+        }
+    })
+    .collect::<Vec<_>>();
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        ical::calendar(&events, now),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/calendar-token",
+    tag = "entries",
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "A new feed token; any earlier one stops working", body = CalendarToken),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn create_calendar_token(State(state): State<AppState>, auth: AuthUser) -> Result<impl IntoResponse, AppError> {
+    let token = random_token();
+
+    sqlx::query!(
+        "UPDATE users SET calendar_token_hash = $1 WHERE id = $2 AND deleted_at IS NULL",
+        hash_token(&token),
+        auth.id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let url = format!("/entries/calendar.ics?token={}", token);
+
+    Ok((StatusCode::CREATED, Json(CalendarToken { token, url })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/calendar-token",
+    tag = "entries",
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "The feed token no longer works"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn revoke_calendar_token(State(state): State<AppState>, auth: AuthUser) -> Result<StatusCode, AppError> {
+    sqlx::query!("UPDATE users SET calendar_token_hash = NULL WHERE id = $1", auth.id)
+        .execute(&state.pool)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
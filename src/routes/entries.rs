@@ -0,0 +1,647 @@
+use axum::{
+    extract::{rejection::PathRejection, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+// Unlike axum's, this one reads repeated parameters into a `Vec`.
+use axum_extra::extract::{Query, QueryRejection};
+use chrono::{DateTime, Datelike, Days, Months, Utc};
+use serde_json::json;
+use std::collections::HashSet;
+
+use crate::auth::AuthUser;
+use crate::cache;
+use crate::clock::local_midnight;
+use crate::error::{entry_error, AppError, BodyErrorResponse, EntriesErrorResponse, ErrorResponse, TimesheetLockedResponse};
+use crate::export::{self, TimesheetFilter};
+use crate::extract::JsonBody;
+use crate::live::EventKind;
+use crate::models::{
+    BulkDeleteRequest, BulkResult, BulkUpdateRequest, EntryExportQuery, EntryQuery, EntryWriteQuery, Page, TimeEntry, TimeEntryRequest,
+    WebhookEvent,
+};
+use crate::routes::tags::set_entry_tags;
+use crate::routes::timer::finish_pomodoros;
+use crate::routes::users::load_settings;
+use crate::routes::{invalid_query, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+use crate::webhooks::Audience;
+
+// Every query here is scoped to the signed in user. Someone else's entry
+// is reported missing rather than forbidden, so ids don't leak whose they
+// are.
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/entries", get(read_entries).post(create_entry))
+        .route("/entries/{id}", get(read_entry).put(update_entry).delete(delete_entry))
+        .route("/entries/bulk", post(bulk_update_entries))
+        .route("/entries/bulk-delete", post(bulk_delete_entries))
+        .route("/entries/export", get(export_entries))
+}
+
+#[utoipa::path(
+    post,
+    path = "/entries",
+    tag = "entries",
+    params(EntryWriteQuery),
+    request_body = TimeEntryRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Entry created", body = TimeEntry,
+            headers(("Location" = String, description = "Where the new entry lives"))),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Overlaps other entries, or is left running while another entry already is",
+            body = EntriesErrorResponse),
+        (status = 422, description = "Invalid fields, or a project that isn't the user's", body = ValidationErrors),
+    )
+)]
+async fn create_entry(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<EntryWriteQuery>, QueryRejection>,
+    JsonBody(mut payload): JsonBody<TimeEntryRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+    payload.validate()?;
+
+    let entry = create_entry_for(&state, auth.id, query.allow_overlap, payload).await?;
+    let location = format!("/entries/{}", entry.id);
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(entry)))
+}
+
+/// Creates an entry for the user and tells whoever is listening, for
+/// `POST /entries` and GraphQL's `createEntry`. The payload is validated
+/// already.
+pub(crate) async fn create_entry_for(
+    state: &AppState,
+    user_id: i32,
+    allow_overlap: bool,
+    payload: TimeEntryRequest,
+) -> Result<TimeEntry, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let parallel = allow_overlap || allows_overlap(&mut tx, user_id).await?;
+    if !parallel {
+        check_overlap(&mut tx, user_id, None, payload.started_at, payload.ended_at).await?;
+    }
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO time_entries (user_id, project_id, description, started_at, ended_at, parallel, billable)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id",
+        user_id,
+        payload.project_id,
+        payload.description,
+        payload.started_at,
+        payload.ended_at,
+        parallel,
+        payload.billable
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(entry_error)?;
+
+    set_entry_tags(&mut tx, user_id, &[id], &payload.tags).await?;
+    let entry = fetch(&mut tx, user_id, id).await?.ok_or(AppError::NotFound("entry_not_found"))?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::timer_key(user_id)).await;
+
+    state.live.publish(user_id, EventKind::EntryCreated, &entry);
+
+    Ok(entry)
+}
+
+#[utoipa::path(
+    get,
+    path = "/entries",
+    tag = "entries",
+    params(EntryQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "A page of the user's entries, latest first", body = Page<TimeEntry>),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_entries(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<EntryQuery>, QueryRejection>,
+) -> Result<Json<Page<TimeEntry>>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    list_entries(&state, auth.id, &query).await.map(Json)
+}
+
+/// A page of the user's entries, latest first, for `GET /entries` and
+/// GraphQL's `entries`.
+pub(crate) async fn list_entries(state: &AppState, user_id: i32, query: &EntryQuery) -> Result<Page<TimeEntry>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(invalid_query("limit", &format!("must be between 1 and {}", MAX_PAGE_LIMIT)));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(invalid_query("offset", "must not be negative"));
+    }
+
+    let mut tags: Vec<String> = query.tag.iter().map(|tag| tag.trim().to_lowercase()).collect();
+    tags.sort();
+    tags.dedup();
+
+    finish_pomodoros(state.db().writer(), user_id, state.clock.now()).await?;
+
+    // Tags come back aggregated onto each entry in the same query, rather
+    // than fetched entry by entry. Gathered laterally, they're only gathered
+    // for the page, which comes straight off the index in order.
+    let items = sqlx::query_as!(
+        TimeEntry,
+        r#"SELECT e.id, e.user_id, e.project_id, e.description, e.started_at, e.ended_at,
+            EXTRACT(EPOCH FROM e.ended_at - e.started_at)::bigint AS duration_seconds,
+            COALESCE(t.names, '{}') AS "tags!",
+            e.pomodoro, e.billable, e.rate_cents, e.currency, e.invoice_id, e.created_at
+        FROM time_entries e
+        LEFT JOIN LATERAL (
+            SELECT array_agg(t.name ORDER BY lower(t.name)) AS names
+            FROM time_entry_tags et JOIN tags t ON t.id = et.tag_id
+            WHERE et.entry_id = e.id
+        ) t ON TRUE
+        WHERE e.user_id = $1 AND ($2::int IS NULL OR e.project_id = $2)
+            AND (cardinality($3::text[]) = 0 OR e.id IN (
+                SELECT ft.entry_id FROM time_entry_tags ft JOIN tags f ON f.id = ft.tag_id
+                WHERE f.user_id = $1 AND lower(f.name) = ANY($3)
+                GROUP BY ft.entry_id HAVING count(*) = cardinality($3)
+            ))
+        ORDER BY e.started_at DESC, e.id DESC
+        LIMIT $4 OFFSET $5"#,
+        user_id,
+        query.project_id,
+        &tags,
+        limit,
+        offset
+    )
+    .fetch_all(state.db().reader())
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "total!" FROM time_entries e
+        WHERE e.user_id = $1 AND ($2::int IS NULL OR e.project_id = $2)
+            AND (cardinality($3::text[]) = 0 OR e.id IN (
+                SELECT ft.entry_id FROM time_entry_tags ft JOIN tags f ON f.id = ft.tag_id
+                WHERE f.user_id = $1 AND lower(f.name) = ANY($3)
+                GROUP BY ft.entry_id HAVING count(*) = cardinality($3)
+            ))"#,
+        user_id,
+        query.project_id,
+        &tags
+    )
+    .fetch_one(state.db().reader())
+    .await?;
+
+    // Entries are ordered by when they started, which ids don't follow.
+    Ok(Page {
+        items,
+        total,
+        limit,
+        offset,
+        next_cursor: None,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/entries/export",
+    tag = "entries",
+    params(EntryExportQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The user's finished entries started in the range, oldest first, as a download",
+            content(
+                (String = "text/csv", example = "date,start,end,duration,project,tags,description"),
+                (String = "application/x-ndjson"),
+                (String = "application/json"),
+            )),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn export_entries(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<EntryExportQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let tz = load_settings(state.db().reader(), auth.id).await?.tz();
+
+    let month = state.clock.now().with_timezone(&tz).date_naive().with_day(1).unwrap();
+    let from = query.from.unwrap_or(month);
+    let to = query.to.unwrap_or(month + Months::new(1) - Days::new(1));
+    if to < from {
+        return Err(invalid_query("to", "must not be before from"));
+    }
+
+    // A whole month is named for it, as timesheets usually are.
+    let whole_month = from.day() == 1 && from + Months::new(1) - Days::new(1) == to;
+    let filename = if whole_month {
+        format!("tictoc-{}.{}", from.format("%Y-%m"), query.format.extension())
+    } else {
+        format!("tictoc-{}-{}.{}", from, to, query.format.extension())
+    };
+
+    let filter = TimesheetFilter {
+        user_id: auth.id,
+        start: local_midnight(from, tz),
+        end: local_midnight(to + Days::new(1), tz),
+        project_id: query.project_id,
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        export::timesheet(state.db().reader().clone(), filter, tz, query.format),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/entries/{id}",
+    tag = "entries",
+    params(("id" = i32, Path, description = "Entry id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The entry", body = TimeEntry),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such entry of the user's", body = ErrorResponse),
+    )
+)]
+async fn read_entry(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<TimeEntry>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let mut conn = state.pool.acquire().await?;
+    finish_pomodoros(&mut *conn, auth.id, state.clock.now()).await?;
+    let entry = fetch(&mut conn, auth.id, id).await?.ok_or(AppError::NotFound("entry_not_found"))?;
+
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    put,
+    path = "/entries/{id}",
+    tag = "entries",
+    params(("id" = i32, Path, description = "Entry id"), EntryWriteQuery),
+    request_body = TimeEntryRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The updated entry", body = TimeEntry),
+        (status = 400, description = "Invalid id or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such entry of the user's", body = ErrorResponse),
+        (status = 409, description = "Invoiced already, overlaps other entries, or is left running while another \
+            entry already is", body = EntriesErrorResponse),
+        (status = 423, description = "In a week submitted for approval, or approved", body = TimesheetLockedResponse),
+        (status = 422, description = "Invalid fields, or a project that isn't the user's", body = ValidationErrors),
+    )
+)]
+async fn update_entry(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    query: Result<Query<EntryWriteQuery>, QueryRejection>,
+    JsonBody(mut payload): JsonBody<TimeEntryRequest>,
+) -> Result<Json<TimeEntry>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+    payload.validate()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    lock_entry(&mut tx, auth.id, id).await?;
+
+    let parallel = query.allow_overlap || allows_overlap(&mut tx, auth.id).await?;
+    if !parallel {
+        check_overlap(&mut tx, auth.id, Some(id), payload.started_at, payload.ended_at).await?;
+    }
+
+    sqlx::query!(
+        "UPDATE time_entries SET project_id = $1, description = $2, started_at = $3, ended_at = $4, parallel = $5,
+            billable = $6
+        WHERE id = $7",
+        payload.project_id,
+        payload.description,
+        payload.started_at,
+        payload.ended_at,
+        parallel,
+        payload.billable,
+        id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(entry_error)?;
+
+    set_entry_tags(&mut tx, auth.id, &[id], &payload.tags).await?;
+    let entry = fetch(&mut tx, auth.id, id).await?.ok_or(AppError::NotFound("entry_not_found"))?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::timer_key(auth.id)).await;
+
+    state.live.publish(auth.id, EventKind::EntryUpdated, &entry);
+
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/entries/{id}",
+    tag = "entries",
+    params(("id" = i32, Path, description = "Entry id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Entry deleted"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such entry of the user's", body = ErrorResponse),
+        (status = 409, description = "Invoiced already", body = EntriesErrorResponse),
+        (status = 423, description = "In a week submitted for approval, or approved", body = TimesheetLockedResponse),
+    )
+)]
+async fn delete_entry(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let mut tx = state.pool.begin().await?;
+    lock_entry(&mut tx, auth.id, id).await?;
+
+    sqlx::query!("DELETE FROM time_entries WHERE id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::timer_key(auth.id)).await;
+
+    state.webhooks.emit(WebhookEvent::EntryDeleted, Audience::User(auth.id), json!({ "id": id }));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/entries/bulk",
+    tag = "entries",
+    request_body = BulkUpdateRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Every entry changed", body = BulkResult),
+        (status = 400, description = "Malformed body or nothing to change", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Some of the entries are invoiced, so none changed", body = EntriesErrorResponse),
+        (status = 423, description = "Some of the entries are in a week submitted for approval, or approved",
+            body = TimesheetLockedResponse),
+        (status = 422, description = "Invalid fields, a project that isn't the user's, or ids of entries that \
+            aren't the user's, in which case nothing changed", body = EntriesErrorResponse),
+    )
+)]
+async fn bulk_update_entries(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<BulkUpdateRequest>,
+) -> Result<Json<BulkResult>, AppError> {
+    payload.validate()?;
+
+    let set = &payload.set;
+    if set.project_id.is_none() && set.tags.is_none() && set.description.is_none() && set.billable.is_none() {
+        return Err(AppError::BadRequest("no_fields_to_update"));
+    }
+
+    let mut tx = state.pool.begin().await?;
+    lock_entries(&mut tx, auth.id, &payload.ids).await?;
+
+    if set.project_id.is_some() || set.description.is_some() || set.billable.is_some() {
+        sqlx::query!(
+            "UPDATE time_entries
+            SET project_id = CASE WHEN $1 THEN $2 ELSE project_id END, description = COALESCE($3, description),
+                billable = COALESCE($4, billable)
+            WHERE id = ANY($5)",
+            set.project_id.is_some(),
+            set.project_id.flatten(),
+            set.description,
+            set.billable,
+            &payload.ids
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(entry_error)?;
+    }
+
+    if let Some(tags) = &set.tags {
+        set_entry_tags(&mut tx, auth.id, &payload.ids, tags).await?;
+    }
+
+    tx.commit().await?;
+    state.cache.delete(&cache::timer_key(auth.id)).await;
+
+    Ok(Json(BulkResult {
+        affected: payload.ids.len() as u64,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/entries/bulk-delete",
+    tag = "entries",
+    request_body = BulkDeleteRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Every entry deleted", body = BulkResult),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Some of the entries are invoiced, so none were deleted",
+            body = EntriesErrorResponse),
+        (status = 423, description = "Some of the entries are in a week submitted for approval, or approved",
+            body = TimesheetLockedResponse),
+        (status = 422, description = "Invalid ids, or ids of entries that aren't the user's, in which case \
+            nothing was deleted", body = EntriesErrorResponse),
+    )
+)]
+async fn bulk_delete_entries(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<BulkDeleteRequest>,
+) -> Result<Json<BulkResult>, AppError> {
+    payload.validate()?;
+
+    let mut tx = state.pool.begin().await?;
+    lock_entries(&mut tx, auth.id, &payload.ids).await?;
+
+    let result = sqlx::query!("DELETE FROM time_entries WHERE id = ANY($1)", &payload.ids)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::timer_key(auth.id)).await;
+
+    for id in &payload.ids {
+        state.webhooks.emit(WebhookEvent::EntryDeleted, Audience::User(auth.id), json!({ "id": id }));
+    }
+
+    Ok(Json(BulkResult {
+        affected: result.rows_affected(),
+    }))
+}
+
+/// Locks those of `ids` that are the user's entries, or fails with the
+/// ones that aren't. Locking in id order keeps overlapping bulk changes
+/// from deadlocking.
+async fn lock_entries(conn: &mut sqlx::PgConnection, user_id: i32, ids: &[i32]) -> Result<(), AppError> {
+    let found = sqlx::query!(
+        r#"SELECT e.id, e.invoice_id IS NOT NULL AS "invoiced!", t.week_start AS "week?"
+        FROM time_entries e
+        LEFT JOIN timesheets t ON t.id = e.timesheet_id
+        WHERE e.id = ANY($1) AND e.user_id = $2 ORDER BY e.id FOR UPDATE OF e"#,
+        ids,
+        user_id
+    )
+    .fetch_all(conn)
+    .await?;
+
+    let ids_found: HashSet<i32> = found.iter().map(|entry| entry.id).collect();
+    let missing: Vec<i32> = ids.iter().copied().filter(|id| !ids_found.contains(id)).collect();
+    if !missing.is_empty() {
+        return Err(AppError::EntriesNotFound(missing));
+    }
+
+    let invoiced: Vec<i32> = found.iter().filter(|entry| entry.invoiced).map(|entry| entry.id).collect();
+    if !invoiced.is_empty() {
+        return Err(AppError::EntriesInvoiced(invoiced));
+    }
+
+    // Pointing at the first locked week is enough to go and look.
+    if let Some(week) = found.iter().find_map(|entry| entry.week) {
+        let entry_ids = found.iter().filter(|entry| entry.week.is_some()).map(|entry| entry.id).collect();
+        return Err(AppError::TimesheetLocked { entry_ids, week });
+    }
+
+    Ok(())
+}
+
+/// Holds one of the user's entries until the transaction ends, as long as
+/// it hasn't been invoiced or submitted on a timesheet.
+async fn lock_entry(conn: &mut sqlx::PgConnection, user_id: i32, id: i32) -> Result<(), AppError> {
+    let entry = sqlx::query!(
+        r#"SELECT e.invoice_id IS NOT NULL AS "invoiced!", t.week_start AS "week?"
+        FROM time_entries e
+        LEFT JOIN timesheets t ON t.id = e.timesheet_id
+        WHERE e.id = $1 AND e.user_id = $2
+        FOR UPDATE OF e"#,
+        id,
+        user_id
+    )
+    .fetch_optional(conn)
+    .await?
+    .ok_or(AppError::NotFound("entry_not_found"))?;
+
+    if entry.invoiced {
+        return Err(AppError::EntriesInvoiced(vec![id]));
+    }
+    if let Some(week) = entry.week {
+        return Err(AppError::TimesheetLocked { entry_ids: vec![id], week });
+    }
+
+    Ok(())
+}
+
+/// One of the user's entries, with its tags.
+pub(crate) async fn fetch(
+    conn: &mut sqlx::PgConnection,
+    user_id: i32,
+    id: i32,
+) -> Result<Option<TimeEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        TimeEntry,
+        r#"SELECT id, user_id, project_id, description, started_at, ended_at,
+            EXTRACT(EPOCH FROM ended_at - started_at)::bigint AS duration_seconds,
+            ARRAY(
+                SELECT t.name FROM time_entry_tags et JOIN tags t ON t.id = et.tag_id
+                WHERE et.entry_id = time_entries.id
+                ORDER BY lower(t.name)
+            ) AS "tags!",
+            pomodoro, billable, rate_cents, currency, invoice_id, created_at
+        FROM time_entries
+        WHERE id = $1 AND user_id = $2"#,
+        id,
+        user_id
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+async fn allows_overlap(conn: &mut sqlx::PgConnection, user_id: i32) -> Result<bool, AppError> {
+    sqlx::query_scalar!("SELECT allow_overlap FROM users WHERE id = $1 AND deleted_at IS NULL", user_id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or(AppError::NotFound("user_not_found"))
+}
+
+/// Fails with the ids of the user's entries, other than `except`, that an
+/// entry over `[started_at, ended_at)` would overlap. No end means it runs
+/// on forever.
+pub(crate) async fn check_overlap(
+    conn: &mut sqlx::PgConnection,
+    user_id: i32,
+    except: Option<i32>,
+    started_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+) -> Result<(), AppError> {
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM time_entries
+        WHERE user_id = $1 AND NOT parallel AND ($2::int IS NULL OR id <> $2)
+            AND tstzrange(started_at, ended_at) && tstzrange($3, $4)
+        ORDER BY started_at, id",
+        user_id,
+        except,
+        started_at,
+        ended_at
+    )
+    .fetch_all(conn)
+    .await?;
+
+    if ids.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Overlap(ids))
+    }
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures::{Stream, StreamExt};
+use serde_json::json;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auth::AuthUser;
+use crate::error::ErrorResponse;
+use crate::state::AppState;
+
+/// Well under the minute or so proxies give an idle connection.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/events", get(read_events))
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "entries",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Server-sent events as the user's timer starts and stops and their \
+            entries are created or updated one at a time, each named by its type with the entry as JSON data. \
+            Nothing from before connecting is replayed. A `lagged` event means some were missed, so re-fetch.",
+            content_type = "text/event-stream", body = String),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_events(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.live.subscribe(auth.id);
+
+    let events = futures::stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => Event::default().event(event.kind.as_str()).data(event.data.to_string()),
+            Err(RecvError::Lagged(missed)) => Event::default().event("lagged").data(json!({ "missed": missed }).to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    })
+    // Otherwise open streams would hold shutdown up for the whole grace
+    // period.
+    .take_until(state.shutdown.clone().cancelled_owned());
+
+    Sse::new(events).keep_alive(KeepAlive::new().interval(KEEP_ALIVE_INTERVAL))
+}
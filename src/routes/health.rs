@@ -0,0 +1,119 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use std::time::Duration;
+
+use crate::auth::tokens::hash_token;
+use crate::error::{AppError, ErrorResponse};
+use crate::models::HealthResponse;
+use crate::state::AppState;
+
+pub const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/metrics", get(read_metrics))
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses(
+        (status = 200, description = "The process is up"),
+    )
+)]
+async fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Ready once the database answers. The timeout also covers waiting for a
+/// pooled connection, so a hung database can't tie one up for long.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "The database answers", body = HealthResponse),
+        (status = 503, description = "The database is unreachable", body = HealthResponse),
+    )
+)]
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let ping = sqlx::query("SELECT 1").execute(&state.pool);
+
+    match tokio::time::timeout(READINESS_TIMEOUT, ping).await {
+        Ok(Ok(_)) => (
+            StatusCode::OK,
+            Json(HealthResponse {
+                status: "ok".to_string(),
+                database: "ok".to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                git_sha: option_env!("GIT_SHA").map(str::to_string),
+            }),
+        ),
+        Ok(Err(err)) => {
+            tracing::warn!(error = %err, "readiness check failed");
+            unavailable()
+        }
+        Err(_) => {
+            tracing::warn!("readiness check timed out");
+            unavailable()
+        }
+    }
+}
+
+fn unavailable() -> (StatusCode, Json<HealthResponse>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(HealthResponse {
+            status: "unavailable".to_string(),
+            database: "unreachable".to_string(),
+            version: None,
+            git_sha: None,
+        }),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Prometheus metrics", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or wrong metrics token", body = ErrorResponse),
+        (status = 404, description = "No metrics token configured", body = ErrorResponse),
+    )
+)]
+async fn read_metrics(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let expected = state
+        .metrics_token
+        .as_deref()
+        .ok_or(AppError::NotFound("not_found"))?;
+
+    // Compared as hashes so the check takes the same time however much of
+    // the token matches.
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(hash_token);
+
+    if presented != Some(hash_token(expected)) {
+        return Err(AppError::InvalidToken("invalid_metrics_token"));
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(&state.pool),
+    ))
+}
@@ -0,0 +1,333 @@
+use askama::Template;
+use axum::{
+    extract::{rejection::PathRejection, rejection::QueryRejection, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+use crate::auth::AuthUser;
+use crate::clock::local_midnight;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::JsonBody;
+use crate::models::{Invoice, InvoiceFormat, InvoiceGrouping, InvoiceLine, InvoiceQuery, InvoiceRequest};
+use crate::routes::users::load_settings;
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/invoices", post(create_invoice))
+        .route("/invoices/{id}", get(read_invoice))
+}
+
+/// A billable entry about to be invoiced.
+struct Billed {
+    id: i32,
+    started_at: DateTime<Utc>,
+    seconds: i64,
+    description: String,
+    rate_cents: i32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/invoices",
+    tag = "invoices",
+    request_body = InvoiceRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "An invoice for the project's billable entries in the range not invoiced yet",
+            body = Invoice, headers(("Location" = String, description = "Where the new invoice lives"))),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, a project that isn't the user's, or entries billed in more \
+            than one currency", body = ValidationErrors),
+    )
+)]
+async fn create_invoice(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<InvoiceRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let tz = load_settings(&state.pool, auth.id).await?.tz();
+
+    let mut tx = state.pool.begin().await?;
+
+    // Taking the number holds the user's row, so invoices of theirs are
+    // made one at a time and the entries can't be billed twice.
+    let user = sqlx::query!(
+        "UPDATE users SET last_invoice_number = last_invoice_number + 1 WHERE id = $1
+        RETURNING last_invoice_number, tax_rate_bps, currency",
+        auth.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let project = sqlx::query!(
+        "SELECT name, currency FROM projects WHERE id = $1 AND user_id = $2",
+        payload.project_id,
+        auth.id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| {
+        let mut errors = ValidationErrors::default();
+        errors.add("project_id", "not found");
+        AppError::Validation(errors)
+    })?;
+
+    let entries = sqlx::query!(
+        r#"SELECT id, started_at, EXTRACT(EPOCH FROM ended_at - started_at)::bigint AS "seconds!", description,
+            rate_cents AS "rate_cents!", currency AS "currency!"
+        FROM time_entries
+        WHERE user_id = $1 AND project_id = $2 AND billable AND rate_cents IS NOT NULL AND ended_at IS NOT NULL
+            AND invoice_id IS NULL AND started_at >= $3 AND started_at < $4
+        ORDER BY started_at, id
+        FOR UPDATE"#,
+        auth.id,
+        payload.project_id,
+        local_midnight(payload.from, tz),
+        local_midnight(payload.to + Days::new(1), tz)
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let currency = match entries.first() {
+        Some(first) if entries.iter().any(|entry| entry.currency != first.currency) => {
+            return Err(AppError::Unprocessable("mixed_currencies"));
+        }
+        Some(first) => first.currency.clone(),
+        None => project.currency.unwrap_or(user.currency),
+    };
+
+    let entry_ids: Vec<i32> = entries.iter().map(|entry| entry.id).collect();
+    let billed: Vec<Billed> = entries
+        .into_iter()
+        .map(|entry| Billed {
+            id: entry.id,
+            started_at: entry.started_at,
+            seconds: entry.seconds,
+            description: entry.description,
+            rate_cents: entry.rate_cents,
+        })
+        .collect();
+    let lines = invoice_lines(&billed, payload.group_by, tz, &project.name);
+
+    let tax_rate_bps = payload.tax_rate_bps.unwrap_or(user.tax_rate_bps);
+    let subtotal_cents: i64 = lines.iter().map(|line| line.amount_cents).sum();
+    let tax_cents = (subtotal_cents * i64::from(tax_rate_bps) + 5_000) / 10_000;
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO invoices (user_id, number, project_id, project_name, period_from, period_to, group_by, currency,
+            tax_rate_bps, subtotal_cents, tax_cents, total_cents)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        RETURNING id",
+        auth.id,
+        user.last_invoice_number,
+        payload.project_id,
+        project.name,
+        payload.from,
+        payload.to,
+        payload.group_by as InvoiceGrouping,
+        currency,
+        tax_rate_bps,
+        subtotal_cents,
+        tax_cents,
+        subtotal_cents + tax_cents
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO invoice_lines (invoice_id, position, date, description, seconds, rate_cents, amount_cents)
+        SELECT $1, position, date, description, seconds, rate_cents, amount_cents
+        FROM unnest($2::date[], $3::text[], $4::bigint[], $5::int[], $6::bigint[])
+            WITH ORDINALITY AS l(date, description, seconds, rate_cents, amount_cents, position)",
+        id,
+        &lines.iter().map(|line| line.date).collect::<Vec<_>>(),
+        &lines.iter().map(|line| line.description.clone()).collect::<Vec<_>>(),
+        &lines.iter().map(|line| line.seconds).collect::<Vec<_>>(),
+        &lines.iter().map(|line| line.rate_cents).collect::<Vec<_>>(),
+        &lines.iter().map(|line| line.amount_cents).collect::<Vec<_>>()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("UPDATE time_entries SET invoice_id = $1 WHERE id = ANY($2)", id, &entry_ids)
+        .execute(&mut *tx)
+        .await?;
+
+    let invoice = fetch(&mut tx, auth.id, id).await?.ok_or(AppError::NotFound("invoice_not_found"))?;
+    tx.commit().await?;
+
+    let location = format!("/invoices/{}", invoice.id);
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(invoice)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/invoices/{id}",
+    tag = "invoices",
+    params(("id" = i32, Path, description = "Invoice id"), InvoiceQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The invoice, or with `format=html` a printable page of it",
+            content((Invoice = "application/json"), (String = "text/html"))),
+        (status = 400, description = "Invalid id or query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such invoice of the user's", body = ErrorResponse),
+    )
+)]
+async fn read_invoice(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    query: Result<Query<InvoiceQuery>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let mut conn = state.pool.acquire().await?;
+    let invoice = fetch(&mut conn, auth.id, id).await?.ok_or(AppError::NotFound("invoice_not_found"))?;
+
+    match query.format {
+        InvoiceFormat::Json => Ok(Json(invoice).into_response()),
+        InvoiceFormat::Html => {
+            let page = InvoicePage::new(&invoice).render()?;
+            Ok(([(header::CONTENT_TYPE, "text/html; charset=utf-8")], page).into_response())
+        }
+    }
+}
+
+async fn fetch(conn: &mut sqlx::PgConnection, user_id: i32, id: i32) -> Result<Option<Invoice>, sqlx::Error> {
+    let Some(invoice) = sqlx::query!(
+        r#"SELECT id, number, project_id, project_name, period_from, period_to,
+            group_by AS "group_by: InvoiceGrouping", currency, tax_rate_bps, subtotal_cents, tax_cents, total_cents,
+            created_at
+        FROM invoices WHERE id = $1 AND user_id = $2"#,
+        id,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let lines = sqlx::query_as!(
+        InvoiceLine,
+        "SELECT date, description, seconds, rate_cents, amount_cents FROM invoice_lines
+        WHERE invoice_id = $1
+        ORDER BY position",
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Some(Invoice {
+        id: invoice.id,
+        number: invoice.number,
+        project_id: invoice.project_id,
+        project_name: invoice.project_name,
+        from: invoice.period_from,
+        to: invoice.period_to,
+        group_by: invoice.group_by,
+        currency: invoice.currency,
+        tax_rate_bps: invoice.tax_rate_bps,
+        subtotal_cents: invoice.subtotal_cents,
+        tax_cents: invoice.tax_cents,
+        total_cents: invoice.total_cents,
+        created_at: invoice.created_at,
+        lines,
+    }))
+}
+
+/// Lines for `entries`, which come in the order they started. A day's
+/// line says what was worked on, in that order; lines left without a
+/// description get the project's name.
+fn invoice_lines(entries: &[Billed], group_by: InvoiceGrouping, tz: Tz, project_name: &str) -> Vec<InvoiceLine> {
+    let mut groups: BTreeMap<(NaiveDate, i32, i32), (i64, Vec<&str>)> = BTreeMap::new();
+    for entry in entries {
+        let date = entry.started_at.with_timezone(&tz).date_naive();
+        // Past the date and rate, entries are told apart only when each
+        // gets a line of its own.
+        let own = if group_by == InvoiceGrouping::Entry { entry.id } else { 0 };
+        let (seconds, descriptions) = groups.entry((date, entry.rate_cents, own)).or_default();
+        *seconds += entry.seconds;
+        if !entry.description.is_empty() && !descriptions.contains(&entry.description.as_str()) {
+            descriptions.push(&entry.description);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|((date, rate_cents, _), (seconds, descriptions))| InvoiceLine {
+            date,
+            description: if descriptions.is_empty() { project_name.to_string() } else { descriptions.join("; ") },
+            seconds,
+            rate_cents,
+            amount_cents: (seconds * i64::from(rate_cents) + 1_800) / 3_600,
+        })
+        .collect()
+}
+
+#[derive(Template)]
+#[template(path = "invoice.html")]
+struct InvoicePage {
+    number: i32,
+    project_name: String,
+    period: String,
+    created: String,
+    lines: Vec<PageLine>,
+    subtotal: String,
+    tax_rate: String,
+    tax: String,
+    total: String,
+}
+
+struct PageLine {
+    date: String,
+    description: String,
+    hours: String,
+    rate: String,
+    amount: String,
+}
+
+impl InvoicePage {
+    fn new(invoice: &Invoice) -> Self {
+        let money = |cents: i64| format!("{}.{:02} {}", cents / 100, cents % 100, invoice.currency);
+        Self {
+            number: invoice.number,
+            project_name: invoice.project_name.clone(),
+            period: format!("{} to {}", invoice.from, invoice.to),
+            created: invoice.created_at.format("%Y-%m-%d").to_string(),
+            lines: invoice
+                .lines
+                .iter()
+                .map(|line| PageLine {
+                    date: line.date.to_string(),
+                    description: line.description.clone(),
+                    hours: format!("{}:{:02}", line.seconds / 3600, line.seconds % 3600 / 60),
+                    rate: money(i64::from(line.rate_cents)),
+                    amount: money(line.amount_cents),
+                })
+                .collect(),
+            subtotal: money(invoice.subtotal_cents),
+            tax_rate: format!("{}.{:02}%", invoice.tax_rate_bps / 100, invoice.tax_rate_bps % 100),
+            tax: money(invoice.tax_cents),
+            total: money(invoice.total_cents),
+        }
+    }
+}
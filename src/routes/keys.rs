@@ -0,0 +1,188 @@
+use axum::{
+    extract::{rejection::PathRejection, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use jsonwebtoken::jwk::JwkSet;
+use serde_json::json;
+
+use crate::audit::{self, Event};
+use crate::auth::tokens::{hash_token, random_token};
+use crate::auth::{API_KEY_PREFIX, AdminUser, AuthUser};
+use crate::config::load_secrets_file;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::{ClientIp, JsonBody, UserAgent};
+use crate::models::{ApiKey, AuditEventType, CreateApiKeyRequest, CreateApiKeyResponse, RotateKeysResponse};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/admin/keys/rotate", post(rotate_keys))
+        .route("/api-keys", get(read_api_keys).post(create_api_key))
+        .route("/api-keys/{id}", delete(revoke_api_key))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    tag = "keys",
+    request_body = CreateApiKeyRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Key created; it is only shown this once", body = CreateApiKeyResponse),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn create_api_key(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<CreateApiKeyRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let key = format!("{}{}", API_KEY_PREFIX, random_token());
+
+    let created = sqlx::query!(
+        "INSERT INTO api_keys (key_hash, user_id, label) VALUES ($1, $2, $3) RETURNING id, label, created_at",
+        hash_token(&key),
+        auth.id,
+        payload.label
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    let response = CreateApiKeyResponse {
+        id: created.id,
+        label: created.label,
+        key,
+        created_at: created.created_at,
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    tag = "keys",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Keys not yet revoked", body = Vec<ApiKey>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_api_keys(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<ApiKey>>, AppError> {
+    let keys = sqlx::query_as!(
+        ApiKey,
+        "SELECT id, label, created_at, last_used_at FROM api_keys
+        WHERE user_id = $1 AND revoked_at IS NULL ORDER BY id",
+        auth.id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(keys))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api-keys/{id}",
+    tag = "keys",
+    params(("id" = i32, Path, description = "API key id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such key", body = ErrorResponse),
+    )
+)]
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        id,
+        auth.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("api_key_not_found"));
+    }
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::TokenRevoked,
+        actor: Some(auth.id),
+        target: Some(("api_key", id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({ "token": "api_key" }),
+    })
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Re-reads `JWT_SECRETS_FILE`. Tokens signed with keys still in the file
+/// keep working; new ones are signed with the first.
+#[utoipa::path(
+    post,
+    path = "/admin/keys/rotate",
+    tag = "keys",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Keys reloaded", body = RotateKeysResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 409, description = "Keys were not loaded from a file", body = ErrorResponse),
+        (status = 500, description = "The file could not be read", body = ErrorResponse),
+    )
+)]
+async fn rotate_keys(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> Result<Json<RotateKeysResponse>, AppError> {
+    let path = state
+        .jwt_secrets_file
+        .as_ref()
+        .ok_or(AppError::Conflict("keys_not_reloadable"))?;
+
+    state.jwt.replace(load_secrets_file(path).map_err(AppError::Config)?);
+
+    Ok(Json(RotateKeysResponse {
+        signing_kid: state.jwt.signing_kid(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "keys",
+    responses(
+        (status = 200, description = "Public keys for verifying tokens, as a JWK set", body = Object),
+    )
+)]
+async fn jwks(State(state): State<AppState>) -> Json<JwkSet> {
+    Json(state.jwt.jwks())
+}
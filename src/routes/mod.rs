@@ -0,0 +1,33 @@
+use crate::AppError;
+
+pub mod admin;
+pub mod auth;
+pub mod avatars;
+pub mod calendar;
+pub mod entries;
+pub mod events;
+pub mod health;
+pub mod invoices;
+pub mod keys;
+pub mod organizations;
+pub mod privacy;
+pub mod projects;
+pub mod reports;
+pub mod sessions;
+pub mod tags;
+pub mod timer;
+pub mod timesheets;
+pub mod users;
+pub mod webhooks;
+pub mod ws;
+
+pub(crate) const DEFAULT_PAGE_LIMIT: i64 = 50;
+pub(crate) const MAX_PAGE_LIMIT: i64 = 200;
+
+pub(crate) fn invalid_query(field: &str, detail: &str) -> AppError {
+    AppError::InvalidInput {
+        error: "invalid_query",
+        field: Some(field.to_string()),
+        detail: detail.to_string(),
+    }
+}
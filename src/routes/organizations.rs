@@ -0,0 +1,537 @@
+use axum::{
+    extract::{rejection::PathRejection, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json, Router,
+};
+
+use crate::auth::tokens::{hash_token, random_token};
+use crate::auth::AuthUser;
+use crate::cache;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::JsonBody;
+use crate::mail::templates::{self, Invitation as InvitationEmail, Locale};
+use crate::models::{
+    AcceptInvitationRequest, Invitation, InvitationRequest, Member, MemberRole, MemberRoleRequest, Organization,
+    OrganizationRequest,
+};
+use crate::policy::{self, Action};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+
+/// How long an invitation can be accepted for.
+const INVITATION_TTL_DAYS: i32 = 7;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/organizations", get(read_organizations).post(create_organization))
+        .route("/organizations/{id}", get(read_organization).put(update_organization))
+        .route("/organizations/{id}/invitations", post(create_invitation))
+        .route("/organizations/{id}/members", get(read_members))
+        .route("/organizations/{id}/members/{user_id}", put(update_member).delete(remove_member))
+        .route("/invitations/accept", post(accept_invitation))
+}
+
+#[utoipa::path(
+    post,
+    path = "/organizations",
+    tag = "organizations",
+    request_body = OrganizationRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Organization created, with the caller as its owner", body = Organization,
+            headers(("Location" = String, description = "Where the new organization lives"))),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn create_organization(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<OrganizationRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let organization = sqlx::query!(
+        "INSERT INTO organizations (name, timezone) VALUES ($1, $2) RETURNING id, name, timezone, created_at",
+        payload.name,
+        payload.timezone
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)",
+        organization.id,
+        auth.id,
+        MemberRole::Owner as MemberRole
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let location = format!("/organizations/{}", organization.id);
+    let organization = Organization {
+        id: organization.id,
+        name: organization.name,
+        timezone: organization.timezone,
+        role: MemberRole::Owner,
+        created_at: organization.created_at,
+    };
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(organization)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/organizations",
+    tag = "organizations",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The organizations the caller is in, by name", body = [Organization]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_organizations(State(state): State<AppState>, auth: AuthUser) -> Result<Json<Vec<Organization>>, AppError> {
+    let organizations = sqlx::query_as!(
+        Organization,
+        r#"SELECT o.id, o.name, o.timezone, m.role AS "role: MemberRole", o.created_at
+        FROM organizations o
+        JOIN organization_members m ON m.organization_id = o.id
+        WHERE m.user_id = $1
+        ORDER BY lower(o.name), o.id"#,
+        auth.id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(organizations))
+}
+
+#[utoipa::path(
+    get,
+    path = "/organizations/{id}",
+    tag = "organizations",
+    params(("id" = i32, Path, description = "Organization id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The organization", body = Organization),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in", body = ErrorResponse),
+    )
+)]
+async fn read_organization(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<Organization>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let role = policy::authorize(&state.pool, auth.id, id, Action::View).await?;
+
+    let organization = sqlx::query!("SELECT id, name, timezone, created_at FROM organizations WHERE id = $1", id)
+        .fetch_one(&state.pool)
+        .await?;
+
+    Ok(Json(Organization {
+        id: organization.id,
+        name: organization.name,
+        timezone: organization.timezone,
+        role,
+        created_at: organization.created_at,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/organizations/{id}",
+    tag = "organizations",
+    params(("id" = i32, Path, description = "Organization id")),
+    request_body = OrganizationRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The updated organization", body = Organization),
+        (status = 400, description = "Invalid id or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of the organization", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn update_organization(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    JsonBody(mut payload): JsonBody<OrganizationRequest>,
+) -> Result<Json<Organization>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    payload.validate()?;
+
+    let role = policy::authorize(&state.pool, auth.id, id, Action::Update).await?;
+
+    let organization = sqlx::query!(
+        "UPDATE organizations SET name = $1, timezone = $2 WHERE id = $3 RETURNING id, name, timezone, created_at",
+        payload.name,
+        payload.timezone,
+        id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Json(Organization {
+        id: organization.id,
+        name: organization.name,
+        timezone: organization.timezone,
+        role,
+        created_at: organization.created_at,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/organizations/{id}/invitations",
+    tag = "organizations",
+    params(("id" = i32, Path, description = "Organization id")),
+    request_body = InvitationRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Invitation emailed, with a token to accept it by", body = Invitation),
+        (status = 400, description = "Invalid id or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "The caller's role can't invite with that role", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in", body = ErrorResponse),
+        (status = 409, description = "The email's user is a member already", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn create_invitation(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    JsonBody(mut payload): JsonBody<InvitationRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    payload.validate()?;
+
+    policy::authorize(&state.pool, auth.id, id, Action::Invite(payload.role)).await?;
+
+    let member = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM organization_members m JOIN users u ON u.id = m.user_id
+            WHERE m.organization_id = $1 AND lower(u.email) = $2
+        ) AS "member!""#,
+        id,
+        payload.email
+    )
+    .fetch_one(&state.pool)
+    .await?;
+    if member {
+        return Err(AppError::Conflict("already_a_member"));
+    }
+
+    let token = random_token();
+
+    let invitation = sqlx::query_as!(
+        Invitation,
+        r#"INSERT INTO organization_invitations (organization_id, email, role, token_hash, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, now() + make_interval(days => $6))
+        RETURNING id, organization_id, email, role AS "role: MemberRole", expires_at, created_at"#,
+        id,
+        payload.email,
+        payload.role as MemberRole,
+        hash_token(&token),
+        auth.id,
+        INVITATION_TTL_DAYS
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    let names = sqlx::query!(
+        r#"SELECT o.name, (SELECT name FROM users WHERE id = $2) AS "inviter!" FROM organizations o WHERE o.id = $1"#,
+        id,
+        auth.id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    let email = InvitationEmail {
+        email: invitation.email.clone(),
+        organization: names.name,
+        inviter: names.inviter,
+        link: format!("{}/invitations/accept?token={}", state.app_url, token),
+        token,
+        expires_in_secs: INVITATION_TTL_DAYS as u64 * 24 * 60 * 60,
+    };
+    templates::send(&*state.mailer, &invitation.email, Locale::En, &email);
+
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/invitations/accept",
+    tag = "organizations",
+    request_body = AcceptInvitationRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The organization joined", body = Organization),
+        (status = 400, description = "Invalid, used or expired token, or a malformed body", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "The invitation is for another email", body = ErrorResponse),
+    )
+)]
+async fn accept_invitation(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(payload): JsonBody<AcceptInvitationRequest>,
+) -> Result<Json<Organization>, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let invitation = sqlx::query!(
+        r#"SELECT i.id, i.organization_id, i.role AS "role: MemberRole", i.expires_at > now() AS "live!",
+            i.accepted_at IS NOT NULL AS "used!", lower(i.email) = lower(u.email) AS "for_caller!"
+        FROM organization_invitations i, users u
+        WHERE i.token_hash = $1 AND u.id = $2
+        FOR UPDATE OF i"#,
+        hash_token(&payload.token),
+        auth.id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::BadRequest("invalid_invitation_token"))?;
+
+    if invitation.used {
+        return Err(AppError::BadRequest("invitation_token_used"));
+    }
+    if !invitation.live {
+        return Err(AppError::BadRequest("invitation_token_expired"));
+    }
+    if !invitation.for_caller {
+        return Err(AppError::Forbidden("invitation_for_another_email"));
+    }
+
+    // Someone who joined another way since keeps the role they have.
+    sqlx::query!(
+        "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)
+        ON CONFLICT (organization_id, user_id) DO NOTHING",
+        invitation.organization_id,
+        auth.id,
+        invitation.role as MemberRole
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("UPDATE organization_invitations SET accepted_at = now() WHERE id = $1", invitation.id)
+        .execute(&mut *tx)
+        .await?;
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"SELECT o.id, o.name, o.timezone, m.role AS "role: MemberRole", o.created_at
+        FROM organizations o
+        JOIN organization_members m ON m.organization_id = o.id AND m.user_id = $2
+        WHERE o.id = $1"#,
+        invitation.organization_id,
+        auth.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    // The organization's projects are theirs to see now.
+    cache::forget_projects(&*state.cache, [auth.id]).await;
+
+    Ok(Json(organization))
+}
+
+#[utoipa::path(
+    get,
+    path = "/organizations/{id}/members",
+    tag = "organizations",
+    params(("id" = i32, Path, description = "Organization id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The organization's members, by name", body = [Member]),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in", body = ErrorResponse),
+    )
+)]
+async fn read_members(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<Vec<Member>>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    policy::authorize(&state.pool, auth.id, id, Action::View).await?;
+
+    let members = sqlx::query_as!(
+        Member,
+        r#"SELECT u.id AS user_id, u.name, u.email, m.role AS "role: MemberRole", m.created_at AS joined_at
+        FROM organization_members m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.organization_id = $1
+        ORDER BY lower(u.name), u.id"#,
+        id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(members))
+}
+
+#[utoipa::path(
+    put,
+    path = "/organizations/{id}/members/{user_id}",
+    tag = "organizations",
+    params(("id" = i32, Path, description = "Organization id"), ("user_id" = i32, Path, description = "Member's user id")),
+    request_body = MemberRoleRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The member, with their new role", body = Member),
+        (status = 400, description = "Invalid ids or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "The caller's role can't make that change", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in, or no such member", body = ErrorResponse),
+        (status = 409, description = "It would leave the organization without an owner", body = ErrorResponse),
+    )
+)]
+async fn update_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ids: Result<Path<(i32, i32)>, PathRejection>,
+    JsonBody(payload): JsonBody<MemberRoleRequest>,
+) -> Result<Json<Member>, AppError> {
+    let Path((id, user_id)) = ids.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let mut tx = state.pool.begin().await?;
+    let current = lock_member(&mut tx, auth.id, id, user_id).await?;
+
+    policy::authorize(&mut *tx, auth.id, id, Action::ChangeRole { from: current, to: payload.role }).await?;
+    if current == MemberRole::Owner && payload.role != MemberRole::Owner {
+        require_another_owner(&mut tx, id, user_id).await?;
+    }
+
+    let member = sqlx::query_as!(
+        Member,
+        r#"UPDATE organization_members m SET role = $3
+        FROM users u
+        WHERE m.organization_id = $1 AND m.user_id = $2 AND u.id = m.user_id
+        RETURNING u.id AS user_id, u.name, u.email, m.role AS "role: MemberRole", m.created_at AS joined_at"#,
+        id,
+        user_id,
+        payload.role as MemberRole
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(member))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/organizations/{id}/members/{user_id}",
+    tag = "organizations",
+    params(("id" = i32, Path, description = "Organization id"), ("user_id" = i32, Path, description = "Member's user id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Member removed, or the caller left; their entries stay theirs, and count \
+            in team reports up to now"),
+        (status = 400, description = "Invalid ids", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "The caller's role can't remove that member", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in, or no such member", body = ErrorResponse),
+        (status = 409, description = "It would leave the organization without an owner", body = ErrorResponse),
+    )
+)]
+async fn remove_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ids: Result<Path<(i32, i32)>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path((id, user_id)) = ids.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let mut tx = state.pool.begin().await?;
+    let current = lock_member(&mut tx, auth.id, id, user_id).await?;
+
+    if user_id != auth.id {
+        policy::authorize(&mut *tx, auth.id, id, Action::Remove(current)).await?;
+    }
+    if current == MemberRole::Owner {
+        require_another_owner(&mut tx, id, user_id).await?;
+    }
+
+    sqlx::query!(
+        "DELETE FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO organization_former_members (organization_id, user_id, left_at) VALUES ($1, $2, $3)
+        ON CONFLICT (organization_id, user_id) DO UPDATE SET left_at = EXCLUDED.left_at",
+        id,
+        user_id,
+        state.clock.now()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    cache::forget_projects(&*state.cache, [user_id]).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The role of the organization's member `user_id`. Locks the
+/// organization first, so concurrent changes can't each leave the other
+/// as the last owner and both go through.
+async fn lock_member(
+    conn: &mut sqlx::PgConnection,
+    caller: i32,
+    id: i32,
+    user_id: i32,
+) -> Result<MemberRole, AppError> {
+    policy::authorize(&mut *conn, caller, id, Action::View).await?;
+
+    sqlx::query!("SELECT id FROM organizations WHERE id = $1 FOR UPDATE", id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    sqlx::query_scalar!(
+        r#"SELECT role AS "role: MemberRole" FROM organization_members WHERE organization_id = $1 AND user_id = $2"#,
+        id,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(AppError::NotFound("member_not_found"))
+}
+
+async fn require_another_owner(conn: &mut sqlx::PgConnection, id: i32, user_id: i32) -> Result<(), AppError> {
+    let others = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM organization_members
+        WHERE organization_id = $1 AND role = 'owner' AND user_id <> $2"#,
+        id,
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if others == 0 {
+        return Err(AppError::Conflict("last_owner"));
+    }
+
+    Ok(())
+}
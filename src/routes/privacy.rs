@@ -0,0 +1,213 @@
+use axum::{
+    extract::{rejection::PathRejection, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::audit::{self, Event};
+use crate::auth::AuthUser;
+use crate::cache;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::{ClientIp, JsonBody, UserAgent};
+use crate::jobs;
+use crate::models::{AccountExport, AccountExportStatus, AuditEventType, EraseAccountRequest};
+use crate::privacy::{self, ExportJob};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/me/export", get(export_account))
+        .route("/me/exports/{id}", get(read_account_export))
+        .route("/me/erase", post(erase_account))
+}
+
+/// Where an export still being built will be.
+fn pending(id: i32, created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> Response {
+    let download_url = format!("/me/exports/{}", id);
+    let status = AccountExportStatus { id, download_url: download_url.clone(), created_at, expires_at };
+
+    (StatusCode::ACCEPTED, [(header::LOCATION, download_url)], Json(status)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/export",
+    tag = "users",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Everything kept about the account", body = AccountExport),
+        (status = 202, description = "Too big to build right away; it will be at the download URL", body = AccountExportStatus,
+            headers(("Location" = String, description = "Where the export will be"))),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn export_account(State(state): State<AppState>, auth: AuthUser) -> Result<Response, AppError> {
+    let entries = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM time_entries WHERE user_id = $1"#, auth.id)
+        .fetch_one(&state.pool)
+        .await?;
+
+    if entries <= privacy::INLINE_EXPORT_MAX_ENTRIES {
+        let export = privacy::build(&state.pool, auth.id)
+            .await?
+            .ok_or(AppError::NotFound("user_not_found"))?;
+        return Ok(Json(export).into_response());
+    }
+
+    // Asking again while one is on its way points at that one.
+    let mut tx = state.pool.begin().await?;
+    sqlx::query!("SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE", auth.id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::NotFound("user_not_found"))?;
+
+    let existing = sqlx::query!(
+        "SELECT id, created_at, expires_at FROM account_exports WHERE user_id = $1 AND data IS NULL AND expires_at > now()",
+        auth.id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(existing) = existing {
+        return Ok(pending(existing.id, existing.created_at, existing.expires_at));
+    }
+
+    let export = sqlx::query!(
+        "INSERT INTO account_exports (user_id, expires_at) VALUES ($1, now() + make_interval(secs => $2))
+        RETURNING id, created_at, expires_at",
+        auth.id,
+        privacy::EXPORT_TTL_SECS as f64
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    jobs::enqueue(&mut *tx, privacy::EXPORT_JOB, ExportJob { export_id: export.id }).await?;
+    tx.commit().await?;
+
+    Ok(pending(export.id, export.created_at, export.expires_at))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/exports/{id}",
+    tag = "users",
+    params(("id" = i32, Path, description = "Export id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The finished export", body = AccountExport),
+        (status = 202, description = "Still being built", body = AccountExportStatus),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such export, or it expired", body = ErrorResponse),
+    )
+)]
+async fn read_account_export(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Response, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let export = sqlx::query!(
+        "SELECT id, data, created_at, expires_at FROM account_exports WHERE id = $1 AND user_id = $2 AND expires_at > now()",
+        id,
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("export_not_found"))?;
+
+    Ok(match export.data {
+        Some(data) => Json(data).into_response(),
+        None => pending(export.id, export.created_at, export.expires_at),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/erase",
+    tag = "users",
+    request_body = EraseAccountRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Account erased: personal data is gone, and what was invoiced or approved is kept without it"),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Wrong password", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 409, description = "Last owner of an organization", body = ErrorResponse),
+    )
+)]
+async fn erase_account(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(payload): JsonBody<EraseAccountRequest>,
+) -> Result<StatusCode, AppError> {
+    let password_hash = sqlx::query_scalar!(
+        "SELECT password_hash FROM users WHERE id = $1 AND deleted_at IS NULL",
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+
+    if !state.passwords.verify(payload.password, password_hash).await? {
+        return Err(AppError::Forbidden("invalid_password"));
+    }
+
+    let mut tx = state.pool.begin().await?;
+
+    let email = sqlx::query_scalar!("SELECT email FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE", auth.id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::NotFound("user_not_found"))?;
+
+    // An organization is never left without an owner.
+    let sole_owner = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM organization_members m WHERE m.user_id = $1 AND m.role = 'owner'
+                AND NOT EXISTS (SELECT 1 FROM organization_members o
+                    WHERE o.organization_id = m.organization_id AND o.role = 'owner' AND o.user_id <> $1)
+        ) AS "exists!""#,
+        auth.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if sole_owner {
+        return Err(AppError::Conflict("last_owner"));
+    }
+
+    privacy::erase(&mut tx, auth.id, &email).await?;
+
+    // Refresh tokens went with the rest; the token used here goes too.
+    if auth.api_key_id.is_none() {
+        sqlx::query!(
+            "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, to_timestamp($2)) ON CONFLICT (jti) DO NOTHING",
+            auth.jti,
+            auth.exp as f64
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::AccountErased,
+        actor: Some(auth.id),
+        target: Some(("user", auth.id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({}),
+    })
+    .await?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::me_key(auth.id)).await;
+    state.cache.delete(&cache::timer_key(auth.id)).await;
+    cache::forget_projects(&*state.cache, [auth.id]).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,326 @@
+use axum::{
+    extract::{rejection::{PathRejection, QueryRejection}, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+
+use crate::auth::AuthUser;
+use crate::cache;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::JsonBody;
+use crate::models::{Page, Project, ProjectQuery, ProjectRequest};
+use crate::policy::{self, Action};
+use crate::routes::{invalid_query, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+
+/// Raised when a project still has entries on it.
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+// A project is visible to its owner, or when it's an organization's, to
+// the organization's members; any other is reported missing. What may be
+// done with one is up to `policy`.
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/projects", get(read_projects).post(create_project))
+        .route("/projects/{id}", get(read_project).put(update_project).delete(delete_project))
+}
+
+#[utoipa::path(
+    post,
+    path = "/projects",
+    tag = "entries",
+    request_body = ProjectRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Project created", body = Project,
+            headers(("Location" = String, description = "Where the new project lives"))),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of the organization", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, or an organization the user isn't in", body = ValidationErrors),
+    )
+)]
+async fn create_project(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<ProjectRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+    if let Some(organization_id) = payload.organization_id {
+        authorize_organization(&state, auth.id, organization_id).await?;
+    }
+
+    let project = sqlx::query_as!(
+        Project,
+        "INSERT INTO projects (user_id, name, color, archived, hourly_rate_cents, currency, organization_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, user_id, name, color, archived, hourly_rate_cents, currency, organization_id, created_at",
+        auth.id,
+        payload.name,
+        payload.color,
+        payload.archived,
+        payload.hourly_rate_cents,
+        payload.currency,
+        payload.organization_id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+    forget_lists(&state, project.user_id, &[project.organization_id]).await?;
+
+    let location = format!("/projects/{}", project.id);
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(project)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/projects",
+    tag = "entries",
+    params(ProjectQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "A page of the user's projects and their organizations', by name",
+            body = Page<Project>),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_projects(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<ProjectQuery>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    // The first page is what the apps load every time they open.
+    if query.limit.is_none() && query.offset.is_none() {
+        let key = cache::projects_key(auth.id, query.include_archived);
+        return cache::cached_json(&*state.cache, &key, state.cache_ttl, list_projects(&state, auth.id, &query)).await;
+    }
+
+    list_projects(&state, auth.id, &query).await.map(|page| Json(page).into_response())
+}
+
+/// A page of the projects the user can see, for `GET /projects` and
+/// GraphQL's `projects`.
+pub(crate) async fn list_projects(state: &AppState, user_id: i32, query: &ProjectQuery) -> Result<Page<Project>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(invalid_query("limit", &format!("must be between 1 and {}", MAX_PAGE_LIMIT)));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(invalid_query("offset", "must not be negative"));
+    }
+
+    let items = sqlx::query_as!(
+        Project,
+        "SELECT id, user_id, name, color, archived, hourly_rate_cents, currency, organization_id, created_at FROM projects
+        WHERE (organization_id IS NULL AND user_id = $1
+                OR organization_id IN (SELECT organization_id FROM organization_members WHERE user_id = $1))
+            AND ($2 OR NOT archived)
+        ORDER BY lower(name), id
+        LIMIT $3 OFFSET $4",
+        user_id,
+        query.include_archived,
+        limit,
+        offset
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "total!" FROM projects
+        WHERE (organization_id IS NULL AND user_id = $1
+                OR organization_id IN (SELECT organization_id FROM organization_members WHERE user_id = $1))
+            AND ($2 OR NOT archived)"#,
+        user_id,
+        query.include_archived
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Page {
+        items,
+        total,
+        limit,
+        offset,
+        next_cursor: None,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/projects/{id}",
+    tag = "entries",
+    params(("id" = i32, Path, description = "Project id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The project, archived or not", body = Project),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such project the user can see", body = ErrorResponse),
+    )
+)]
+async fn read_project(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<Project>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    policy::authorize_project(&state.pool, auth.id, id, Action::View).await?;
+
+    let project = sqlx::query_as!(
+        Project,
+        "SELECT id, user_id, name, color, archived, hourly_rate_cents, currency, organization_id, created_at FROM projects WHERE id = $1",
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("project_not_found"))?;
+
+    Ok(Json(project))
+}
+
+#[utoipa::path(
+    put,
+    path = "/projects/{id}",
+    tag = "entries",
+    params(("id" = i32, Path, description = "Project id")),
+    request_body = ProjectRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The updated project", body = Project),
+        (status = 400, description = "Invalid id or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of the project's organization, or of the one it's moved to",
+            body = ErrorResponse),
+        (status = 404, description = "No such project the user can see", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, or an organization the user isn't in", body = ValidationErrors),
+    )
+)]
+async fn update_project(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    JsonBody(mut payload): JsonBody<ProjectRequest>,
+) -> Result<Json<Project>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    payload.validate()?;
+
+    policy::authorize_project(&state.pool, auth.id, id, Action::ManageProjects).await?;
+
+    let current = sqlx::query!("SELECT user_id, organization_id FROM projects WHERE id = $1", id)
+        .fetch_optional(&state.pool)
+        .await?
+        .ok_or(AppError::NotFound("project_not_found"))?;
+    if payload.organization_id != current.organization_id {
+        match payload.organization_id {
+            Some(organization_id) => authorize_organization(&state, auth.id, organization_id).await?,
+            // An organization's project only goes back to the one who
+            // made it.
+            None if current.user_id != auth.id => return Err(AppError::Forbidden("insufficient_role")),
+            None => {}
+        }
+    }
+
+    let project = sqlx::query_as!(
+        Project,
+        "UPDATE projects SET name = $1, color = $2, archived = $3, hourly_rate_cents = $4, currency = $5,
+            organization_id = $6
+        WHERE id = $7
+        RETURNING id, user_id, name, color, archived, hourly_rate_cents, currency, organization_id, created_at",
+        payload.name,
+        payload.color,
+        payload.archived,
+        payload.hourly_rate_cents,
+        payload.currency,
+        payload.organization_id,
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("project_not_found"))?;
+    // Moving it takes it out of one organization's lists and into another's.
+    forget_lists(&state, project.user_id, &[current.organization_id, project.organization_id]).await?;
+
+    Ok(Json(project))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/projects/{id}",
+    tag = "entries",
+    params(("id" = i32, Path, description = "Project id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Project deleted"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of the project's organization", body = ErrorResponse),
+        (status = 404, description = "No such project the user can see", body = ErrorResponse),
+        (status = 409, description = "The project still has entries; archive it instead", body = ErrorResponse),
+    )
+)]
+async fn delete_project(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    policy::authorize_project(&state.pool, auth.id, id, Action::ManageProjects).await?;
+
+    let deleted = sqlx::query!("DELETE FROM projects WHERE id = $1 RETURNING user_id, organization_id", id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|err| match &err {
+            sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some(FOREIGN_KEY_VIOLATION) => {
+                AppError::Conflict("project_has_entries")
+            }
+            _ => AppError::from(err),
+        })?
+        .ok_or(AppError::NotFound("project_not_found"))?;
+    forget_lists(&state, deleted.user_id, &[deleted.organization_id]).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Forgets the cached project lists of everyone who sees a project of
+/// `owner_id`'s in any of `organization_ids`.
+async fn forget_lists(state: &AppState, owner_id: i32, organization_ids: &[Option<i32>]) -> Result<(), AppError> {
+    let organization_ids: Vec<i32> = organization_ids.iter().flatten().copied().collect();
+    let mut user_ids = sqlx::query_scalar!(
+        "SELECT user_id FROM organization_members WHERE organization_id = ANY($1)",
+        &organization_ids
+    )
+    .fetch_all(&state.pool)
+    .await?;
+    user_ids.push(owner_id);
+
+    cache::forget_projects(&*state.cache, user_ids).await;
+    Ok(())
+}
+
+/// Checks the user may put projects in the organization. One they aren't
+/// in is a field error here, like a project that isn't theirs on an entry.
+async fn authorize_organization(state: &AppState, user_id: i32, organization_id: i32) -> Result<(), AppError> {
+    match policy::authorize(&state.pool, user_id, organization_id, Action::ManageProjects).await {
+        Err(AppError::NotFound(_)) => {
+            let mut errors = ValidationErrors::default();
+            errors.add("organization_id", "not found");
+            Err(AppError::Validation(errors))
+        }
+        result => result.map(|_| ()),
+    }
+}
@@ -0,0 +1,460 @@
+use axum::{
+    extract::{rejection::{PathRejection, QueryRejection}, Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{Days, Months, Weekday};
+use chrono_tz::Tz;
+
+use crate::auth::AuthUser;
+use crate::clock::local_midnight;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::models::{
+    PomodoroQuery, PomodoroReport, ReportAmount, ReportBucket, ReportGroup, ReportQuery, SummaryReport, TeamReport,
+    TeamReportGroup, TeamReportQuery, WeekStart,
+};
+use crate::policy::{self, Action};
+use crate::routes::invalid_query;
+use crate::routes::timer::{finish_organization_pomodoros, finish_pomodoros};
+use crate::routes::users::load_settings;
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/reports/summary", get(read_summary))
+        .route("/reports/pomodoros", get(read_pomodoros))
+        .route("/organizations/{id}/reports", get(read_team_report))
+}
+
+// Every query here starts from the same `spans`: the part of each of the
+// user's entries inside the range, a running one counting up to now. All
+// the summing happens in Postgres. Days are the user's, in their time zone.
+
+#[utoipa::path(
+    get,
+    path = "/reports/summary",
+    tag = "reports",
+    params(ReportQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Time tracked in the range, in buckets", body = SummaryReport),
+        (status = 400, description = "Invalid query parameters, or a range over a year", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_summary(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<ReportQuery>, QueryRejection>,
+) -> Result<Json<SummaryReport>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    summary(&state, auth.id, &query).await.map(Json)
+}
+
+/// The user's time in the range, for `GET /reports/summary` and GraphQL's
+/// `reportSummary`.
+pub(crate) async fn summary(state: &AppState, user_id: i32, query: &ReportQuery) -> Result<SummaryReport, AppError> {
+    let settings = load_settings(state.db().reader(), user_id).await?;
+    let tz = settings.tz();
+
+    // Written to the primary; the replica can take a moment to catch up.
+    let now = state.clock.now();
+    finish_pomodoros(state.db().writer(), user_id, now).await?;
+
+    let week = now.with_timezone(&tz).date_naive().week(settings.week_start.weekday());
+    let from = query.from.unwrap_or(week.first_day());
+    let to = query.to.unwrap_or(week.last_day());
+
+    if to < from {
+        return Err(invalid_query("to", "must not be before from"));
+    }
+    if from.checked_add_months(Months::new(12)).is_none_or(|limit| to >= limit) {
+        return Err(invalid_query("to", "range must be under a year"));
+    }
+
+    let start = local_midnight(from, tz);
+    let end = local_midnight(to + Days::new(1), tz);
+
+    let totals = sqlx::query!(
+        r#"WITH spans AS (
+            SELECT billable, GREATEST(started_at, $2) AS lo, LEAST(COALESCE(ended_at, $4), $3) AS hi
+            FROM time_entries
+            WHERE user_id = $1 AND started_at < $3 AND GREATEST(started_at, $2) < LEAST(COALESCE(ended_at, $4), $3)
+        )
+        SELECT COALESCE(EXTRACT(EPOCH FROM sum(hi - lo)), 0)::bigint AS "tracked_secs!", count(*) AS "entries!",
+            COALESCE(EXTRACT(EPOCH FROM sum(hi - lo) FILTER (WHERE billable)), 0)::bigint AS "billable_seconds!"
+        FROM spans"#,
+        user_id,
+        start,
+        end,
+        now
+    )
+    .fetch_one(state.db().reader())
+    .await?;
+
+    // Each entry is worth the rate it was made under, not the project's
+    // rate today.
+    let amounts = sqlx::query_as!(
+        ReportAmount,
+        r#"WITH spans AS (
+            SELECT rate_cents, currency, GREATEST(started_at, $2) AS lo, LEAST(COALESCE(ended_at, $4), $3) AS hi
+            FROM time_entries
+            WHERE user_id = $1 AND billable AND rate_cents IS NOT NULL
+                AND started_at < $3 AND GREATEST(started_at, $2) < LEAST(COALESCE(ended_at, $4), $3)
+        )
+        SELECT currency AS "currency!", EXTRACT(EPOCH FROM sum(hi - lo))::bigint AS "billable_seconds!",
+            round(sum(EXTRACT(EPOCH FROM hi - lo) * rate_cents) / 3600)::bigint AS "amount_cents!"
+        FROM spans
+        GROUP BY currency
+        ORDER BY currency"#,
+        user_id,
+        start,
+        end,
+        now
+    )
+    .fetch_all(state.db().reader())
+    .await?;
+
+    let buckets = match query.group_by {
+        ReportGroup::Day | ReportGroup::Week => {
+            let unit = if query.group_by == ReportGroup::Day { "day" } else { "week" };
+            // `date_trunc` weeks start on Monday; Sunday ones are found a
+            // day ahead.
+            let shift = i32::from(query.group_by == ReportGroup::Week && settings.week_start == WeekStart::Sunday);
+            sqlx::query_as!(
+                ReportBucket,
+                r#"WITH spans AS (
+                    SELECT id, GREATEST(started_at, $2) AS lo, LEAST(COALESCE(ended_at, $4), $3) AS hi
+                    FROM time_entries
+                    WHERE user_id = $1 AND started_at < $3 AND GREATEST(started_at, $2) < LEAST(COALESCE(ended_at, $4), $3)
+                ),
+                buckets AS (
+                    SELECT day, day AT TIME ZONE $6 AS lo, (day + ('1 ' || $5)::interval) AT TIME ZONE $6 AS hi
+                    FROM generate_series(
+                        date_trunc($5, ($2 AT TIME ZONE $6) + make_interval(days => $7)) - make_interval(days => $7),
+                        $3 AT TIME ZONE $6 - interval '1 microsecond',
+                        ('1 ' || $5)::interval
+                    ) AS day
+                )
+                SELECT to_char(b.day, 'YYYY-MM-DD') AS key, to_char(b.day, 'YYYY-MM-DD') AS label,
+                    EXTRACT(EPOCH FROM sum(LEAST(s.hi, b.hi) - GREATEST(s.lo, b.lo)))::bigint AS "tracked_secs!",
+                    count(*) AS "entries!"
+                FROM buckets b
+                JOIN spans s ON s.lo < b.hi AND s.hi > b.lo
+                GROUP BY b.day
+                ORDER BY b.day"#,
+                user_id,
+                start,
+                end,
+                now,
+                unit,
+                tz.name(),
+                shift
+            )
+            .fetch_all(state.db().reader())
+            .await?
+        }
+        ReportGroup::Project => {
+            sqlx::query_as!(
+                ReportBucket,
+                r#"WITH spans AS (
+                    SELECT project_id, GREATEST(started_at, $2) AS lo, LEAST(COALESCE(ended_at, $4), $3) AS hi
+                    FROM time_entries
+                    WHERE user_id = $1 AND started_at < $3 AND GREATEST(started_at, $2) < LEAST(COALESCE(ended_at, $4), $3)
+                )
+                SELECT s.project_id::text AS key, p.name::text AS label,
+                    EXTRACT(EPOCH FROM sum(s.hi - s.lo))::bigint AS "tracked_secs!",
+                    count(*) AS "entries!"
+                FROM spans s
+                LEFT JOIN projects p ON p.id = s.project_id
+                GROUP BY s.project_id, p.name
+                ORDER BY lower(p.name) NULLS LAST, s.project_id"#,
+                user_id,
+                start,
+                end,
+                now
+            )
+            .fetch_all(state.db().reader())
+            .await?
+        }
+        ReportGroup::Tag => {
+            sqlx::query_as!(
+                ReportBucket,
+                r#"WITH spans AS (
+                    SELECT id, GREATEST(started_at, $2) AS lo, LEAST(COALESCE(ended_at, $4), $3) AS hi
+                    FROM time_entries
+                    WHERE user_id = $1 AND started_at < $3 AND GREATEST(started_at, $2) < LEAST(COALESCE(ended_at, $4), $3)
+                )
+                SELECT t.name::text AS key, t.name::text AS label,
+                    EXTRACT(EPOCH FROM sum(s.hi - s.lo))::bigint AS "tracked_secs!",
+                    count(*) AS "entries!"
+                FROM spans s
+                LEFT JOIN time_entry_tags et ON et.entry_id = s.id
+                LEFT JOIN tags t ON t.id = et.tag_id
+                GROUP BY t.id, t.name
+                ORDER BY lower(t.name) NULLS LAST"#,
+                user_id,
+                start,
+                end,
+                now
+            )
+            .fetch_all(state.db().reader())
+            .await?
+        }
+    };
+
+    Ok(SummaryReport {
+        from,
+        to,
+        timezone: tz.name().to_string(),
+        group_by: query.group_by,
+        tracked_secs: totals.tracked_secs,
+        entries: totals.entries,
+        billable_seconds: totals.billable_seconds,
+        amount_cents: (amounts.len() == 1).then(|| amounts[0].amount_cents),
+        currency: (amounts.len() == 1).then(|| amounts[0].currency.clone()),
+        amounts,
+        buckets,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/pomodoros",
+    tag = "reports",
+    params(PomodoroQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Pomodoros run to their target on the day, counted by when they started",
+            body = PomodoroReport),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_pomodoros(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<PomodoroQuery>, QueryRejection>,
+) -> Result<Json<PomodoroReport>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let tz = load_settings(state.db().reader(), auth.id).await?.tz();
+    let now = state.clock.now();
+    finish_pomodoros(state.db().writer(), auth.id, now).await?;
+
+    let date = query.date.unwrap_or(now.with_timezone(&tz).date_naive());
+
+    let totals = sqlx::query!(
+        r#"SELECT count(*) AS "completed!", COALESCE(EXTRACT(EPOCH FROM sum(ended_at - started_at)), 0)::bigint AS "tracked_secs!"
+        FROM time_entries
+        WHERE user_id = $1 AND pomodoro AND started_at >= $2 AND started_at < $3"#,
+        auth.id,
+        local_midnight(date, tz),
+        local_midnight(date + Days::new(1), tz)
+    )
+    .fetch_one(state.db().reader())
+    .await?;
+
+    Ok(Json(PomodoroReport {
+        date,
+        timezone: tz.name().to_string(),
+        completed: totals.completed,
+        tracked_secs: totals.tracked_secs,
+    }))
+}
+
+// Team reports start from `spans` too, over the organization's projects
+// only. A former member's time counts up to when they left, so past
+// ranges read the same after someone goes.
+
+#[utoipa::path(
+    get,
+    path = "/organizations/{id}/reports",
+    tag = "reports",
+    params(("id" = i32, Path, description = "Organization id"), TeamReportQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Time tracked on the organization's projects in the range, in buckets",
+            body = TeamReport),
+        (status = 400, description = "Invalid id or query parameters, or a range over a year", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of the organization", body = ErrorResponse),
+        (status = 404, description = "No such organization the caller is in", body = ErrorResponse),
+    )
+)]
+async fn read_team_report(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    query: Result<Query<TeamReportQuery>, QueryRejection>,
+) -> Result<Json<TeamReport>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    // Against the primary, so a member just removed is refused right away.
+    policy::authorize(state.db().writer(), auth.id, id, Action::ViewReports).await?;
+
+    let timezone = sqlx::query_scalar!("SELECT timezone FROM organizations WHERE id = $1", id)
+        .fetch_one(state.db().reader())
+        .await?;
+    let tz = timezone.and_then(|timezone| timezone.parse().ok()).unwrap_or(Tz::UTC);
+
+    let now = state.clock.now();
+    finish_organization_pomodoros(state.db().writer(), id, now).await?;
+
+    let week = now.with_timezone(&tz).date_naive().week(Weekday::Mon);
+    let from = query.from.unwrap_or(week.first_day());
+    let to = query.to.unwrap_or(week.last_day());
+
+    if to < from {
+        return Err(invalid_query("to", "must not be before from"));
+    }
+    if from.checked_add_months(Months::new(12)).is_none_or(|limit| to >= limit) {
+        return Err(invalid_query("to", "range must be under a year"));
+    }
+
+    let start = local_midnight(from, tz);
+    let end = local_midnight(to + Days::new(1), tz);
+
+    let totals = sqlx::query!(
+        r#"WITH spans AS (
+            SELECT GREATEST(e.started_at, $2) AS lo,
+                LEAST(COALESCE(e.ended_at, $4), $3, CASE WHEN m.user_id IS NULL THEN f.left_at END) AS hi
+            FROM time_entries e
+            JOIN projects p ON p.id = e.project_id
+            LEFT JOIN organization_members m ON m.organization_id = p.organization_id AND m.user_id = e.user_id
+            LEFT JOIN organization_former_members f ON f.organization_id = p.organization_id AND f.user_id = e.user_id
+            WHERE p.organization_id = $1 AND (m.user_id IS NOT NULL OR f.user_id IS NOT NULL) AND e.started_at < $3
+        )
+        SELECT COALESCE(EXTRACT(EPOCH FROM sum(hi - lo)), 0)::bigint AS "tracked_secs!", count(*) AS "entries!"
+        FROM spans
+        WHERE lo < hi"#,
+        id,
+        start,
+        end,
+        now
+    )
+    .fetch_one(state.db().reader())
+    .await?;
+
+    let buckets = match query.group_by {
+        TeamReportGroup::Member => {
+            sqlx::query_as!(
+                ReportBucket,
+                r#"WITH spans AS (
+                    SELECT e.user_id, GREATEST(e.started_at, $2) AS lo,
+                        LEAST(COALESCE(e.ended_at, $4), $3, CASE WHEN m.user_id IS NULL THEN f.left_at END) AS hi
+                    FROM time_entries e
+                    JOIN projects p ON p.id = e.project_id
+                    LEFT JOIN organization_members m ON m.organization_id = p.organization_id AND m.user_id = e.user_id
+                    LEFT JOIN organization_former_members f
+                        ON f.organization_id = p.organization_id AND f.user_id = e.user_id
+                    WHERE p.organization_id = $1 AND (m.user_id IS NOT NULL OR f.user_id IS NOT NULL) AND e.started_at < $3
+                )
+                SELECT s.user_id::text AS key, u.name::text AS label,
+                    EXTRACT(EPOCH FROM sum(s.hi - s.lo))::bigint AS "tracked_secs!",
+                    count(*) AS "entries!"
+                FROM spans s
+                JOIN users u ON u.id = s.user_id
+                WHERE s.lo < s.hi
+                GROUP BY s.user_id, u.name
+                ORDER BY lower(u.name), s.user_id"#,
+                id,
+                start,
+                end,
+                now
+            )
+            .fetch_all(state.db().reader())
+            .await?
+        }
+        TeamReportGroup::Project => {
+            sqlx::query_as!(
+                ReportBucket,
+                r#"WITH spans AS (
+                    SELECT e.project_id, GREATEST(e.started_at, $2) AS lo,
+                        LEAST(COALESCE(e.ended_at, $4), $3, CASE WHEN m.user_id IS NULL THEN f.left_at END) AS hi
+                    FROM time_entries e
+                    JOIN projects p ON p.id = e.project_id
+                    LEFT JOIN organization_members m ON m.organization_id = p.organization_id AND m.user_id = e.user_id
+                    LEFT JOIN organization_former_members f
+                        ON f.organization_id = p.organization_id AND f.user_id = e.user_id
+                    WHERE p.organization_id = $1 AND (m.user_id IS NOT NULL OR f.user_id IS NOT NULL) AND e.started_at < $3
+                )
+                SELECT s.project_id::text AS key, p.name::text AS label,
+                    EXTRACT(EPOCH FROM sum(s.hi - s.lo))::bigint AS "tracked_secs!",
+                    count(*) AS "entries!"
+                FROM spans s
+                JOIN projects p ON p.id = s.project_id
+                WHERE s.lo < s.hi
+                GROUP BY s.project_id, p.name
+                ORDER BY lower(p.name), s.project_id"#,
+                id,
+                start,
+                end,
+                now
+            )
+            .fetch_all(state.db().reader())
+            .await?
+        }
+        TeamReportGroup::Day => {
+            sqlx::query_as!(
+                ReportBucket,
+                r#"WITH spans AS (
+                    SELECT GREATEST(e.started_at, $2) AS lo,
+                        LEAST(COALESCE(e.ended_at, $4), $3, CASE WHEN m.user_id IS NULL THEN f.left_at END) AS hi
+                    FROM time_entries e
+                    JOIN projects p ON p.id = e.project_id
+                    LEFT JOIN organization_members m ON m.organization_id = p.organization_id AND m.user_id = e.user_id
+                    LEFT JOIN organization_former_members f
+                        ON f.organization_id = p.organization_id AND f.user_id = e.user_id
+                    WHERE p.organization_id = $1 AND (m.user_id IS NOT NULL OR f.user_id IS NOT NULL) AND e.started_at < $3
+                ),
+                buckets AS (
+                    SELECT day, day AT TIME ZONE $5 AS lo, (day + interval '1 day') AT TIME ZONE $5 AS hi
+                    FROM generate_series(
+                        date_trunc('day', $2 AT TIME ZONE $5),
+                        $3 AT TIME ZONE $5 - interval '1 microsecond',
+                        interval '1 day'
+                    ) AS day
+                )
+                SELECT to_char(b.day, 'YYYY-MM-DD') AS key, to_char(b.day, 'YYYY-MM-DD') AS label,
+                    EXTRACT(EPOCH FROM sum(LEAST(s.hi, b.hi) - GREATEST(s.lo, b.lo)))::bigint AS "tracked_secs!",
+                    count(*) AS "entries!"
+                FROM buckets b
+                JOIN spans s ON s.lo < s.hi AND s.lo < b.hi AND s.hi > b.lo
+                GROUP BY b.day
+                ORDER BY b.day"#,
+                id,
+                start,
+                end,
+                now,
+                tz.name()
+            )
+            .fetch_all(state.db().reader())
+            .await?
+        }
+    };
+
+    Ok(Json(TeamReport {
+        from,
+        to,
+        timezone: tz.name().to_string(),
+        group_by: query.group_by,
+        tracked_secs: totals.tracked_secs,
+        entries: totals.entries,
+        buckets,
+    }))
+}
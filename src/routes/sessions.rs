@@ -0,0 +1,208 @@
+use axum::{
+    extract::{rejection::PathRejection, Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde_json::json;
+use sqlx::PgConnection;
+use std::net::IpAddr;
+
+use crate::audit::{self, Event};
+use crate::auth::AuthUser;
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::{ClientIp, UserAgent};
+use crate::models::{AuditEventType, LoginSession};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/me/sessions", get(read_sessions).delete(revoke_other_sessions))
+        .route("/me/sessions/{id}", delete(revoke_session))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/sessions",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Live sessions, the most recently used first", body = Vec<LoginSession>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_sessions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<LoginSession>>, AppError> {
+    let sessions = sqlx::query!(
+        "SELECT id, user_agent, ip, created_at, last_used_at FROM sessions
+        WHERE user_id = $1 AND EXISTS (
+            SELECT 1 FROM refresh_tokens
+            WHERE session_id = sessions.id AND NOT revoked AND expires_at > now()
+        )
+        ORDER BY last_used_at DESC, id DESC",
+        auth.id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| {
+                let (browser, os) = session.user_agent.as_deref().map(describe).unwrap_or_default();
+                LoginSession {
+                    id: session.id,
+                    browser: browser.map(str::to_string),
+                    os: os.map(str::to_string),
+                    user_agent: session.user_agent,
+                    ip: session.ip,
+                    created_at: session.created_at,
+                    last_used_at: session.last_used_at,
+                    current: auth.session_id == Some(session.id),
+                }
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/sessions/{id}",
+    tag = "auth",
+    params(("id" = i32, Path, description = "Session id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Session signed out, its tokens revoked"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such live session", body = ErrorResponse),
+    )
+)]
+async fn revoke_session(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let mut tx = state.pool.begin().await?;
+
+    if revoke(&mut tx, auth.id, Some(id), None).await?.is_empty() {
+        return Err(AppError::NotFound("session_not_found"));
+    }
+    record(&mut tx, &auth, ip, user_agent.as_deref(), &[id]).await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/sessions",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Every other session signed out; with an API key, every session"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let revoked = revoke(&mut tx, auth.id, None, auth.session_id).await?;
+    if !revoked.is_empty() {
+        record(&mut tx, &auth, ip, user_agent.as_deref(), &revoked).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revokes the user's live sessions, only `id` or all but `except`, by
+/// their refresh tokens and the access token each issued last. Returns the
+/// ones revoked. The refresh tokens are deleted rather than marked revoked,
+/// since presenting a revoked one is taken as it having leaked and signs
+/// out every session.
+async fn revoke(
+    tx: &mut PgConnection,
+    user_id: i32,
+    id: Option<i32>,
+    except: Option<i32>,
+) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"WITH revoked AS (
+            DELETE FROM refresh_tokens
+            WHERE user_id = $1 AND session_id IS NOT NULL AND NOT revoked AND expires_at > now()
+                AND ($2::INT IS NULL OR session_id = $2) AND ($3::INT IS NULL OR session_id <> $3)
+            RETURNING session_id
+        ), access AS (
+            INSERT INTO revoked_tokens (jti, expires_at)
+            SELECT access_jti, access_expires_at FROM sessions
+            WHERE id IN (SELECT session_id FROM revoked) AND access_jti IS NOT NULL AND access_expires_at > now()
+            ON CONFLICT (jti) DO NOTHING
+        )
+        SELECT DISTINCT session_id AS "id!" FROM revoked ORDER BY 1"#,
+        user_id,
+        id,
+        except
+    )
+    .fetch_all(tx)
+    .await
+}
+
+async fn record(
+    tx: &mut PgConnection,
+    auth: &AuthUser,
+    ip: Option<IpAddr>,
+    user_agent: Option<&str>,
+    sessions: &[i32],
+) -> Result<(), AppError> {
+    audit::record(tx, Event {
+        event_type: AuditEventType::TokenRevoked,
+        actor: Some(auth.id),
+        target: Some(("user", auth.id)),
+        ip,
+        user_agent,
+        details: json!({ "token": "session", "sessions": sessions }),
+    })
+    .await
+}
+
+/// The browser and operating system a user agent names, for the few common
+/// enough to be worth telling apart. Order matters: Edge and Opera claim to
+/// be Chrome, Chrome claims to be Safari, and Android is Linux underneath.
+fn describe(user_agent: &str) -> (Option<&'static str>, Option<&'static str>) {
+    const BROWSERS: [(&str, &str); 6] = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ];
+    const SYSTEMS: [(&str, &str); 7] = [
+        ("Windows", "Windows"),
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iOS"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ];
+
+    let find = |names: &[(&str, &'static str)]| {
+        names.iter().find(|(marker, _)| user_agent.contains(marker)).map(|(_, name)| *name)
+    };
+    (find(&BROWSERS), find(&SYSTEMS))
+}
@@ -0,0 +1,76 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use crate::auth::AuthUser;
+use crate::error::{AppError, ErrorResponse};
+use crate::models::Tag;
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/tags", get(read_tags))
+}
+
+#[utoipa::path(
+    get,
+    path = "/tags",
+    tag = "entries",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "All of the user's tags, by name", body = [Tag]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_tags(State(state): State<AppState>, auth: AuthUser) -> Result<Json<Vec<Tag>>, AppError> {
+    let tags = sqlx::query_as!(
+        Tag,
+        r#"SELECT t.id, t.name, count(et.entry_id) AS "entries!" FROM tags t
+        LEFT JOIN time_entry_tags et ON et.tag_id = t.id
+        WHERE t.user_id = $1
+        GROUP BY t.id
+        ORDER BY lower(t.name)"#,
+        auth.id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(tags))
+}
+
+/// Makes `names` the tags of each of the entries, creating the ones the
+/// user doesn't have yet. Names are matched ignoring case, and the
+/// spelling given here becomes the tag's name.
+pub(crate) async fn set_entry_tags(
+    conn: &mut sqlx::PgConnection,
+    user_id: i32,
+    entry_ids: &[i32],
+    names: &[String],
+) -> Result<(), sqlx::Error> {
+    let tag_ids = sqlx::query_scalar!(
+        "INSERT INTO tags (user_id, name) SELECT $1, unnest($2::text[])
+        ON CONFLICT (user_id, lower(name)) DO UPDATE SET name = EXCLUDED.name
+        RETURNING id",
+        user_id,
+        names
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM time_entry_tags WHERE entry_id = ANY($1) AND tag_id <> ALL($2)",
+        entry_ids,
+        &tag_ids
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO time_entry_tags (entry_id, tag_id)
+        SELECT entry_id, tag_id FROM unnest($1::int[]) AS entry_id CROSS JOIN unnest($2::int[]) AS tag_id
+        ON CONFLICT DO NOTHING",
+        entry_ids,
+        &tag_ids
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use axum::{
+    extract::{rejection::QueryRejection, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+
+use crate::auth::AuthUser;
+use crate::cache;
+use crate::error::{entry_error, AppError, BodyErrorResponse, ErrorResponse, EntriesErrorResponse};
+use crate::extract::JsonBody;
+use crate::live::EventKind;
+use crate::models::{Countdown, RunningTimer, StartTimerQuery, StartTimerRequest, TimeEntry, TimerMode, WebhookEvent};
+use crate::routes::entries;
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+use crate::webhooks::Audience;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/timer/start", post(start_timer))
+        .route("/timer/stop", post(stop_timer))
+        .route("/timer/current", get(read_current_timer))
+}
+
+#[utoipa::path(
+    post,
+    path = "/timer/start",
+    tag = "entries",
+    params(StartTimerQuery),
+    request_body(content = Option<StartTimerRequest>, description = "Optional"),
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "The new running entry", body = TimeEntry,
+            headers(("Location" = String, description = "Where the entry lives"))),
+        (status = 400, description = "Malformed body or unknown mode", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "A timer is running and auto_stop_timer is off, or a later entry is in the way",
+            body = EntriesErrorResponse),
+        (status = 422, description = "Invalid fields, or a project that isn't the user's", body = ValidationErrors),
+    )
+)]
+async fn start_timer(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<StartTimerQuery>, QueryRejection>,
+    payload: Option<JsonBody<StartTimerRequest>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+    let mut payload = payload.map_or_else(StartTimerRequest::default, |JsonBody(payload)| payload);
+    payload.validate()?;
+
+    let entry = start_timer_for(&state, auth.id, query.mode, payload).await?;
+    let location = format!("/entries/{}", entry.id);
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(entry)))
+}
+
+/// Starts a timer for the user, first stopping the one running if they
+/// have `auto_stop_timer` on, and tells whoever is listening. The payload
+/// is validated already. `/ws` starts timers through here too.
+pub(crate) async fn start_timer_for(
+    state: &AppState,
+    user_id: i32,
+    mode: TimerMode,
+    payload: StartTimerRequest,
+) -> Result<TimeEntry, AppError> {
+    let now = state.clock.now();
+    let mut tx = state.pool.begin().await?;
+
+    // Holding the user's row lines up concurrent starts, so the second sees
+    // the first's timer. The unique index backs this up.
+    let user = sqlx::query!(
+        "SELECT auto_stop_timer, allow_overlap, pomodoro_work_secs FROM users WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+
+    finish_pomodoros(&mut *tx, user_id, now).await?;
+
+    let stopped = match user.auto_stop_timer {
+        true => stop(&mut tx, user_id, now).await?,
+        false => None,
+    };
+
+    // Checked first, since a running timer overlaps whatever starts after it
+    // too.
+    let running = sqlx::query_scalar!("SELECT id FROM time_entries WHERE user_id = $1 AND ended_at IS NULL", user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if running.is_some() {
+        return Err(AppError::Conflict("timer_already_running"));
+    }
+
+    if !user.allow_overlap {
+        entries::check_overlap(&mut tx, user_id, None, now, None).await?;
+    }
+
+    let target_secs = (mode == TimerMode::Pomodoro).then_some(user.pomodoro_work_secs);
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO time_entries (user_id, project_id, description, started_at, parallel, pomodoro_target_secs, billable)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id",
+        user_id,
+        payload.project_id,
+        payload.description,
+        now,
+        user.allow_overlap,
+        target_secs,
+        payload.billable
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(entry_error)?;
+
+    let entry = entries::fetch(&mut tx, user_id, id).await?.ok_or(AppError::NotFound("entry_not_found"))?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::timer_key(user_id)).await;
+
+    if let Some(stopped) = stopped {
+        state.live.publish(user_id, EventKind::TimerStopped, &stopped);
+        state.webhooks.emit(WebhookEvent::TimerStopped, Audience::User(user_id), stopped);
+    }
+    state.live.publish(user_id, EventKind::TimerStarted, &entry);
+    state.webhooks.emit(WebhookEvent::TimerStarted, Audience::User(user_id), &entry);
+
+    Ok(entry)
+}
+
+/// Stops the user's pomodoro if it has run to its target, at the target
+/// rather than whenever this happens to be called. Anything that reads
+/// running entries calls this first.
+pub(crate) async fn finish_pomodoros<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: i32,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE time_entries SET ended_at = started_at + pomodoro_target_secs * interval '1 second', pomodoro = TRUE
+        WHERE user_id = $1 AND ended_at IS NULL AND started_at + pomodoro_target_secs * interval '1 second' <= $2",
+        user_id,
+        now
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// [`finish_pomodoros`] for everyone with a pomodoro running on one of the
+/// organization's projects.
+pub(crate) async fn finish_organization_pomodoros<'e>(
+    executor: impl PgExecutor<'e>,
+    organization_id: i32,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE time_entries e SET ended_at = started_at + pomodoro_target_secs * interval '1 second', pomodoro = TRUE
+        FROM projects p
+        WHERE p.id = e.project_id AND p.organization_id = $1
+            AND e.ended_at IS NULL AND e.started_at + e.pomodoro_target_secs * interval '1 second' <= $2",
+        organization_id,
+        now
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Ends whatever entry of the user's is running, at `now`.
+async fn stop(
+    tx: &mut sqlx::PgConnection,
+    user_id: i32,
+    now: DateTime<Utc>,
+) -> Result<Option<TimeEntry>, sqlx::Error> {
+    // Entries can't be empty, so one stopped the instant it started still
+    // gets the shortest length there is.
+    let id = sqlx::query_scalar!(
+        "UPDATE time_entries SET ended_at = GREATEST($2, started_at + interval '1 microsecond')
+        WHERE user_id = $1 AND ended_at IS NULL
+        RETURNING id",
+        user_id,
+        now
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match id {
+        Some(id) => entries::fetch(tx, user_id, id).await,
+        None => Ok(None),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/timer/stop",
+    tag = "entries",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The entry just stopped", body = TimeEntry),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "No timer running", body = ErrorResponse),
+    )
+)]
+async fn stop_timer(State(state): State<AppState>, auth: AuthUser) -> Result<Json<TimeEntry>, AppError> {
+    stop_timer_for(&state, auth.id).await.map(Json)
+}
+
+/// Stops the user's running timer and tells whoever is listening, for
+/// `POST /timer/stop` and `/ws`.
+pub(crate) async fn stop_timer_for(state: &AppState, user_id: i32) -> Result<TimeEntry, AppError> {
+    let now = state.clock.now();
+    let mut conn = state.pool.acquire().await?;
+
+    // A pomodoro past its target has already stopped, and stopping one
+    // before then doesn't count it.
+    finish_pomodoros(&mut *conn, user_id, now).await?;
+    let entry = stop(&mut conn, user_id, now)
+        .await?
+        .ok_or(AppError::Conflict("no_running_timer"))?;
+    state.cache.delete(&cache::timer_key(user_id)).await;
+
+    state.live.publish(user_id, EventKind::TimerStopped, &entry);
+    state.webhooks.emit(WebhookEvent::TimerStopped, Audience::User(user_id), &entry);
+
+    Ok(entry)
+}
+
+#[utoipa::path(
+    get,
+    path = "/timer/current",
+    tag = "entries",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The running entry, or the pomodoro just finished while its break is on",
+            body = RunningTimer),
+        (status = 204, description = "No timer running"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_current_timer(State(state): State<AppState>, auth: AuthUser) -> Result<Response, AppError> {
+    let now = state.clock.now();
+    let key = cache::timer_key(auth.id);
+
+    let cached = state.cache.get(&key).await.and_then(|body| serde_json::from_slice::<CachedTimer>(&body).ok());
+    let current = match cached {
+        Some(cached) if now < cached.valid_until => cached.current,
+        _ => {
+            let cached = load_current_timer(&state, auth.id, now).await?;
+            let ttl = (cached.valid_until - now).to_std().unwrap_or_default().min(state.cache_ttl);
+            if !ttl.is_zero() {
+                let body = serde_json::to_vec(&cached).unwrap_or_default();
+                state.cache.set(&key, body.into(), ttl).await;
+            }
+            cached.current
+        }
+    };
+
+    let Some(CurrentTimer { entry, target_secs, break_secs }) = current else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    let countdown = target_secs.map(|target_secs| {
+        let target_secs = i64::from(target_secs);
+        let ends_at = entry.started_at + TimeDelta::seconds(target_secs);
+        Countdown {
+            target_secs,
+            remaining_seconds: (ends_at - now).num_seconds().max(0),
+            elapsed: entry.ended_at.is_some(),
+            break_ends_at: ends_at + TimeDelta::seconds(break_secs.into()),
+        }
+    });
+    let elapsed_secs = (entry.ended_at.unwrap_or(now) - entry.started_at).num_seconds().max(0);
+
+    Ok(Json(RunningTimer { entry, elapsed_secs, countdown }).into_response())
+}
+
+/// What `GET /timer/current` shows, short of the counts that move with the
+/// clock. It holds until the pomodoro or its break ends, whatever the TTL.
+#[derive(Serialize, Deserialize)]
+struct CachedTimer {
+    current: Option<CurrentTimer>,
+    valid_until: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CurrentTimer {
+    entry: TimeEntry,
+    target_secs: Option<i32>,
+    break_secs: i32,
+}
+
+async fn load_current_timer(state: &AppState, user_id: i32, now: DateTime<Utc>) -> Result<CachedTimer, AppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    finish_pomodoros(&mut *conn, user_id, now).await?;
+
+    let break_secs = sqlx::query_scalar!("SELECT pomodoro_break_secs FROM users WHERE id = $1", user_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::NotFound("user_not_found"))?;
+
+    let current = sqlx::query!(
+        "SELECT id, pomodoro_target_secs FROM time_entries
+        WHERE user_id = $1 AND (ended_at IS NULL OR (pomodoro AND ended_at + $3::int * interval '1 second' > $2))
+        ORDER BY ended_at DESC NULLS FIRST
+        LIMIT 1",
+        user_id,
+        now,
+        break_secs
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let mut valid_until = now + state.cache_ttl;
+    let Some(current) = current else {
+        return Ok(CachedTimer { current: None, valid_until });
+    };
+    let entry = entries::fetch(&mut conn, user_id, current.id).await?.ok_or(AppError::NotFound("entry_not_found"))?;
+
+    if let Some(target_secs) = current.pomodoro_target_secs {
+        let ends_at = entry.started_at + TimeDelta::seconds(target_secs.into());
+        let until = match entry.ended_at {
+            Some(_) => ends_at + TimeDelta::seconds(break_secs.into()),
+            None => ends_at,
+        };
+        valid_until = valid_until.min(until);
+    }
+
+    Ok(CachedTimer {
+        current: Some(CurrentTimer { entry, target_secs: current.pomodoro_target_secs, break_secs }),
+        valid_until,
+    })
+}
@@ -0,0 +1,376 @@
+use axum::{
+    extract::{rejection::{PathRejection, QueryRejection}, Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{Datelike, Days, NaiveDate};
+
+use crate::auth::AuthUser;
+use crate::clock::local_midnight;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::JsonBody;
+use crate::models::{Timesheet, TimesheetQuery, TimesheetReview, TimesheetState};
+use crate::policy;
+use crate::routes::timer::finish_pomodoros;
+use crate::routes::users::load_settings;
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/timesheets", get(read_timesheets))
+        .route("/timesheets/{week}", get(read_timesheet))
+        .route("/timesheets/{week}/submit", post(submit_timesheet))
+        .route("/users/{id}/timesheets/{week}/approve", post(approve_timesheet))
+        .route("/users/{id}/timesheets/{week}/reject", post(reject_timesheet))
+}
+
+// A timesheet covers the entries that started in the user's week, in their
+// time zone, and is named by the week's first day. The user submits their
+// own; admins of an organization they're in review them.
+
+#[utoipa::path(
+    get,
+    path = "/timesheets",
+    tag = "timesheets",
+    params(TimesheetQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The caller's timesheets and those they can review, by week",
+            body = [Timesheet]),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_timesheets(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    query: Result<Query<TimesheetQuery>, QueryRejection>,
+) -> Result<Json<Vec<Timesheet>>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    // Who can be reviewed is `policy::authorize_review`'s rule, as a filter.
+    let timesheets = sqlx::query_as!(
+        Timesheet,
+        r#"SELECT user_id, week_start AS week, state AS "state: TimesheetState", entries, tracked_secs,
+            submitted_at, reviewed_by, reviewed_at, comment
+        FROM timesheets t
+        WHERE ($2::text IS NULL OR state = $2)
+            AND (user_id = $1 OR EXISTS (
+                SELECT 1 FROM organization_members mine
+                JOIN organization_members theirs ON theirs.organization_id = mine.organization_id
+                WHERE mine.user_id = $1 AND mine.role IN ('owner', 'admin') AND theirs.user_id = t.user_id
+            ))
+        ORDER BY week_start, user_id"#,
+        auth.id,
+        query.state as Option<TimesheetState>
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(timesheets))
+}
+
+#[utoipa::path(
+    get,
+    path = "/timesheets/{week}",
+    tag = "timesheets",
+    params(("week" = NaiveDate, Path, description = "The week's first day")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The caller's timesheet for the week, a draft if never submitted",
+            body = Timesheet),
+        (status = 400, description = "Not the first day of a week of the caller's", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_timesheet(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    week: Result<Path<NaiveDate>, PathRejection>,
+) -> Result<Json<Timesheet>, AppError> {
+    let (week, start, end) = week_range(&state, auth.id, week).await?;
+
+    let timesheet = sqlx::query_as!(
+        Timesheet,
+        r#"SELECT user_id, week_start AS week, state AS "state: TimesheetState", entries, tracked_secs,
+            submitted_at, reviewed_by, reviewed_at, comment
+        FROM timesheets WHERE user_id = $1 AND week_start = $2"#,
+        auth.id,
+        week
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+    if let Some(timesheet) = timesheet {
+        return Ok(Json(timesheet));
+    }
+
+    let now = state.clock.now();
+    finish_pomodoros(&state.pool, auth.id, now).await?;
+
+    let totals = sqlx::query!(
+        r#"SELECT count(*) AS "entries!",
+            COALESCE(EXTRACT(EPOCH FROM sum(COALESCE(ended_at, $4) - started_at)), 0)::bigint AS "tracked_secs!"
+        FROM time_entries
+        WHERE user_id = $1 AND started_at >= $2 AND started_at < $3"#,
+        auth.id,
+        start,
+        end,
+        now
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(Json(Timesheet {
+        user_id: auth.id,
+        week,
+        state: TimesheetState::Draft,
+        entries: totals.entries,
+        tracked_secs: totals.tracked_secs,
+        submitted_at: None,
+        reviewed_by: None,
+        reviewed_at: None,
+        comment: None,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/timesheets/{week}/submit",
+    tag = "timesheets",
+    params(("week" = NaiveDate, Path, description = "The week's first day")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Submitted; the week's entries are locked until it's rejected", body = Timesheet),
+        (status = 400, description = "Not the first day of a week of the caller's", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Submitted or approved already, or an entry in the week is still running",
+            body = ErrorResponse),
+    )
+)]
+async fn submit_timesheet(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    week: Result<Path<NaiveDate>, PathRejection>,
+) -> Result<Json<Timesheet>, AppError> {
+    let (week, start, end) = week_range(&state, auth.id, week).await?;
+    let now = state.clock.now();
+
+    let mut tx = state.pool.begin().await?;
+    finish_pomodoros(&mut *tx, auth.id, now).await?;
+
+    sqlx::query!(
+        "INSERT INTO timesheets (user_id, week_start) VALUES ($1, $2) ON CONFLICT (user_id, week_start) DO NOTHING",
+        auth.id,
+        week
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let timesheet = sqlx::query!(
+        r#"SELECT id, state AS "state: TimesheetState" FROM timesheets WHERE user_id = $1 AND week_start = $2 FOR UPDATE"#,
+        auth.id,
+        week
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    match timesheet.state {
+        TimesheetState::Submitted => return Err(AppError::Conflict("timesheet_submitted")),
+        TimesheetState::Approved => return Err(AppError::Conflict("timesheet_approved")),
+        TimesheetState::Draft | TimesheetState::Rejected => {}
+    }
+
+    let running = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM time_entries WHERE user_id = $1 AND ended_at IS NULL AND started_at >= $2 AND started_at < $3
+        ) AS "running!""#,
+        auth.id,
+        start,
+        end
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if running {
+        return Err(AppError::Conflict("timer_running"));
+    }
+
+    let totals = sqlx::query!(
+        r#"WITH locked AS (
+            UPDATE time_entries SET timesheet_id = $1
+            WHERE user_id = $2 AND started_at >= $3 AND started_at < $4
+            RETURNING ended_at - started_at AS length
+        )
+        SELECT count(*) AS "entries!", COALESCE(EXTRACT(EPOCH FROM sum(length)), 0)::bigint AS "tracked_secs!"
+        FROM locked"#,
+        timesheet.id,
+        auth.id,
+        start,
+        end
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let timesheet = sqlx::query_as!(
+        Timesheet,
+        r#"UPDATE timesheets SET state = 'submitted', entries = $2, tracked_secs = $3, submitted_at = $4,
+            reviewed_by = NULL, reviewed_at = NULL, comment = NULL
+        WHERE id = $1
+        RETURNING user_id, week_start AS week, state AS "state: TimesheetState", entries, tracked_secs,
+            submitted_at, reviewed_by, reviewed_at, comment"#,
+        timesheet.id,
+        totals.entries,
+        totals.tracked_secs,
+        now
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(timesheet))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{id}/timesheets/{week}/approve",
+    tag = "timesheets",
+    params(("id" = i32, Path, description = "The timesheet's user"), ("week" = NaiveDate, Path, description = "The week's first day")),
+    request_body = TimesheetReview,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Approved; the week's entries stay locked", body = Timesheet),
+        (status = 400, description = "Invalid path or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of an organization the user is in, or the caller's own",
+            body = ErrorResponse),
+        (status = 404, description = "No one the caller shares an organization with", body = ErrorResponse),
+        (status = 409, description = "Not waiting for approval", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn approve_timesheet(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    path: Result<Path<(i32, NaiveDate)>, PathRejection>,
+    JsonBody(mut payload): JsonBody<TimesheetReview>,
+) -> Result<Json<Timesheet>, AppError> {
+    let Path((user_id, week)) = path.map_err(|_| AppError::BadRequest("invalid_path"))?;
+    payload.validate()?;
+
+    review(&state, auth.id, user_id, week, TimesheetState::Approved, payload.comment).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{id}/timesheets/{week}/reject",
+    tag = "timesheets",
+    params(("id" = i32, Path, description = "The timesheet's user"), ("week" = NaiveDate, Path, description = "The week's first day")),
+    request_body = TimesheetReview,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Rejected; the week's entries can be changed and submitted again",
+            body = Timesheet),
+        (status = 400, description = "Invalid path or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin of an organization the user is in, or the caller's own",
+            body = ErrorResponse),
+        (status = 404, description = "No one the caller shares an organization with", body = ErrorResponse),
+        (status = 409, description = "Not waiting for approval", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, or no comment saying why", body = ValidationErrors),
+    )
+)]
+async fn reject_timesheet(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    path: Result<Path<(i32, NaiveDate)>, PathRejection>,
+    JsonBody(mut payload): JsonBody<TimesheetReview>,
+) -> Result<Json<Timesheet>, AppError> {
+    let Path((user_id, week)) = path.map_err(|_| AppError::BadRequest("invalid_path"))?;
+    payload.validate()?;
+    if payload.comment.as_deref().is_none_or(str::is_empty) {
+        let mut errors = ValidationErrors::default();
+        errors.add("comment", "required");
+        return Err(AppError::Validation(errors));
+    }
+
+    review(&state, auth.id, user_id, week, TimesheetState::Rejected, payload.comment).await.map(Json)
+}
+
+/// Takes a submitted timesheet to `decision`, unlocking its entries when
+/// that's a rejection.
+async fn review(
+    state: &AppState,
+    reviewer: i32,
+    user_id: i32,
+    week: NaiveDate,
+    decision: TimesheetState,
+    comment: Option<String>,
+) -> Result<Timesheet, AppError> {
+    let mut tx = state.pool.begin().await?;
+
+    policy::authorize_review(&mut *tx, reviewer, user_id).await?;
+
+    let timesheet = sqlx::query!(
+        r#"SELECT id, state AS "state: TimesheetState" FROM timesheets WHERE user_id = $1 AND week_start = $2 FOR UPDATE"#,
+        user_id,
+        week
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let id = match timesheet {
+        Some(timesheet) if timesheet.state == TimesheetState::Submitted => timesheet.id,
+        Some(timesheet) if timesheet.state == TimesheetState::Approved => {
+            return Err(AppError::Conflict("timesheet_approved"));
+        }
+        _ => return Err(AppError::Conflict("timesheet_not_submitted")),
+    };
+
+    if decision == TimesheetState::Rejected {
+        sqlx::query!("UPDATE time_entries SET timesheet_id = NULL WHERE timesheet_id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let timesheet = sqlx::query_as!(
+        Timesheet,
+        r#"UPDATE timesheets SET state = $2, reviewed_by = $3, reviewed_at = $4, comment = $5
+        WHERE id = $1
+        RETURNING user_id, week_start AS week, state AS "state: TimesheetState", entries, tracked_secs,
+            submitted_at, reviewed_by, reviewed_at, comment"#,
+        id,
+        decision as TimesheetState,
+        reviewer,
+        state.clock.now(),
+        comment
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(timesheet)
+}
+
+/// The week's first day with when it starts and ends, checked to be the
+/// first day of one of the user's weeks.
+async fn week_range(
+    state: &AppState,
+    user_id: i32,
+    week: Result<Path<NaiveDate>, PathRejection>,
+) -> Result<(NaiveDate, chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>), AppError> {
+    let Path(week) = week.map_err(|_| AppError::BadRequest("invalid_week"))?;
+
+    let settings = load_settings(&state.pool, user_id).await?;
+    if week.weekday() != settings.week_start.weekday() {
+        return Err(AppError::BadRequest("invalid_week"));
+    }
+
+    let tz = settings.tz();
+    Ok((week, local_midnight(week, tz), local_midnight(week + Days::new(7), tz)))
+}
@@ -0,0 +1,954 @@
+use axum::{
+    extract::{rejection::{PathRejection, QueryRejection}, DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::NaiveTime;
+use serde_json::json;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::audit::{self, Event};
+use crate::cache;
+use crate::auth::{AdminUser, AuthUser};
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse, email_conflict};
+use crate::extract::{ClientIp, Format, JsonBody, Negotiated, UserAgent};
+use crate::export;
+use crate::idempotency::{self, Claim, IdempotencyKey, StoredResponse};
+use crate::import::{self, ImportRows, Importer};
+use crate::models::{AuditEventType, ChangePasswordRequest, CreateUserRequest, CreateUserResponse, ExportQuery, ImportQuery, ImportReport, LoginAttempt, Page, Pagination, PatchUserRequest, Preferences, PreferencesPatch, UpdateUserRequest, UserResponse, UserSettings, WebhookEvent, WeekStart};
+use crate::routes::auth::{create_verification, send_verification};
+use crate::routes::{invalid_query, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+use crate::webhooks::Audience;
+
+const LOGIN_ATTEMPTS_LIMIT: i64 = 50;
+const CREATE_USER_SCOPE: &str = "create_user";
+const SORT_FIELDS: [&str; 4] = ["id", "name", "email", "created_at"];
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/users", get(read_user))
+        .route("/users/{id}", get(read_user_by_id).put(update_user).patch(patch_user).delete(delete_user))
+        .route("/users/{id}/restore", post(restore_user))
+        .route("/users/{id}/login-attempts", get(read_login_attempts))
+        .route("/users/create", post(create_user))
+        .route("/users/export", get(export_users))
+        .route("/users/import", post(import_users).layer(DefaultBodyLimit::max(import::MAX_BODY_BYTES)))
+        .route("/me", get(read_me))
+        .route("/me/password", post(change_password))
+        .route("/me/preferences", get(read_preferences).patch(update_preferences))
+        .route("/me/settings", get(read_settings).put(update_settings))
+}
+
+/// Builds an ILIKE pattern matching `term` anywhere, with LIKE wildcards in
+/// the term itself matched literally.
+fn contains_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(Pagination),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "A page of users",
+            content((Page<UserResponse> = "application/json"), (Page<UserResponse> = "application/msgpack")),
+            headers(("ETag" = String, description = "Send back in If-None-Match"))),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+    )
+)]
+async fn read_user(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    format: Format,
+    pagination: Result<Query<Pagination>, QueryRejection>,
+) -> Result<Negotiated<Page<UserResponse>>, AppError> {
+    let Query(pagination) = pagination.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    list_users(&state, &pagination).await.map(|page| Negotiated(format, page))
+}
+
+/// A page of the accounts not deleted, for `GET /users` and GraphQL's
+/// `users`. Only admins may see it.
+pub(crate) async fn list_users(state: &AppState, pagination: &Pagination) -> Result<Page<UserResponse>, AppError> {
+    let limit = pagination.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(invalid_query("limit", &format!("must be between 1 and {}", MAX_PAGE_LIMIT)));
+    }
+
+    let offset = pagination.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(invalid_query("offset", "must not be negative"));
+    }
+
+    if pagination.after_id.is_some() && offset != 0 {
+        return Err(invalid_query("after_id", "cannot be combined with offset"));
+    }
+
+    let requested = pagination.sort.as_deref().unwrap_or("id");
+    let sort = SORT_FIELDS
+        .into_iter()
+        .find(|field| *field == requested)
+        .ok_or_else(|| invalid_query("sort", &format!("must be one of {}", SORT_FIELDS.join(", "))))?;
+
+    let order = match pagination.order.as_deref().unwrap_or("asc") {
+        "asc" => "ASC",
+        "desc" => "DESC",
+        _ => return Err(invalid_query("order", "must be one of asc, desc")),
+    };
+
+    if pagination.after_id.is_some() && sort != "id" {
+        return Err(invalid_query("after_id", "requires sort=id"));
+    }
+
+    let search = pagination
+        .q
+        .as_deref()
+        .filter(|q| !q.is_empty())
+        .map(contains_pattern);
+
+    let inactive_since = pagination
+        .inactive_since
+        .map(|day| day.and_time(NaiveTime::MIN).and_utc());
+
+    // Only the whitelisted column and direction above are ever spliced into
+    // the SQL; everything else is bound.
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT id, name, email, created_at, updated_at, version, last_login_at FROM users WHERE deleted_at IS NULL",
+    );
+
+    if let Some(after_id) = pagination.after_id {
+        query
+            .push(if order == "ASC" { " AND id > " } else { " AND id < " })
+            .push_bind(after_id);
+    }
+
+    if let Some(search) = &search {
+        query
+            .push(" AND (name ILIKE ")
+            .push_bind(search.clone())
+            .push(" OR email ILIKE ")
+            .push_bind(search.clone())
+            .push(")");
+    }
+
+    if let Some(inactive_since) = inactive_since {
+        query
+            .push(" AND COALESCE(last_login_at, created_at) < ")
+            .push_bind(inactive_since);
+    }
+
+    // id breaks ties so pages stay stable on non-unique columns. One extra
+    // row is fetched to tell whether another page follows.
+    query
+        .push(format!(" ORDER BY {} {}, id {}", sort, order, order))
+        .push(" LIMIT ")
+        .push_bind(limit + 1)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let mut items = query
+        .build_query_as::<UserResponse>()
+        .fetch_all(state.db().reader())
+        .await?;
+
+    let has_more = items.len() as i64 > limit;
+    items.truncate(limit as usize);
+
+    let next_cursor = match sort {
+        "id" if has_more => items.last().map(|user| user.id),
+        _ => None,
+    };
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "total!" FROM users
+        WHERE deleted_at IS NULL AND ($1::text IS NULL OR name ILIKE $1 OR email ILIKE $1)
+            AND ($2::timestamptz IS NULL OR COALESCE(last_login_at, created_at) < $2)"#,
+        search,
+        inactive_since
+    )
+    .fetch_one(state.db().reader())
+    .await?;
+
+    Ok(Page {
+        items,
+        total,
+        limit,
+        offset,
+        next_cursor,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The user",
+            content((UserResponse = "application/json"), (UserResponse = "application/msgpack")),
+            headers(("ETag" = String, description = "Pass as If-Match to update"))),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the account owner or an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn read_user_by_id(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    format: Format,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    auth.require_self_or_admin(id)?;
+
+    let user = load_user(&state.pool, id).await?;
+
+    Ok(([(header::ETAG, version_etag(user.version))], Negotiated(format, user)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me",
+    tag = "users",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The signed in user", body = UserResponse,
+            headers(("ETag" = String, description = "Send back in If-None-Match"))),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn read_me(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Response, AppError> {
+    cache::cached_json(&*state.cache, &cache::me_key(auth.id), state.cache_ttl, load_user(&state.pool, auth.id)).await
+}
+
+/// The account, unless it's deleted.
+pub(crate) async fn load_user(pool: &PgPool, id: i32) -> Result<UserResponse, AppError> {
+    sqlx::query_as!(
+        UserResponse,
+        "SELECT id, name, email, created_at, updated_at, version, last_login_at FROM users WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/preferences",
+    tag = "users",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The signed in user's preferences", body = Preferences),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn read_preferences(State(state): State<AppState>, auth: AuthUser) -> Result<Json<Preferences>, AppError> {
+    let preferences = sqlx::query_as!(
+        Preferences,
+        "SELECT auto_stop_timer, allow_overlap, pomodoro_work_secs, pomodoro_break_secs, hourly_rate_cents, currency,
+            tax_rate_bps
+        FROM users WHERE id = $1 AND deleted_at IS NULL",
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+
+    Ok(Json(preferences))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/me/preferences",
+    tag = "users",
+    request_body = PreferencesPatch,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The preferences now", body = Preferences),
+        (status = 400, description = "Malformed body or no fields given", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 422, description = "Pomodoro lengths or rate out of range, or an invalid currency",
+            body = ValidationErrors),
+    )
+)]
+async fn update_preferences(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<PreferencesPatch>,
+) -> Result<Json<Preferences>, AppError> {
+    let empty = payload.auto_stop_timer.is_none()
+        && payload.allow_overlap.is_none()
+        && payload.pomodoro_work_secs.is_none()
+        && payload.pomodoro_break_secs.is_none()
+        && payload.hourly_rate_cents.is_none()
+        && payload.currency.is_none()
+        && payload.tax_rate_bps.is_none();
+    if empty {
+        return Err(AppError::BadRequest("no_fields_to_update"));
+    }
+    payload.validate()?;
+
+    let preferences = sqlx::query_as!(
+        Preferences,
+        "UPDATE users SET auto_stop_timer = COALESCE($1, auto_stop_timer), allow_overlap = COALESCE($2, allow_overlap),
+            pomodoro_work_secs = COALESCE($3, pomodoro_work_secs), pomodoro_break_secs = COALESCE($4, pomodoro_break_secs),
+            hourly_rate_cents = CASE WHEN $5 THEN $6 ELSE hourly_rate_cents END, currency = COALESCE($7, currency),
+            tax_rate_bps = COALESCE($8, tax_rate_bps)
+        WHERE id = $9 AND deleted_at IS NULL
+        RETURNING auto_stop_timer, allow_overlap, pomodoro_work_secs, pomodoro_break_secs, hourly_rate_cents, currency,
+            tax_rate_bps",
+        payload.auto_stop_timer,
+        payload.allow_overlap,
+        payload.pomodoro_work_secs,
+        payload.pomodoro_break_secs,
+        payload.hourly_rate_cents.is_some(),
+        payload.hourly_rate_cents.flatten(),
+        payload.currency,
+        payload.tax_rate_bps,
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+    state.cache.delete(&cache::me_key(auth.id)).await;
+    // The break after a pomodoro runs for as long as they say.
+    state.cache.delete(&cache::timer_key(auth.id)).await;
+
+    Ok(Json(preferences))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/settings",
+    tag = "users",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The signed in user's time zone and week start", body = UserSettings),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn read_settings(State(state): State<AppState>, auth: AuthUser) -> Result<Json<UserSettings>, AppError> {
+    Ok(Json(load_settings(&state.pool, auth.id).await?))
+}
+
+/// The user's settings, for anything that lays times out in their days.
+pub(crate) async fn load_settings(pool: &PgPool, user_id: i32) -> Result<UserSettings, AppError> {
+    sqlx::query_as!(
+        UserSettings,
+        r#"SELECT timezone, week_start AS "week_start: WeekStart" FROM users WHERE id = $1 AND deleted_at IS NULL"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))
+}
+
+#[utoipa::path(
+    put,
+    path = "/me/settings",
+    tag = "users",
+    request_body = UserSettings,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The settings now", body = UserSettings),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 422, description = "An unknown time zone", body = ValidationErrors),
+    )
+)]
+async fn update_settings(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<UserSettings>,
+) -> Result<Json<UserSettings>, AppError> {
+    payload.validate()?;
+
+    let settings = sqlx::query_as!(
+        UserSettings,
+        r#"UPDATE users SET timezone = $1, week_start = $2
+        WHERE id = $3 AND deleted_at IS NULL
+        RETURNING timezone, week_start AS "week_start: WeekStart""#,
+        payload.timezone,
+        payload.week_start as WeekStart,
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+    state.cache.delete(&cache::me_key(auth.id)).await;
+
+    Ok(Json(settings))
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/password",
+    tag = "users",
+    request_body = ChangePasswordRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Password changed; other sessions are signed out"),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Wrong current password", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn change_password(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(mut payload): JsonBody<ChangePasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    payload.validate()?;
+
+    let password_hash = sqlx::query_scalar!(
+        "SELECT password_hash FROM users WHERE id = $1 AND deleted_at IS NULL",
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+
+    if !state.passwords.verify(payload.current_password, password_hash).await? {
+        return Err(AppError::Forbidden("invalid_current_password"));
+    }
+    state.check_breached("new_password", &payload.new_password).await?;
+
+    let password_hash = state.passwords.hash(payload.new_password).await?;
+
+    // Anyone holding a refresh token from before the change is cut off.
+    let mut tx = state.pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        auth.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("UPDATE refresh_tokens SET revoked = TRUE WHERE user_id = $1", auth.id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::PasswordChanged,
+        actor: Some(auth.id),
+        target: Some(("user", auth.id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({}),
+    })
+    .await?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::me_key(auth.id)).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/create",
+    tag = "users",
+    params(("Idempotency-Key" = Option<String>, Header,
+        description = "Retries with the same key and body get the first response back")),
+    request_body(content((CreateUserRequest = "application/json"), (CreateUserRequest = "application/msgpack"))),
+    responses(
+        (status = 201, description = "User created, or the replayed response for a repeated Idempotency-Key",
+            content((CreateUserResponse = "application/json"), (CreateUserResponse = "application/msgpack")),
+            headers(
+                ("Location" = String, description = "Where the new user lives"),
+                ("Idempotent-Replay" = bool, description = "Present on replays"),
+            )),
+        (status = 400, description = "Malformed body or Idempotency-Key", body = BodyErrorResponse),
+        (status = 409, description = "Email already registered", body = ErrorResponse),
+        (status = 422, description = "Invalid fields, a breached password, or an Idempotency-Key used for a different body",
+            body = ValidationErrors),
+    )
+)]
+async fn create_user(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    IdempotencyKey(key): IdempotencyKey,
+    Negotiated(format, mut payload): Negotiated<CreateUserRequest>,
+) -> Result<Response, AppError> {
+    // Taken before normalizing, so a retry has to match what was sent.
+    let fingerprint = idempotency::fingerprint(&payload);
+    payload.validate()?;
+    state.check_breached("password", &payload.password).await?;
+
+    let password_hash = state.passwords.hash(payload.password).await?;
+
+    let mut tx = state.pool.begin().await?;
+
+    if let Some(key) = &key {
+        if let Claim::Replay(response) = idempotency::claim(&mut tx, CREATE_USER_SCOPE, key, &fingerprint).await? {
+            return Ok(response.replay(format));
+        }
+    }
+
+    let user = sqlx::query_as!(
+        CreateUserResponse,
+        "INSERT INTO users (name, email, password_hash) VALUES ($1, $2, $3) RETURNING id, name, email, created_at, updated_at, version",
+        payload.name,
+        payload.email,
+        password_hash
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(email_conflict)?;
+
+    let token = create_verification(&mut tx, &state, user.id).await?;
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::UserCreated,
+        actor: Some(user.id),
+        target: Some(("user", user.id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({ "email": user.email }),
+    })
+    .await?;
+
+    let location = format!("/users/{}", user.id);
+
+    if let Some(key) = &key {
+        let response = StoredResponse {
+            status: StatusCode::CREATED,
+            location: Some(location.clone()),
+            body: serde_json::to_value(&user).unwrap_or_default(),
+        };
+        idempotency::store(&mut tx, CREATE_USER_SCOPE, key, &response).await?;
+    }
+
+    tx.commit().await?;
+
+    send_verification(&state, &user.name, &user.email, &token);
+    state.webhooks.emit(WebhookEvent::UserCreated, Audience::Admins, &user);
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Negotiated(format, user)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/export",
+    tag = "users",
+    params(ExportQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Every live user, oldest first, as a download",
+            content(
+                (String = "text/csv", example = "id,name,email,created_at,updated_at,version,last_login_at"),
+                (UserResponse = "application/x-ndjson"),
+                (Vec<UserResponse> = "application/json"),
+            )),
+        (status = 400, description = "Unknown format", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+    )
+)]
+async fn export_users(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let filename = format!("users-{}.{}", state.clock.now().format("%Y-%m-%d"), query.format.extension());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        export::users(state.db().reader().clone(), query.format),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/import",
+    tag = "users",
+    params(ImportQuery),
+    request_body(
+        description = "Users to create, as a JSON array or CSV with a `name,email,password` header",
+        content((Vec<CreateUserRequest> = "application/json"), (String = "text/csv")),
+    ),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "What happened to each row", body = ImportReport),
+        (status = 400, description = "Unreadable body, bad query or too many rows", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 413, description = "Body too large", body = ErrorResponse),
+        (status = 415, description = "Neither JSON nor CSV", body = ErrorResponse),
+    )
+)]
+async fn import_users(
+    State(state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    query: Result<Query<ImportQuery>, QueryRejection>,
+    ImportRows(rows): ImportRows,
+) -> Result<Json<ImportReport>, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    let importer = Importer { id: admin.id, ip, user_agent: user_agent.as_deref() };
+
+    Ok(Json(import::import(&state, importer, rows, query.dry_run).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    params(
+        ("id" = i32, Path, description = "User id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being edited, or `*` for any"),
+    ),
+    request_body = UpdateUserRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The updated user", body = CreateUserResponse,
+            headers(("ETag" = String, description = "The new version"))),
+        (status = 400, description = "Malformed body, or an unreadable If-Match", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the account owner or an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 409, description = "Email already registered", body = ErrorResponse),
+        (status = 412, description = "The user changed since that version; the body holds it as it is now"),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+        (status = 428, description = "Neither If-Match nor a version was given", body = ErrorResponse),
+    )
+)]
+async fn update_user(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    headers: HeaderMap,
+    JsonBody(mut payload): JsonBody<UpdateUserRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    auth.require_self_or_admin(id)?;
+    payload.validate()?;
+    let version = expected_version(&state, &headers, payload.version)?;
+
+    let user = sqlx::query_as!(
+        CreateUserResponse,
+        "UPDATE users SET name = $1, email = $2, version = version + 1
+        WHERE id = $3 AND deleted_at IS NULL AND ($4::int IS NULL OR version = $4)
+        RETURNING id, name, email, created_at, updated_at, version",
+        payload.name,
+        payload.email,
+        id,
+        version
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(email_conflict)?;
+
+    match user {
+        Some(user) => {
+            state.cache.delete(&cache::me_key(id)).await;
+            Ok(([(header::ETAG, version_etag(user.version))], Json(user)))
+        }
+        None => Err(update_failed(&state, id).await),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/users/{id}",
+    tag = "users",
+    params(
+        ("id" = i32, Path, description = "User id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being edited, or `*` for any"),
+    ),
+    request_body = PatchUserRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The updated user", body = CreateUserResponse,
+            headers(("ETag" = String, description = "The new version"))),
+        (status = 400, description = "Malformed body or no fields given, or an unreadable If-Match", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the account owner or an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+        (status = 409, description = "Email already registered", body = ErrorResponse),
+        (status = 412, description = "The user changed since that version; the body holds it as it is now"),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+        (status = 428, description = "Neither If-Match nor a version was given", body = ErrorResponse),
+    )
+)]
+async fn patch_user(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    headers: HeaderMap,
+    JsonBody(mut payload): JsonBody<PatchUserRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    auth.require_self_or_admin(id)?;
+
+    if payload.name.is_none() && payload.email.is_none() {
+        return Err(AppError::BadRequest("no_fields_to_update"));
+    }
+    payload.validate()?;
+    let version = expected_version(&state, &headers, payload.version)?;
+
+    let user = sqlx::query_as!(
+        CreateUserResponse,
+        "UPDATE users SET name = COALESCE($1, name), email = COALESCE($2, email), version = version + 1
+        WHERE id = $3 AND deleted_at IS NULL AND ($4::int IS NULL OR version = $4)
+        RETURNING id, name, email, created_at, updated_at, version",
+        payload.name,
+        payload.email,
+        id,
+        version
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(email_conflict)?;
+
+    match user {
+        Some(user) => {
+            state.cache.delete(&cache::me_key(id)).await;
+            Ok(([(header::ETAG, version_etag(user.version))], Json(user)))
+        }
+        None => Err(update_failed(&state, id).await),
+    }
+}
+
+fn version_etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+/// The version an update was made against, from `If-Match` or else the
+/// body. `None` means any version will do.
+fn expected_version(state: &AppState, headers: &HeaderMap, body: Option<i32>) -> Result<Option<i32>, AppError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return match body {
+            Some(version) => Ok(Some(version)),
+            None if state.allow_unconditional_updates => Ok(None),
+            None => Err(AppError::PreconditionRequired),
+        };
+    };
+
+    match if_match.to_str().map(str::trim) {
+        Ok("*") => Ok(None),
+        Ok(etag) => etag
+            .strip_prefix('"')
+            .and_then(|etag| etag.strip_suffix('"'))
+            .and_then(|version| version.parse().ok())
+            .map(Some)
+            .ok_or(AppError::BadRequest("invalid_if_match")),
+        Err(_) => Err(AppError::BadRequest("invalid_if_match")),
+    }
+}
+
+/// Why a conditional update touched nothing: the user is gone, or was
+/// changed by someone else first.
+async fn update_failed(state: &AppState, id: i32) -> AppError {
+    let current = sqlx::query_as!(
+        CreateUserResponse,
+        "SELECT id, name, email, created_at, updated_at, version FROM users WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await;
+
+    match current {
+        Ok(Some(current)) => AppError::PreconditionFailed {
+            etag: version_etag(current.version),
+            current: serde_json::to_value(current).unwrap_or_default(),
+        },
+        Ok(None) => AppError::NotFound("user_not_found"),
+        Err(err) => err.into(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "User deleted; restorable for a grace period"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the account owner or an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn delete_user(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    UserAgent(user_agent): UserAgent,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    auth.require_self_or_admin(id)?;
+
+    // Accounts are only soft-deleted so they can be restored within the
+    // grace period; their sessions end right away.
+    let mut tx = state.pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE users SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("user_not_found"));
+    }
+
+    sqlx::query!("UPDATE refresh_tokens SET revoked = TRUE WHERE user_id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut *tx, Event {
+        event_type: AuditEventType::UserDeleted,
+        actor: Some(auth.id),
+        target: Some(("user", id)),
+        ip,
+        user_agent: user_agent.as_deref(),
+        details: json!({}),
+    })
+    .await?;
+
+    tx.commit().await?;
+    state.cache.delete(&cache::me_key(id)).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{id}/restore",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The restored user", body = CreateUserResponse),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 404, description = "No user deleted within the grace period", body = ErrorResponse),
+        (status = 409, description = "Email taken by another account since", body = ErrorResponse),
+    )
+)]
+async fn restore_user(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<CreateUserResponse>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let user = sqlx::query_as!(
+        CreateUserResponse,
+        "UPDATE users SET deleted_at = NULL
+        WHERE id = $1 AND deleted_at > now() - make_interval(secs => $2) AND erased_at IS NULL
+        RETURNING id, name, email, created_at, updated_at, version",
+        id,
+        state.restore_grace_secs as f64
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(email_conflict)?
+    .ok_or(AppError::NotFound("user_not_found"))?;
+    state.cache.delete(&cache::me_key(id)).await;
+
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/login-attempts",
+    tag = "users",
+    params(("id" = i32, Path, description = "User id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Most recent login attempts first", body = Vec<LoginAttempt>),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+async fn read_login_attempts(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<Vec<LoginAttempt>>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND deleted_at IS NULL) AS "exists!""#,
+        id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound("user_not_found"));
+    }
+
+    let attempts = sqlx::query_as!(
+        LoginAttempt,
+        "SELECT ip, succeeded, attempted_at FROM login_attempts
+        WHERE user_id = $1 ORDER BY attempted_at DESC, id DESC LIMIT $2",
+        id,
+        LOGIN_ATTEMPTS_LIMIT
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(attempts))
+}
@@ -0,0 +1,266 @@
+use axum::{
+    extract::{rejection::PathRejection, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::json;
+
+use crate::auth::tokens::random_token;
+use crate::auth::AuthUser;
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::extract::JsonBody;
+use crate::models::{CreateWebhookResponse, Webhook, WebhookDelivery, WebhookEvent, WebhookRequest};
+use crate::state::AppState;
+use crate::validation::{Validate, ValidationErrors};
+use crate::webhooks::Audience;
+
+/// Attempts `GET /webhooks/{id}/deliveries` shows, newest first.
+const RECENT_DELIVERIES: i64 = 100;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/webhooks", get(read_webhooks).post(create_webhook))
+        .route("/webhooks/{id}", get(read_webhook).put(update_webhook).delete(delete_webhook))
+        .route("/webhooks/{id}/deliveries", get(read_deliveries))
+        .route("/webhooks/{id}/test", post(test_webhook))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = WebhookRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Webhook created; its secret is only shown this once", body = CreateWebhookResponse,
+            headers(("Location" = String, description = "Where the new webhook lives"))),
+        (status = 400, description = "Malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn create_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    JsonBody(mut payload): JsonBody<WebhookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let webhook = sqlx::query_as!(
+        CreateWebhookResponse,
+        r#"INSERT INTO webhooks (user_id, url, secret, events, active) VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, url, events AS "events: Vec<WebhookEvent>", active, secret, created_at"#,
+        auth.id,
+        payload.url,
+        random_token(),
+        &payload.events as &[WebhookEvent],
+        payload.active
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    let location = format!("/webhooks/{}", webhook.id);
+
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(webhook)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The user's webhooks", body = [Webhook]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn read_webhooks(State(state): State<AppState>, auth: AuthUser) -> Result<Json<Vec<Webhook>>, AppError> {
+    let webhooks = sqlx::query_as!(
+        Webhook,
+        r#"SELECT id, url, events AS "events: Vec<WebhookEvent>", active, created_at FROM webhooks
+        WHERE user_id = $1 ORDER BY id"#,
+        auth.id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(webhooks))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The webhook", body = Webhook),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such webhook of the user's", body = ErrorResponse),
+    )
+)]
+async fn read_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<Webhook>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let webhook = sqlx::query_as!(
+        Webhook,
+        r#"SELECT id, url, events AS "events: Vec<WebhookEvent>", active, created_at FROM webhooks
+        WHERE id = $1 AND user_id = $2"#,
+        id,
+        auth.id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("webhook_not_found"))?;
+
+    Ok(Json(webhook))
+}
+
+#[utoipa::path(
+    put,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    request_body = WebhookRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Webhook updated; its secret stays the same", body = Webhook),
+        (status = 400, description = "Invalid id or malformed body", body = BodyErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such webhook of the user's", body = ErrorResponse),
+        (status = 422, description = "Invalid fields", body = ValidationErrors),
+    )
+)]
+async fn update_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+    JsonBody(mut payload): JsonBody<WebhookRequest>,
+) -> Result<Json<Webhook>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    payload.validate()?;
+
+    let webhook = sqlx::query_as!(
+        Webhook,
+        r#"UPDATE webhooks SET url = $3, events = $4, active = $5
+        WHERE id = $1 AND user_id = $2
+        RETURNING id, url, events AS "events: Vec<WebhookEvent>", active, created_at"#,
+        id,
+        auth.id,
+        payload.url,
+        &payload.events as &[WebhookEvent],
+        payload.active
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or(AppError::NotFound("webhook_not_found"))?;
+
+    Ok(Json(webhook))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Webhook and its deliveries deleted"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such webhook of the user's", body = ErrorResponse),
+    )
+)]
+async fn delete_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+
+    let result = sqlx::query!("DELETE FROM webhooks WHERE id = $1 AND user_id = $2", id, auth.id)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("webhook_not_found"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The last 100 delivery attempts, newest first", body = [WebhookDelivery]),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such webhook of the user's", body = ErrorResponse),
+    )
+)]
+async fn read_deliveries(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<Json<Vec<WebhookDelivery>>, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    find_webhook(&state, auth.id, id).await?;
+
+    let deliveries = sqlx::query_as!(
+        WebhookDelivery,
+        r#"SELECT id, event_id, event AS "event: WebhookEvent", payload, attempt, status_code, error, created_at
+        FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY id DESC LIMIT $2"#,
+        id,
+        RECENT_DELIVERIES
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(deliveries))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/test",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 202, description = "A `ping` is on its way, even to an inactive webhook"),
+        (status = 400, description = "Invalid id", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such webhook of the user's", body = ErrorResponse),
+    )
+)]
+async fn test_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    id: Result<Path<i32>, PathRejection>,
+) -> Result<StatusCode, AppError> {
+    let Path(id) = id.map_err(|_| AppError::BadRequest("invalid_id"))?;
+    find_webhook(&state, auth.id, id).await?;
+
+    state.webhooks.emit(WebhookEvent::Ping, Audience::Webhook(id), json!({ "webhook_id": id }));
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn find_webhook(state: &AppState, user_id: i32, id: i32) -> Result<(), AppError> {
+    sqlx::query_scalar!("SELECT id FROM webhooks WHERE id = $1 AND user_id = $2", id, user_id)
+        .fetch_optional(&state.pool)
+        .await?
+        .ok_or(AppError::NotFound("webhook_not_found"))?;
+
+    Ok(())
+}
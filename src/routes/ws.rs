@@ -0,0 +1,240 @@
+use axum::{
+    body::to_bytes,
+    extract::{
+        rejection::QueryRejection,
+        ws::{rejection::WebSocketUpgradeRejection, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use jsonwebtoken::get_current_timestamp;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval_at, sleep, Instant};
+
+use crate::auth::{authenticate, AuthUser};
+use crate::error::{AppError, BodyErrorResponse, ErrorResponse};
+use crate::models::{StartTimerRequest, TimerMode, WsQuery};
+use crate::routes::timer::{start_timer_for, stop_timer_for};
+use crate::state::AppState;
+use crate::validation::Validate;
+
+/// The token was missing or invalid.
+pub const CLOSE_UNAUTHORIZED: u16 = 4001;
+/// The token expired while the socket was open.
+pub const CLOSE_TOKEN_EXPIRED: u16 = 4002;
+/// Nothing, not even a pong, came from the client for [`IDLE_TIMEOUT`].
+pub const CLOSE_IDLE: u16 = 4003;
+/// The server is shutting down.
+const CLOSE_GOING_AWAY: u16 = 1001;
+
+/// How long a socket opened without `?token=` has to send `auth`.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+// A socket gets what `GET /events` streams, as `{"event": ..., "data": ...}`
+// messages, and can start and stop the timer. Commands that work are only
+// answered by the events they cause, which every socket of the user's
+// gets; ones that don't get an `error` message with the body and status
+// the HTTP endpoint would have answered with.
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/ws", get(connect))
+}
+
+/// What clients send, by `action`.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum Command {
+    /// The first message, when the token isn't in the URL.
+    Auth { token: String },
+    Start {
+        #[serde(default)]
+        mode: TimerMode,
+        #[serde(flatten)]
+        timer: StartTimerRequest,
+    },
+    Stop,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "entries",
+    params(WsQuery),
+    responses(
+        (status = 101, description = "Upgraded. Send `{\"action\":\"start\",\"description\":...}` or \
+            `{\"action\":\"stop\"}`; receive the events `GET /events` streams. Without `token`, the first \
+            message must be `{\"action\":\"auth\",\"token\":...}`. Closes with 4001 on a bad token, 4002 when \
+            it expires and 4003 after 90 seconds without hearing from the client."),
+        (status = 400, description = "Not a WebSocket upgrade, or invalid query parameters", body = BodyErrorResponse),
+        (status = 401, description = "Invalid `token`", body = ErrorResponse),
+    )
+)]
+async fn connect(
+    State(state): State<AppState>,
+    query: Result<Query<WsQuery>, QueryRejection>,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Result<Response, AppError> {
+    let Query(query) = query.map_err(|rejection| AppError::InvalidInput {
+        error: "invalid_query",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+    let upgrade = upgrade.map_err(|rejection| AppError::InvalidInput {
+        error: "websocket_required",
+        field: None,
+        detail: rejection.body_text(),
+    })?;
+
+    // Checked before upgrading, so a bad token in the URL is a plain 401.
+    let user = match &query.token {
+        Some(token) => Some(authenticate(&state, token).await?),
+        None => None,
+    };
+
+    Ok(upgrade.on_upgrade(move |socket| session(state, socket, user)))
+}
+
+async fn session(state: AppState, mut socket: WebSocket, user: Option<AuthUser>) {
+    let user = match user {
+        Some(user) => user,
+        None => match first_message_auth(&state, &mut socket).await {
+            Ok(user) => user,
+            Err(reason) => return close(socket, CLOSE_UNAUTHORIZED, reason).await,
+        },
+    };
+
+    // Subscribed before saying so, so nothing after `ready` is missed.
+    let mut events = state.live.subscribe(user.id);
+    if send(&mut socket, "ready", json!({ "user_id": user.id })).await.is_err() {
+        return;
+    }
+
+    // API keys don't expire.
+    let expires_in = user
+        .api_key_id
+        .is_none()
+        .then(|| Duration::from_secs(user.exp.saturating_sub(get_current_timestamp())));
+    let expiry = async move {
+        match expires_in {
+            Some(expires_in) => sleep(expires_in).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expiry);
+
+    let idle = sleep(IDLE_TIMEOUT);
+    tokio::pin!(idle);
+    let mut pings = interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => return close(socket, CLOSE_GOING_AWAY, "shutting_down").await,
+            _ = &mut expiry => return close(socket, CLOSE_TOKEN_EXPIRED, "token_expired").await,
+            _ = &mut idle => return close(socket, CLOSE_IDLE, "idle_timeout").await,
+            _ = pings.tick() => {
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    return;
+                }
+            }
+            event = events.recv() => {
+                let sent = match event {
+                    Ok(event) => send(&mut socket, event.kind.as_str(), event.data).await,
+                    Err(RecvError::Lagged(missed)) => send(&mut socket, "lagged", json!({ "missed": missed })).await,
+                    Err(RecvError::Closed) => return,
+                };
+                if sent.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => {
+                let Some(Ok(message)) = message else {
+                    return;
+                };
+                idle.as_mut().reset(Instant::now() + IDLE_TIMEOUT);
+
+                let failed = match message {
+                    Message::Text(text) => run(&state, &user, &text).await.err(),
+                    Message::Binary(_) => Some(AppError::BadRequest("text_messages_only")),
+                    // Pongs to our pings are sent for us.
+                    Message::Ping(_) | Message::Pong(_) => None,
+                    Message::Close(_) => return,
+                };
+                if let Some(err) = failed {
+                    if send_error(&mut socket, err).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Waits for `{"action":"auth"}`, answering with why it failed if it does.
+async fn first_message_auth(state: &AppState, socket: &mut WebSocket) -> Result<AuthUser, &'static str> {
+    let message = tokio::time::timeout(AUTH_TIMEOUT, socket.recv()).await.map_err(|_| "auth_timeout")?;
+
+    let Some(Ok(Message::Text(text))) = message else {
+        return Err("auth_required");
+    };
+    let Ok(Command::Auth { token }) = serde_json::from_str(&text) else {
+        return Err("auth_required");
+    };
+
+    authenticate(state, &token).await.map_err(|err| match err {
+        AppError::InvalidToken(reason) => reason,
+        _ => "invalid_token",
+    })
+}
+
+async fn run(state: &AppState, user: &AuthUser, text: &str) -> Result<(), AppError> {
+    let command = serde_json::from_str(text).map_err(|err| AppError::InvalidInput {
+        error: "invalid_command",
+        field: None,
+        detail: err.to_string(),
+    })?;
+
+    match command {
+        Command::Start { mode, mut timer } => {
+            timer.validate()?;
+            start_timer_for(state, user.id, mode, timer).await?;
+        }
+        Command::Stop => {
+            stop_timer_for(state, user.id).await?;
+        }
+        Command::Auth { .. } => return Err(AppError::BadRequest("already_authenticated")),
+    }
+
+    Ok(())
+}
+
+async fn send(socket: &mut WebSocket, event: &str, data: Value) -> Result<(), axum::Error> {
+    let message = json!({ "event": event, "data": data });
+    socket.send(Message::Text(message.to_string().into())).await
+}
+
+/// Sends the error as `GET /timer/start` and the like would answer with it.
+async fn send_error(socket: &mut WebSocket, err: AppError) -> Result<(), axum::Error> {
+    let response = err.into_response();
+    let status = response.status().as_u16();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+    let data: Value = serde_json::from_slice(&body).unwrap_or_default();
+
+    let message = json!({ "event": "error", "status": status, "data": data });
+    socket.send(Message::Text(message.to_string().into())).await
+}
+
+async fn close(mut socket: WebSocket, code: u16, reason: &'static str) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    // The client may be gone already.
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
@@ -0,0 +1,57 @@
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::extract::from_trusted_proxy;
+use crate::AppState;
+
+/// A year, and every subdomain with it.
+const HSTS: HeaderValue = HeaderValue::from_static("max-age=31536000; includeSubDomains");
+
+/// Sets the security headers every response should carry. A header the
+/// handler set already is left alone, so a route that needs something else
+/// sets its own. `Content-Security-Policy` only goes on HTML, Swagger UI and
+/// the invoice page, so the calendar feed and `/metrics` go without.
+pub async fn apply(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let https = state.https
+        || (from_trusted_proxy(request.extensions(), &state.trusted_proxies) && forwarded_https(request.headers()));
+    let mut response = next.run(request).await;
+    let html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let headers = response.headers_mut();
+
+    headers
+        .entry(header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    headers.entry(header::X_FRAME_OPTIONS).or_insert(HeaderValue::from_static("DENY"));
+    headers
+        .entry(header::REFERRER_POLICY)
+        .or_insert(HeaderValue::from_static("no-referrer"));
+
+    // Over plain http it would be ignored at best, and at worst outlive a
+    // deployment that isn't behind TLS yet.
+    if https {
+        headers.entry(header::STRICT_TRANSPORT_SECURITY).or_insert(HSTS);
+    }
+
+    if html {
+        if let Ok(policy) = HeaderValue::from_str(&state.content_security_policy) {
+            headers.entry(header::CONTENT_SECURITY_POLICY).or_insert(policy);
+        }
+    }
+
+    response
+}
+
+/// What the proxy in front says the client used, only to be believed from
+/// one of `TRUSTED_PROXIES`.
+fn forwarded_https(headers: &HeaderMap) -> bool {
+    headers
+        .get("x-forwarded-proto")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|proto| proto.eq_ignore_ascii_case("https"))
+}
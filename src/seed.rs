@@ -0,0 +1,70 @@
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::audit::{self, Event};
+use crate::auth::password::Passwords;
+use crate::models::{AuditEventType, CreateUserRequest, Role};
+use crate::validation::Validate;
+use crate::AppError;
+
+#[derive(Debug, PartialEq)]
+pub enum Seeded {
+    Created { id: i32 },
+    /// Someone already has the email. Nothing was changed, not even their
+    /// role.
+    Exists { id: i32, role: Role },
+}
+
+async fn existing(pool: &PgPool, email: &str) -> Result<Option<Seeded>, AppError> {
+    let user = sqlx::query!(
+        r#"SELECT id, role AS "role: Role" FROM users WHERE email = $1 AND deleted_at IS NULL"#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user.map(|user| Seeded::Exists { id: user.id, role: user.role }))
+}
+
+/// Creates the first admin, verified from the start. Safe to run again.
+pub async fn seed_admin(pool: &PgPool, passwords: &Passwords, mut admin: CreateUserRequest) -> Result<Seeded, AppError> {
+    admin.validate()?;
+
+    if let Some(seeded) = existing(pool, &admin.email).await? {
+        return Ok(seeded);
+    }
+
+    let password_hash = passwords.hash(admin.password).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO users (name, email, password_hash, role, verified_at) VALUES ($1, $2, $3, 'admin', now())
+        ON CONFLICT (email) WHERE deleted_at IS NULL DO NOTHING
+        RETURNING id",
+        admin.name,
+        admin.email,
+        password_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match id {
+        Some(id) => {
+            audit::record(&mut *tx, Event {
+                event_type: AuditEventType::UserCreated,
+                actor: None,
+                target: Some(("user", id)),
+                ip: None,
+                user_agent: None,
+                details: json!({ "email": admin.email, "role": Role::Admin, "via": "seed-admin" }),
+            })
+            .await?;
+            tx.commit().await?;
+
+            Ok(Seeded::Created { id })
+        }
+        // Another run got in between the check and the insert.
+        None => existing(pool, &admin.email).await?.ok_or(AppError::Conflict("email_already_registered")),
+    }
+}
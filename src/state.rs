@@ -0,0 +1,160 @@
+use ipnet::IpNet;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::auth::breach::{BreachCheck, HttpRangeApi, DEFAULT_RANGE_URL};
+use crate::auth::jwt::JwtKeys;
+use crate::cache::{Cache, MemoryCache};
+use crate::auth::password::{PasswordError, Passwords};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, Retention};
+use crate::db::Db;
+use crate::jobs::Jobs;
+use crate::live::Live;
+use crate::mail::Mailer;
+use crate::metrics::Metrics;
+use crate::privacy;
+use crate::rate_limit::{MemoryRateLimiter, RateLimiter};
+use crate::validation::ValidationErrors;
+use crate::webhooks::Webhooks;
+
+pub const LOGIN_MAX_ATTEMPTS: u32 = 10;
+pub const LOGIN_WINDOW_SECS: u64 = 5 * 60;
+
+#[derive(Clone)]
+pub struct AppState {
+    /// The primary.
+    pub pool: PgPool,
+    /// A replica, connected by `main` when `DATABASE_READ_URL` is set. Use
+    /// [`AppState::db`] to pick between the two.
+    pub read_pool: Option<PgPool>,
+    pub jwt: JwtKeys,
+    /// Where the HMAC secrets are re-read from when rotating keys.
+    pub jwt_secrets_file: Option<PathBuf>,
+    pub token_ttl_secs: u64,
+    pub refresh_ttl_secs: u64,
+    pub restore_grace_secs: u64,
+    pub retention: Retention,
+    pub maintenance_interval_secs: u64,
+    pub reset_ttl_secs: u64,
+    pub verification_ttl_secs: u64,
+    /// Minimum time between verification emails to the same account.
+    pub verification_resend_secs: u64,
+    pub request_timeout_secs: u64,
+    pub max_in_flight_requests: usize,
+    pub import_max_rows: usize,
+    pub require_verified_email: bool,
+    /// Checks new passwords against breaches; off when `None`.
+    pub breach_check: Option<BreachCheck>,
+    /// Peers whose `X-Forwarded-For` and `X-Forwarded-Proto` are believed.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Served over TLS by this process, rather than by a proxy in front.
+    pub https: bool,
+    pub api_docs: bool,
+    pub graphql_playground: bool,
+    /// `Content-Security-Policy` for HTML pages.
+    pub content_security_policy: String,
+    pub allow_unconditional_updates: bool,
+    pub allowed_origins: Vec<String>,
+    pub cors_allow_credentials: bool,
+    pub passwords: Passwords,
+    pub mailer: Arc<dyn Mailer>,
+    /// What links in emails start with.
+    pub app_url: String,
+    /// Login attempts, keyed by both client IP and account email. In
+    /// memory unless `main` swaps in Redis.
+    pub login_limiter: Arc<dyn RateLimiter>,
+    pub clock: Arc<dyn Clock>,
+    /// Responses of the hottest reads; in memory unless `main` swaps in
+    /// Redis.
+    pub cache: Arc<dyn Cache>,
+    /// Zero when caching is off.
+    pub cache_ttl: Duration,
+    pub metrics: Arc<Metrics>,
+    /// Bearer token `/metrics` wants. Without one the endpoint is off.
+    pub metrics_token: Option<String>,
+    /// Bearer token the gRPC API wants. Without one every call is refused.
+    pub grpc_token: Option<String>,
+    /// Where handlers emit events for webhooks; `webhooks::spawn_worker`
+    /// delivers them.
+    pub webhooks: Webhooks,
+    /// Handlers for background jobs; `jobs::spawn_worker` runs them.
+    pub jobs: Jobs,
+    /// What `GET /events` streams to each user.
+    pub live: Live,
+    /// Cancelled when the server starts shutting down; background tasks
+    /// should stop when it fires.
+    pub shutdown: CancellationToken,
+}
+
+impl AppState {
+    pub fn new(config: &Config, pool: PgPool, mailer: Arc<dyn Mailer>) -> Result<AppState, PasswordError> {
+        let passwords = Passwords::from_config(config)?;
+        // Every kind the app itself enqueues, so any worker can run them.
+        let jobs = Jobs::new(
+            config.job_max_attempts,
+            Duration::from_secs(config.job_retry_base_secs),
+            Duration::from_secs(config.shutdown_grace_secs),
+        );
+        privacy::register(&jobs);
+
+        Ok(AppState {
+            pool,
+            read_pool: None,
+            jwt: config.jwt.clone(),
+            jwt_secrets_file: config.jwt_secrets_file.clone(),
+            token_ttl_secs: config.token_ttl_secs,
+            refresh_ttl_secs: config.refresh_ttl_secs,
+            restore_grace_secs: config.restore_grace_secs,
+            retention: config.retention.clone(),
+            maintenance_interval_secs: config.maintenance_interval_secs,
+            reset_ttl_secs: config.reset_ttl_secs,
+            verification_ttl_secs: config.verification_ttl_secs,
+            verification_resend_secs: config.verification_resend_secs,
+            request_timeout_secs: config.request_timeout_secs,
+            max_in_flight_requests: config.max_in_flight_requests,
+            import_max_rows: config.import_max_rows,
+            require_verified_email: config.require_verified_email,
+            breach_check: config.breached_password_check.then(|| {
+                BreachCheck::new(Arc::new(HttpRangeApi::new(DEFAULT_RANGE_URL)), config.breached_password_threshold)
+            }),
+            trusted_proxies: config.trusted_proxies.clone(),
+            https: config.tls.is_some(),
+            api_docs: config.api_docs,
+            graphql_playground: config.graphql_playground,
+            content_security_policy: config.content_security_policy.clone(),
+            allow_unconditional_updates: config.allow_unconditional_updates,
+            allowed_origins: config.allowed_origins.clone(),
+            cors_allow_credentials: config.cors_allow_credentials,
+            passwords,
+            mailer,
+            app_url: config.app_url.clone(),
+            login_limiter: Arc::new(MemoryRateLimiter::new(LOGIN_MAX_ATTEMPTS, Duration::from_secs(LOGIN_WINDOW_SECS))),
+            clock: Arc::new(SystemClock),
+            cache: Arc::new(MemoryCache::default()),
+            cache_ttl: Duration::from_secs(config.cache_ttl_secs),
+            metrics: Arc::new(Metrics::new()),
+            metrics_token: config.metrics_token.clone(),
+            grpc_token: config.grpc_token.clone(),
+            jobs,
+            live: Live::default(),
+            webhooks: Webhooks::new(config.webhook_max_attempts, Duration::from_secs(config.webhook_retry_base_secs)),
+            shutdown: CancellationToken::new(),
+        })
+    }
+
+    pub fn db(&self) -> Db<'_> {
+        Db::new(&self.pool, self.read_pool.as_ref(), &self.metrics)
+    }
+
+    /// Passes a new password with [`BreachCheck`] off.
+    pub async fn check_breached(&self, field: &str, password: &str) -> Result<(), ValidationErrors> {
+        match &self.breach_check {
+            Some(check) => check.check(field, password).await,
+            None => Ok(()),
+        }
+    }
+}
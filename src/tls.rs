@@ -0,0 +1,66 @@
+use axum::extract::Request;
+use axum::http::{header, uri::PathAndQuery};
+use axum::response::Redirect;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::sync::Arc;
+
+use crate::config::TlsFiles;
+use crate::AppError;
+
+/// Reads the PEM certificate chain, leaf first, and its private key.
+fn server_config(files: &TlsFiles) -> Result<ServerConfig, String> {
+    let certs = CertificateDer::pem_file_iter(&files.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("could not read {}: {}", files.cert.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates in {}", files.cert.display()));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(&files.key)
+        .map_err(|err| format!("could not read {}: {}", files.key.display(), err))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|err| format!("invalid certificate or key: {}", err))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+pub fn load(files: &TlsFiles) -> Result<RustlsConfig, String> {
+    Ok(RustlsConfig::from_config(Arc::new(server_config(files)?)))
+}
+
+/// Swaps in the certificate currently on disk. Connections already open
+/// keep the old one; on failure nothing changes.
+pub fn reload(tls: &RustlsConfig, files: &TlsFiles) -> Result<(), String> {
+    tls.reload_from_config(Arc::new(server_config(files)?));
+    Ok(())
+}
+
+/// Answers everything on the plain port with a permanent redirect to the
+/// same path over https on `https_port`.
+pub fn redirect_app(https_port: u16) -> Router {
+    Router::new().fallback(move |request: Request| async move {
+        let host = request
+            .headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| host.parse::<axum::http::uri::Authority>().ok())
+            .ok_or(AppError::BadRequest("missing_host"))?;
+
+        // Whatever port the client used was the plain one.
+        let authority = match https_port {
+            443 => host.host().to_string(),
+            port => format!("{}:{}", host.host(), port),
+        };
+        let path = request.uri().path_and_query().map_or("/", PathAndQuery::as_str);
+
+        Ok::<_, AppError>(Redirect::permanent(&format!("https://{}{}", authority, path)))
+    })
+}
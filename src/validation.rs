@@ -0,0 +1,271 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::collections::HashSet;
+
+pub const MAX_NAME_LEN: usize = 100;
+pub const MAX_EMAIL_LEN: usize = 254;
+pub const MIN_PASSWORD_LEN: usize = 8;
+pub const MAX_DESCRIPTION_LEN: usize = 500;
+pub const MAX_TAG_LEN: usize = 50;
+pub const MAX_TAGS: usize = 20;
+pub const MAX_BULK_IDS: usize = 500;
+pub const MAX_URL_LEN: usize = 2048;
+
+pub use tictoc_types::ValidationErrors;
+
+/// Checks a request payload, normalizing fields in place where the stored
+/// form differs from what clients may send.
+pub trait Validate {
+    fn validate(&mut self) -> Result<(), ValidationErrors>;
+}
+
+pub fn name(errors: &mut ValidationErrors, field: &str, name: &mut String) {
+    *name = name.trim().to_string();
+
+    if name.is_empty() {
+        errors.add(field, "required");
+    } else if name.chars().count() > MAX_NAME_LEN {
+        errors.add(field, "too long");
+    }
+}
+
+pub fn email(errors: &mut ValidationErrors, field: &str, email: &mut String) {
+    *email = email.trim().to_lowercase();
+
+    if email.is_empty() {
+        errors.add(field, "required");
+        return;
+    }
+
+    if email.len() > MAX_EMAIL_LEN {
+        errors.add(field, "too long");
+    }
+
+    if !is_email(email) {
+        errors.add(field, "invalid format");
+    }
+}
+
+pub fn password(errors: &mut ValidationErrors, field: &str, password: &str) {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        errors.add(field, "too short");
+    }
+}
+
+pub fn required(errors: &mut ValidationErrors, field: &str, value: &str) {
+    if value.is_empty() {
+        errors.add(field, "required");
+    }
+}
+
+pub fn description(errors: &mut ValidationErrors, field: &str, description: &mut String) {
+    *description = description.trim().to_string();
+
+    if description.chars().count() > MAX_DESCRIPTION_LEN {
+        errors.add(field, "too long");
+    }
+}
+
+/// Trims each tag and drops repeats, which differ at most in case; the
+/// first spelling is kept.
+pub fn tags(errors: &mut ValidationErrors, field: &str, tags: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    *tags = tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| seen.insert(tag.to_lowercase()))
+        .collect();
+
+    if tags.iter().any(|tag| tag.is_empty()) {
+        errors.add(field, "must not contain empty tags");
+    }
+    if tags.iter().any(|tag| tag.chars().count() > MAX_TAG_LEN) {
+        errors.add(field, "tag too long");
+    }
+    if tags.len() > MAX_TAGS {
+        errors.add(field, "too many");
+    }
+}
+
+/// The ids a bulk change applies to, each once.
+pub fn bulk_ids(errors: &mut ValidationErrors, field: &str, ids: &mut Vec<i32>) {
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+
+    if ids.is_empty() {
+        errors.add(field, "required");
+    } else if ids.len() > MAX_BULK_IDS {
+        errors.add(field, "too many");
+    }
+}
+
+/// `#rrggbb`, stored lowercase.
+pub fn color(errors: &mut ValidationErrors, field: &str, color: &mut String) {
+    *color = color.trim().to_ascii_lowercase();
+
+    let valid = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !valid {
+        errors.add(field, "invalid format");
+    }
+}
+
+/// An ISO 4217 code, stored uppercase.
+pub fn currency(errors: &mut ValidationErrors, field: &str, currency: &mut String) {
+    *currency = currency.trim().to_ascii_uppercase();
+
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+        errors.add(field, "invalid format");
+    }
+}
+
+/// Cents an hour, up to a million dollars or so.
+pub fn rate(errors: &mut ValidationErrors, field: &str, rate: i32) {
+    if !(0..=100_000_000).contains(&rate) {
+        errors.add(field, "must be between 0 and 100000000");
+    }
+}
+
+/// Basis points, up to 100%.
+pub fn tax_rate(errors: &mut ValidationErrors, field: &str, bps: i32) {
+    if !(0..=10_000).contains(&bps) {
+        errors.add(field, "must be between 0 and 10000");
+    }
+}
+
+/// A name from the tz database.
+pub fn timezone(errors: &mut ValidationErrors, field: &str, timezone: &mut String) {
+    *timezone = timezone.trim().to_string();
+
+    if timezone.parse::<Tz>().is_err() {
+        errors.add(field, "unknown time zone");
+    }
+}
+
+/// An absolute http or https URL.
+pub fn url(errors: &mut ValidationErrors, field: &str, url: &mut String) {
+    *url = url.trim().to_string();
+
+    if url.is_empty() {
+        errors.add(field, "required");
+        return;
+    }
+    if url.len() > MAX_URL_LEN {
+        errors.add(field, "too long");
+    }
+
+    let valid = reqwest::Url::parse(url)
+        .is_ok_and(|parsed| matches!(parsed.scheme(), "http" | "https") && parsed.host().is_some());
+    if !valid {
+        errors.add(field, "invalid format");
+    }
+}
+
+/// Reads a duration like `1h 30m`, `90m` or `45s` into seconds. Units go
+/// from largest to smallest, each at most once, and spaces between them
+/// are optional.
+pub fn parse_duration(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("empty duration".to_string());
+    }
+
+    let mut seconds: i64 = 0;
+    let mut last_unit = None;
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(format!("expected a number at '{}'", rest));
+        }
+        let (number, after) = rest.split_at(digits);
+
+        let letters = after.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(after.len());
+        let (unit, after) = after.split_at(letters);
+        let (rank, scale) = match unit {
+            "h" => (0, 3600),
+            "m" => (1, 60),
+            "s" => (2, 1),
+            "" => return Err(format!("missing unit after '{}'", number)),
+            _ => return Err(format!("unknown unit '{}', expected h, m or s", unit)),
+        };
+        if last_unit.is_some_and(|last| rank <= last) {
+            return Err(format!("'{}' out of order or repeated", unit));
+        }
+        last_unit = Some(rank);
+
+        seconds = number
+            .parse::<i64>()
+            .ok()
+            .and_then(|number| number.checked_mul(scale))
+            .and_then(|part| seconds.checked_add(part))
+            .ok_or_else(|| "duration too long".to_string())?;
+
+        rest = after.trim_start();
+    }
+
+    Ok(seconds)
+}
+
+/// An end, when there is one, has to come after the start.
+pub fn time_range(errors: &mut ValidationErrors, field: &str, start: DateTime<Utc>, end: Option<DateTime<Utc>>) {
+    if end.is_some_and(|end| end <= start) {
+        errors.add(field, "must be after started_at");
+    }
+}
+
+fn is_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !email.chars().any(char::is_whitespace)
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_format() {
+        for valid in ["chad@gmail.com", "first.last+tag@mail.example.org"] {
+            assert!(is_email(valid), "{}", valid);
+        }
+
+        for invalid in ["not-an-email", "@gmail.com", "chad@", "chad@gmail", "chad@@gmail.com", "chad@gmail..com", "ch ad@gmail.com"] {
+            assert!(!is_email(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_normalization() {
+        let mut errors = ValidationErrors::default();
+
+        let mut value = "  Chad  ".to_string();
+        name(&mut errors, "name", &mut value);
+        assert_eq!(value, "Chad");
+
+        let mut value = " Chad@GMail.com ".to_string();
+        email(&mut errors, "email", &mut value);
+        assert_eq!(value, "chad@gmail.com");
+
+        assert_eq!(errors.into_result(), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        for (text, seconds) in [("90m", 5400), ("1h30m", 5400), ("1h 30m", 5400), ("2h", 7200), (" 45s ", 45), ("1h 0m 5s", 3605)] {
+            assert_eq!(parse_duration(text), Ok(seconds), "{}", text);
+        }
+
+        for text in ["soonish", "", "90", "1d", "30m 1h", "1h 1h", "h", "-5m", "99999999999999999999h"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+    }
+}
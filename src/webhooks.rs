@@ -0,0 +1,214 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::models::WebhookEvent;
+use crate::state::AppState;
+
+/// Events waiting for the worker past this many are dropped.
+pub const QUEUE_LEN: usize = 1024;
+/// How long an endpoint gets to answer before the attempt counts as failed.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Handlers emit events once what they describe has committed; a worker
+// finds the webhooks that want each one and delivers it to them, retrying
+// with backoff. Nothing here can fail or slow down the request that
+// emitted the event.
+
+/// Whose webhooks hear about an event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Audience {
+    /// The webhooks of the user it happened to.
+    User(i32),
+    /// Admins' webhooks.
+    Admins,
+    /// Just this one, active or not and whatever it subscribes to.
+    Webhook(i32),
+}
+
+#[derive(Debug)]
+pub struct Event {
+    pub kind: WebhookEvent,
+    pub audience: Audience,
+    pub data: Value,
+}
+
+/// The queue handlers emit onto, and how deliveries are made.
+#[derive(Clone)]
+pub struct Webhooks {
+    sender: mpsc::Sender<Event>,
+    /// Taken by [`spawn_worker`].
+    receiver: Arc<Mutex<Option<mpsc::Receiver<Event>>>>,
+    client: reqwest::Client,
+    /// Attempts at each delivery, the first included.
+    pub max_attempts: u32,
+    /// The wait before the first retry; each one after waits twice as long.
+    pub retry_base: Duration,
+}
+
+impl Webhooks {
+    pub fn new(max_attempts: u32, retry_base: Duration) -> Webhooks {
+        let (sender, receiver) = mpsc::channel(QUEUE_LEN);
+        // A redirect isn't an answer, and following one would let a webhook
+        // point deliveries somewhere its URL doesn't say.
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("tictoc-webhooks/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("the HTTP client's TLS backend initializes");
+
+        Webhooks {
+            sender,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+            client,
+            max_attempts,
+            retry_base,
+        }
+    }
+
+    /// Queues an event for delivery, dropping it with a warning when the
+    /// queue is full.
+    pub fn emit(&self, kind: WebhookEvent, audience: Audience, data: impl Serialize) {
+        let event = Event {
+            kind,
+            audience,
+            data: serde_json::to_value(data).unwrap_or_default(),
+        };
+
+        if let Err(err) = self.sender.try_send(event) {
+            tracing::warn!(event = kind.as_str(), error = %err, "dropping webhook event");
+        }
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` under `secret`, as sent in
+/// `X-Tictoc-Signature`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Starts delivering queued events, until shutdown. Only the first call for
+/// a state starts a worker; later ones return `None`. Retries still waiting
+/// at shutdown are given up.
+pub fn spawn_worker(state: &AppState) -> Option<JoinHandle<()>> {
+    let mut receiver = state.webhooks.receiver.lock().unwrap().take()?;
+    let state = state.clone();
+
+    Some(tokio::spawn(async move {
+        while let Some(Some(event)) = state.shutdown.run_until_cancelled(receiver.recv()).await {
+            if let Err(err) = dispatch(&state, event).await {
+                tracing::error!(error = %err, "could not look up webhooks for an event");
+            }
+        }
+    }))
+}
+
+struct Target {
+    id: i32,
+    url: String,
+    secret: String,
+}
+
+/// Starts a delivery to each webhook that wants the event.
+async fn dispatch(state: &AppState, event: Event) -> Result<(), sqlx::Error> {
+    let (user_id, admins, webhook_id) = match event.audience {
+        Audience::User(id) => (Some(id), false, None),
+        Audience::Admins => (None, true, None),
+        Audience::Webhook(id) => (None, false, Some(id)),
+    };
+
+    let targets = sqlx::query_as!(
+        Target,
+        "SELECT w.id, w.url, w.secret FROM webhooks w
+        JOIN users u ON u.id = w.user_id
+        WHERE u.deleted_at IS NULL AND CASE
+            WHEN $4::int IS NOT NULL THEN w.id = $4
+            ELSE w.active AND $1 = ANY(w.events) AND (w.user_id = $2 OR ($3 AND u.role = 'admin'))
+        END",
+        event.kind as WebhookEvent,
+        user_id,
+        admins,
+        webhook_id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    let event_id = uuid::Uuid::new_v4().to_string();
+    let payload = json!({
+        "id": event_id,
+        "type": event.kind,
+        "created_at": state.clock.now(),
+        "data": event.data,
+    });
+
+    for target in targets {
+        tokio::spawn(deliver(state.clone(), target, event_id.clone(), event.kind, payload.clone()));
+    }
+
+    Ok(())
+}
+
+/// POSTs the payload until the endpoint answers with a 2xx or the attempts
+/// run out, recording each attempt.
+async fn deliver(state: AppState, target: Target, event_id: String, kind: WebhookEvent, payload: Value) {
+    let body = serde_json::to_vec(&payload).unwrap_or_default();
+    let signature = signature(&target.secret, &body);
+
+    for attempt in 1..=state.webhooks.max_attempts {
+        let response = state
+            .webhooks
+            .client
+            .post(&target.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Tictoc-Event", kind.as_str())
+            .header("X-Tictoc-Delivery", &event_id)
+            .header("X-Tictoc-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        let (status_code, error) = match &response {
+            Ok(response) => (Some(i32::from(response.status().as_u16())), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        let recorded = sqlx::query!(
+            "INSERT INTO webhook_deliveries (webhook_id, event_id, event, payload, attempt, status_code, error, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            target.id,
+            event_id,
+            kind as WebhookEvent,
+            payload,
+            attempt as i32,
+            status_code,
+            error,
+            state.clock.now()
+        )
+        .execute(&state.pool)
+        .await;
+        if let Err(err) = recorded {
+            tracing::error!(webhook_id = target.id, error = %err, "could not record a webhook delivery");
+        }
+
+        if response.is_ok_and(|response| response.status().is_success()) {
+            return;
+        }
+        if attempt == state.webhooks.max_attempts {
+            tracing::warn!(webhook_id = target.id, event = kind.as_str(), attempts = attempt, "giving up on a webhook delivery");
+            return;
+        }
+
+        let backoff = state.webhooks.retry_base.saturating_mul(2u32.saturating_pow(attempt - 1));
+        if state.shutdown.run_until_cancelled(tokio::time::sleep(backoff)).await.is_none() {
+            return;
+        }
+    }
+}
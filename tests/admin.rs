@@ -0,0 +1,168 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use tictoc::app;
+use tictoc::models::{AuditEvent, AuditEventType, CreateUserResponse, Page, StatsResponse, UserCounts};
+
+use common::*;
+
+#[tokio::test]
+async fn test_stats() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    // Ages in hours: one per bucket edge, plus a deleted account that
+    // mustn't count.
+    for (i, hours) in [1, 23, 25, 24 * 6, 24 * 8, 24 * 29, 24 * 31, 24 * 365].into_iter().enumerate() {
+        sqlx::query!(
+            "INSERT INTO users (name, email, password_hash, created_at)
+            VALUES ('User', $1, 'hash', now() - make_interval(hours => $2))",
+            format!("user{}@gmail.com", i),
+            hours
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+    sqlx::query!("INSERT INTO users (name, email, password_hash, deleted_at) VALUES ('Gone', 'gone@gmail.com', 'hash', now())")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    for (succeeded, hours) in [(true, 1), (true, 2), (false, 1), (true, 30)] {
+        sqlx::query!(
+            "INSERT INTO login_attempts (user_id, succeeded, attempted_at)
+            SELECT min(id), $1, now() - make_interval(hours => $2) FROM users",
+            succeeded,
+            hours
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+
+    let stats = |token: &str| with_token(Request::get("/admin/stats").body(Body::empty()).unwrap(), token);
+
+    let response = send(&app, stats(&test_token(1))).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = send(&app, stats(&admin_token(1))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let stats: StatsResponse = read_json(response).await;
+
+    assert_eq!(stats.users, UserCounts {
+        total: 8,
+        created_last_24h: 2,
+        created_last_7d: 4,
+        created_last_30d: 6,
+    });
+    assert_eq!(stats.logins_last_24h, 2);
+    assert!(stats.pool.size >= 1 && stats.pool.size <= stats.pool.max);
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let mut request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad40@gmail.com",
+        "password": "password"
+    }));
+    request.headers_mut().insert(header::USER_AGENT, "tictoc-tests".parse().unwrap());
+    let created: CreateUserResponse = read_json(send(&app, request).await).await;
+
+    let request = with_token(
+        Request::delete(format!("/users/{}", created.id)).body(Body::empty()).unwrap(),
+        &test_token(created.id),
+    );
+    assert_eq!(send(&app, request).await.status(), StatusCode::NO_CONTENT);
+
+    let audit = |query: &str| with_token(
+        Request::get(format!("/admin/audit{}", query)).body(Body::empty()).unwrap(),
+        &admin_token(1),
+    );
+
+    let response = send(&app, audit("")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: Page<AuditEvent> = read_json(response).await;
+    assert_eq!(page.total, 2);
+
+    // Newest first.
+    let deleted = &page.items[0];
+    assert_eq!(deleted.event_type, AuditEventType::UserDeleted);
+    assert_eq!(deleted.actor_user_id, Some(created.id));
+    assert_eq!((deleted.target_type.as_deref(), deleted.target_id), (Some("user"), Some(created.id)));
+    assert_eq!(deleted.user_agent, None);
+
+    let signed_up = &page.items[1];
+    assert_eq!(signed_up.event_type, AuditEventType::UserCreated);
+    assert_eq!(signed_up.actor_user_id, Some(created.id));
+    assert_eq!((signed_up.target_type.as_deref(), signed_up.target_id), (Some("user"), Some(created.id)));
+    assert_eq!(signed_up.user_agent.as_deref(), Some("tictoc-tests"));
+    assert_eq!(signed_up.details, json!({ "email": "chad40@gmail.com" }));
+    assert!(signed_up.created_at <= deleted.created_at);
+
+    let page: Page<AuditEvent> = read_json(send(&app, audit("?event_type=user_deleted")).await).await;
+    assert_eq!(page.items.iter().map(|event| event.id).collect::<Vec<_>>(), [deleted.id]);
+    let page: Page<AuditEvent> = read_json(send(&app, audit("?actor=999")).await).await;
+    assert_eq!(page.total, 0);
+    let page: Page<AuditEvent> = read_json(send(&app, audit("?from=2999-01-01T00:00:00Z")).await).await;
+    assert_eq!(page.total, 0);
+    let response = send(&app, audit("?event_type=coffee_made")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = send(&app, with_token(Request::get("/admin/audit").body(Body::empty()).unwrap(), &test_token(1))).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let tampered = sqlx::query!("UPDATE audit_events SET actor_user_id = NULL").execute(&db.pool).await;
+    assert!(tampered.is_err());
+    let tampered = sqlx::query!("DELETE FROM audit_events").execute(&db.pool).await;
+    assert!(tampered.is_err());
+}
+
+#[tokio::test]
+async fn test_audit_logins() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad41@gmail.com",
+        "password": "password"
+    }));
+    let created: CreateUserResponse = read_json(send(&app, request).await).await;
+
+    for password in ["wrong password", "password"] {
+        let request = json_request("POST", "/users/login", json!({
+            "email": "chad41@gmail.com",
+            "password": password
+        }));
+        send(&app, request).await;
+    }
+    let request = json_request("POST", "/users/login", json!({
+        "email": "nobody@gmail.com",
+        "password": "password"
+    }));
+    send(&app, request).await;
+
+    let events = sqlx::query!(
+        "SELECT actor_user_id, event_type, target_id, details FROM audit_events WHERE event_type LIKE 'login_%' ORDER BY id"
+    )
+    .fetch_all(&db.pool)
+    .await
+    .unwrap();
+    let events: Vec<_> = events
+        .into_iter()
+        .map(|event| (event.actor_user_id, event.event_type, event.target_id, event.details))
+        .collect();
+
+    assert_eq!(events, [
+        (None, "login_failed".to_string(), Some(created.id), json!({ "reason": "wrong_password" })),
+        (Some(created.id), "login_succeeded".to_string(), Some(created.id), json!({})),
+        (None, "login_failed".to_string(), None, json!({ "reason": "unknown_email", "email": "nobody@gmail.com" })),
+    ]);
+}
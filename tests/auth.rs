@@ -0,0 +1,724 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tictoc::auth::jwt::JwtKeys;
+use tictoc::auth::password::{Argon2id, Passwords};
+use tictoc::auth::tokens::random_token;
+use tictoc::auth::totp;
+use tictoc::config::DEFAULT_VERIFICATION_RESEND_SECS;
+use tictoc::error::ErrorResponse;
+use tictoc::models::{Claims, CreateUserResponse, LoginAttempt, LoginUserResponse, RecoveryCodesResponse, TwoFactorChallenge, TwoFactorSetupResponse};
+use tictoc::rate_limit::MemoryRateLimiter;
+use tictoc::routes::auth::{LOCKOUT_SECS, MAX_FAILED_LOGINS, RECOVERY_CODE_COUNT};
+use tictoc::state::{LOGIN_MAX_ATTEMPTS, LOGIN_WINDOW_SECS};
+use tictoc::{app, AppState};
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_login() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad2@gmail.com",
+        "password": "password"
+    }));
+
+    let created: CreateUserResponse = read_json(app.clone().oneshot(request).await.unwrap()).await;
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad2@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json(&response);
+
+    let token_response: LoginUserResponse = read_json(response).await;
+
+    let token_data = JwtKeys::from_secret(TEST_SECRET)
+        .decode::<Claims>(&token_response.token)
+        .unwrap();
+
+    assert_eq!(token_data.claims.sub, created.id.to_string());
+    assert_eq!(token_data.claims.email, "chad2@gmail.com");
+    assert!(token_data.claims.exp > token_data.claims.iat);
+}
+
+#[tokio::test]
+async fn test_login_failures() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad3@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad3@gmail.com",
+        "password": "wrong password"
+    }));
+    let wrong_password = app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "nobody@gmail.com",
+        "password": "password"
+    }));
+    let unknown_email = app.oneshot(request).await.unwrap();
+
+    assert_eq!(wrong_password.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(unknown_email.status(), wrong_password.status());
+
+    let wrong_password = to_bytes(wrong_password.into_body(), usize::MAX).await.unwrap();
+    let unknown_email = to_bytes(unknown_email.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(wrong_password, unknown_email);
+    assert_eq!(
+        serde_json::from_slice::<ErrorResponse>(&wrong_password).unwrap(),
+        ErrorResponse { error: "invalid_credentials".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_token_rotation() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad11@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad11@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let first: LoginUserResponse = read_json(response).await;
+
+    let request = json_request("POST", "/token/refresh", json!({
+        "refresh_token": first.refresh_token
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let second: LoginUserResponse = read_json(response).await;
+    assert_ne!(second.refresh_token, first.refresh_token);
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &second.token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = json_request("POST", "/token/refresh", json!({
+        "refresh_token": second.refresh_token
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = json_request("POST", "/token/refresh", json!({
+        "refresh_token": "not-a-refresh-token"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "invalid_refresh_token".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_token_reuse() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad12@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad12@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let first: LoginUserResponse = read_json(response).await;
+
+    let request = json_request("POST", "/token/refresh", json!({
+        "refresh_token": first.refresh_token
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let second: LoginUserResponse = read_json(response).await;
+
+    let request = json_request("POST", "/token/refresh", json!({
+        "refresh_token": first.refresh_token
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "refresh_token_reused".to_string() }
+    );
+
+    let request = json_request("POST", "/token/refresh", json!({
+        "refresh_token": second.refresh_token
+    }));
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_logout() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad13@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let token = login_token(&app, "chad13@gmail.com", "password").await;
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_token(Request::post("/users/logout").body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &token);
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "token_revoked".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_login_rehashes_password() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let state = test_state(pool.clone());
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad14@gmail.com",
+        "password": "password"
+    }));
+    app(state.clone()).oneshot(request).await.unwrap();
+
+    let app = app(AppState {
+        passwords: Passwords::new(Argon2id::default()).unwrap(),
+        ..state
+    });
+
+    let login = || json_request("POST", "/users/login", json!({
+        "email": "chad14@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let password_hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE email = 'chad14@gmail.com'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(password_hash.starts_with("$argon2id$"));
+
+    let response = app.oneshot(login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_change_password() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad19@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad19@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let tokens: LoginUserResponse = read_json(response).await;
+
+    let change = |current: &str, new: &str| with_token(json_request("POST", "/me/password", json!({
+        "current_password": current,
+        "new_password": new
+    })), &tokens.token);
+
+    let response = app.clone().oneshot(change("wrong password", "new password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "invalid_current_password".to_string() }
+    );
+
+    let response = app.clone().oneshot(change("password", "password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = app.clone().oneshot(change("password", "new password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(json_request("POST", "/users/login", json!({
+        "email": "chad19@gmail.com",
+        "password": "password"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.clone().oneshot(json_request("POST", "/users/login", json!({
+        "email": "chad19@gmail.com",
+        "password": "new password"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.oneshot(json_request("POST", "/token/refresh", json!({
+        "refresh_token": tokens.refresh_token
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_password_reset() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let mailer = Arc::new(TestMailer::default());
+    let app = app(test_state_with_mailer(pool.clone(), mailer.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad20@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad20@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let tokens: LoginUserResponse = read_json(response).await;
+
+    let request_reset = |email: &str| json_request("POST", "/users/password-reset/request", json!({
+        "email": email
+    }));
+    let confirm_reset = |token: &str, password: &str| json_request("POST", "/users/password-reset/confirm", json!({
+        "token": token,
+        "new_password": password
+    }));
+
+    // Unknown emails look exactly like known ones.
+    let response = app.clone().oneshot(request_reset("nobody@gmail.com")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(mailer.last_token("nobody@gmail.com"), None);
+
+    let response = app.clone().oneshot(request_reset("chad20@gmail.com")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let expired = mailer.last_token("chad20@gmail.com").unwrap();
+
+    sqlx::query("UPDATE password_resets SET expires_at = now() - interval '1 second'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = app.clone().oneshot(confirm_reset(&expired, "new password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "reset_token_expired".to_string() }
+    );
+
+    app.clone().oneshot(request_reset("chad20@gmail.com")).await.unwrap();
+    let token = mailer.last_token("chad20@gmail.com").unwrap();
+
+    let response = app.clone().oneshot(confirm_reset(&token, "new password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(confirm_reset(&token, "newer password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "reset_token_used".to_string() }
+    );
+
+    let response = app.clone().oneshot(confirm_reset(&random_token(), "newer password")).await.unwrap();
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "invalid_reset_token".to_string() }
+    );
+
+    let response = app.clone().oneshot(json_request("POST", "/users/login", json!({
+        "email": "chad20@gmail.com",
+        "password": "new password"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.oneshot(json_request("POST", "/token/refresh", json!({
+        "refresh_token": tokens.refresh_token
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_email_verification() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let mailer = Arc::new(TestMailer::default());
+    let mut state = test_state_with_mailer(pool.clone(), mailer.clone());
+    state.require_verified_email = true;
+    let strict = app(state.clone());
+    state.require_verified_email = false;
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad21@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+    let token = mailer.last_token("chad21@gmail.com").unwrap();
+    {
+        let sent = mailer.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].to.as_str(), sent[0].subject.as_str()), ("chad21@gmail.com", "Verify your email"));
+        assert!(sent[0].html_body.contains(&token));
+    }
+
+    let login = || json_request("POST", "/users/login", json!({
+        "email": "chad21@gmail.com",
+        "password": "password"
+    }));
+
+    let response = strict.clone().oneshot(login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.clone().oneshot(login()).await.unwrap();
+    let tokens: LoginUserResponse = read_json(response).await;
+    assert!(!tokens.verified);
+
+    // Signing up just sent one, so a resend has to wait.
+    let resend = || with_token(Request::builder()
+        .method("POST")
+        .uri("/users/verify/resend")
+        .body(Body::empty())
+        .unwrap(), &tokens.token);
+
+    let response = app.clone().oneshot(resend()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= DEFAULT_VERIFICATION_RESEND_SECS);
+
+    sqlx::query("UPDATE email_verifications SET created_at = now() - interval '1 hour'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = app.clone().oneshot(resend()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_ne!(mailer.last_token("chad21@gmail.com").unwrap(), token);
+
+    let verify = |token: &str| json_request("POST", "/users/verify", json!({ "token": token }));
+
+    let response = app.clone().oneshot(verify(&token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(verify(&token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "verification_token_used".to_string() }
+    );
+
+    let response = strict.oneshot(login()).await.unwrap();
+    let tokens: LoginUserResponse = read_json(response).await;
+    assert!(tokens.verified);
+
+    let response = app.oneshot(resend()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_login_rate_limit() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let mut state = test_state(pool);
+    state.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad22@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let login = |ip: &str, password: &str| {
+        let mut request = json_request("POST", "/users/login", json!({
+            "email": "chad22@gmail.com",
+            "password": password
+        }));
+        request.headers_mut().insert("x-forwarded-for", ip.parse().unwrap());
+        from_peer(request, "10.0.0.1")
+    };
+
+    for _ in 0..LOGIN_MAX_ATTEMPTS {
+        let response = app.clone().oneshot(login("203.0.113.1", "wrong password")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = app.clone().oneshot(login("203.0.113.1", "password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+    // The account is over its limit too, whichever address it's tried from.
+    let response = app.clone().oneshot(login("10.0.0.1, 203.0.113.2", "password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_login_success_resets_account_limit() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let mut state = test_state(pool);
+    state.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad23@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    // Each address stays under its own limit, so only the per-account
+    // counter is in play.
+    let login = |attempt: u32, password: &str| {
+        let mut request = json_request("POST", "/users/login", json!({
+            "email": "chad23@gmail.com",
+            "password": password
+        }));
+        let ip = format!("203.0.113.{}", attempt);
+        request.headers_mut().insert("x-forwarded-for", ip.parse().unwrap());
+        from_peer(request, "10.0.0.1")
+    };
+
+    for attempt in 0..LOGIN_MAX_ATTEMPTS - 1 {
+        app.clone().oneshot(login(attempt, "wrong password")).await.unwrap();
+    }
+
+    let response = app.clone().oneshot(login(100, "password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for attempt in 0..LOGIN_MAX_ATTEMPTS {
+        let response = app.clone().oneshot(login(attempt + 200, "wrong password")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[tokio::test]
+async fn test_account_lockout() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let clock = Arc::new(TestClock { now: Mutex::new(Utc::now()) });
+    let mut state = test_state(pool);
+    state.clock = clock.clone();
+    // Out of the way, so only the lockout is being tested.
+    state.login_limiter = Arc::new(MemoryRateLimiter::new(100, Duration::from_secs(LOGIN_WINDOW_SECS)));
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad24@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let user: CreateUserResponse = read_json(response).await;
+
+    let login = |password: &str| json_request("POST", "/users/login", json!({
+        "email": "chad24@gmail.com",
+        "password": password
+    }));
+
+    for _ in 0..MAX_FAILED_LOGINS {
+        let response = app.clone().oneshot(login("wrong password")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = app.clone().oneshot(login("password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::LOCKED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "account_locked".to_string() }
+    );
+
+    clock.advance(LOCKOUT_SECS - 1);
+    let response = app.clone().oneshot(login("password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::LOCKED);
+
+    clock.advance(1);
+    let response = app.clone().oneshot(login("password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The streak starts over, so one more failure doesn't lock again.
+    let response = app.clone().oneshot(login("wrong password")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = with_token(Request::builder()
+        .uri(format!("/users/{}/login-attempts", user.id))
+        .body(Body::empty())
+        .unwrap(), &admin_token(user.id));
+
+    let response = app.oneshot(request).await.unwrap();
+    let attempts: Vec<LoginAttempt> = read_json(response).await;
+
+    assert_eq!(attempts.len(), MAX_FAILED_LOGINS as usize + 4);
+    assert_eq!(
+        attempts.iter().take(4).map(|attempt| attempt.succeeded).collect::<Vec<_>>(),
+        vec![false, true, false, false]
+    );
+    assert!(attempts.iter().all(|attempt| attempt.ip.is_none()));
+}
+
+#[tokio::test]
+async fn test_two_factor_login() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let clock = Arc::new(TestClock { now: Mutex::new(DateTime::from_timestamp(59, 0).unwrap()) });
+    let mut state = test_state(pool.clone());
+    state.clock = clock.clone();
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad27@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+    let token = login_token(&app, "chad27@gmail.com", "password").await;
+
+    let request = with_token(Request::builder()
+        .method("POST")
+        .uri("/me/2fa/setup")
+        .body(Body::empty())
+        .unwrap(), &token);
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let setup: TwoFactorSetupResponse = read_json(response).await;
+    assert!(setup.otpauth_uri.starts_with("otpauth://totp/tictoc:chad27@gmail.com?secret="));
+
+    // Swap in the RFC 6238 test secret so codes are known ahead of time.
+    sqlx::query("UPDATE users SET totp_secret = $1")
+        .bind(b"12345678901234567890".to_vec())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let enable = |code: &str| with_token(json_request("POST", "/me/2fa/enable", json!({ "code": code })), &token);
+
+    let response = app.clone().oneshot(enable("000000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app.clone().oneshot(enable("287082")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let recovery: RecoveryCodesResponse = read_json(response).await;
+    assert_eq!(recovery.recovery_codes.len(), RECOVERY_CODE_COUNT);
+
+    let login = || json_request("POST", "/users/login", json!({
+        "email": "chad27@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(login()).await.unwrap();
+    let challenge: TwoFactorChallenge = read_json(response).await;
+    assert!(challenge.two_factor_required);
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &challenge.challenge_token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let second_factor = |body: serde_json::Value| json_request("POST", "/users/login/2fa", body);
+
+    // Already used to enable, so it can't be replayed.
+    let response = app.clone().oneshot(second_factor(json!({
+        "challenge_token": challenge.challenge_token,
+        "code": "287082"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    clock.advance(totp::PERIOD_SECS as i64);
+    let response = app.clone().oneshot(second_factor(json!({
+        "challenge_token": challenge.challenge_token,
+        "code": totp::code_at(b"12345678901234567890", 2)
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let tokens: LoginUserResponse = read_json(response).await;
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &tokens.token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let recovery_login = || second_factor(json!({
+        "challenge_token": challenge.challenge_token,
+        "recovery_code": recovery.recovery_codes[0]
+    }));
+
+    let response = app.clone().oneshot(recovery_login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.oneshot(recovery_login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "invalid_two_factor_code".to_string() }
+    );
+}
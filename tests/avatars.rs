@@ -0,0 +1,114 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, Response, StatusCode};
+use serde_json::json;
+use tictoc::app;
+use tower::ServiceExt;
+
+use common::*;
+
+/// The smallest valid PNG there is: one transparent pixel.
+const PNG: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4, 0x89, 0x00, 0x00, 0x00, 0x0D, 0x49,
+    0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x00, 0x01, 0x00, 0x00, 0x05, 0x00, 0x01, 0x0D, 0x0A, 0x2D, 0xB4, 0x00, 0x00,
+    0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+];
+
+const BOUNDARY: &str = "avatar-boundary";
+
+fn upload(id: i32, token: &str, filename: &str, data: &[u8]) -> Request<Body> {
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"{filename}\"\r\n\
+        Content-Type: image/png\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+    let request = Request::post(format!("/users/{}/avatar", id))
+        .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={BOUNDARY}"))
+        .body(Body::from(body))
+        .unwrap();
+    with_token(request, token)
+}
+
+fn read(id: i32, token: &str) -> Request<Body> {
+    with_token(Request::get(format!("/users/{}/avatar", id)).body(Body::empty()).unwrap(), token)
+}
+
+async fn body_bytes(response: Response<Body>) -> Vec<u8> {
+    to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn test_avatar() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    for email in ["chad60@gmail.com", "chad61@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let owner = test_token(1);
+
+    let response = app.clone().oneshot(read(1, &owner)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app.clone().oneshot(upload(1, &owner, "me.png", PNG)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Anyone signed in can see it.
+    let response = app.clone().oneshot(read(1, &test_token(2))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "private, max-age=3600");
+    let etag = response.headers()[header::ETAG].clone();
+    assert_eq!(body_bytes(response).await, PNG);
+
+    let mut request = read(1, &owner);
+    request.headers_mut().insert(header::IF_NONE_MATCH, etag.clone());
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // A replacement takes the old one's place.
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+    jpeg.extend_from_slice(b"not much of a picture");
+    let response = app.clone().oneshot(upload(1, &owner, "me.jpg", &jpeg)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app.clone().oneshot(read(1, &owner)).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+    assert_ne!(response.headers()[header::ETAG], etag);
+    assert_eq!(body_bytes(response).await, jpeg);
+    let count = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM avatars"#).fetch_one(&db.pool).await.unwrap();
+    assert_eq!(count, 1);
+
+    let response = app.clone().oneshot(upload(1, &owner, "fake.png", b"just some text")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let response = app.clone().oneshot(read(1, &owner)).await.unwrap();
+    assert_eq!(body_bytes(response).await, jpeg);
+
+    let too_big = [PNG, &vec![0; 2 * 1024 * 1024]].concat();
+    let response = app.clone().oneshot(upload(1, &owner, "big.png", &too_big)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = app.clone().oneshot(upload(1, &test_token(2), "me.png", PNG)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let request = with_token(json_request("POST", "/users/1/avatar", json!({ "avatar": "" })), &owner);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let delete = || with_token(Request::delete("/users/1/avatar").body(Body::empty()).unwrap(), &owner);
+    let response = app.clone().oneshot(delete()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app.clone().oneshot(delete()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = app.oneshot(read(1, &owner)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
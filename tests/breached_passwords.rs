@@ -0,0 +1,98 @@
+mod common;
+
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tictoc::app;
+use tictoc::auth::breach::{BreachCheck, RangeApi};
+use tictoc::validation::ValidationErrors;
+use tower::BoxError;
+
+use common::*;
+
+/// The SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8.
+const PWNED_RANGE: &str = "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+    1E4C9B93F3F0682250B6CF8331B7EE68FD8:10434004\r\n\
+    01330C689E5D64F660D6947A93AD634EF8F:0";
+
+enum Answer {
+    Range(&'static str),
+    Down,
+    Hang,
+}
+
+/// Answers every lookup the same way, keeping the prefixes it was asked.
+struct StubApi {
+    answer: Answer,
+    prefixes: Mutex<Vec<String>>,
+}
+
+impl StubApi {
+    fn new(answer: Answer) -> Arc<StubApi> {
+        Arc::new(StubApi { answer, prefixes: Mutex::default() })
+    }
+}
+
+impl RangeApi for StubApi {
+    fn range<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<String, BoxError>> {
+        self.prefixes.lock().unwrap().push(prefix.to_string());
+        Box::pin(async move {
+            match self.answer {
+                Answer::Range(body) => Ok(body.to_string()),
+                Answer::Down => Err("connection refused".into()),
+                Answer::Hang => {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    unreachable!("the check gave up waiting")
+                }
+            }
+        })
+    }
+}
+
+async fn sign_up(api: Arc<StubApi>, email: &str, password: &str) -> axum::response::Response {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.breach_check = Some(BreachCheck::new(api, 0));
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": password }));
+    send(&app(state), request).await
+}
+
+#[tokio::test]
+async fn test_breached_password_is_refused() {
+    let api = StubApi::new(Answer::Range(PWNED_RANGE));
+    let response = sign_up(api.clone(), "chad222@gmail.com", "password").await;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ValidationErrors = read_json(response).await;
+    assert_eq!(body.errors["password"], ["has appeared in 10434004 known data breaches; choose another"]);
+    // Only the prefix was sent.
+    assert_eq!(*api.prefixes.lock().unwrap(), ["5BAA6"]);
+
+    // One not in the range, or only there as padding, is fine.
+    let response = sign_up(StubApi::new(Answer::Range(PWNED_RANGE)), "chad223@gmail.com", "correct horse battery").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_breach_check_fails_open() {
+    let started = Instant::now();
+    let response = sign_up(StubApi::new(Answer::Hang), "chad224@gmail.com", "password").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+
+    let response = sign_up(StubApi::new(Answer::Down), "chad225@gmail.com", "password").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_padding_entries_do_not_count() {
+    let check = BreachCheck::new(StubApi::new(Answer::Range("1E4C9B93F3F0682250B6CF8331B7EE68FD8:0")), 0);
+    assert_eq!(check.times_seen("password").await, Some(0));
+    assert_eq!(check.check("password", "password").await, Ok(()));
+
+    let check = BreachCheck::new(StubApi::new(Answer::Range(PWNED_RANGE)), 20_000_000);
+    assert_eq!(check.check("password", "password").await, Ok(()));
+}
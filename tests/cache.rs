@@ -0,0 +1,111 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tictoc::models::{Page, Project, RunningTimer};
+use tictoc::{app, cache};
+
+use common::*;
+
+async fn get(app: &axum::Router, uri: &str, token: &str) -> axum::response::Response {
+    send(app, with_token(Request::get(uri).body(Body::empty()).unwrap(), token)).await
+}
+
+async fn project_names(app: &axum::Router, token: &str) -> Vec<String> {
+    let page: Page<Project> = read_json(get(app, "/projects", token).await).await;
+    page.items.into_iter().map(|project| project.name).collect()
+}
+
+#[tokio::test]
+async fn test_me_is_cached_until_changed() {
+    let db = TestDb::new().await;
+    let state = test_state(db.pool.clone());
+    let app = app(state.clone());
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad215@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    let token = test_token(1);
+
+    assert!(state.cache.get(&cache::me_key(1)).await.is_none());
+    let me: UserSummary = read_json(get(&app, "/me", &token).await).await;
+    assert_eq!(me.name, "Chad");
+    assert!(state.cache.get(&cache::me_key(1)).await.is_some());
+
+    // Changed behind the app's back, it's still the cached one.
+    sqlx::query("UPDATE users SET name = 'Stale' WHERE id = 1").execute(&db.pool).await.unwrap();
+    let me: UserSummary = read_json(get(&app, "/me", &token).await).await;
+    assert_eq!(me.name, "Chad");
+
+    let request = if_match(with_token(json_request("PATCH", "/users/1", json!({ "name": "Brad" })), &token), "*");
+    assert_eq!(send(&app, request).await.status(), StatusCode::OK);
+    assert!(state.cache.get(&cache::me_key(1)).await.is_none());
+    let me: UserSummary = read_json(get(&app, "/me", &token).await).await;
+    assert_eq!(me.name, "Brad");
+}
+
+#[tokio::test]
+async fn test_timer_and_projects_follow_writes() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    let clock = Arc::new(TestClock { now: Mutex::new("2025-04-01T09:00:00Z".parse().unwrap()) });
+    state.clock = clock.clone();
+    let app = app(state.clone());
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad216@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    let token = test_token(1);
+
+    assert_eq!(get(&app, "/timer/current", &token).await.status(), StatusCode::NO_CONTENT);
+    let request = with_token(Request::post("/timer/start?mode=pomodoro").body(Body::empty()).unwrap(), &token);
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+
+    // Counted from the clock on every read, cached or not.
+    clock.advance(60);
+    let timer: RunningTimer = read_json(get(&app, "/timer/current", &token).await).await;
+    assert_eq!(timer.elapsed_secs, 60);
+    clock.advance(60);
+    let timer: RunningTimer = read_json(get(&app, "/timer/current", &token).await).await;
+    assert_eq!(timer.elapsed_secs, 120);
+    let countdown = timer.countdown.unwrap();
+    assert!(!countdown.elapsed);
+
+    // Past the target the pomodoro is over, though the cache hasn't expired.
+    clock.advance(countdown.remaining_seconds);
+    let timer: RunningTimer = read_json(get(&app, "/timer/current", &token).await).await;
+    assert!(timer.entry.ended_at.is_some() && timer.entry.pomodoro);
+
+    let request = with_token(Request::post("/timer/start").body(Body::empty()).unwrap(), &token);
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    let timer: RunningTimer = read_json(get(&app, "/timer/current", &token).await).await;
+    assert!(timer.entry.ended_at.is_none() && timer.countdown.is_none());
+    let request = with_token(Request::post("/timer/stop").body(Body::empty()).unwrap(), &token);
+    assert_eq!(send(&app, request).await.status(), StatusCode::OK);
+    // Back to the pomodoro's break.
+    let timer: RunningTimer = read_json(get(&app, "/timer/current", &token).await).await;
+    assert!(timer.entry.pomodoro && timer.countdown.unwrap().elapsed);
+
+    assert!(project_names(&app, &token).await.is_empty());
+    let request = with_token(json_request("POST", "/projects", json!({ "name": "Website" })), &token);
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    assert_eq!(project_names(&app, &token).await, ["Website"]);
+
+    let request = with_token(json_request("PUT", "/projects/1", json!({ "name": "Shop" })), &token);
+    assert_eq!(send(&app, request).await.status(), StatusCode::OK);
+    assert_eq!(project_names(&app, &token).await, ["Shop"]);
+}
+
+#[tokio::test]
+async fn test_zero_ttl_turns_caching_off() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.cache_ttl = std::time::Duration::ZERO;
+    let app = app(state.clone());
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad217@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+
+    assert_eq!(get(&app, "/me", &test_token(1)).await.status(), StatusCode::OK);
+    assert!(state.cache.get(&cache::me_key(1)).await.is_none());
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tictoc::app;
+use tictoc::error::ErrorResponse;
+use tictoc::models::{CalendarToken, Project};
+use tower::ServiceExt;
+
+use common::*;
+
+/// A component's properties by name, values unescaped.
+type Properties = HashMap<String, String>;
+
+/// Just enough of RFC 5545 to check a feed: CRLF lines of at most 75
+/// octets, folded ones rejoined, and the events inside one VCALENDAR.
+fn parse_calendar(text: &str) -> (Properties, Vec<Properties>) {
+    assert!(text.ends_with("\r\n"));
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.strip_suffix("\r\n").unwrap().split("\r\n") {
+        assert!(line.len() <= 75, "{:?} is too long", line);
+        assert!(!line.contains('\n'));
+        match line.strip_prefix(' ') {
+            Some(rest) => lines.last_mut().unwrap().push_str(rest),
+            None => lines.push(line.to_string()),
+        }
+    }
+
+    let mut calendar = Properties::new();
+    let mut events = Vec::new();
+    let mut event: Option<Properties> = None;
+    assert_eq!(lines.first().map(String::as_str), Some("BEGIN:VCALENDAR"));
+    assert_eq!(lines.last().map(String::as_str), Some("END:VCALENDAR"));
+
+    for line in &lines[1..lines.len() - 1] {
+        let (name, value) = line.split_once(':').unwrap();
+        match (name, value) {
+            ("BEGIN", "VEVENT") => event = Some(Properties::new()),
+            ("END", "VEVENT") => events.push(event.take().unwrap()),
+            _ => {
+                let mut unescaped = String::new();
+                let mut chars = value.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => match chars.next().unwrap() {
+                            'n' | 'N' => unescaped.push('\n'),
+                            escaped => unescaped.push(escaped),
+                        },
+                        c => unescaped.push(c),
+                    }
+                }
+                event.as_mut().unwrap_or(&mut calendar).insert(name.to_string(), unescaped);
+            }
+        }
+    }
+
+    assert!(event.is_none());
+    (calendar, events)
+}
+
+#[tokio::test]
+async fn test_calendar_feed() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.clock = Arc::new(TestClock { now: Mutex::new("2025-04-20T12:00:00Z".parse().unwrap()) });
+    let app = app(state);
+
+    for email in ["chad150@gmail.com", "chad151@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let (alice, bob) = (test_token(1), test_token(2));
+
+    let request = with_token(json_request("POST", "/projects", json!({ "name": "Acme; Inc" })), &alice);
+    let acme: Project = read_json(app.clone().oneshot(request).await.unwrap()).await;
+
+    let description = format!("Planning, then {}\nnotes", "ünïcödé ".repeat(10));
+    let mut ids = Vec::new();
+    for entry in [
+        json!({
+            "project_id": acme.id,
+            "started_at": "2025-04-07T09:00:00Z",
+            "ended_at": "2025-04-07T10:30:00Z",
+            "description": description,
+            "tags": ["meeting", "billable"]
+        }),
+        json!({ "started_at": "2025-04-08T09:00:00Z", "ended_at": "2025-04-08T09:15:00Z" }),
+        // Too long ago for the default window.
+        json!({ "started_at": "2025-01-02T09:00:00Z", "ended_at": "2025-01-02T10:00:00Z" }),
+        // Still running.
+        json!({ "started_at": "2025-04-20T11:00:00Z" }),
+    ] {
+        let response = app.clone().oneshot(with_token(json_request("POST", "/entries", entry), &alice)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        ids.push(read_json::<serde_json::Value>(response).await["id"].as_i64().unwrap());
+    }
+    let request = with_token(json_request("POST", "/entries", json!({
+        "started_at": "2025-04-09T09:00:00Z",
+        "ended_at": "2025-04-09T10:00:00Z"
+    })), &bob);
+    app.clone().oneshot(request).await.unwrap();
+
+    let feed = |query: &str| Request::get(format!("/entries/calendar.ics{}", query)).body(Body::empty()).unwrap();
+    let read_feed = |response: axum::response::Response| async move {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/calendar; charset=utf-8");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        parse_calendar(std::str::from_utf8(&body).unwrap())
+    };
+
+    let response = app.clone().oneshot(feed("")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let (calendar, events) = read_feed(app.clone().oneshot(with_token(feed(""), &alice)).await.unwrap()).await;
+    assert_eq!(calendar["VERSION"], "2.0");
+    assert!(calendar.contains_key("PRODID"));
+    assert_eq!(events.len(), 2);
+
+    let first = &events[0];
+    assert_eq!(first["UID"], format!("entry-{}@tictoc", ids[0]));
+    assert_eq!((first["DTSTART"].as_str(), first["DTEND"].as_str()), ("20250407T090000Z", "20250407T103000Z"));
+    assert_eq!(first["DTSTAMP"], "20250420T120000Z");
+    assert_eq!(first["SUMMARY"], format!("Acme; Inc: {}", description));
+    assert_eq!(first["CATEGORIES"], "billable,meeting");
+    assert_eq!(events[1]["SUMMARY"], "Time entry");
+    assert!(!events[1].contains_key("CATEGORIES"));
+
+    let (_, events) = read_feed(app.clone().oneshot(with_token(feed("?days=120"), &alice)).await.unwrap()).await;
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["UID"], format!("entry-{}@tictoc", ids[2]));
+
+    for query in ["?days=0", "?days=1000", "?days=many"] {
+        let response = app.clone().oneshot(with_token(feed(query), &alice)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+
+    // With a feed token instead of a header.
+    let response = app.clone()
+        .oneshot(with_token(Request::post("/me/calendar-token").body(Body::empty()).unwrap(), &alice))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let token: CalendarToken = read_json(response).await;
+    assert_eq!(token.url, format!("/entries/calendar.ics?token={}", token.token));
+
+    let uri = token.url.clone();
+    let (_, events) = read_feed(app.clone().oneshot(Request::get(&uri).body(Body::empty()).unwrap()).await.unwrap()).await;
+    assert_eq!(events[0]["UID"], format!("entry-{}@tictoc", ids[0]));
+
+    // A new token replaces the old one.
+    let response = app.clone()
+        .oneshot(with_token(Request::post("/me/calendar-token").body(Body::empty()).unwrap(), &alice))
+        .await
+        .unwrap();
+    let renewed: CalendarToken = read_json(response).await;
+    assert_ne!(renewed.token, token.token);
+
+    let response = app.clone().oneshot(Request::get(&uri).body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: ErrorResponse = read_json(response).await;
+    assert_eq!(body.error, "invalid_calendar_token");
+
+    let response = app.clone().oneshot(Request::get(&renewed.url).body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone()
+        .oneshot(with_token(Request::delete("/me/calendar-token").body(Body::empty()).unwrap(), &alice))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(Request::get(&renewed.url).body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
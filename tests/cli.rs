@@ -0,0 +1,115 @@
+mod common;
+
+use tictoc::auth::password::Passwords;
+use tictoc::db::{self, DbError};
+use tictoc::models::{CreateUserRequest, Role};
+use tictoc::seed::{seed_admin, Seeded};
+use tictoc::AppError;
+
+use common::*;
+
+#[tokio::test]
+async fn test_migrate_and_db_check() {
+    let db = TestDb::empty().await;
+    let all: Vec<i64> = db::MIGRATOR.iter().map(|migration| migration.version).collect();
+
+    assert!(matches!(db::check(&db.pool).await, Err(DbError::Pending(pending)) if pending == all));
+
+    let applied = db::migrate(&db.pool).await.unwrap();
+    assert_eq!(applied.iter().map(|migration| migration.version).collect::<Vec<_>>(), all);
+    assert!(db::check(&db.pool).await.is_ok());
+
+    assert!(db::migrate(&db.pool).await.unwrap().is_empty());
+
+    // Forgetting the last one makes it pending again. Plain queries, since
+    // the bookkeeping table is sqlx's rather than part of the schema.
+    let last = *all.last().unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(last)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    assert!(matches!(db::check(&db.pool).await, Err(DbError::Pending(pending)) if pending == [last]));
+
+    sqlx::query("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = $1")
+        .bind(all[0])
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    assert!(matches!(db::check(&db.pool).await, Err(DbError::Changed(changed)) if changed == [all[0]]));
+}
+
+fn admin(password: &str) -> CreateUserRequest {
+    CreateUserRequest {
+        name: "Admin".to_string(),
+        email: " Admin@Example.com ".to_string(),
+        password: password.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_seed_admin() {
+    let db = TestDb::new().await;
+    let passwords = Passwords::from_config(&test_config()).unwrap();
+
+    let id = match seed_admin(&db.pool, &passwords, admin("password")).await.unwrap() {
+        Seeded::Created { id } => id,
+        seeded => panic!("expected a new admin, got {:?}", seeded),
+    };
+
+    let user = sqlx::query!(
+        r#"SELECT email, role AS "role: Role", verified_at IS NOT NULL AS "verified!" FROM users WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    assert_eq!((user.email.as_str(), user.role, user.verified), ("admin@example.com", Role::Admin, true));
+
+    // The second run leaves the account alone, password included.
+    let hash = sqlx::query_scalar!("SELECT password_hash FROM users WHERE id = $1", id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        seed_admin(&db.pool, &passwords, admin("another password")).await.unwrap(),
+        Seeded::Exists { id, role: Role::Admin }
+    );
+    let users = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM users"#)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 1);
+    let unchanged = sqlx::query_scalar!("SELECT password_hash FROM users WHERE id = $1", id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(unchanged, hash);
+}
+
+#[tokio::test]
+async fn test_seed_admin_existing_user() {
+    let db = TestDb::new().await;
+    let passwords = Passwords::from_config(&test_config()).unwrap();
+    let id = sqlx::query_scalar!(
+        "INSERT INTO users (name, email, password_hash) VALUES ('User', 'admin@example.com', 'hash') RETURNING id"
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+
+    // Not promoted; that is what ADMIN_EMAIL is for.
+    assert_eq!(
+        seed_admin(&db.pool, &passwords, admin("password")).await.unwrap(),
+        Seeded::Exists { id, role: Role::User }
+    );
+}
+
+#[tokio::test]
+async fn test_seed_admin_validates() {
+    let db = TestDb::new().await;
+    let passwords = Passwords::from_config(&test_config()).unwrap();
+
+    let result = seed_admin(&db.pool, &passwords, admin("short")).await;
+    assert!(matches!(result, Err(AppError::Validation(errors)) if errors.errors.contains_key("password")));
+}
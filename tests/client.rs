@@ -0,0 +1,126 @@
+mod common;
+
+use chrono::{TimeDelta, TimeZone, Utc};
+use std::time::Duration;
+use tictoc::{app, serve};
+use tictoc_client::types::{
+    CreateUserRequest, DurationInput, EntryQuery, ProjectQuery, ProjectRequest, StartTimerRequest, TimeEntryRequest,
+    DEFAULT_PROJECT_COLOR,
+};
+use tictoc_client::{Client, Error, ErrorBody, StatusCode, TimerMode};
+use tokio_util::sync::CancellationToken;
+
+use common::*;
+
+/// The real server on an ephemeral port, and the shutdown that stops it.
+async fn start(db: &TestDb) -> (Client, CancellationToken) {
+    let state = test_state(db.pool.clone());
+    let shutdown = state.shutdown.clone();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener, app(state), shutdown.clone(), Duration::from_secs(5)));
+
+    (Client::new(format!("http://{}/", addr)), shutdown)
+}
+
+fn entry(tags: &[&str]) -> TimeEntryRequest {
+    TimeEntryRequest {
+        project_id: None,
+        description: "Review".to_string(),
+        started_at: Utc.with_ymd_and_hms(2025, 3, 3, 9, 0, 0).unwrap(),
+        ended_at: None,
+        duration_seconds: Some(DurationInput::Text("1h 30m".to_string())),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        billable: false,
+    }
+}
+
+#[tokio::test]
+async fn test_entries_through_the_client() {
+    let db = TestDb::new().await;
+    let (client, shutdown) = start(&db).await;
+
+    let signup = CreateUserRequest {
+        name: "Chad".to_string(),
+        email: "chad234@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let user = client.create_user(&signup).await.unwrap();
+    let client = client.login("chad234@gmail.com", "password").await.unwrap();
+    assert_eq!(client.me().await.unwrap().id, user.id);
+
+    let project = ProjectRequest {
+        name: "Website".to_string(),
+        color: DEFAULT_PROJECT_COLOR.to_string(),
+        archived: false,
+        hourly_rate_cents: None,
+        currency: None,
+        organization_id: None,
+    };
+    let project = client.create_project(&project).await.unwrap();
+    assert_eq!(client.projects(&ProjectQuery::default()).await.unwrap().items, vec![project.clone()]);
+
+    let created = client
+        .create_entry(&TimeEntryRequest { project_id: Some(project.id), ..entry(&["client", "design"]) })
+        .await
+        .unwrap();
+    assert_eq!(created.duration_seconds, Some(5400));
+    let later = TimeEntryRequest { started_at: created.started_at + TimeDelta::hours(2), ..entry(&["client"]) };
+    client.create_entry(&later).await.unwrap();
+
+    let err = client.create_entry(&entry(&[])).await.unwrap_err();
+    let Error::Api { status: StatusCode::CONFLICT, body: ErrorBody::Entries(overlap) } = err else {
+        panic!("not an overlap: {:?}", err);
+    };
+    assert_eq!((overlap.error.as_str(), overlap.entry_ids), ("entries_overlap", vec![created.id]));
+
+    let query = EntryQuery { tag: vec!["client".to_string(), "design".to_string()], ..Default::default() };
+    let page = client.entries(&query).await.unwrap();
+    assert_eq!((page.total, page.items), (1, vec![created]));
+
+    let started = client.start_timer(TimerMode::Pomodoro, &StartTimerRequest::default()).await.unwrap();
+    assert!(started.ended_at.is_none());
+    let err = client.start_timer(TimerMode::Normal, &StartTimerRequest::default()).await.unwrap_err();
+    assert_eq!((err.status(), err.code()), (Some(StatusCode::CONFLICT), Some("timer_already_running")));
+    assert!(matches!(err, Error::Api { body: ErrorBody::Other(_), .. }), "{:?}", err);
+    assert_eq!(client.stop_timer().await.unwrap().id, started.id);
+
+    client.delete_entry(started.id).await.unwrap();
+    let err = client.entry(started.id).await.unwrap_err();
+    assert_eq!((err.status(), err.code()), (Some(StatusCode::NOT_FOUND), Some("entry_not_found")));
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn test_errors_are_typed() {
+    let db = TestDb::new().await;
+    let (client, shutdown) = start(&db).await;
+
+    let signup = CreateUserRequest {
+        name: " ".to_string(),
+        email: "chad235@gmail.com".to_string(),
+        password: "short".to_string(),
+    };
+    let err = client.create_user(&signup).await.unwrap_err();
+    let Error::Api { status, body: ErrorBody::Validation(errors) } = err else {
+        panic!("not a validation error: {:?}", err);
+    };
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(errors.errors.keys().collect::<Vec<_>>(), ["name", "password"]);
+
+    let err = client.login("chad235@gmail.com", "password").await.unwrap_err();
+    assert_eq!((err.status(), err.code()), (Some(StatusCode::UNAUTHORIZED), Some("invalid_credentials")));
+
+    let err = client.me().await.unwrap_err();
+    assert_eq!(err.code(), Some("missing_token"));
+    assert!(matches!(err, Error::Api { body: ErrorBody::Other(_), .. }));
+    let err = client.clone().with_token("not-a-token").me().await.unwrap_err();
+    assert_eq!(err.to_string(), "401 Unauthorized: invalid_token");
+
+    // Nothing listening any more.
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(matches!(client.me().await.unwrap_err(), Error::Http(_)));
+}
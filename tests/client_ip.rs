@@ -0,0 +1,70 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tictoc::app;
+use tictoc::rate_limit::MemoryRateLimiter;
+
+use common::*;
+
+/// Fails a login from `peer` with `forwarded` as its `X-Forwarded-For`, and
+/// returns the address the attempt was recorded under.
+async fn recorded_ip(app: &axum::Router, db: &TestDb, peer: &str, forwarded: Option<&str>) -> Option<String> {
+    let mut request = json_request("POST", "/users/login", json!({ "email": "chad221@gmail.com", "password": "wrong" }));
+    if let Some(forwarded) = forwarded {
+        request.headers_mut().insert("x-forwarded-for", forwarded.parse().unwrap());
+    }
+    assert_eq!(send(app, from_peer(request, peer)).await.status(), StatusCode::UNAUTHORIZED);
+
+    sqlx::query_scalar("SELECT ip FROM login_attempts ORDER BY id DESC LIMIT 1")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_client_ip_behind_trusted_proxies() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap(), "192.0.2.7/32".parse().unwrap()];
+    // More failed logins than the account would be allowed.
+    state.login_limiter = Arc::new(MemoryRateLimiter::new(100, Duration::from_secs(60)));
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad221@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    let ip = |peer, forwarded| recorded_ip(&app, &db, peer, forwarded);
+
+    // Straight from the client, with or without the header.
+    assert_eq!(ip("203.0.113.9", None).await.as_deref(), Some("203.0.113.9"));
+
+    // One trusted hop, and a chain of them.
+    assert_eq!(ip("10.0.0.1", Some("203.0.113.9")).await.as_deref(), Some("203.0.113.9"));
+    assert_eq!(ip("10.0.0.1", Some("198.51.100.4, 203.0.113.9, 192.0.2.7")).await.as_deref(), Some("203.0.113.9"));
+    // A v4 proxy reaching a dual-stack listener.
+    assert_eq!(ip("::ffff:10.0.0.1", Some("203.0.113.9")).await.as_deref(), Some("203.0.113.9"));
+
+    // What a client sends itself is ignored, whatever it claims.
+    assert_eq!(ip("203.0.113.9", Some("198.51.100.4")).await.as_deref(), Some("203.0.113.9"));
+    // A client forging hops in front of a trusted proxy only gets as far as
+    // the address the proxy saw.
+    assert_eq!(ip("10.0.0.1", Some("10.0.0.2, 198.51.100.4, 203.0.113.9")).await.as_deref(), Some("203.0.113.9"));
+
+    // Garbage stops at the proxy it came through.
+    assert_eq!(ip("10.0.0.1", Some("not an address")).await.as_deref(), Some("10.0.0.1"));
+    assert_eq!(ip("10.0.0.1", Some("203.0.113.9, 192.0.2.7:443")).await.as_deref(), Some("10.0.0.1"));
+    assert_eq!(ip("10.0.0.1", Some("203.0.113.9,, 10.0.0.2")).await.as_deref(), Some("10.0.0.2"));
+}
+
+#[tokio::test]
+async fn test_forwarded_for_ignored_without_trusted_proxies() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad221@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+
+    assert_eq!(recorded_ip(&app, &db, "10.0.0.1", Some("203.0.113.9")).await.as_deref(), Some("10.0.0.1"));
+}
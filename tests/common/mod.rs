@@ -0,0 +1,332 @@
+//! Shared by the integration tests. Each test binary only uses some of it.
+#![allow(dead_code)]
+
+use axum::Router;
+use axum::extract::ConnectInfo;
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request};
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use dotenv::dotenv;
+use jsonwebtoken::get_current_timestamp;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Connection, PgConnection, PgPool};
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::SyncRunner;
+use testcontainers_modules::testcontainers::{Container, ImageExt};
+use tictoc::{db, AppState};
+use tictoc::auth::jwt::JwtKeys;
+use tictoc::clock::Clock;
+use tictoc::config::{Config, DEFAULT_TOKEN_TTL_SECS};
+use tictoc::mail::{Email, Mailer};
+use tictoc::models::{Claims, LoginUserResponse, Role};
+use tower::ServiceExt;
+
+/// The stable part of a user response, for comparing against literals.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct UserSummary {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+}
+
+const TEST_POOL_SIZE: u32 = 5;
+
+pub const TEST_SECRET: &[u8] = b"test-secret-that-is-at-least-32-bytes";
+
+/// Keeps sent emails around so tests can read tokens out of them.
+#[derive(Default)]
+pub struct TestMailer {
+    pub sent: Mutex<Vec<Email>>,
+}
+
+impl Mailer for TestMailer {
+    fn send(&self, to: &str, subject: &str, html_body: &str, text_body: &str) {
+        self.sent.lock().unwrap().push(Email {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            html_body: html_body.to_string(),
+            text_body: text_body.to_string(),
+        });
+    }
+}
+
+/// A clock that only moves when told to.
+pub struct TestClock {
+    pub now: Mutex<DateTime<Utc>>,
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl TestClock {
+    pub fn advance(&self, secs: i64) {
+        *self.now.lock().unwrap() += chrono::Duration::seconds(secs);
+    }
+}
+
+impl TestMailer {
+    /// The last 64-character hex token sent to `to`.
+    pub fn last_token(&self, to: &str) -> Option<String> {
+        self.sent.lock().unwrap().iter().rev()
+            .filter(|email| email.to == to)
+            .flat_map(|email| email.text_body.split(|c: char| !c.is_ascii_hexdigit()))
+            .find(|word| word.len() == 64)
+            .map(str::to_string)
+    }
+}
+
+pub fn test_state(pool: PgPool) -> AppState {
+    test_state_with_mailer(pool, Arc::new(TestMailer::default()))
+}
+
+pub fn test_state_with_mailer(pool: PgPool, mailer: Arc<dyn Mailer>) -> AppState {
+    AppState::new(&test_config(), pool, mailer).unwrap()
+}
+
+/// Nothing comes from the environment, so whatever is set there can't
+/// change how the tests behave. Pools come from [`TestDb`], so the database
+/// URL is never used.
+pub fn test_config() -> Config {
+    Config::from_vars(
+        |name| match name {
+            "DATABASE_URL" => Some("postgres://localhost/unused".to_string()),
+            "JWT_SECRET" => Some(std::str::from_utf8(TEST_SECRET).unwrap().to_string()),
+            // Cheap enough that tests don't crawl.
+            "BCRYPT_COST" => Some("4".to_string()),
+            "METRICS_TOKEN" => Some("metrics-token".to_string()),
+            "API_DOCS" => Some("true".to_string()),
+            _ => None,
+        },
+        false,
+    )
+    .unwrap()
+}
+
+/// The router over a pool that never connects, for tests that don't get as
+/// far as the database.
+pub fn offline_app() -> Router {
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    tictoc::app(test_state(pool))
+}
+
+/// Drives one request through the whole stack: routing, extractors and
+/// middleware included.
+pub async fn send(app: &Router, request: Request<Body>) -> Response {
+    app.clone().oneshot(request).await.unwrap()
+}
+
+pub fn assert_json(response: &Response) {
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+}
+
+/// A database of a test's own, migrated from scratch and dropped once the
+/// test is done with it, panics included.
+pub struct TestDb {
+    name: String,
+    server: TestServer,
+    pub pool: PgPool,
+}
+
+impl TestDb {
+    pub async fn new() -> TestDb {
+        let db = TestDb::empty().await;
+        db::MIGRATOR.run(&db.pool).await.unwrap();
+        db
+    }
+
+    /// Without the migrations run.
+    pub async fn empty() -> TestDb {
+        let name = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let server = TestServer::get();
+
+        let mut admin = PgConnection::connect(&server.url).await.unwrap();
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&mut admin)
+            .await
+            .unwrap();
+
+        // Kept small so a full parallel run stays under the server's
+        // connection limit.
+        let pool = PgPoolOptions::new()
+            .max_connections(TEST_POOL_SIZE)
+            .connect(&database_url(&server.url, &name))
+            .await
+            .unwrap();
+
+        TestDb { name, server, pool }
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let name = std::mem::take(&mut self.name);
+        let server = self.server.clone();
+
+        // The test's own runtime may be unwinding or shutting down, so the
+        // drop gets a runtime of its own.
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+            let dropped = runtime.block_on(async {
+                let mut admin = PgConnection::connect(&server.url).await?;
+                sqlx::query(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", name))
+                    .execute(&mut admin)
+                    .await
+            });
+
+            // The last test out takes the container down with it.
+            drop(server);
+            dropped
+        });
+
+        if let Ok(Err(err)) = dropped.join() {
+            eprintln!("could not drop test database: {}", err);
+        }
+    }
+}
+
+/// The Postgres server test databases are made on: `TEST_DATABASE_URL` when
+/// set, otherwise a throwaway container shared by the tests running at the
+/// time.
+#[derive(Clone)]
+struct TestServer {
+    url: String,
+    container: Option<Arc<Container<Postgres>>>,
+}
+
+static CONTAINER: Mutex<Weak<Container<Postgres>>> = Mutex::new(Weak::new());
+
+impl TestServer {
+    fn get() -> TestServer {
+        dotenv().ok();
+
+        if let Ok(url) = env::var("TEST_DATABASE_URL") {
+            return TestServer { url, container: None };
+        }
+
+        let mut shared = CONTAINER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let container = shared.upgrade().unwrap_or_else(|| {
+            // The blocking runner brings its own runtime, which can't be
+            // started from inside the test's.
+            let container = std::thread::spawn(|| {
+                Postgres::default()
+                    .with_tag("17-alpine")
+                    .start()
+                    .expect("TEST_DATABASE_URL is not set and no Postgres container could be started")
+            })
+            .join()
+            .unwrap();
+
+            let container = Arc::new(container);
+            *shared = Arc::downgrade(&container);
+            container
+        });
+
+        let url = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                format!(
+                    "postgres://postgres:postgres@{}:{}/postgres",
+                    container.get_host().unwrap(),
+                    container.get_host_port_ipv4(5432).unwrap()
+                )
+            })
+            .join()
+            .unwrap()
+        });
+
+        TestServer { url, container: Some(container) }
+    }
+}
+
+/// `base_url` with its database swapped for `name`.
+fn database_url(base_url: &str, name: &str) -> String {
+    let (server, _) = base_url.rsplit_once('/').unwrap();
+    format!("{}/{}", server, name)
+}
+
+pub async fn read_json<T: DeserializeOwned>(response: Response) -> T {
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+pub fn test_token(id: i32) -> String {
+    test_token_with_role(id, Role::User)
+}
+
+pub fn admin_token(id: i32) -> String {
+    test_token_with_role(id, Role::Admin)
+}
+
+pub fn test_token_with_role(id: i32, role: Role) -> String {
+    let now = get_current_timestamp();
+
+    JwtKeys::from_secret(TEST_SECRET).encode(&Claims {
+        sub: id.to_string(),
+        email: "test@gmail.com".to_string(),
+        jti: uuid::Uuid::new_v4().to_string(),
+        iat: now,
+        exp: now + DEFAULT_TOKEN_TTL_SECS,
+        role,
+        sid: None,
+    }).unwrap()
+}
+
+pub fn with_token(mut request: Request<Body>, token: &str) -> Request<Body> {
+    request.headers_mut().insert(
+        header::AUTHORIZATION,
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    request
+}
+
+/// As if the connection came from `peer`, which [`send`] can't fill in.
+pub fn from_peer(mut request: Request<Body>, peer: &str) -> Request<Body> {
+    let addr = SocketAddr::new(peer.parse().unwrap(), 40000);
+    request.extensions_mut().insert(ConnectInfo(addr));
+    request
+}
+
+pub fn if_match(mut request: Request<Body>, etag: &str) -> Request<Body> {
+    request.headers_mut().insert(header::IF_MATCH, etag.parse().unwrap());
+    request
+}
+
+pub async fn login_token(app: &Router, email: &str, password: &str) -> String {
+    let request = json_request("POST", "/users/login", json!({
+        "email": email,
+        "password": password
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    read_json::<LoginUserResponse>(response).await.token
+}
+
+pub async fn promote(pool: &PgPool, email: &str) {
+    sqlx::query("UPDATE users SET role = 'admin' WHERE email = $1")
+        .bind(email)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+pub fn json_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
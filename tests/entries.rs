@@ -0,0 +1,631 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tictoc::app;
+use tictoc::clock::Clock;
+use tictoc::error::{EntriesErrorResponse, ErrorResponse};
+use tictoc::models::{BulkResult, Page, PomodoroReport, Preferences, Project, RunningTimer, TimeEntry};
+use tictoc::validation::{self, ValidationErrors};
+use tower::ServiceExt;
+
+use common::*;
+
+async fn create_users(app: &axum::Router, emails: &[&str]) {
+    for email in emails {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+}
+
+fn post(uri: &str, token: &str) -> Request<Body> {
+    with_token(Request::post(uri).body(Body::empty()).unwrap(), token)
+}
+
+#[tokio::test]
+async fn test_entries_crud() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    create_users(&app, &["chad70@gmail.com", "chad71@gmail.com"]).await;
+    let (alice, bob) = (test_token(1), test_token(2));
+
+    let create = |body: serde_json::Value| with_token(json_request("POST", "/entries", body), &alice);
+
+    let response = app.clone().oneshot(create(json!({
+        "description": "  Writing tests  ",
+        "started_at": "2025-04-01T09:00:00Z",
+        "ended_at": "2025-04-01T10:30:00Z"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[header::LOCATION], "/entries/1");
+    let entry: TimeEntry = read_json(response).await;
+    assert_eq!(entry.user_id, 1);
+    assert_eq!(entry.description, "Writing tests");
+    assert_eq!(entry.ended_at, Some("2025-04-01T10:30:00Z".parse::<DateTime<Utc>>().unwrap()));
+
+    // Still running.
+    let response = app.clone().oneshot(create(json!({ "started_at": "2025-04-02T09:00:00Z" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let running: TimeEntry = read_json(response).await;
+    assert_eq!((running.description.as_str(), running.ended_at), ("", None));
+
+    let list = |query: &str, token: &str| {
+        with_token(Request::get(format!("/entries{}", query)).body(Body::empty()).unwrap(), token)
+    };
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(list("", &alice)).await.unwrap()).await;
+    assert_eq!(page.items.iter().map(|entry| entry.id).collect::<Vec<_>>(), [running.id, entry.id]);
+    assert_eq!(page.total, 2);
+
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(list("?limit=1&offset=1", &alice)).await.unwrap()).await;
+    assert_eq!(page.items, [entry]);
+
+    let response = app.clone().oneshot(list("?limit=0", &alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(list("", &bob)).await.unwrap()).await;
+    assert_eq!(page.total, 0);
+
+    let read = |id: i32, token: &str| with_token(Request::get(format!("/entries/{}", id)).body(Body::empty()).unwrap(), token);
+    let response = app.clone().oneshot(read(1, &alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let update = |id: i32, token: &str, body: serde_json::Value| {
+        with_token(json_request("PUT", &format!("/entries/{}", id), body), token)
+    };
+    let stopped = json!({
+        "description": "Stand-up",
+        "started_at": "2025-04-02T09:00:00Z",
+        "ended_at": "2025-04-02T09:15:00Z"
+    });
+    let response = app.clone().oneshot(update(running.id, &alice, stopped.clone())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let updated: TimeEntry = read_json(response).await;
+    assert_eq!(updated.description, "Stand-up");
+    assert_eq!(updated.created_at, running.created_at);
+
+    // Someone else's entries look the same as ones that don't exist.
+    for response in [
+        app.clone().oneshot(read(1, &bob)).await.unwrap(),
+        app.clone().oneshot(update(1, &bob, stopped)).await.unwrap(),
+        app.clone()
+            .oneshot(with_token(Request::delete("/entries/1").body(Body::empty()).unwrap(), &bob))
+            .await
+            .unwrap(),
+        app.clone().oneshot(read(999, &alice)).await.unwrap(),
+    ] {
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    let response = app.clone().oneshot(read(1, &alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(create(json!({
+        "description": "x".repeat(validation::MAX_DESCRIPTION_LEN + 1),
+        "started_at": "2025-04-03T09:00:00Z",
+        "ended_at": "2025-04-03T09:00:00Z"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["description"], ["too long"]);
+    assert_eq!(errors.errors["ended_at"], ["must be after started_at"]);
+
+    let response = app.clone().oneshot(create(json!({ "description": "No start" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let delete = || with_token(Request::delete("/entries/1").body(Body::empty()).unwrap(), &alice);
+    let response = app.clone().oneshot(delete()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app.clone().oneshot(delete()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app.oneshot(Request::get("/entries").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_timer() {
+    let db = TestDb::new().await;
+    let clock = Arc::new(TestClock { now: Mutex::new("2025-04-01T09:00:00Z".parse().unwrap()) });
+    let mut state = test_state(db.pool.clone());
+    state.clock = clock.clone();
+    let app = app(state);
+    create_users(&app, &["chad72@gmail.com"]).await;
+    let token = test_token(1);
+
+    let current = || with_token(Request::get("/timer/current").body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(current()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(post("/timer/stop", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(read_json::<ErrorResponse>(response).await.error, "no_running_timer");
+
+    let request = with_token(json_request("POST", "/timer/start", json!({ "description": "Tic" })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let started: TimeEntry = read_json(response).await;
+    assert_eq!(started.description, "Tic");
+    assert_eq!(started.started_at, clock.now());
+    assert_eq!(started.ended_at, None);
+
+    clock.advance(90);
+    let response = app.clone().oneshot(current()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let running: RunningTimer = read_json(response).await;
+    assert_eq!(running.entry, started);
+    assert_eq!(running.elapsed_secs, 90);
+
+    // Without the preference a second start is turned away, as is a
+    // running entry made by hand.
+    let response = app.clone().oneshot(post("/timer/start", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(read_json::<ErrorResponse>(response).await.error, "timer_already_running");
+    let request = with_token(json_request("POST", "/entries", json!({ "started_at": "2025-03-01T09:00:00Z" })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    clock.advance(30);
+    let response = app.clone().oneshot(post("/timer/stop", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let stopped: TimeEntry = read_json(response).await;
+    assert_eq!(stopped.id, started.id);
+    assert_eq!(stopped.ended_at, Some(clock.now()));
+
+    let response = app.clone().oneshot(current()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let request = with_token(json_request("PATCH", "/me/preferences", json!({ "auto_stop_timer": true })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(read_json::<Preferences>(response).await, Preferences {
+        auto_stop_timer: true,
+        allow_overlap: false,
+        pomodoro_work_secs: 1500,
+        pomodoro_break_secs: 300,
+        hourly_rate_cents: None,
+        currency: "USD".to_string(),
+        tax_rate_bps: 0,
+    });
+
+    let first: TimeEntry = read_json(app.clone().oneshot(post("/timer/start", &token)).await.unwrap()).await;
+    clock.advance(60);
+    let response = app.clone().oneshot(post("/timer/start", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let second: TimeEntry = read_json(response).await;
+
+    let read = |id: i32| with_token(Request::get(format!("/entries/{}", id)).body(Body::empty()).unwrap(), &token);
+    let first: TimeEntry = read_json(app.clone().oneshot(read(first.id)).await.unwrap()).await;
+    assert_eq!(first.ended_at, Some(second.started_at));
+
+    let response = app.oneshot(current()).await.unwrap();
+    assert_eq!(read_json::<RunningTimer>(response).await.entry.id, second.id);
+}
+
+#[tokio::test]
+async fn test_timer_start_race() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    create_users(&app, &["chad73@gmail.com"]).await;
+    let token = test_token(1);
+
+    let (first, second) = tokio::join!(
+        app.clone().oneshot(post("/timer/start", &token)),
+        app.clone().oneshot(post("/timer/start", &token)),
+    );
+    let mut statuses = [first.unwrap().status(), second.unwrap().status()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+
+    let running = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM time_entries WHERE ended_at IS NULL"#)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(running, 1);
+}
+
+#[tokio::test]
+async fn test_pomodoro() {
+    let db = TestDb::new().await;
+    let clock = Arc::new(TestClock { now: Mutex::new("2025-04-01T09:00:00Z".parse().unwrap()) });
+    let mut state = test_state(db.pool.clone());
+    state.clock = clock.clone();
+    let app = app(state);
+    create_users(&app, &["chad160@gmail.com"]).await;
+    let token = test_token(1);
+
+    let current = || with_token(Request::get("/timer/current").body(Body::empty()).unwrap(), &token);
+    let pomodoros = |query: &str| {
+        with_token(Request::get(format!("/reports/pomodoros{}", query)).body(Body::empty()).unwrap(), &token)
+    };
+
+    let response = app.clone().oneshot(post("/timer/start?mode=pomodoro", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let started: TimeEntry = read_json(response).await;
+
+    clock.advance(10 * 60);
+    let running: RunningTimer = read_json(app.clone().oneshot(current()).await.unwrap()).await;
+    let countdown = running.countdown.unwrap();
+    assert_eq!((countdown.target_secs, countdown.remaining_seconds, countdown.elapsed), (1500, 900, false));
+    assert_eq!(countdown.break_ends_at.to_rfc3339(), "2025-04-01T09:30:00+00:00");
+
+    // It stops itself at 25 minutes, however late it's next looked at, and
+    // stays current through the break.
+    clock.advance(17 * 60);
+    let running: RunningTimer = read_json(app.clone().oneshot(current()).await.unwrap()).await;
+    assert_eq!((running.entry.id, running.elapsed_secs), (started.id, 1500));
+    assert_eq!(running.entry.ended_at.unwrap().to_rfc3339(), "2025-04-01T09:25:00+00:00");
+    assert!(running.entry.pomodoro);
+    let countdown = running.countdown.unwrap();
+    assert_eq!((countdown.remaining_seconds, countdown.elapsed), (0, true));
+
+    let response = app.clone().oneshot(post("/timer/stop", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    clock.advance(3 * 60);
+    let response = app.clone().oneshot(current()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Another, which the entries listing also sees stopped.
+    app.clone().oneshot(post("/timer/start?mode=pomodoro", &token)).await.unwrap();
+    clock.advance(30 * 60);
+    let request = with_token(Request::get("/entries").body(Body::empty()).unwrap(), &token);
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert!(page.items.iter().all(|entry| entry.pomodoro && entry.duration_seconds == Some(1500)));
+
+    // Stopped early, one doesn't count.
+    let request = with_token(json_request("PATCH", "/me/preferences", json!({ "pomodoro_work_secs": 600 })), &token);
+    app.clone().oneshot(request).await.unwrap();
+    let response = app.clone().oneshot(post("/timer/start?mode=pomodoro", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    clock.advance(5 * 60);
+    let stopped: TimeEntry = read_json(app.clone().oneshot(post("/timer/stop", &token)).await.unwrap()).await;
+    assert!(!stopped.pomodoro);
+
+    // Nor does an ordinary timer.
+    app.clone().oneshot(post("/timer/start", &token)).await.unwrap();
+    clock.advance(60 * 60);
+    let running: RunningTimer = read_json(app.clone().oneshot(current()).await.unwrap()).await;
+    assert!(running.countdown.is_none());
+    app.clone().oneshot(post("/timer/stop", &token)).await.unwrap();
+
+    let report: PomodoroReport = read_json(app.clone().oneshot(pomodoros("")).await.unwrap()).await;
+    assert_eq!((report.date.to_string(), report.completed, report.tracked_secs), ("2025-04-01".into(), 2, 3000));
+
+    let report: PomodoroReport = read_json(app.clone().oneshot(pomodoros("?date=2025-04-02")).await.unwrap()).await;
+    assert_eq!(report.completed, 0);
+
+    let request = with_token(json_request("PATCH", "/me/preferences", json!({ "pomodoro_break_secs": 5 })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = app.oneshot(post("/timer/start?mode=tomato", &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_entry_overlaps() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    create_users(&app, &["chad74@gmail.com", "chad75@gmail.com"]).await;
+    let token = test_token(1);
+
+    let create = |query: &str, started_at: &str, ended_at: Option<&str>| {
+        let body = json!({
+            "started_at": format!("2025-04-01T{}:00Z", started_at),
+            "ended_at": ended_at.map(|ended_at| format!("2025-04-01T{}:00Z", ended_at))
+        });
+        with_token(json_request("POST", &format!("/entries{}", query), body), &token)
+    };
+    let conflict = |response: axum::response::Response| async move {
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body: EntriesErrorResponse = read_json(response).await;
+        assert_eq!(body.error, "entries_overlap");
+        body.entry_ids
+    };
+
+    let first: TimeEntry = read_json(app.clone().oneshot(create("", "09:00", Some("10:00"))).await.unwrap()).await;
+
+    // Exactly, and partly.
+    for (started_at, ended_at) in [("09:00", "10:00"), ("09:30", "10:30"), ("08:00", "09:01")] {
+        let response = app.clone().oneshot(create("", started_at, Some(ended_at))).await.unwrap();
+        assert_eq!(conflict(response).await, [first.id]);
+    }
+
+    // Touching is fine.
+    let response = app.clone().oneshot(create("", "10:00", Some("11:00"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let second: TimeEntry = read_json(response).await;
+    let response = app.clone().oneshot(create("", "08:00", Some("09:00"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app.clone().oneshot(create("", "09:30", Some("10:30"))).await.unwrap();
+    assert_eq!(conflict(response).await, [first.id, second.id]);
+
+    // A running entry reaches on forever.
+    let running: TimeEntry = read_json(app.clone().oneshot(create("", "12:00", None)).await.unwrap()).await;
+    let response = app.clone().oneshot(create("", "13:00", Some("14:00"))).await.unwrap();
+    assert_eq!(conflict(response).await, [running.id]);
+
+    // An entry doesn't overlap itself.
+    let update = |id: i32, started_at: &str, ended_at: &str| {
+        let body = json!({
+            "started_at": format!("2025-04-01T{}:00Z", started_at),
+            "ended_at": format!("2025-04-01T{}:00Z", ended_at)
+        });
+        with_token(json_request("PUT", &format!("/entries/{}", id), body), &token)
+    };
+    let response = app.clone().oneshot(update(second.id, "10:00", "11:30")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.clone().oneshot(update(second.id, "09:59", "11:30")).await.unwrap();
+    assert_eq!(conflict(response).await, [first.id]);
+
+    // Opting out, once and for good.
+    let response = app.clone().oneshot(create("?allow_overlap=true", "09:15", Some("09:45"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    // Nor does that one count against later entries.
+    let response = app.clone().oneshot(create("", "09:15", Some("09:45"))).await.unwrap();
+    assert_eq!(conflict(response).await, [first.id]);
+
+    let request = with_token(json_request("PATCH", "/me/preferences", json!({ "allow_overlap": true })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(read_json::<Preferences>(response).await, Preferences {
+        auto_stop_timer: false,
+        allow_overlap: true,
+        pomodoro_work_secs: 1500,
+        pomodoro_break_secs: 300,
+        hourly_rate_cents: None,
+        currency: "USD".to_string(),
+        tax_rate_bps: 0,
+    });
+    let response = app.clone().oneshot(create("", "13:00", Some("14:00"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Other people's entries are no obstacle.
+    let request = with_token(json_request("POST", "/entries", json!({
+        "started_at": "2025-04-01T09:00:00Z",
+        "ended_at": "2025-04-01T10:00:00Z"
+    })), &test_token(2));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // The database holds the line against writes that skip the check.
+    let err = sqlx::query!(
+        "INSERT INTO time_entries (user_id, started_at, ended_at) VALUES (1, '2025-04-01T09:10:00Z', '2025-04-01T09:20:00Z')"
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap_err();
+    assert_eq!(err.as_database_error().and_then(|err| err.constraint()), Some("time_entries_no_overlap"));
+}
+
+#[tokio::test]
+async fn test_entry_durations() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    create_users(&app, &["chad76@gmail.com"]).await;
+    let token = test_token(1);
+
+    let create = |body: serde_json::Value| with_token(json_request("POST", "/entries", body), &token);
+
+    let response = app.clone().oneshot(create(json!({
+        "started_at": "2025-04-01T09:00:00Z",
+        "ended_at": "2025-04-01T09:45:00Z"
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(read_json::<TimeEntry>(response).await.duration_seconds, Some(2700));
+
+    for (day, duration, seconds) in [(2, json!(2700), 2700), (3, json!("1h 30m"), 5400), (4, json!("2h"), 7200)] {
+        let response = app.clone().oneshot(create(json!({
+            "started_at": format!("2025-04-{:02}T09:00:00Z", day),
+            "duration_seconds": duration
+        }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let entry: TimeEntry = read_json(response).await;
+        assert_eq!(entry.duration_seconds, Some(seconds));
+        assert_eq!(entry.ended_at, Some(entry.started_at + chrono::Duration::seconds(seconds)));
+    }
+
+    let running: TimeEntry = read_json(app.clone().oneshot(create(json!({ "started_at": "2025-04-05T09:00:00Z" }))).await.unwrap()).await;
+    assert_eq!(running.duration_seconds, None);
+
+    for (body, reason) in [
+        (
+            json!({ "started_at": "2025-04-06T09:00:00Z", "ended_at": "2025-04-06T10:00:00Z", "duration_seconds": 3600 }),
+            "give either ended_at or duration_seconds, not both",
+        ),
+        (json!({ "started_at": "2025-04-06T09:00:00Z", "duration_seconds": "soonish" }), "expected a number at 'soonish'"),
+        (json!({ "started_at": "2025-04-06T09:00:00Z", "duration_seconds": 0 }), "must be positive"),
+    ] {
+        let response = app.clone().oneshot(create(body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let errors: ValidationErrors = read_json(response).await;
+        assert_eq!(errors.errors["duration_seconds"], [reason]);
+    }
+}
+
+#[tokio::test]
+async fn test_bulk_entries() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    create_users(&app, &["chad77@gmail.com", "chad78@gmail.com"]).await;
+    let (alice, bob) = (test_token(1), test_token(2));
+
+    let mut ids = Vec::new();
+    for (day, token) in [(1, &alice), (2, &alice), (3, &alice), (4, &bob)] {
+        let request = with_token(json_request("POST", "/entries", json!({
+            "description": "Misc",
+            "started_at": format!("2025-04-{:02}T09:00:00Z", day),
+            "ended_at": format!("2025-04-{:02}T10:00:00Z", day),
+            "tags": ["old"]
+        })), token);
+        ids.push(read_json::<TimeEntry>(app.clone().oneshot(request).await.unwrap()).await.id);
+    }
+    let request = with_token(json_request("POST", "/projects", json!({ "name": "Client X" })), &alice);
+    let project: Project = read_json(app.clone().oneshot(request).await.unwrap()).await;
+
+    let bulk = |body: serde_json::Value| with_token(json_request("POST", "/entries/bulk", body), &alice);
+    let read = |id: i32| with_token(Request::get(format!("/entries/{}", id)).body(Body::empty()).unwrap(), &alice);
+
+    let response = app.clone().oneshot(bulk(json!({
+        "ids": [ids[0], ids[1]],
+        "set": { "project_id": project.id, "tags": ["meeting", "client-x"] }
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read_json::<BulkResult>(response).await, BulkResult { affected: 2 });
+
+    for id in &ids[..2] {
+        let entry: TimeEntry = read_json(app.clone().oneshot(read(*id)).await.unwrap()).await;
+        assert_eq!(entry.project_id, Some(project.id));
+        assert_eq!(entry.tags, ["client-x", "meeting"]);
+        assert_eq!(entry.description, "Misc");
+    }
+    let untouched: TimeEntry = read_json(app.clone().oneshot(read(ids[2])).await.unwrap()).await;
+    assert_eq!((untouched.project_id, untouched.tags), (None, vec!["old".to_string()]));
+
+    // null clears the project and nothing else.
+    let response = app.clone().oneshot(bulk(json!({ "ids": [ids[0]], "set": { "project_id": null } }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let entry: TimeEntry = read_json(app.clone().oneshot(read(ids[0])).await.unwrap()).await;
+    assert_eq!((entry.project_id, entry.tags.len()), (None, 2));
+
+    // Anything that isn't the caller's fails the lot.
+    let response = app.clone().oneshot(bulk(json!({
+        "ids": [ids[0], ids[3], 999],
+        "set": { "description": "Changed" }
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: EntriesErrorResponse = read_json(response).await;
+    assert_eq!((body.error.as_str(), body.entry_ids), ("entries_not_found", vec![ids[3], 999]));
+    let entry: TimeEntry = read_json(app.clone().oneshot(read(ids[0])).await.unwrap()).await;
+    assert_eq!(entry.description, "Misc");
+
+    let response = app.clone().oneshot(bulk(json!({
+        "ids": (1..=validation::MAX_BULK_IDS as i32 + 1).collect::<Vec<_>>(),
+        "set": { "description": "Changed" }
+    }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["ids"], ["too many"]);
+
+    let response = app.clone().oneshot(bulk(json!({ "ids": [ids[0]], "set": {} }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let delete = |body: serde_json::Value| with_token(json_request("POST", "/entries/bulk-delete", body), &alice);
+
+    let response = app.clone().oneshot(delete(json!({ "ids": [ids[0], ids[3]] }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = app.clone().oneshot(read(ids[0])).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(delete(json!({ "ids": [ids[1], ids[2], ids[1]] }))).await.unwrap();
+    assert_eq!(read_json::<BulkResult>(response).await, BulkResult { affected: 2 });
+    let page: Page<TimeEntry> =
+        read_json(app.clone().oneshot(with_token(Request::get("/entries").body(Body::empty()).unwrap(), &alice)).await.unwrap()).await;
+    assert_eq!(page.items.iter().map(|entry| entry.id).collect::<Vec<_>>(), [ids[0]]);
+}
+
+#[tokio::test]
+async fn test_export_entries() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.clock = Arc::new(TestClock { now: Mutex::new("2025-04-20T12:00:00Z".parse().unwrap()) });
+    let app = app(state);
+    create_users(&app, &["chad140@gmail.com", "chad141@gmail.com"]).await;
+    let (alice, bob) = (test_token(1), test_token(2));
+
+    // UTC-3 all year.
+    let request = with_token(json_request("PUT", "/me/settings", json!({
+        "timezone": "America/Sao_Paulo",
+        "week_start": "monday"
+    })), &alice);
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = with_token(json_request("POST", "/projects", json!({ "name": "Acme, Inc" })), &alice);
+    let acme: Project = read_json(app.clone().oneshot(request).await.unwrap()).await;
+
+    for (token, entry) in [
+        // The last evening of March, locally.
+        (&alice, json!({ "started_at": "2025-04-01T01:00:00Z", "ended_at": "2025-04-01T02:00:00Z" })),
+        (&alice, json!({
+            "project_id": acme.id,
+            "started_at": "2025-04-07T12:00:00Z",
+            "ended_at": "2025-04-07T13:30:05Z",
+            "tags": ["meeting", "billable"],
+            "description": "Said \"hi\",\nthen left"
+        })),
+        // Started on April's last evening, locally.
+        (&alice, json!({ "started_at": "2025-05-01T02:00:00Z", "duration_seconds": "26h" })),
+        // Still running, so left out.
+        (&alice, json!({ "started_at": "2025-04-20T11:00:00Z" })),
+        (&bob, json!({ "started_at": "2025-04-10T09:00:00Z", "ended_at": "2025-04-10T10:00:00Z" })),
+    ] {
+        let request = json_request("POST", "/entries?allow_overlap=true", entry);
+        let response = app.clone().oneshot(with_token(request, token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let export = |query: &str| with_token(Request::get(format!("/entries/export{}", query)).body(Body::empty()).unwrap(), &alice);
+    let rows = |body: &[u8]| {
+        csv::Reader::from_reader(body)
+            .records()
+            .map(|record| record.unwrap().iter().map(str::to_string).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+
+    // This month, by default.
+    let response = app.clone().oneshot(export("")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+    assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"tictoc-2025-04.csv\"");
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut reader = csv::Reader::from_reader(&body[..]);
+    let headers: Vec<&str> = reader.headers().unwrap().iter().collect();
+    assert_eq!(headers, ["date", "start", "end", "duration", "project", "tags", "description"]);
+    assert_eq!(rows(&body), [
+        [
+            "2025-04-07",
+            "2025-04-07 09:00:00",
+            "2025-04-07 10:30:05",
+            "01:30:05",
+            "Acme, Inc",
+            "billable;meeting",
+            "Said \"hi\",\nthen left",
+        ],
+        ["2025-04-30", "2025-04-30 23:00:00", "2025-05-02 01:00:00", "26:00:00", "", "", ""],
+    ]);
+
+    let response = app.clone().oneshot(export(&format!("?from=2025-03-01&to=2025-04-07&project_id={}", acme.id))).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"tictoc-2025-03-01-2025-04-07.csv\"");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(rows(&body).iter().map(|row| row[1].as_str()).collect::<Vec<_>>(), ["2025-04-07 09:00:00"]);
+
+    let response = app.clone().oneshot(export("?from=2025-03-31&to=2025-03-31&format=ndjson")).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"tictoc-2025-03-31-2025-03-31.ndjson\"");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines, [json!({
+        "date": "2025-03-31",
+        "start": "2025-03-31 22:00:00",
+        "end": "2025-03-31 23:00:00",
+        "duration": "01:00:00",
+        "project": null,
+        "tags": "",
+        "description": ""
+    })]);
+
+    for query in ["?from=2025-04-10&to=2025-04-09", "?format=xml", "?from=soon"] {
+        let response = app.clone().oneshot(export(query)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
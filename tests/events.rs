@@ -0,0 +1,89 @@
+mod common;
+
+use axum::body::{Body, BodyDataStream};
+use axum::http::{header, Request, StatusCode};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tictoc::models::TimeEntry;
+use tower::ServiceExt;
+
+use common::*;
+
+/// The next event on the stream, as its name and JSON data.
+async fn next_event(stream: &mut BodyDataStream) -> (String, Value) {
+    let mut text = String::new();
+    loop {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("no event in time")
+            .unwrap()
+            .unwrap();
+        text.push_str(std::str::from_utf8(&chunk).unwrap());
+
+        let Some(end) = text.find("\n\n") else { continue };
+        let (mut event, mut data) = (String::new(), String::new());
+        for line in text[..end].lines() {
+            if let Some(name) = line.strip_prefix("event: ") {
+                event = name.to_string();
+            } else if let Some(line) = line.strip_prefix("data: ") {
+                data.push_str(line);
+            }
+        }
+        return (event, serde_json::from_str(&data).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn test_event_stream() {
+    let db = TestDb::new().await;
+    let app = tictoc::app(test_state(db.pool.clone()));
+    for email in ["chad204@gmail.com", "chad205@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": "password" }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let token = test_token(1);
+
+    let response = app.clone().oneshot(Request::get("/events").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = with_token(Request::get("/events").body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+    let mut stream = response.into_body().into_data_stream();
+
+    // Another user's timer doesn't show up.
+    let request = with_token(json_request("POST", "/timer/start", json!({})), &test_token(2));
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+
+    let request = with_token(json_request("POST", "/timer/start", json!({ "description": "Tic" })), &token);
+    let entry: TimeEntry = read_json(app.clone().oneshot(request).await.unwrap()).await;
+
+    let (event, data) = next_event(&mut stream).await;
+    assert_eq!(event, "timer.started");
+    assert_eq!(serde_json::from_value::<TimeEntry>(data).unwrap(), entry);
+
+    let request = with_token(json_request("POST", "/timer/stop", json!({})), &token);
+    let stopped: TimeEntry = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    let (event, data) = next_event(&mut stream).await;
+    assert_eq!((event.as_str(), data["id"].clone()), ("timer.stopped", json!(entry.id)));
+    assert_eq!(data["ended_at"], json!(stopped.ended_at));
+
+    let request = with_token(json_request("PUT", &format!("/entries/{}", entry.id), json!({
+        "description": "Toc",
+        "started_at": stopped.started_at,
+        "ended_at": stopped.ended_at
+    })), &token);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    let (event, data) = next_event(&mut stream).await;
+    assert_eq!((event.as_str(), data["description"].clone()), ("entry.updated", json!("Toc")));
+
+    let request = with_token(json_request("POST", "/entries", json!({
+        "started_at": "2025-01-01T08:00:00Z",
+        "ended_at": "2025-01-01T09:00:00Z"
+    })), &token);
+    let created: TimeEntry = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    let (event, data) = next_event(&mut stream).await;
+    assert_eq!((event.as_str(), data["id"].clone()), ("entry.created", json!(created.id)));
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::{json, Value};
+use tictoc::auth::AuthUser;
+use tictoc::graphql;
+use tictoc::models::{CreateUserResponse, Project, Role};
+use tictoc::app;
+
+use common::*;
+
+async fn sign_up(app: &axum::Router, email: &str) -> (CreateUserResponse, String) {
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": "password" }));
+    let response = send(app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let user = read_json(response).await;
+    (user, login_token(app, email, "password").await)
+}
+
+fn graphql_request(query: &str, token: Option<&str>) -> Request<Body> {
+    let request = json_request("POST", "/graphql", json!({ "query": query }));
+    match token {
+        Some(token) => with_token(request, token),
+        None => request,
+    }
+}
+
+#[tokio::test]
+async fn test_query_through_the_schema() {
+    let db = TestDb::new().await;
+    let state = test_state(db.pool.clone());
+    let app = app(state.clone());
+    let (user, token) = sign_up(&app, "chad230@gmail.com").await;
+
+    let request = with_token(json_request("POST", "/projects", json!({ "name": "Website" })), &token);
+    let project: Project = read_json(send(&app, request).await).await;
+    for (hour, tags) in [(9, json!(["client", "design"])), (11, json!(["client"])), (13, json!([]))] {
+        let entry = json!({
+            "project_id": project.id,
+            "description": format!("At {}", hour),
+            "started_at": format!("2025-03-03T{:02}:00:00Z", hour),
+            "duration_seconds": 3600,
+            "tags": tags,
+        });
+        let response = send(&app, with_token(json_request("POST", "/entries", entry), &token)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let auth = AuthUser {
+        id: user.id,
+        email: user.email.clone(),
+        jti: "graphql-test".to_string(),
+        exp: u64::MAX,
+        role: Role::User,
+        api_key_id: None,
+        session_id: None,
+    };
+    let query = r#"{
+        me { email }
+        projects { total items { name } }
+        entries(limit: 10) {
+            total
+            items { description durationSeconds project { name } tags { name entries } }
+        }
+        reportSummary(from: "2025-03-03", to: "2025-03-03", groupBy: PROJECT) {
+            trackedSecs
+            buckets { label trackedSecs }
+        }
+    }"#;
+    let response = graphql::schema(state.clone()).execute(graphql::request(query, &state, Some(auth))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["me"]["email"], "chad230@gmail.com");
+    assert_eq!(data["projects"], json!({ "total": 1, "items": [{ "name": "Website" }] }));
+    assert_eq!(data["entries"]["total"], 3);
+    assert_eq!(
+        data["entries"]["items"],
+        json!([
+            { "description": "At 13", "durationSeconds": 3600, "project": { "name": "Website" }, "tags": [] },
+            {
+                "description": "At 11", "durationSeconds": 3600, "project": { "name": "Website" },
+                "tags": [{ "name": "client", "entries": 2 }],
+            },
+            {
+                "description": "At 9", "durationSeconds": 3600, "project": { "name": "Website" },
+                "tags": [{ "name": "client", "entries": 2 }, { "name": "design", "entries": 1 }],
+            },
+        ])
+    );
+    assert_eq!(
+        data["reportSummary"],
+        json!({ "trackedSecs": 10800, "buckets": [{ "label": "Website", "trackedSecs": 10800 }] })
+    );
+
+    // Without a user, fields fail as the endpoints would.
+    let response = graphql::schema(state.clone()).execute(graphql::request("{ me { id } }", &state, None)).await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!((&error["message"], &error["extensions"]["status"]), (&json!("missing_token"), &json!(401)));
+}
+
+#[tokio::test]
+async fn test_mutations_over_http() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    let (_, token) = sign_up(&app, "chad231@gmail.com").await;
+
+    let response = send(&app, graphql_request(r#"mutation { startTimer(description: "Standup") { id endedAt } }"#, Some(&token))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = read_json(response).await;
+    let started = &body["data"]["startTimer"];
+    assert!(started["id"].is_i64() && started["endedAt"].is_null(), "{}", body);
+
+    let body: Value = read_json(send(&app, graphql_request("mutation { stopTimer { id endedAt } }", Some(&token))).await).await;
+    assert_eq!(body["data"]["stopTimer"]["id"], started["id"]);
+    assert!(body["data"]["stopTimer"]["endedAt"].is_string());
+
+    // The REST endpoint's error, field by field.
+    let body: Value = read_json(send(&app, graphql_request("mutation { stopTimer { id } }", Some(&token))).await).await;
+    assert_eq!(body["errors"][0]["extensions"]["code"], "no_running_timer");
+    assert_eq!(body["errors"][0]["extensions"]["status"], 409);
+
+    let mutation = r#"mutation {
+        createEntry(input: { startedAt: "2025-03-03T09:00:00Z", endedAt: "2025-03-03T08:00:00Z" }) { id }
+    }"#;
+    let body: Value = read_json(send(&app, graphql_request(mutation, Some(&token))).await).await;
+    assert_eq!(body["errors"][0]["extensions"]["code"], "validation_failed");
+    assert!(body["errors"][0]["extensions"]["body"]["errors"]["ended_at"].is_array(), "{}", body);
+
+    let mutation = r#"mutation {
+        createEntry(input: { description: "Review", startedAt: "2025-03-03T09:00:00Z", durationSeconds: 1800, tags: ["Ops"] }) {
+            durationSeconds
+            tags { name }
+        }
+    }"#;
+    let body: Value = read_json(send(&app, graphql_request(mutation, Some(&token))).await).await;
+    assert_eq!(body["data"]["createEntry"], json!({ "durationSeconds": 1800, "tags": [{ "name": "Ops" }] }));
+
+    // Only admins list users; a bad token is refused before the query runs.
+    let body: Value = read_json(send(&app, graphql_request("{ users { total } }", Some(&token))).await).await;
+    assert_eq!(body["errors"][0]["extensions"]["code"], "forbidden");
+    let body: Value = read_json(send(&app, graphql_request("{ users { total } }", Some(&admin_token(1)))).await).await;
+    assert_eq!(body["data"]["users"]["total"], 1);
+    let response = send(&app, graphql_request("{ me { id } }", Some("not-a-token"))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_playground_only_when_enabled() {
+    let db = TestDb::new().await;
+    let playground = || Request::get("/graphql/playground").body(Body::empty()).unwrap();
+
+    let response = send(&app(test_state(db.pool.clone())), playground()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let mut state = test_state(db.pool.clone());
+    state.graphql_playground = true;
+    let response = send(&app(state), playground()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+    assert!(response.headers()[header::CONTENT_SECURITY_POLICY].to_str().unwrap().contains("https://unpkg.com"));
+}
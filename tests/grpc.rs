@@ -0,0 +1,168 @@
+mod common;
+
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tictoc::grpc::proto::tictoc_client::TictocClient;
+use tictoc::grpc::proto::{self, Role};
+use tictoc::models::{CreateUserResponse, LoginUserResponse};
+use tictoc::validation::MAX_DESCRIPTION_LEN;
+use tictoc::{app, grpc, serve};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Channel;
+use tonic::{Code, Request};
+
+use common::*;
+
+const SERVICE_TOKEN: &str = "grpc-service-token";
+
+/// Both servers on ephemeral ports, over the one state, with the shutdown
+/// that stops them.
+struct Servers {
+    http: SocketAddr,
+    grpc: SocketAddr,
+    shutdown: CancellationToken,
+    handles: (tokio::task::JoinHandle<std::io::Result<()>>, tokio::task::JoinHandle<Result<(), tonic::transport::Error>>),
+}
+
+async fn start(db: &TestDb) -> Servers {
+    let mut state = test_state(db.pool.clone());
+    state.grpc_token = Some(SERVICE_TOKEN.to_string());
+    let shutdown = state.shutdown.clone();
+    let grace = Duration::from_secs(5);
+
+    let http = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let grpc = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addrs = (http.local_addr().unwrap(), grpc.local_addr().unwrap());
+    let handles = (
+        tokio::spawn(serve(http, app(state.clone()), shutdown.clone(), grace)),
+        tokio::spawn(grpc::serve(grpc, state, shutdown.clone(), grace)),
+    );
+
+    Servers { http: addrs.0, grpc: addrs.1, shutdown, handles }
+}
+
+async fn post(addr: SocketAddr, path: &str, body: Value) -> String {
+    reqwest::Client::new()
+        .post(format!("http://{}{}", addr, path))
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn client(addr: SocketAddr) -> TictocClient<Channel> {
+    TictocClient::connect(format!("http://{}", addr)).await.unwrap()
+}
+
+fn authorized<T>(message: T, token: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn test_validate_token_from_http_login() {
+    let db = TestDb::new().await;
+    let servers = start(&db).await;
+
+    let credentials = json!({ "name": "Chad", "email": "chad232@gmail.com", "password": "password" });
+    let user: CreateUserResponse = serde_json::from_str(&post(servers.http, "/users/create", credentials.clone()).await).unwrap();
+    let login: LoginUserResponse = serde_json::from_str(&post(servers.http, "/users/login", credentials).await).unwrap();
+
+    let mut client = client(servers.grpc).await;
+    let validated = client
+        .validate_token(authorized(proto::ValidateTokenRequest { token: login.token.clone() }, SERVICE_TOKEN))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(validated.valid, "{:?}", validated);
+    assert_eq!((validated.user_id, validated.email.as_str()), (user.id, "chad232@gmail.com"));
+    assert_eq!(validated.role(), Role::User);
+    assert!(validated.expires_at.is_some());
+
+    let validated = client
+        .validate_token(authorized(proto::ValidateTokenRequest { token: "not-a-token".to_string() }, SERVICE_TOKEN))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!((validated.valid, validated.reason.as_str()), (false, "invalid_token"));
+
+    // The user's own token isn't the service's.
+    for token in [login.token.as_str(), "wrong"] {
+        let status = client
+            .validate_token(authorized(proto::ValidateTokenRequest { token: login.token.clone() }, token))
+            .await
+            .unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "invalid_token"));
+    }
+    let status = client
+        .validate_token(proto::ValidateTokenRequest { token: login.token.clone() })
+        .await
+        .unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "missing_token"));
+
+    servers.shutdown.cancel();
+    servers.handles.0.await.unwrap().unwrap();
+    servers.handles.1.await.unwrap().unwrap();
+    assert!(tokio::net::TcpStream::connect(servers.grpc).await.is_err());
+}
+
+#[tokio::test]
+async fn test_timer_and_entries() {
+    let db = TestDb::new().await;
+    let servers = start(&db).await;
+
+    let credentials = json!({ "name": "Chad", "email": "chad233@gmail.com", "password": "password" });
+    let user: CreateUserResponse = serde_json::from_str(&post(servers.http, "/users/create", credentials).await).unwrap();
+    let mut client = client(servers.grpc).await;
+
+    let fetched = client
+        .get_user(authorized(proto::GetUserRequest { id: user.id }, SERVICE_TOKEN))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(fetched.email, "chad233@gmail.com");
+    let status = client.get_user(authorized(proto::GetUserRequest { id: 0 }, SERVICE_TOKEN)).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::NotFound, "user_not_found"));
+
+    let start = proto::StartTimerRequest { user_id: user.id, description: "Standup".to_string(), ..Default::default() };
+    let started = client.start_timer(authorized(start.clone(), SERVICE_TOKEN)).await.unwrap().into_inner();
+    assert!(started.ended_at.is_none());
+
+    // The endpoint's error, with its body as the details.
+    let status = client.start_timer(authorized(start, SERVICE_TOKEN)).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::FailedPrecondition, "timer_already_running"));
+    let body: Value = serde_json::from_slice(status.details()).unwrap();
+    assert_eq!(body["error"], "timer_already_running");
+
+    let start = proto::StartTimerRequest {
+        user_id: user.id,
+        description: "x".repeat(MAX_DESCRIPTION_LEN + 1),
+        ..Default::default()
+    };
+    let status = client.start_timer(authorized(start, SERVICE_TOKEN)).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "validation_failed"));
+
+    let stopped = client
+        .stop_timer(authorized(proto::StopTimerRequest { user_id: user.id }, SERVICE_TOKEN))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stopped.id, started.id);
+    assert!(stopped.ended_at.is_some());
+
+    let listed = client
+        .list_entries(authorized(proto::ListEntriesRequest { user_id: user.id, ..Default::default() }, SERVICE_TOKEN))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(listed.total, 1);
+    assert_eq!(listed.entries[0].description, "Standup");
+
+    servers.shutdown.cancel();
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tictoc::app;
+use tictoc::models::{Page, TimeEntry};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+use common::*;
+
+const SEEDED_ENTRIES: i64 = 5_000;
+
+/// Counts the statements sqlx runs, off the events it logs for each one.
+#[derive(Clone, Default)]
+struct QueryCounter(Arc<AtomicUsize>);
+
+impl<S: tracing::Subscriber> Layer<S> for QueryCounter {
+    fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+        if event.metadata().target() == "sqlx::query" {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl QueryCounter {
+    /// The statements `request` took, from the extractors to the response.
+    async fn count(&self, app: &axum::Router, request: Request<Body>) -> (usize, Page<TimeEntry>) {
+        let before = self.0.load(Ordering::SeqCst);
+        let response = send(app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = read_json(response).await;
+        (self.0.load(Ordering::SeqCst) - before, page)
+    }
+}
+
+fn list(query: &str) -> Request<Body> {
+    with_token(Request::get(format!("/entries{}", query)).body(Body::empty()).unwrap(), &test_token(1))
+}
+
+#[tokio::test]
+async fn test_entry_listing_takes_the_same_queries_at_any_size() {
+    let db = TestDb::new().await;
+    sqlx::raw_sql(&format!(
+        "INSERT INTO users (name, email, password_hash) VALUES ('Chad', 'chad220@gmail.com', '');
+        INSERT INTO tags (user_id, name) VALUES (1, 'Client'), (1, 'deep'), (1, 'admin');
+        INSERT INTO time_entries (user_id, started_at, ended_at)
+            SELECT 1, now() - make_interval(hours => i), now() - make_interval(hours => i) + interval '30 minutes'
+            FROM generate_series(1, {}) i;
+        INSERT INTO time_entry_tags (entry_id, tag_id)
+            SELECT id, 1 FROM time_entries UNION ALL SELECT id, 2 FROM time_entries WHERE id % 2 = 0;
+        ANALYZE time_entries, time_entry_tags, tags;",
+        SEEDED_ENTRIES
+    ))
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let counter = QueryCounter::default();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(counter.clone()));
+    let app = app(test_state(db.pool.clone()));
+
+    let (small, page) = counter.count(&app, list("?limit=1")).await;
+    assert_eq!(page.total, SEEDED_ENTRIES);
+    let (large, page) = counter.count(&app, list("?limit=100")).await;
+    assert_eq!(page.items.len(), 100);
+    // Entry 1 started latest, and has only the one tag.
+    assert_eq!(page.items[0].tags, ["Client"]);
+    assert_eq!(page.items[1].tags, ["Client", "deep"]);
+    assert_eq!(small, large);
+    assert!((1..=5).contains(&large), "{} queries", large);
+
+    let (filtered, page) = counter.count(&app, list("?limit=100&tag=deep&tag=client")).await;
+    assert_eq!(page.total, SEEDED_ENTRIES / 2);
+    assert!(page.items.iter().all(|entry| entry.tags == ["Client", "deep"]));
+    assert_eq!(filtered, large);
+
+    // A page is read off the index in order, not sorted out of every entry.
+    let plan: Vec<String> = sqlx::query_scalar(
+        "EXPLAIN SELECT id FROM time_entries WHERE user_id = 1 ORDER BY started_at DESC, id DESC LIMIT 100",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .unwrap();
+    assert!(plan.iter().any(|line| line.contains("time_entries_user_id_started_at_id_idx")), "{:#?}", plan);
+}
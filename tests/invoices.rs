@@ -0,0 +1,168 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tictoc::app;
+use tictoc::error::EntriesErrorResponse;
+use tictoc::models::{Invoice, InvoiceGrouping, InvoiceLine, Preferences, Project, TimeEntry};
+use tictoc::validation::ValidationErrors;
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_invoices() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.clock = Arc::new(TestClock { now: Mutex::new("2025-05-10T12:00:00Z".parse().unwrap()) });
+    let app = app(state);
+
+    for email in ["chad180@gmail.com", "chad181@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let (token, other) = (test_token(1), test_token(2));
+
+    let request = with_token(json_request("PATCH", "/me/preferences", json!({ "tax_rate_bps": 2000 })), &token);
+    let preferences: Preferences = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!(preferences.tax_rate_bps, 2000);
+
+    let request = with_token(json_request("POST", "/projects", json!({
+        "name": "Website",
+        "hourly_rate_cents": 12000,
+        "currency": "USD"
+    })), &token);
+    let website: Project = read_json(app.clone().oneshot(request).await.unwrap()).await;
+
+    let mut entries = Vec::new();
+    for (started_at, ended_at, description, billable) in [
+        ("2025-04-01T09:00:00Z", "2025-04-01T10:00:00Z", "Design", true),
+        ("2025-04-01T13:00:00Z", "2025-04-01T13:30:00Z", "Review", true),
+        ("2025-04-02T09:00:00Z", "2025-04-02T10:30:00Z", "", true),
+        ("2025-04-03T09:00:00Z", "2025-04-03T10:00:00Z", "Chat", false),
+        ("2025-05-02T09:00:00Z", "2025-05-02T10:00:00Z", "Fixes", true),
+        ("2025-05-02T11:00:00Z", "2025-05-02T11:15:00Z", "Fixes", true),
+    ] {
+        let request = with_token(json_request("POST", "/entries", json!({
+            "project_id": website.id,
+            "description": description,
+            "started_at": started_at,
+            "ended_at": ended_at,
+            "billable": billable
+        })), &token);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        entries.push(read_json::<TimeEntry>(response).await);
+    }
+
+    let create = |body: serde_json::Value| with_token(json_request("POST", "/invoices", body), &token);
+
+    let response = app.clone()
+        .oneshot(create(json!({ "project_id": website.id, "from": "2025-04-01", "to": "2025-04-30" })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let location = response.headers()[header::LOCATION].to_str().unwrap().to_string();
+    let invoice: Invoice = read_json(response).await;
+    assert_eq!(location, format!("/invoices/{}", invoice.id));
+    assert_eq!((invoice.number, invoice.group_by, invoice.currency.as_str()), (1, InvoiceGrouping::Day, "USD"));
+    // Entries on the same day share a line; one without a description is
+    // billed under the project's name.
+    assert_eq!(invoice.lines, [
+        InvoiceLine {
+            date: "2025-04-01".parse().unwrap(),
+            description: "Design; Review".to_string(),
+            seconds: 5400,
+            rate_cents: 12000,
+            amount_cents: 18000,
+        },
+        InvoiceLine {
+            date: "2025-04-02".parse().unwrap(),
+            description: "Website".to_string(),
+            seconds: 5400,
+            rate_cents: 12000,
+            amount_cents: 18000,
+        },
+    ]);
+    assert_eq!((invoice.subtotal_cents, invoice.tax_cents, invoice.total_cents), (36000, 7200, 43200));
+
+    // Billed entries can't change any more, but the rest can.
+    let entry = |id: i32| format!("/entries/{}", id);
+    let request = with_token(json_request("PUT", &entry(entries[0].id), json!({
+        "project_id": website.id,
+        "description": "Redesign",
+        "started_at": "2025-04-01T09:00:00Z",
+        "ended_at": "2025-04-01T10:00:00Z"
+    })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: EntriesErrorResponse = read_json(response).await;
+    assert_eq!((body.error.as_str(), body.entry_ids), ("entries_invoiced", vec![entries[0].id]));
+
+    let request = with_token(Request::delete(entry(entries[1].id)).body(Body::empty()).unwrap(), &token);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CONFLICT);
+
+    let request = with_token(json_request("POST", "/entries/bulk", json!({
+        "ids": [entries[2].id, entries[3].id],
+        "set": { "description": "Changed" }
+    })), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: EntriesErrorResponse = read_json(response).await;
+    assert_eq!(body.entry_ids, [entries[2].id]);
+
+    let request = with_token(Request::delete(entry(entries[3].id)).body(Body::empty()).unwrap(), &token);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+    // Nothing is billed twice.
+    let request = create(json!({ "project_id": website.id, "from": "2025-04-01", "to": "2025-04-30", "tax_rate_bps": 0 }));
+    let again: Invoice = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!((again.number, again.lines.len(), again.total_cents), (2, 0, 0));
+
+    let request = create(json!({ "project_id": website.id, "from": "2025-05-01", "to": "2025-05-31", "group_by": "entry" }));
+    let by_entry: Invoice = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!(by_entry.number, 3);
+    assert_eq!(
+        by_entry.lines.iter().map(|line| (line.seconds, line.amount_cents)).collect::<Vec<_>>(),
+        [(3600, 12000), (900, 3000)]
+    );
+
+    let read = |uri: &str, token: &str| with_token(Request::get(uri).body(Body::empty()).unwrap(), token);
+
+    let response = app.clone().oneshot(read(&location, &token)).await.unwrap();
+    assert_eq!(read_json::<Invoice>(response).await, invoice);
+
+    let response = app.clone().oneshot(read(&format!("{}?format=html", location), &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+    let page = String::from_utf8(to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap();
+    assert!(page.contains("Invoice 1"));
+    assert!(page.contains("Design; Review"));
+    assert!(page.contains("432.00 USD"));
+
+    let response = app.clone().oneshot(read(&location, &other)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Someone else's project can't be billed.
+    let request = with_token(
+        json_request("POST", "/invoices", json!({ "project_id": website.id, "from": "2025-04-01", "to": "2025-04-30" })),
+        &other,
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["project_id"], ["not found"]);
+
+    let response = app.clone()
+        .oneshot(create(json!({ "project_id": website.id, "from": "2025-04-30", "to": "2025-04-01", "tax_rate_bps": 10001 })))
+        .await
+        .unwrap();
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors.len(), 2);
+}
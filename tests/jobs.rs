@@ -0,0 +1,164 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tictoc::jobs::{self, JobError};
+use tictoc::models::{Job, JobState, Page};
+use tictoc::{app, AppState};
+
+use common::*;
+
+async fn setup(db: &TestDb) -> AppState {
+    let mut state = test_state(db.pool.clone());
+    state.jobs.max_attempts = 3;
+    state.jobs.retry_base = Duration::from_millis(10);
+    state.jobs.poll_interval = Duration::from_millis(10);
+    state.jobs.shutdown_grace = Duration::from_millis(100);
+
+    sqlx::query("INSERT INTO users (name, email, password_hash, role) VALUES ('Chad', 'chad208@gmail.com', '', 'admin')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    state
+}
+
+async fn read_jobs(app: &axum::Router, state: &str) -> Vec<Job> {
+    let request = Request::get(format!("/admin/jobs?state={}", state)).body(Body::empty()).unwrap();
+    let response = send(app, with_token(request, &admin_token(1))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: Page<Job> = read_json(response).await;
+    page.items
+}
+
+async fn retry(app: &axum::Router, id: i64) -> axum::response::Response {
+    let request = Request::post(format!("/admin/jobs/{}/retry", id)).body(Body::empty()).unwrap();
+    send(app, with_token(request, &admin_token(1))).await
+}
+
+async fn count_jobs(db: &TestDb) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM jobs").fetch_one(&db.pool).await.unwrap()
+}
+
+/// Polls until `done` holds, for up to five seconds.
+async fn eventually<F: std::future::Future<Output = bool>>(mut done: impl FnMut() -> F) {
+    for _ in 0..500 {
+        if done().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("gave up waiting");
+}
+
+#[tokio::test]
+async fn test_jobs() {
+    let db = TestDb::new().await;
+    let state = setup(&db).await;
+    let app = app(state.clone());
+
+    let greeted = Arc::new(Mutex::new(Vec::new()));
+    state.jobs.register("greet", {
+        let greeted = greeted.clone();
+        move |_, payload: Value| {
+            let greeted = greeted.clone();
+            async move {
+                greeted.lock().unwrap().push(payload["name"].as_str().unwrap_or_default().to_string());
+                Ok::<_, JobError>(())
+            }
+        }
+    });
+    let broken = Arc::new(AtomicBool::new(true));
+    state.jobs.register("flaky", {
+        let broken = broken.clone();
+        move |_, _| {
+            let broken = broken.load(Ordering::SeqCst);
+            async move {
+                match broken {
+                    true => Err("the printer is on fire".into()),
+                    false => Ok(()),
+                }
+            }
+        }
+    });
+
+    // Only what commits is enqueued.
+    let mut tx = db.pool.begin().await.unwrap();
+    jobs::enqueue(&mut *tx, "greet", json!({ "name": "rolled back" })).await.unwrap();
+    tx.rollback().await.unwrap();
+    let mut tx = db.pool.begin().await.unwrap();
+    jobs::enqueue(&mut *tx, "greet", json!({ "name": "Chad" })).await.unwrap();
+    tx.commit().await.unwrap();
+    let flaky = jobs::enqueue(&db.pool, "flaky", json!({})).await.unwrap();
+    let unknown = jobs::enqueue(&db.pool, "dance", json!({})).await.unwrap();
+
+    assert_eq!(read_jobs(&app, "pending").await.len(), 3);
+    let worker = jobs::spawn_worker(&state).unwrap();
+    assert!(jobs::spawn_worker(&state).is_none());
+
+    // Done with, the job is gone; failing every attempt dead-letters it, and
+    // a kind nothing handles goes straight there.
+    eventually(|| async { read_jobs(&app, "failed").await.len() == 2 }).await;
+    assert_eq!(*greeted.lock().unwrap(), ["Chad"]);
+    assert_eq!(count_jobs(&db).await, 2);
+
+    let failed = read_jobs(&app, "failed").await;
+    let (dead, lost) = (&failed.iter().find(|job| job.id == flaky).unwrap(), &failed.iter().find(|job| job.id == unknown).unwrap());
+    assert_eq!((dead.state, dead.attempts), (JobState::Failed, 3));
+    assert_eq!(dead.last_error.as_deref(), Some("the printer is on fire"));
+    assert!(dead.failed_at.is_some() && dead.locked_by.is_none());
+    assert_eq!(lost.attempts, 1);
+    assert_eq!(lost.last_error.as_deref(), Some("no handler for \"dance\""));
+
+    // Once fixed, a retry gets it through.
+    broken.store(false, Ordering::SeqCst);
+    let response = retry(&app, flaky).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let job: Job = read_json(response).await;
+    assert_eq!((job.state, job.attempts, job.failed_at), (JobState::Pending, 0, None));
+    eventually(|| async { count_jobs(&db).await == 1 }).await;
+
+    assert_eq!(retry(&app, flaky).await.status(), StatusCode::NOT_FOUND);
+    let later: i64 = sqlx::query_scalar("INSERT INTO jobs (kind, payload, run_at) VALUES ('greet', '{}', now() + interval '1 hour') RETURNING id")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(retry(&app, later).await.status(), StatusCode::CONFLICT);
+    assert_eq!(read_jobs(&app, "pending").await.iter().map(|job| job.id).collect::<Vec<_>>(), [later]);
+
+    let request = with_token(Request::get("/admin/jobs").body(Body::empty()).unwrap(), &test_token(1));
+    assert_eq!(send(&app, request).await.status(), StatusCode::FORBIDDEN);
+    let request = with_token(Request::get("/admin/jobs?state=stuck").body(Body::empty()).unwrap(), &admin_token(1));
+    assert_eq!(send(&app, request).await.status(), StatusCode::BAD_REQUEST);
+
+    state.shutdown.cancel();
+    worker.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_job_released_at_shutdown() {
+    let db = TestDb::new().await;
+    let state = setup(&db).await;
+    let app = app(state.clone());
+
+    state.jobs.register("nap", |_, _| async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok::<_, JobError>(())
+    });
+    let id = jobs::enqueue(&db.pool, "nap", json!({})).await.unwrap();
+
+    let worker = jobs::spawn_worker(&state).unwrap();
+    eventually(|| async { read_jobs(&app, "running").await.len() == 1 }).await;
+
+    // It doesn't finish within the grace, so it's handed back as it was.
+    state.shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), worker).await.unwrap().unwrap();
+
+    let pending = read_jobs(&app, "pending").await;
+    assert_eq!(pending.iter().map(|job| (job.id, job.attempts)).collect::<Vec<_>>(), [(id, 0)]);
+    assert!(pending[0].locked_by.is_none());
+}
@@ -0,0 +1,201 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use jsonwebtoken::jwk::JwkSet;
+use serde_json::json;
+use std::env;
+use tictoc::app;
+use tictoc::auth::API_KEY_PREFIX;
+use tictoc::auth::jwt::JwtKeys;
+use tictoc::config::load_secrets_file;
+use tictoc::error::ErrorResponse;
+use tictoc::models::{ApiKey, Claims, CreateApiKeyResponse, RotateKeysResponse};
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_api_keys() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad26@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+    let token = login_token(&app, "chad26@gmail.com", "password").await;
+
+    let request = with_token(json_request("POST", "/api-keys", json!({ "label": "cron" })), &token);
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: CreateApiKeyResponse = read_json(response).await;
+    assert!(created.key.starts_with(API_KEY_PREFIX));
+
+    let me = |key: &str| with_token(Request::get("/me").body(Body::empty()).unwrap(), key);
+
+    let response = app.clone().oneshot(me(&created.key)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read_json::<UserSummary>(response).await.email, "chad26@gmail.com");
+
+    let request = with_token(Request::get("/api-keys").body(Body::empty()).unwrap(), &created.key);
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let keys: Vec<ApiKey> = read_json(response).await;
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].label, "cron");
+    assert!(keys[0].last_used_at.is_some());
+
+    let revoke = || with_token(Request::builder()
+        .method("DELETE")
+        .uri(format!("/api-keys/{}", created.id))
+        .body(Body::empty())
+        .unwrap(), &token);
+
+    let response = app.clone().oneshot(revoke()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(revoke()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app.oneshot(me(&created.key)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "invalid_api_key".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_asymmetric_tokens_verify_with_jwks() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad28@gmail.com",
+        "password": "password"
+    }));
+
+    app(test_state(pool.clone())).oneshot(request).await.unwrap();
+
+    let key_sets = [
+        (
+            jsonwebtoken::Algorithm::RS256,
+            JwtKeys::from_rsa_pem(
+                include_bytes!("../testdata/jwt_rs256_private.pem"),
+                include_bytes!("../testdata/jwt_rs256_public.pem"),
+            ),
+        ),
+        (
+            jsonwebtoken::Algorithm::EdDSA,
+            JwtKeys::from_ed_pem(
+                include_bytes!("../testdata/jwt_eddsa_private.pem"),
+                include_bytes!("../testdata/jwt_eddsa_public.pem"),
+            ),
+        ),
+    ];
+
+    for (algorithm, keys) in key_sets {
+        let mut state = test_state(pool.clone());
+        state.jwt = keys.unwrap();
+        let app = app(state);
+
+        let token = login_token(&app, "chad28@gmail.com", "password").await;
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.alg, algorithm);
+
+        let request = Request::get("/.well-known/jwks.json").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let jwks: JwkSet = read_json(response).await;
+
+        // What another service would do with nothing but the JWKS URL.
+        let jwk = jwks.find(&header.kid.unwrap()).unwrap();
+        let claims = jsonwebtoken::decode::<Claims>(
+            &token,
+            &jsonwebtoken::DecodingKey::from_jwk(jwk).unwrap(),
+            &jsonwebtoken::Validation::new(algorithm),
+        ).unwrap().claims;
+        assert_eq!(claims.email, "chad28@gmail.com");
+
+        let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &token);
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // The default shared secret publishes nothing and sets no kid.
+    let app = app(test_state(pool));
+    let token = login_token(&app, "chad28@gmail.com", "password").await;
+    let header = jsonwebtoken::decode_header(&token).unwrap();
+    assert_eq!((header.alg, header.kid), (jsonwebtoken::Algorithm::HS256, None));
+
+    let request = Request::get("/.well-known/jwks.json").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert!(read_json::<JwkSet>(response).await.keys.is_empty());
+}
+
+#[tokio::test]
+async fn test_jwt_key_rotation() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+
+    let path = env::temp_dir().join(format!("jwt_secrets_{}", uuid::Uuid::new_v4().simple()));
+    let old_secret = std::str::from_utf8(TEST_SECRET).unwrap();
+    let new_secret = "a-brand-new-secret-that-is-32-bytes-long";
+    std::fs::write(&path, old_secret).unwrap();
+
+    let mut state = test_state(pool);
+    state.jwt = load_secrets_file(&path).unwrap();
+    state.jwt_secrets_file = Some(path.clone());
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad29@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+    let old_token = login_token(&app, "chad29@gmail.com", "password").await;
+    let old_kid = jsonwebtoken::decode_header(&old_token).unwrap().kid.unwrap();
+
+    let me = |token: &str| with_token(Request::get("/me").body(Body::empty()).unwrap(), token);
+
+    let rotate = || with_token(Request::builder()
+        .method("POST")
+        .uri("/admin/keys/rotate")
+        .body(Body::empty())
+        .unwrap(), &admin_token(1));
+
+    std::fs::write(&path, format!("{}\n{}\n", new_secret, old_secret)).unwrap();
+    let response = app.clone().oneshot(rotate()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let rotated: RotateKeysResponse = read_json(response).await;
+    let new_kid = rotated.signing_kid.unwrap();
+    assert_ne!(new_kid, old_kid);
+
+    let new_token = login_token(&app, "chad29@gmail.com", "password").await;
+    assert_eq!(jsonwebtoken::decode_header(&new_token).unwrap().kid, Some(new_kid));
+
+    let response = app.clone().oneshot(me(&old_token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.clone().oneshot(me(&new_token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Once the old secret is dropped, so are its tokens.
+    std::fs::write(&path, new_secret).unwrap();
+    let response = app.clone().oneshot(rotate()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(me(&old_token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.oneshot(me(&new_token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tictoc::app;
+use tictoc::config::Retention;
+use tictoc::maintenance;
+use tictoc::models::{MaintenanceReport, PurgedRows};
+
+use common::*;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Two of everything: one an hour past where it stops being useful, within
+// a day's retention, and one two days past it.
+const SEED: &str = "
+    INSERT INTO users (name, email, password_hash) VALUES ('Chad', 'chad209@gmail.com', '');
+    INSERT INTO users (name, email, password_hash, deleted_at) VALUES ('Gone', 'chad210@gmail.com', '', now() - interval '1 hour');
+    INSERT INTO users (name, email, password_hash, deleted_at) VALUES ('Gone', 'chad211@gmail.com', '', now() - interval '2 days');
+    INSERT INTO time_entries (user_id, started_at) VALUES (3, now() - interval '3 days');
+
+    INSERT INTO revoked_tokens (jti, expires_at) VALUES ('kept', now() + interval '1 hour'), ('purged', now() - interval '1 hour');
+    INSERT INTO refresh_tokens (token_hash, user_id, expires_at) VALUES
+        ('kept', 1, now() - interval '1 hour'), ('purged', 1, now() - interval '2 days');
+    INSERT INTO sessions (user_id, last_used_at) VALUES (1, now() - interval '1 hour'), (1, now() - interval '2 days');
+    INSERT INTO password_resets (token_hash, user_id, expires_at) VALUES
+        ('kept', 1, now() - interval '1 hour'), ('purged', 1, now() - interval '2 days');
+    INSERT INTO email_verifications (token_hash, user_id, expires_at) VALUES
+        ('kept', 1, now() - interval '1 hour'), ('purged', 1, now() - interval '2 days');
+    INSERT INTO organizations (name) VALUES ('Acme');
+    INSERT INTO organization_invitations (organization_id, email, role, token_hash, expires_at) VALUES
+        (1, 'kept@gmail.com', 'member', 'kept', now() - interval '1 hour'),
+        (1, 'purged@gmail.com', 'member', 'purged', now() - interval '2 days');
+    INSERT INTO idempotency_keys (scope, key, request_hash, expires_at) VALUES
+        ('test', 'kept', '', now() + interval '1 hour'), ('test', 'purged', '', now() - interval '1 hour');
+    INSERT INTO login_attempts (user_id, succeeded, attempted_at) VALUES
+        (1, true, now() - interval '1 hour'), (1, false, now() - interval '2 days');
+    INSERT INTO webhooks (user_id, url, secret, events) VALUES (1, 'https://example.com', '', '{}');
+    INSERT INTO webhook_deliveries (webhook_id, event_id, event, payload, attempt, created_at) VALUES
+        (1, 'kept', 'ping', '{}', 1, now() - interval '1 hour'),
+        (1, 'purged', 'ping', '{}', 1, now() - interval '2 days');
+    INSERT INTO account_exports (user_id, expires_at) VALUES (1, now() + interval '1 hour'), (1, now() - interval '1 hour');
+    INSERT INTO users (name, email, password_hash, deleted_at, erased_at) VALUES
+        ('Erased user', 'erased-4@erased.invalid', '', now() - interval '2 days', now() - interval '2 days');
+";
+
+const TABLES: [&str; 11] = [
+    "revoked_tokens",
+    "refresh_tokens",
+    "sessions",
+    "password_resets",
+    "email_verifications",
+    "organization_invitations",
+    "idempotency_keys",
+    "login_attempts",
+    "webhook_deliveries",
+    "account_exports",
+    "users",
+];
+
+async fn count(db: &TestDb, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT count(*) FROM {}", table)).fetch_one(&db.pool).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_maintenance() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.restore_grace_secs = DAY;
+    state.retention = Retention {
+        refresh_tokens_secs: DAY,
+        password_resets_secs: DAY,
+        email_verifications_secs: DAY,
+        invitations_secs: DAY,
+        login_attempts_secs: DAY,
+        webhook_deliveries_secs: DAY,
+    };
+    sqlx::raw_sql(SEED).execute(&db.pool).await.unwrap();
+
+    let report = maintenance::run(&state).await.unwrap();
+    let expected: Vec<PurgedRows> = TABLES.iter().map(|table| PurgedRows { table: table.to_string(), rows: 1 }).collect();
+    assert_eq!(report, expected);
+
+    for table in TABLES {
+        let kept = if table == "users" { 3 } else { 1 };
+        assert_eq!(count(&db, table).await, kept, "{}", table);
+    }
+    assert_eq!(count(&db, "time_entries").await, 0);
+    let emails: Vec<String> = sqlx::query_scalar("SELECT email FROM users ORDER BY id").fetch_all(&db.pool).await.unwrap();
+    assert_eq!(emails, ["chad209@gmail.com", "chad210@gmail.com", "erased-4@erased.invalid"]);
+
+    // Nothing left to purge; the metrics count what went.
+    let app = app(state.clone());
+    let request = with_token(Request::post("/admin/maintenance/run").body(Body::empty()).unwrap(), &admin_token(1));
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: MaintenanceReport = read_json(response).await;
+    assert!(report.purged.iter().all(|purged| purged.rows == 0), "{:?}", report);
+
+    let metrics = state.metrics.render(&db.pool);
+    assert!(metrics.contains("maintenance_purged_rows_total{table=\"users\"} 1"), "{}", metrics);
+
+    let request = with_token(Request::post("/admin/maintenance/run").body(Body::empty()).unwrap(), &test_token(1));
+    assert_eq!(send(&app, request).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_maintenance_in_batches() {
+    let db = TestDb::new().await;
+    let state = test_state(db.pool.clone());
+
+    let expired = maintenance::BATCH_SIZE * 2 + 1;
+    sqlx::query("INSERT INTO revoked_tokens (jti, expires_at) SELECT i::text, now() - interval '1 hour' FROM generate_series(1, $1) i")
+        .bind(expired)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let report = maintenance::run(&state).await.unwrap();
+    assert_eq!(report[0], PurgedRows { table: "revoked_tokens".to_string(), rows: expired as u64 });
+    assert_eq!(count(&db, "revoked_tokens").await, 0);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use serde_json::json;
+use tictoc::app;
+use tictoc::models::{CreateUserRequest, CreateUserResponse, Page, UserResponse};
+
+use common::*;
+
+const MSGPACK: &str = "application/msgpack";
+
+async fn body_bytes(response: Response) -> Vec<u8> {
+    to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+}
+
+fn list_users(accept: &str) -> Request<Body> {
+    let request = Request::get("/users?limit=100").header(header::ACCEPT, accept).body(Body::empty()).unwrap();
+    with_token(request, &admin_token(1))
+}
+
+#[tokio::test]
+async fn test_create_user_in_msgpack() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let payload = CreateUserRequest {
+        name: "Chad".to_string(),
+        email: "chad229@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let request = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, MSGPACK)
+        .header(header::ACCEPT, MSGPACK)
+        .header("idempotency-key", "msgpack-signup")
+        .body(Body::from(rmp_serde::to_vec_named(&payload).unwrap()))
+        .unwrap();
+    let replay = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, MSGPACK)
+        .header(header::ACCEPT, MSGPACK)
+        .header("idempotency-key", "msgpack-signup")
+        .body(Body::from(rmp_serde::to_vec_named(&payload).unwrap()))
+        .unwrap();
+
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], MSGPACK);
+    assert_eq!(response.headers()[header::VARY], "accept");
+    let created: CreateUserResponse = rmp_serde::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!((created.name.as_str(), created.email.as_str()), ("Chad", "chad229@gmail.com"));
+
+    // A replay comes back in the same format.
+    let response = send(&app, replay).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["idempotent-replay"], "true");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], MSGPACK);
+    let replayed: CreateUserResponse = rmp_serde::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(replayed, created);
+
+    // Errors stay JSON.
+    let request = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, MSGPACK)
+        .header(header::ACCEPT, MSGPACK)
+        .body(Body::from(rmp_serde::to_vec_named(&json!({ "name": "Chad", "email": 7 })).unwrap()))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_json(&response);
+    let body: serde_json::Value = read_json(response).await;
+    assert_eq!((body["error"].as_str(), body["field"].as_str()), (Some("invalid_field"), Some("email")));
+
+    let request = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, MSGPACK)
+        .body(Body::from(vec![0xc1]))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = read_json(response).await;
+    assert_eq!(body["error"], "malformed_msgpack");
+}
+
+#[tokio::test]
+async fn test_msgpack_list_is_smaller_than_json() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash)
+        SELECT 'User ' || n, 'user' || n || '@example.com', 'x' FROM generate_series(1, 100) AS n",
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let response = send(&app, list_users(MSGPACK)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], MSGPACK);
+    let msgpack = body_bytes(response).await;
+
+    let response = send(&app, list_users("application/json")).await;
+    assert_json(&response);
+    let json = body_bytes(response).await;
+
+    let from_msgpack: Page<UserResponse> = rmp_serde::from_slice(&msgpack).unwrap();
+    let from_json: Page<UserResponse> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(from_msgpack.items.len(), 100);
+    assert_eq!(
+        from_msgpack.items.iter().map(|user| &user.email).collect::<Vec<_>>(),
+        from_json.items.iter().map(|user| &user.email).collect::<Vec<_>>()
+    );
+    assert!(msgpack.len() < json.len(), "{} bytes of MessagePack against {} of JSON", msgpack.len(), json.len());
+}
+
+#[tokio::test]
+async fn test_unknown_accept_falls_back_to_json() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    for accept in ["text/html", "*/*", "application/msgpack;q=0, application/json;q=0.5", "application/xml"] {
+        let response = send(&app, list_users(accept)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", accept);
+        assert_json(&response);
+    }
+
+    // The preferred of the two wins.
+    let response = send(&app, list_users("application/json;q=0.4, application/x-msgpack;q=0.9")).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], MSGPACK);
+}
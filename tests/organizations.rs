@@ -0,0 +1,358 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tictoc::app;
+use tictoc::error::ErrorResponse;
+use tictoc::models::{
+    Invitation, Member, MemberRole, Organization, Page, Project, ReportBucket, TeamReport, TimeEntry,
+};
+use tictoc::validation::ValidationErrors;
+use tower::ServiceExt;
+
+use common::*;
+
+async fn create_users(app: &axum::Router, emails: &[&str]) {
+    for email in emails {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+}
+
+fn get(uri: &str, token: &str) -> Request<Body> {
+    with_token(Request::get(uri).body(Body::empty()).unwrap(), token)
+}
+
+async fn error(response: axum::response::Response) -> (StatusCode, String) {
+    (response.status(), read_json::<ErrorResponse>(response).await.error)
+}
+
+#[tokio::test]
+async fn test_invitations() {
+    let db = TestDb::new().await;
+    let mailer = Arc::new(TestMailer::default());
+    let app = app(test_state_with_mailer(db.pool.clone(), mailer.clone()));
+    create_users(&app, &["chad182@gmail.com", "chad183@gmail.com", "chad184@gmail.com"]).await;
+    let (owner, bob, carol) = (test_token(1), test_token(2), test_token(3));
+
+    let request = with_token(json_request("POST", "/organizations", json!({ "name": " Acme " })), &owner);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let acme: Organization = read_json(response).await;
+    assert_eq!((acme.name.as_str(), acme.role), ("Acme", MemberRole::Owner));
+
+    let invite = |body: serde_json::Value, token: &str| {
+        with_token(json_request("POST", &format!("/organizations/{}/invitations", acme.id), body), token)
+    };
+    let accept = |token: &str, as_user: &str| {
+        with_token(json_request("POST", "/invitations/accept", json!({ "token": token })), as_user)
+    };
+
+    let response = app.clone().oneshot(invite(json!({ "email": "Chad183@gmail.com" }), &owner)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let invitation: Invitation = read_json(response).await;
+    assert_eq!((invitation.email.as_str(), invitation.role), ("chad183@gmail.com", MemberRole::Member));
+
+    // Only the person invited can take it up, and only once.
+    let token = mailer.last_token("chad183@gmail.com").unwrap();
+    let response = app.clone().oneshot(accept(&token, &carol)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::FORBIDDEN, "invitation_for_another_email".to_string()));
+
+    let response = app.clone().oneshot(accept(&token, &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read_json::<Organization>(response).await.role, MemberRole::Member);
+
+    let response = app.clone().oneshot(accept(&token, &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::BAD_REQUEST, "invitation_token_used".to_string()));
+
+    let response = app.clone().oneshot(invite(json!({ "email": "chad183@gmail.com" }), &owner)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "already_a_member".to_string()));
+
+    // Members can't invite anyone.
+    let response = app.clone().oneshot(invite(json!({ "email": "chad184@gmail.com" }), &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::FORBIDDEN, "insufficient_role".to_string()));
+
+    let response = app.clone().oneshot(get(&format!("/organizations/{}/members", acme.id), &bob)).await.unwrap();
+    let members: Vec<Member> = read_json(response).await;
+    assert_eq!(
+        members.iter().map(|member| (member.user_id, member.role)).collect::<Vec<_>>(),
+        [(1, MemberRole::Owner), (2, MemberRole::Member)]
+    );
+
+    let response = app.clone().oneshot(get(&format!("/organizations/{}", acme.id), &carol)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::NOT_FOUND, "organization_not_found".to_string()));
+    let response = app.clone().oneshot(get("/organizations", &carol)).await.unwrap();
+    assert!(read_json::<Vec<Organization>>(response).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_organization_projects() {
+    let db = TestDb::new().await;
+    let mailer = Arc::new(TestMailer::default());
+    let app = app(test_state_with_mailer(db.pool.clone(), mailer.clone()));
+    create_users(&app, &["chad185@gmail.com", "chad186@gmail.com", "chad187@gmail.com"]).await;
+    let (owner, bob, carol) = (test_token(1), test_token(2), test_token(3));
+
+    let request = with_token(json_request("POST", "/organizations", json!({ "name": "Acme" })), &owner);
+    let acme: Organization = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    let request = with_token(
+        json_request("POST", &format!("/organizations/{}/invitations", acme.id), json!({ "email": "chad186@gmail.com" })),
+        &owner,
+    );
+    app.clone().oneshot(request).await.unwrap();
+    let token = mailer.last_token("chad186@gmail.com").unwrap();
+    let request = with_token(json_request("POST", "/invitations/accept", json!({ "token": token })), &bob);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+    let project = |body: serde_json::Value, token: &str| with_token(json_request("POST", "/projects", body), token);
+
+    let response = app.clone()
+        .oneshot(project(json!({ "name": "Shared", "organization_id": acme.id }), &owner))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let shared: Project = read_json(response).await;
+    assert_eq!(shared.organization_id, Some(acme.id));
+
+    // Members see the organization's projects next to their own.
+    app.clone().oneshot(project(json!({ "name": "Mine" }), &bob)).await.unwrap();
+    let response = app.clone().oneshot(get(&format!("/projects/{}", shared.id), &bob)).await.unwrap();
+    assert_eq!(read_json::<Project>(response).await, shared);
+    let page: Page<Project> = read_json(app.clone().oneshot(get("/projects", &bob)).await.unwrap()).await;
+    assert_eq!(page.items.iter().map(|project| project.name.as_str()).collect::<Vec<_>>(), ["Mine", "Shared"]);
+
+    let response = app.clone().oneshot(get(&format!("/projects/{}", shared.id), &carol)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::NOT_FOUND, "project_not_found".to_string()));
+
+    // Entries on it stay each member's own.
+    let entry = |token: &str| {
+        with_token(json_request("POST", "/entries", json!({
+            "project_id": shared.id,
+            "started_at": "2025-04-01T09:00:00Z",
+            "ended_at": "2025-04-01T10:00:00Z"
+        })), token)
+    };
+    let response = app.clone().oneshot(entry(&bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(read_json::<TimeEntry>(response).await.user_id, 2);
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(get("/entries", &owner)).await.unwrap()).await;
+    assert!(page.items.is_empty());
+
+    let response = app.clone().oneshot(entry(&carol)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["project_id"], ["not found"]);
+
+    // Only admins change the organization's projects or add to them.
+    let request = with_token(json_request("PUT", &format!("/projects/{}", shared.id), json!({
+        "name": "Renamed",
+        "organization_id": acme.id
+    })), &bob);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::FORBIDDEN, "insufficient_role".to_string()));
+
+    let response = app.clone()
+        .oneshot(project(json!({ "name": "Other", "organization_id": acme.id }), &bob))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.clone()
+        .oneshot(project(json!({ "name": "Other", "organization_id": acme.id }), &carol))
+        .await
+        .unwrap();
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["organization_id"], ["not found"]);
+
+    // Leaving keeps the entries but not the view of the project.
+    let request = with_token(
+        Request::delete(format!("/organizations/{}/members/2", acme.id)).body(Body::empty()).unwrap(),
+        &bob,
+    );
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(get("/entries", &bob)).await.unwrap()).await;
+    assert_eq!(page.items.len(), 1);
+    let response = app.clone().oneshot(get(&format!("/projects/{}", shared.id), &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_last_owner() {
+    let db = TestDb::new().await;
+    let mailer = Arc::new(TestMailer::default());
+    let app = app(test_state_with_mailer(db.pool.clone(), mailer.clone()));
+    create_users(&app, &["chad188@gmail.com", "chad189@gmail.com", "chad190@gmail.com"]).await;
+    let (owner, bob, carol) = (test_token(1), test_token(2), test_token(3));
+
+    let request = with_token(json_request("POST", "/organizations", json!({ "name": "Acme" })), &owner);
+    let acme: Organization = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    for (email, role, token) in [("chad189@gmail.com", "admin", &bob), ("chad190@gmail.com", "member", &carol)] {
+        let request = with_token(
+            json_request("POST", &format!("/organizations/{}/invitations", acme.id), json!({ "email": email, "role": role })),
+            &owner,
+        );
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+        let invitation = mailer.last_token(email).unwrap();
+        let request = with_token(json_request("POST", "/invitations/accept", json!({ "token": invitation })), token);
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let member = |user_id: i32| format!("/organizations/{}/members/{}", acme.id, user_id);
+    let set_role = |user_id: i32, role: &str, token: &str| {
+        with_token(json_request("PUT", &member(user_id), json!({ "role": role })), token)
+    };
+    let remove = |user_id: i32, token: &str| {
+        with_token(Request::delete(member(user_id)).body(Body::empty()).unwrap(), token)
+    };
+
+    let response = app.clone().oneshot(remove(1, &owner)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "last_owner".to_string()));
+    let response = app.clone().oneshot(set_role(1, "admin", &owner)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "last_owner".to_string()));
+
+    // Admins manage members, but not owners.
+    let response = app.clone().oneshot(set_role(3, "admin", &bob)).await.unwrap();
+    assert_eq!(read_json::<Member>(response).await.role, MemberRole::Admin);
+    let response = app.clone().oneshot(set_role(3, "owner", &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app.clone().oneshot(remove(1, &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // With another owner, the first can go.
+    let response = app.clone().oneshot(set_role(2, "owner", &owner)).await.unwrap();
+    assert_eq!(read_json::<Member>(response).await.role, MemberRole::Owner);
+    let response = app.clone().oneshot(remove(1, &owner)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(remove(2, &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "last_owner".to_string()));
+    let response = app.clone().oneshot(remove(3, &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(get(&format!("/organizations/{}/members", acme.id), &bob)).await.unwrap();
+    let members: Vec<Member> = read_json(response).await;
+    assert_eq!(members.iter().map(|member| member.user_id).collect::<Vec<_>>(), [2]);
+}
+
+#[tokio::test]
+async fn test_team_report() {
+    let db = TestDb::new().await;
+    let mailer = Arc::new(TestMailer::default());
+    let mut state = test_state_with_mailer(db.pool.clone(), mailer.clone());
+    let clock = Arc::new(TestClock { now: Mutex::new("2025-04-20T11:00:00Z".parse().unwrap()) });
+    state.clock = clock.clone();
+    let app = app(state);
+
+    for (name, email) in [("Ann", "chad191@gmail.com"), ("Bob", "chad192@gmail.com"), ("Cat", "chad193@gmail.com"),
+        ("Dan", "chad194@gmail.com")]
+    {
+        let request = json_request("POST", "/users/create", json!({ "name": name, "email": email, "password": "password" }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let (ann, bob, cat, dan) = (test_token(1), test_token(2), test_token(3), test_token(4));
+
+    let request = with_token(
+        json_request("POST", "/organizations", json!({ "name": "Acme", "timezone": "Europe/Berlin" })),
+        &ann,
+    );
+    let acme: Organization = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    for (email, role, token) in [("chad192@gmail.com", "member", &bob), ("chad193@gmail.com", "admin", &cat)] {
+        let request = with_token(
+            json_request("POST", &format!("/organizations/{}/invitations", acme.id), json!({ "email": email, "role": role })),
+            &ann,
+        );
+        app.clone().oneshot(request).await.unwrap();
+        let invitation = mailer.last_token(email).unwrap();
+        let request = with_token(json_request("POST", "/invitations/accept", json!({ "token": invitation })), token);
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    }
+
+    let mut projects = Vec::new();
+    for (name, organization_id, token) in [("Alpha", Some(acme.id), &ann), ("Beta", Some(acme.id), &ann), ("Private", None, &bob)] {
+        let request = with_token(
+            json_request("POST", "/projects", json!({ "name": name, "organization_id": organization_id })),
+            token,
+        );
+        projects.push(read_json::<Project>(app.clone().oneshot(request).await.unwrap()).await.id);
+    }
+    let (alpha, beta, private) = (projects[0], projects[1], projects[2]);
+
+    for (project_id, started_at, ended_at, token) in [
+        (alpha, "2025-04-14T08:00:00Z", "2025-04-14T10:00:00Z", &ann),
+        // After midnight in Berlin, so on the 15th there.
+        (alpha, "2025-04-14T22:30:00Z", "2025-04-14T23:30:00Z", &bob),
+        (beta, "2025-04-16T09:00:00Z", "2025-04-16T10:00:00Z", &bob),
+        (beta, "2025-04-16T10:00:00Z", "2025-04-16T10:30:00Z", &cat),
+        // Never in the organization's report.
+        (private, "2025-04-16T12:00:00Z", "2025-04-16T15:00:00Z", &bob),
+        (alpha, "2025-04-21T08:00:00Z", "2025-04-21T09:00:00Z", &ann),
+    ] {
+        let request = with_token(json_request("POST", "/entries", json!({
+            "project_id": project_id,
+            "started_at": started_at,
+            "ended_at": ended_at
+        })), token);
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+    }
+
+    let report = |range: &str, group_by: &str, token: &str| {
+        get(&format!("/organizations/{}/reports?{}&group_by={}", acme.id, range, group_by), token)
+    };
+    let week = "from=2025-04-14&to=2025-04-20";
+    let buckets = |report: &TeamReport| {
+        report.buckets.iter()
+            .map(|bucket| (bucket.label.clone().unwrap(), bucket.tracked_secs, bucket.entries))
+            .collect::<Vec<_>>()
+    };
+
+    let by_member: TeamReport = read_json(app.clone().oneshot(report(week, "member", &cat)).await.unwrap()).await;
+    assert_eq!((by_member.timezone.as_str(), by_member.tracked_secs, by_member.entries), ("Europe/Berlin", 16200, 4));
+    assert_eq!(buckets(&by_member), [
+        ("Ann".to_string(), 7200, 1),
+        ("Bob".to_string(), 7200, 2),
+        ("Cat".to_string(), 1800, 1),
+    ]);
+    assert_eq!(by_member.buckets[1].key.as_deref(), Some("2"));
+
+    let by_project: TeamReport = read_json(app.clone().oneshot(report(week, "project", &ann)).await.unwrap()).await;
+    assert_eq!(by_project.buckets, [
+        ReportBucket { key: Some(alpha.to_string()), label: Some("Alpha".to_string()), tracked_secs: 10800, entries: 2 },
+        ReportBucket { key: Some(beta.to_string()), label: Some("Beta".to_string()), tracked_secs: 5400, entries: 2 },
+    ]);
+
+    let by_day: TeamReport = read_json(app.clone().oneshot(report(week, "day", &ann)).await.unwrap()).await;
+    assert_eq!(buckets(&by_day), [
+        ("2025-04-14".to_string(), 7200, 1),
+        ("2025-04-15".to_string(), 3600, 1),
+        ("2025-04-16".to_string(), 5400, 2),
+    ]);
+
+    let response = app.clone().oneshot(report(week, "member", &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::FORBIDDEN, "insufficient_role".to_string()));
+    let response = app.clone().oneshot(report(week, "member", &dan)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Bob leaves with a timer running; it counts until he's gone.
+    let request = with_token(json_request("POST", "/timer/start", json!({ "project_id": alpha })), &bob);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+    clock.advance(3600);
+    let request = with_token(
+        Request::delete(format!("/organizations/{}/members/2", acme.id)).body(Body::empty()).unwrap(),
+        &ann,
+    );
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+    clock.advance(2 * 86400);
+
+    let by_member: TeamReport = read_json(app.clone().oneshot(report(week, "member", &ann)).await.unwrap()).await;
+    assert_eq!(buckets(&by_member)[1], ("Bob".to_string(), 10800, 3));
+
+    let next_week = "from=2025-04-21&to=2025-04-27";
+    let by_member: TeamReport = read_json(app.clone().oneshot(report(next_week, "member", &ann)).await.unwrap()).await;
+    assert_eq!(buckets(&by_member), [("Ann".to_string(), 3600, 1)]);
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use std::time::Duration;
+use tictoc::models::{AccountExport, AccountExportStatus};
+use tictoc::{app, jobs, maintenance, privacy};
+
+use common::*;
+
+// User 1 has one of everything; user 2 owns the organization user 1 is in.
+const SEED: &str = "
+    INSERT INTO projects (user_id, name) VALUES (1, 'Website'), (1, 'Unused');
+    INSERT INTO tags (user_id, name) VALUES (1, 'client');
+    INSERT INTO invoices (user_id, number, project_id, project_name, period_from, period_to, group_by, currency,
+        tax_rate_bps, subtotal_cents, tax_cents, total_cents)
+        VALUES (1, 1, 1, 'Website', '2025-04-01', '2025-04-30', 'entry', 'USD', 0, 12000, 0, 12000);
+    INSERT INTO invoice_lines (invoice_id, position, date, description, seconds, rate_cents, amount_cents)
+        VALUES (1, 1, '2025-04-01', 'Design', 3600, 12000, 12000);
+    INSERT INTO timesheets (user_id, week_start, state) VALUES (1, '2025-05-05', 'approved'), (1, '2025-05-12', 'draft');
+    INSERT INTO time_entries (user_id, project_id, description, started_at, ended_at, invoice_id) VALUES
+        (1, 1, 'Design', '2025-04-01T09:00:00Z', '2025-04-01T10:00:00Z', 1);
+    INSERT INTO time_entries (user_id, description, started_at, ended_at, timesheet_id) VALUES
+        (1, 'Approved', '2025-05-05T09:00:00Z', '2025-05-05T10:00:00Z', 1),
+        (1, 'Draft', '2025-05-12T09:00:00Z', '2025-05-12T10:00:00Z', 2);
+    INSERT INTO time_entries (user_id, project_id, description, started_at, ended_at) VALUES
+        (1, 1, 'Dentist', '2025-05-20T09:00:00Z', '2025-05-20T10:00:00Z');
+    INSERT INTO time_entry_tags (entry_id, tag_id) VALUES (1, 1), (4, 1);
+    INSERT INTO organizations (name) VALUES ('Acme');
+    INSERT INTO organization_members (organization_id, user_id, role) VALUES (1, 2, 'owner'), (1, 1, 'member');
+    INSERT INTO organization_invitations (organization_id, email, role, token_hash, expires_at, invited_by) VALUES
+        (1, 'chad212@gmail.com', 'member', 'for', now() + interval '1 day', 2),
+        (1, 'friend@gmail.com', 'member', 'by', now() + interval '1 day', 1);
+    INSERT INTO api_keys (key_hash, user_id, label) VALUES ('hash', 1, 'laptop');
+    INSERT INTO webhooks (user_id, url, secret, events) VALUES (1, 'https://example.com', 'shh', '{}');
+    INSERT INTO webhook_deliveries (webhook_id, event_id, event, payload, attempt) VALUES (1, 'ping', 'ping', '{}', 1);
+    INSERT INTO avatars (user_id, content_type, data) VALUES (1, 'image/png', '\\x00');
+    INSERT INTO recovery_codes (user_id, code_hash) VALUES (1, 'hash');
+    INSERT INTO password_resets (token_hash, user_id, expires_at) VALUES ('hash', 1, now() + interval '1 hour');
+    INSERT INTO email_verifications (token_hash, user_id, expires_at) VALUES ('hash', 1, now() + interval '1 hour');
+";
+
+async fn create_user(app: &axum::Router, email: &str) {
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": "password" }));
+    assert_eq!(send(app, request).await.status(), StatusCode::CREATED);
+}
+
+async fn count(db: &TestDb, sql: &str) -> i64 {
+    sqlx::query_scalar(sql).fetch_one(&db.pool).await.unwrap()
+}
+
+async fn erase(app: &axum::Router, token: &str, password: &str) -> StatusCode {
+    let request = with_token(json_request("POST", "/me/erase", json!({ "password": password })), token);
+    send(app, request).await.status()
+}
+
+#[tokio::test]
+async fn test_every_user_table_is_exported_or_erased() {
+    let db = TestDb::new().await;
+
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT tc.table_name::text FROM information_schema.table_constraints tc
+        JOIN information_schema.constraint_column_usage ccu ON ccu.constraint_name = tc.constraint_name
+        WHERE tc.constraint_type = 'FOREIGN KEY' AND ccu.table_name = 'users'",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .unwrap();
+    assert!(tables.len() > 10, "{:?}", tables);
+
+    for table in tables {
+        assert!(
+            privacy::exported_tables().any(|exported| exported == table) || privacy::erased_tables().any(|erased| erased == table),
+            "{} is neither exported nor erased",
+            table
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_account_export_and_erasure() {
+    let db = TestDb::new().await;
+    let state = test_state(db.pool.clone());
+    let app = app(state.clone());
+
+    create_user(&app, "chad212@gmail.com").await;
+    create_user(&app, "chad213@gmail.com").await;
+    sqlx::raw_sql(SEED).execute(&db.pool).await.unwrap();
+    let token = login_token(&app, "chad212@gmail.com", "password").await;
+
+    let response = send(&app, with_token(Request::get("/me/export").body(Body::empty()).unwrap(), &token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let export: AccountExport = read_json(response).await;
+    assert_eq!(export.profile["email"], "chad212@gmail.com");
+    assert_eq!(export.settings["timezone"], "UTC");
+    for (table, rows) in [("time_entries", 4), ("tags", 1), ("projects", 2), ("invoices", 1), ("webhooks", 1), ("login_attempts", 1), ("sessions", 1)] {
+        assert_eq!(export.tables[table].as_array().unwrap().len(), rows, "{}", table);
+    }
+    assert!(export.tables["audit_events"].as_array().unwrap().iter().any(|event| event["event_type"] == "user_created"));
+    // Secrets are left out.
+    let exported = serde_json::to_string(&export).unwrap();
+    for secret in ["password_hash", "key_hash", "token_hash", "shh"] {
+        assert!(!exported.contains(secret), "{}", secret);
+    }
+
+    // Password first; the last owner of an organization can't go.
+    assert_eq!(erase(&app, &token, "wrong").await, StatusCode::FORBIDDEN);
+    sqlx::query("UPDATE organization_members SET role = 'owner' WHERE user_id = 1").execute(&db.pool).await.unwrap();
+    sqlx::query("DELETE FROM organization_members WHERE user_id = 2").execute(&db.pool).await.unwrap();
+    assert_eq!(erase(&app, &token, "password").await, StatusCode::CONFLICT);
+    sqlx::query("INSERT INTO organization_members (organization_id, user_id, role) VALUES (1, 2, 'owner')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(erase(&app, &token, "password").await, StatusCode::NO_CONTENT);
+
+    // Gone for good: no session, no login, no restore.
+    let request = json_request("POST", "/users/login", json!({ "email": "chad212@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::UNAUTHORIZED);
+    let response = send(&app, with_token(Request::get("/me").body(Body::empty()).unwrap(), &token)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let request = Request::post("/users/1/restore").body(Body::empty()).unwrap();
+    assert_eq!(send(&app, with_token(request, &admin_token(2))).await.status(), StatusCode::NOT_FOUND);
+
+    let user: (String, String, String, bool) =
+        sqlx::query_as("SELECT name, email, password_hash, erased_at IS NOT NULL FROM users WHERE id = 1")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+    assert_eq!(user, ("Erased user".to_string(), "erased-1@erased.invalid".to_string(), String::new(), true));
+
+    // What was invoiced or approved stays, without describing anything.
+    let kept: Vec<(String, Option<i32>)> = sqlx::query_as("SELECT description, project_id FROM time_entries ORDER BY id")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(kept, [(String::new(), Some(1)), (String::new(), None)]);
+    assert_eq!(count(&db, "SELECT count(*) FROM invoices").await, 1);
+    assert_eq!(count(&db, "SELECT count(*) FROM invoice_lines").await, 1);
+    assert_eq!(count(&db, "SELECT count(*) FROM timesheets").await, 1);
+    let projects: Vec<String> = sqlx::query_scalar("SELECT name FROM projects").fetch_all(&db.pool).await.unwrap();
+    assert_eq!(projects, ["Erased project"]);
+
+    for table in ["tags", "api_keys", "webhooks", "avatars", "recovery_codes", "password_resets", "email_verifications", "refresh_tokens", "sessions", "login_attempts", "organization_members"] {
+        let rows = count(&db, &format!("SELECT count(*) FROM {} WHERE user_id = 1", table)).await;
+        assert_eq!(rows, 0, "{}", table);
+    }
+    assert_eq!(count(&db, "SELECT count(*) FROM time_entry_tags").await, 0);
+    let invitations: Vec<String> = sqlx::query_scalar("SELECT email FROM organization_invitations").fetch_all(&db.pool).await.unwrap();
+    assert_eq!(invitations, ["friend@gmail.com"]);
+    assert_eq!(count(&db, "SELECT count(*) FROM audit_events WHERE event_type = 'account_erased' AND actor_user_id = 1").await, 1);
+
+    // Never purged, since invoices hang off it.
+    let mut state = state.clone();
+    state.restore_grace_secs = 0;
+    maintenance::run(&state).await.unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM users WHERE id = 1").await, 1);
+}
+
+#[tokio::test]
+async fn test_large_export_in_background() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.jobs.poll_interval = Duration::from_millis(10);
+    let app = app(state.clone());
+
+    create_user(&app, "chad214@gmail.com").await;
+    let entries = privacy::INLINE_EXPORT_MAX_ENTRIES + 1;
+    sqlx::query("INSERT INTO time_entries (user_id, started_at, ended_at)
+        SELECT 1, now() - make_interval(mins => i), now() - make_interval(mins => i - 1) FROM generate_series(1, $1) i")
+        .bind(entries as i32)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let response = send(&app, with_token(Request::get("/me/export").body(Body::empty()).unwrap(), &test_token(1))).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let location = response.headers()[header::LOCATION].to_str().unwrap().to_string();
+    let status: AccountExportStatus = read_json(response).await;
+    assert_eq!(status.download_url, location);
+
+    // Asking again doesn't start another.
+    let response = send(&app, with_token(Request::get("/me/export").body(Body::empty()).unwrap(), &test_token(1))).await;
+    assert_eq!(read_json::<AccountExportStatus>(response).await.id, status.id);
+    let response = send(&app, with_token(Request::get(&location).body(Body::empty()).unwrap(), &test_token(1))).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let worker = jobs::spawn_worker(&state).unwrap();
+    let mut export = None;
+    for _ in 0..500 {
+        let response = send(&app, with_token(Request::get(&location).body(Body::empty()).unwrap(), &test_token(1))).await;
+        if response.status() == StatusCode::OK {
+            export = Some(read_json::<AccountExport>(response).await);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let export = export.expect("the export never finished");
+    assert_eq!(export.tables["time_entries"].as_array().unwrap().len() as i64, entries);
+
+    // Only its owner gets it.
+    let response = send(&app, with_token(Request::get(&location).body(Body::empty()).unwrap(), &test_token(2))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    state.shutdown.cancel();
+    worker.await.unwrap();
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use tictoc::app;
+use tictoc::error::ErrorResponse;
+use tictoc::models::{Page, Project, TimeEntry};
+use tictoc::validation::ValidationErrors;
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_projects() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    for email in ["chad80@gmail.com", "chad81@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let (alice, bob) = (test_token(1), test_token(2));
+
+    let create = |token: &str, body: serde_json::Value| with_token(json_request("POST", "/projects", body), token);
+    let get = |uri: &str, token: &str| with_token(Request::get(uri).body(Body::empty()).unwrap(), token);
+
+    let response = app.clone().oneshot(create(&alice, json!({ "name": "Website", "color": "#FF8800" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[header::LOCATION], "/projects/1");
+    let website: Project = read_json(response).await;
+    assert_eq!((website.user_id, website.color.as_str(), website.archived), (1, "#ff8800", false));
+
+    let response = app.clone().oneshot(create(&alice, json!({ "name": "Admin" }))).await.unwrap();
+    let admin: Project = read_json(response).await;
+    assert_eq!(admin.color, "#808080");
+
+    let response = app.clone().oneshot(create(&alice, json!({ "name": " ", "color": "orange" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert!(errors.errors.contains_key("name"));
+    assert!(errors.errors.contains_key("color"));
+
+    let response = app.clone().oneshot(create(&bob, json!({ "name": "Secret" }))).await.unwrap();
+    let secret: Project = read_json(response).await;
+
+    let entry = |token: &str, project_id: i32, day: u32| {
+        with_token(json_request("POST", "/entries", json!({
+            "project_id": project_id,
+            "started_at": format!("2025-04-{:02}T09:00:00Z", day),
+            "ended_at": format!("2025-04-{:02}T10:00:00Z", day)
+        })), token)
+    };
+    for day in [1, 2] {
+        let response = app.clone().oneshot(entry(&alice, website.id, day)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    let response = app.clone().oneshot(entry(&alice, admin.id, 3)).await.unwrap();
+    let other: TimeEntry = read_json(response).await;
+    assert_eq!(other.project_id, Some(admin.id));
+
+    // Someone else's project can't be used, and looks like one that doesn't exist.
+    for project_id in [secret.id, 999] {
+        let response = app.clone().oneshot(entry(&alice, project_id, 4)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let errors: ValidationErrors = read_json(response).await;
+        assert_eq!(errors.errors["project_id"], ["not found"]);
+    }
+    let response = app.clone().oneshot(get(&format!("/projects/{}", secret.id), &alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let page: Page<TimeEntry> =
+        read_json(app.clone().oneshot(get(&format!("/entries?project_id={}", website.id), &alice)).await.unwrap()).await;
+    assert_eq!(page.total, 2);
+    assert!(page.items.iter().all(|entry| entry.project_id == Some(website.id)));
+
+    let page: Page<Project> = read_json(app.clone().oneshot(get("/projects", &alice)).await.unwrap()).await;
+    assert_eq!(page.items.iter().map(|project| project.id).collect::<Vec<_>>(), [admin.id, website.id]);
+
+    // Archived projects drop out of the listing but stay readable.
+    let response = app.clone()
+        .oneshot(with_token(
+            json_request("PUT", &format!("/projects/{}", admin.id), json!({ "name": "Admin", "archived": true })),
+            &alice,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let archived: Project = read_json(response).await;
+    assert!(archived.archived);
+
+    let page: Page<Project> = read_json(app.clone().oneshot(get("/projects", &alice)).await.unwrap()).await;
+    assert_eq!((page.total, page.items[0].id), (1, website.id));
+
+    let page: Page<Project> =
+        read_json(app.clone().oneshot(get("/projects?include_archived=true", &alice)).await.unwrap()).await;
+    assert_eq!(page.total, 2);
+
+    let response = app.clone().oneshot(get(&format!("/projects/{}", admin.id), &alice)).await.unwrap();
+    assert_eq!(read_json::<Project>(response).await, archived);
+
+    let response = app.clone().oneshot(get(&format!("/entries/{}", other.id), &alice)).await.unwrap();
+    assert_eq!(read_json::<TimeEntry>(response).await.project_id, Some(admin.id));
+
+    let delete = |id: i32, token: &str| {
+        with_token(Request::delete(format!("/projects/{}", id)).body(Body::empty()).unwrap(), token)
+    };
+
+    // Projects with entries have to be archived instead.
+    let response = app.clone().oneshot(delete(admin.id, &alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: ErrorResponse = read_json(response).await;
+    assert_eq!(body.error, "project_has_entries");
+
+    let response = app.clone().oneshot(delete(secret.id, &alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app.clone().oneshot(delete(secret.id, &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+use tictoc::rate_limit::{MemoryRateLimiter, RateLimiter};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// What every backend has to do, on keys no other run has used.
+async fn check_limits(limiter: &dyn RateLimiter, other: &dyn RateLimiter) {
+    let run = uuid::Uuid::new_v4();
+    let key = format!("test:{}", run);
+    let other_key = format!("test:{}:other", run);
+
+    assert_eq!(limiter.hit(&key).await, Ok(()));
+    assert_eq!(other.hit(&key).await, Ok(()));
+    let retry_after = limiter.hit(&key).await.unwrap_err();
+    assert!(retry_after > Duration::ZERO && retry_after <= WINDOW, "{:?}", retry_after);
+    assert!(other.hit(&key).await.is_err());
+
+    assert_eq!(limiter.hit(&other_key).await, Ok(()));
+
+    limiter.reset(&key).await;
+    assert_eq!(other.hit(&key).await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_memory_rate_limiter() {
+    let limiter = MemoryRateLimiter::new(2, WINDOW);
+    check_limits(&limiter, &limiter).await;
+}
+
+/// Two limiters over one Redis, as two instances would be, share the budget.
+#[cfg(feature = "redis")]
+#[tokio::test]
+async fn test_redis_rate_limiter() {
+    use std::sync::Arc;
+    use tictoc::metrics::Metrics;
+    use tictoc::rate_limit::RedisRateLimiter;
+
+    let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+        eprintln!("TEST_REDIS_URL is not set, skipping");
+        return;
+    };
+    let client = redis::Client::open(url).unwrap();
+    let connect = || redis::aio::ConnectionManager::new(client.clone());
+    let metrics = Arc::new(Metrics::new());
+    let first = RedisRateLimiter::new(connect().await.unwrap(), 2, WINDOW, metrics.clone());
+    let second = RedisRateLimiter::new(connect().await.unwrap(), 2, WINDOW, metrics);
+
+    check_limits(&first, &second).await;
+
+    // Many at once still only let the budget through.
+    let key = format!("test:{}", uuid::Uuid::new_v4());
+    let hits = futures::future::join_all((0..20).map(|i| if i % 2 == 0 { first.hit(&key) } else { second.hit(&key) })).await;
+    assert_eq!(hits.iter().filter(|hit| hit.is_ok()).count(), 2);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use tictoc::{app, AppState};
+
+use common::*;
+
+/// How many reads `db_reads_total` has counted going to `pool`.
+fn reads(state: &AppState, pool: &str) -> u64 {
+    let metrics = state.metrics.render(&state.pool);
+    let prefix = format!("db_reads_total{{pool=\"{}\"}} ", pool);
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map_or(0, |count| count.parse().unwrap())
+}
+
+async fn get(app: &axum::Router, uri: &str, token: &str) -> StatusCode {
+    send(app, with_token(Request::get(uri).body(Body::empty()).unwrap(), token)).await.status()
+}
+
+#[tokio::test]
+async fn test_reads_go_to_the_replica() {
+    let db = TestDb::new().await;
+    // The same database under another pool, standing in for a replica.
+    let mut state = test_state(db.pool.clone());
+    state.read_pool = Some(db.pool.clone());
+    let app = app(state.clone());
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad218@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    let token = test_token(1);
+
+    // Writes, and reading back what they wrote, stay on the primary.
+    let request = with_token(
+        json_request("POST", "/entries", json!({ "started_at": "2025-04-01T09:00:00Z", "ended_at": "2025-04-01T10:00:00Z" })),
+        &token,
+    );
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    let login = json_request("POST", "/users/login", json!({ "email": "chad218@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, login).await.status(), StatusCode::OK);
+    assert_eq!(reads(&state, "replica"), 0);
+
+    assert_eq!(get(&app, "/entries", &token).await, StatusCode::OK);
+    assert_eq!(reads(&state, "replica"), 2);
+    assert_eq!(get(&app, "/reports/summary?from=2025-04-01&to=2025-04-30", &token).await, StatusCode::OK);
+    assert!(reads(&state, "replica") > 2);
+    let before = reads(&state, "replica");
+    assert_eq!(get(&app, "/users", &admin_token(1)).await, StatusCode::OK);
+    assert_eq!(reads(&state, "replica"), before + 2);
+    assert_eq!(reads(&state, "primary"), 0);
+}
+
+#[tokio::test]
+async fn test_reads_fall_back_to_the_primary() {
+    let db = TestDb::new().await;
+    let state = test_state(db.pool.clone());
+    let app = app(state.clone());
+
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad219@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+
+    assert_eq!(get(&app, "/entries", &test_token(1)).await, StatusCode::OK);
+    assert_eq!(reads(&state, "primary"), 2);
+    assert_eq!(reads(&state, "replica"), 0);
+}
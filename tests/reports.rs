@@ -0,0 +1,269 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tictoc::app;
+use tictoc::models::{Preferences, Project, ReportAmount, ReportGroup, SummaryReport, TimeEntry, UserSettings, WeekStart};
+use tictoc::validation::ValidationErrors;
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_summary_report() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    // A Wednesday.
+    state.clock = Arc::new(TestClock { now: Mutex::new("2025-04-09T12:00:00Z".parse().unwrap()) });
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad100@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+    let token = test_token(1);
+
+    let mut projects = Vec::new();
+    for name in ["Website", "Admin"] {
+        let request = with_token(json_request("POST", "/projects", json!({ "name": name })), &token);
+        projects.push(read_json::<Project>(app.clone().oneshot(request).await.unwrap()).await);
+    }
+    let (website, admin) = (projects[0].id, projects[1].id);
+
+    for entry in [
+        json!({ "project_id": website, "started_at": "2025-04-07T09:00:00Z", "ended_at": "2025-04-07T11:00:00Z", "tags": ["meeting"] }),
+        // Across midnight.
+        json!({ "project_id": admin, "started_at": "2025-04-07T23:00:00Z", "ended_at": "2025-04-08T01:00:00Z" }),
+        json!({ "started_at": "2025-04-08T10:00:00Z", "ended_at": "2025-04-08T10:30:00Z", "tags": ["meeting", "deep-work"] }),
+        // Still running, for an hour now.
+        json!({ "project_id": website, "started_at": "2025-04-09T11:00:00Z" }),
+        // Last week.
+        json!({ "started_at": "2025-04-01T09:00:00Z", "ended_at": "2025-04-01T10:00:00Z" }),
+    ] {
+        let response = app.clone().oneshot(with_token(json_request("POST", "/entries", entry), &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let get = |query: &str| {
+        with_token(Request::get(format!("/reports/summary{}", query)).body(Body::empty()).unwrap(), &token)
+    };
+    let buckets = |report: &SummaryReport| {
+        report.buckets.iter()
+            .map(|bucket| (bucket.label.clone(), bucket.tracked_secs, bucket.entries))
+            .collect::<Vec<_>>()
+    };
+    let label = |label: &str| Some(label.to_string());
+
+    // This week, by day.
+    let report: SummaryReport = read_json(app.clone().oneshot(get("")).await.unwrap()).await;
+    assert_eq!((report.from.to_string(), report.to.to_string()), ("2025-04-07".into(), "2025-04-13".into()));
+    assert_eq!(report.group_by, ReportGroup::Day);
+    assert_eq!((report.tracked_secs, report.entries), (19800, 4));
+    assert_eq!(buckets(&report), [
+        (label("2025-04-07"), 10800, 2),
+        (label("2025-04-08"), 5400, 2),
+        (label("2025-04-09"), 3600, 1),
+    ]);
+
+    let report: SummaryReport =
+        read_json(app.clone().oneshot(get("?from=2025-04-01&to=2025-04-13&group_by=week")).await.unwrap()).await;
+    assert_eq!(buckets(&report), [(label("2025-03-31"), 3600, 1), (label("2025-04-07"), 19800, 4)]);
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?group_by=project")).await.unwrap()).await;
+    assert_eq!(buckets(&report), [(label("Admin"), 7200, 1), (label("Website"), 10800, 2), (None, 1800, 1)]);
+    assert_eq!(report.buckets[0].key, Some(admin.to_string()));
+
+    // Entries count toward each of their tags.
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?group_by=tag")).await.unwrap()).await;
+    assert_eq!(buckets(&report), [(label("deep-work"), 1800, 1), (label("meeting"), 9000, 2), (None, 10800, 2)]);
+
+    // Only the part of an entry inside the range counts.
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?from=2025-04-08&to=2025-04-08")).await.unwrap()).await;
+    assert_eq!((report.tracked_secs, report.entries), (5400, 2));
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?from=2025-01-01&to=2025-12-31")).await.unwrap()).await;
+    assert_eq!(report.tracked_secs, 23400);
+
+    for query in ["?from=2025-04-10&to=2025-04-09", "?from=2025-01-01&to=2026-01-01", "?group_by=month", "?from=soon"] {
+        let response = app.clone().oneshot(get(query)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_report_time_zones() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.clock = Arc::new(TestClock { now: Mutex::new("2025-04-09T12:00:00Z".parse().unwrap()) });
+    let app = app(state);
+
+    for email in ["chad101@gmail.com", "chad102@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let (east, west) = (test_token(1), test_token(2));
+
+    let response = app.clone()
+        .oneshot(with_token(Request::get("/me/settings").body(Body::empty()).unwrap(), &east))
+        .await
+        .unwrap();
+    let settings: UserSettings = read_json(response).await;
+    assert_eq!(settings, UserSettings { timezone: "UTC".to_string(), week_start: WeekStart::Monday });
+
+    let put = |token: &str, body: serde_json::Value| with_token(json_request("PUT", "/me/settings", body), token);
+    let response = app.clone().oneshot(put(&east, json!({ "timezone": "Mars/Olympus", "week_start": "monday" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["timezone"], ["unknown time zone"]);
+
+    // UTC+3 and UTC-5, neither with daylight saving.
+    for (token, timezone) in [(&east, "Europe/Istanbul"), (&west, "America/Bogota")] {
+        let response = app.clone().oneshot(put(token, json!({ "timezone": timezone, "week_start": "monday" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = with_token(json_request("POST", "/entries", json!({
+            "started_at": "2025-04-07T23:30:00Z",
+            "ended_at": "2025-04-07T23:45:00Z"
+        })), token);
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let get = |query: &str, token: &str| {
+        with_token(Request::get(format!("/reports/summary{}", query)).body(Body::empty()).unwrap(), token)
+    };
+    let buckets = |report: &SummaryReport| {
+        report.buckets.iter()
+            .map(|bucket| (bucket.key.clone().unwrap(), bucket.tracked_secs))
+            .collect::<Vec<_>>()
+    };
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?from=2025-04-07&to=2025-04-08", &east)).await.unwrap()).await;
+    assert_eq!(report.timezone, "Europe/Istanbul");
+    assert_eq!(buckets(&report), [("2025-04-08".to_string(), 900)]);
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?from=2025-04-07&to=2025-04-08", &west)).await.unwrap()).await;
+    assert_eq!(report.timezone, "America/Bogota");
+    assert_eq!(buckets(&report), [("2025-04-07".to_string(), 900)]);
+
+    // The range is in local days too.
+    let report: SummaryReport = read_json(app.clone().oneshot(get("?from=2025-04-07&to=2025-04-07", &east)).await.unwrap()).await;
+    assert_eq!(report.entries, 0);
+
+    // A Sunday afternoon in Istanbul.
+    let request = with_token(json_request("POST", "/entries", json!({
+        "started_at": "2025-04-13T10:00:00Z",
+        "ended_at": "2025-04-13T11:00:00Z"
+    })), &east);
+    app.clone().oneshot(request).await.unwrap();
+
+    let weeks = "?from=2025-04-07&to=2025-04-13&group_by=week";
+    let report: SummaryReport = read_json(app.clone().oneshot(get(weeks, &east)).await.unwrap()).await;
+    assert_eq!(buckets(&report), [("2025-04-07".to_string(), 4500)]);
+
+    let response = app.clone().oneshot(put(&east, json!({ "timezone": "Europe/Istanbul", "week_start": "sunday" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get(weeks, &east)).await.unwrap()).await;
+    assert_eq!(buckets(&report), [("2025-04-06".to_string(), 900), ("2025-04-13".to_string(), 3600)]);
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get("", &east)).await.unwrap()).await;
+    assert_eq!((report.from.to_string(), report.to.to_string()), ("2025-04-06".into(), "2025-04-12".into()));
+}
+
+#[tokio::test]
+async fn test_billable_amounts() {
+    let db = TestDb::new().await;
+    let mut state = test_state(db.pool.clone());
+    state.clock = Arc::new(TestClock { now: Mutex::new("2025-05-01T12:00:00Z".parse().unwrap()) });
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad170@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+    let token = test_token(1);
+
+    let request = with_token(json_request("PATCH", "/me/preferences", json!({ "hourly_rate_cents": 6000 })), &token);
+    let preferences: Preferences = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!((preferences.hourly_rate_cents, preferences.currency.as_str()), (Some(6000), "USD"));
+
+    let project = |body: serde_json::Value| with_token(json_request("POST", "/projects", body), &token);
+    let response = app.clone().oneshot(project(json!({ "name": "Website", "hourly_rate_cents": 12000 }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["currency"], ["required with hourly_rate_cents"]);
+
+    let request = project(json!({ "name": "Website", "hourly_rate_cents": 12000, "currency": "usd" }));
+    let website: Project = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!(website.currency.as_deref(), Some("USD"));
+    // Billed at the user's rate.
+    let support: Project = read_json(app.clone().oneshot(project(json!({ "name": "Support" }))).await.unwrap()).await;
+
+    let create = |project_id: i32, day: u32, hours: u32, billable: bool| {
+        with_token(json_request("POST", "/entries", json!({
+            "project_id": project_id,
+            "started_at": format!("2025-04-{:02}T09:00:00Z", day),
+            "duration_seconds": format!("{}m", hours * 60),
+            "billable": billable
+        })), &token)
+    };
+    let mut entries = Vec::new();
+    for (project_id, day, hours, billable) in [(website.id, 1, 2, true), (website.id, 2, 1, false), (support.id, 3, 1, true)] {
+        let response = app.clone().oneshot(create(project_id, day, hours, billable)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        entries.push(read_json::<TimeEntry>(response).await);
+    }
+    assert_eq!((entries[0].rate_cents, entries[0].currency.as_deref()), (Some(12000), Some("USD")));
+    assert_eq!(entries[2].rate_cents, Some(6000));
+
+    let get = || {
+        with_token(Request::get("/reports/summary?from=2025-04-01&to=2025-04-30").body(Body::empty()).unwrap(), &token)
+    };
+    let report: SummaryReport = read_json(app.clone().oneshot(get()).await.unwrap()).await;
+    assert_eq!((report.tracked_secs, report.billable_seconds), (14400, 10800));
+    assert_eq!((report.amount_cents, report.currency.as_deref()), (Some(30000), Some("USD")));
+
+    // A raise mid-month only applies to entries made after it.
+    let request = with_token(
+        json_request("PUT", &format!("/projects/{}", website.id), json!({
+            "name": "Website",
+            "hourly_rate_cents": 15000,
+            "currency": "USD"
+        })),
+        &token,
+    );
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    app.clone().oneshot(create(website.id, 15, 1, true)).await.unwrap();
+
+    let response = app.clone()
+        .oneshot(with_token(Request::get(format!("/entries/{}", entries[0].id)).body(Body::empty()).unwrap(), &token))
+        .await
+        .unwrap();
+    assert_eq!(read_json::<TimeEntry>(response).await.rate_cents, Some(12000));
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get()).await.unwrap()).await;
+    assert_eq!(report.billable_seconds, 14400);
+    assert_eq!(report.amount_cents, Some(45000));
+
+    // Another currency, and the totals split up.
+    let request = project(json!({ "name": "Berlin", "hourly_rate_cents": 9000, "currency": "EUR" }));
+    let berlin: Project = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    app.clone().oneshot(create(berlin.id, 20, 1, true)).await.unwrap();
+
+    let report: SummaryReport = read_json(app.clone().oneshot(get()).await.unwrap()).await;
+    assert_eq!((report.amount_cents, report.currency), (None, None));
+    assert_eq!(report.amounts, [
+        ReportAmount { currency: "EUR".to_string(), billable_seconds: 3600, amount_cents: 9000 },
+        ReportAmount { currency: "USD".to_string(), billable_seconds: 14400, amount_cents: 45000 },
+    ]);
+}
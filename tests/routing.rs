@@ -0,0 +1,146 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+use tictoc::AppState;
+use tictoc::error::ErrorResponse;
+
+use common::*;
+
+#[tokio::test]
+async fn test_unknown_route() {
+    let app = offline_app();
+
+    for uri in ["/nope", "/users/1/nope", "/users/"] {
+        let response = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_wrong_method() {
+    let app = offline_app();
+
+    let response = send(&app, Request::get("/users/create").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[header::ALLOW], "POST");
+
+    let response = send(&app, json_request("POST", "/health/live", json!({}))).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[header::ALLOW], "GET,HEAD");
+
+    // Nothing is read from the body before the method is checked.
+    let response = send(&app, json_request("DELETE", "/users/login", json!({}))).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_errors_are_json() {
+    let app = offline_app();
+
+    let response = send(&app, Request::get("/users").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_json(&response);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "missing_token".to_string() }
+    );
+
+    let request = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("name=Chad"))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_json(&response);
+}
+
+#[tokio::test]
+async fn test_openapi_spec() {
+    let app = offline_app();
+
+    let response = send(&app, Request::get("/openapi.json").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json(&response);
+
+    let spec: serde_json::Value = read_json(response).await;
+    let login = &spec["paths"]["/users/login"]["post"];
+    assert!(login["responses"]["200"].is_object());
+    assert!(login["responses"]["401"].is_object());
+    assert!(login["security"].is_null());
+
+    assert_eq!(spec["components"]["securitySchemes"]["bearer"]["scheme"], "bearer");
+    assert_eq!(spec["paths"]["/me"]["get"]["security"], json!([{ "bearer": [] }]));
+
+    let response = send(&app, Request::get("/docs/").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_api_docs_off() {
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let app = tictoc::app(AppState { api_docs: false, ..test_state(pool) });
+
+    for uri in ["/openapi.json", "/docs/"] {
+        let response = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+fn cors_app(origins: &[&str]) -> axum::Router {
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let state = AppState {
+        allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+        ..test_state(pool)
+    };
+
+    tictoc::app(state)
+}
+
+fn preflight(origin: &str) -> Request<Body> {
+    Request::options("/users/login")
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization, content-type")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_cors_preflight() {
+    let app = cors_app(&["https://app.example.com"]);
+
+    let response = send(&app, preflight("https://app.example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+    assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("POST"));
+    assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap().contains("authorization"));
+    assert!(headers.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+
+    let response = send(&app, preflight("https://evil.example.com")).await;
+    assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+    // Actual requests get the origin back along with what they may read.
+    let request = Request::get("/health/live")
+        .header(header::ORIGIN, "https://app.example.com")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+    assert!(response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS].to_str().unwrap().contains("x-request-id"));
+}
+
+#[tokio::test]
+async fn test_cors_origin_settings() {
+    let response = send(&cors_app(&[]), preflight("https://app.example.com")).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+    let response = send(&cors_app(&["*"]), preflight("https://anywhere.example.com")).await;
+    assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+}
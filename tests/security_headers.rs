@@ -0,0 +1,76 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use sqlx::PgPool;
+use tictoc::app;
+
+use common::*;
+
+fn offline_state() -> tictoc::AppState {
+    test_state(PgPool::connect_lazy("postgres://localhost/unused").unwrap())
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_json_responses_carry_security_headers() {
+    let response = send(&offline_app(), get("/health/live")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let headers = response.headers();
+    assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+    assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+    assert!(headers.get(header::CONTENT_SECURITY_POLICY).is_none());
+    // Plain http, with nothing in front to say otherwise.
+    assert!(headers.get(header::STRICT_TRANSPORT_SECURITY).is_none());
+
+    // Errors get them as well.
+    let response = send(&offline_app(), get("/nowhere")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+}
+
+#[tokio::test]
+async fn test_only_html_gets_a_content_security_policy() {
+    let mut state = offline_state();
+    state.content_security_policy = "default-src 'none'".to_string();
+    let app = app(state);
+
+    let response = send(&app, get("/docs/")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_SECURITY_POLICY], "default-src 'none'");
+
+    let response = send(&app, with_token(get("/metrics"), "metrics-token")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::CONTENT_SECURITY_POLICY).is_none());
+    assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+}
+
+#[tokio::test]
+async fn test_hsts_only_over_https() {
+    let hsts = |app: axum::Router, forwarded: Option<&'static str>| async move {
+        let mut request = from_peer(get("/health/live"), "10.0.0.1");
+        if let Some(proto) = forwarded {
+            request.headers_mut().insert("x-forwarded-proto", proto.parse().unwrap());
+        }
+        send(&app, request).await.headers().get(header::STRICT_TRANSPORT_SECURITY).cloned()
+    };
+
+    let mut state = offline_state();
+    state.https = true;
+    assert_eq!(hsts(app(state), None).await.unwrap(), "max-age=31536000; includeSubDomains");
+
+    // A client could send the header itself, so without a trusted proxy it
+    // counts for nothing.
+    assert_eq!(hsts(app(offline_state()), Some("https")).await, None);
+
+    let mut state = offline_state();
+    state.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    assert!(hsts(app(state.clone()), Some("https")).await.is_some());
+    assert_eq!(hsts(app(state.clone()), Some("http")).await, None);
+    assert_eq!(hsts(app(state), None).await, None);
+}
@@ -0,0 +1,399 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::Path;
+use axum::http::{header, Request, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use serde_json::json;
+use sqlx::PgPool;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tictoc::error::ErrorResponse;
+use tictoc::limits;
+use tictoc::models::HealthResponse;
+use tictoc::routes::health::READINESS_TIMEOUT;
+use tictoc::config::TlsFiles;
+use tictoc::{app, serve, serve_tls, tls};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_request_id() {
+    // Nothing here touches the database.
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let app = app(test_state(pool));
+
+    let mut request = Request::get("/.well-known/jwks.json").body(Body::empty()).unwrap();
+    request.headers_mut().insert("x-request-id", "abc-123".parse().unwrap());
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.headers()["x-request-id"], "abc-123");
+
+    let request = Request::get("/.well-known/jwks.json").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let generated = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(generated).is_ok());
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad30@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+    login_token(&app, "chad30@gmail.com", "password").await;
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad30@gmail.com",
+        "password": "wrong password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = Request::get("/users/42").body(Body::empty()).unwrap();
+    app.clone().oneshot(request).await.unwrap();
+
+    let scrape = |token: &str| with_token(Request::get("/metrics").body(Body::empty()).unwrap(), token);
+
+    let response = app.clone().oneshot(scrape("wrong")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.oneshot(scrape("metrics-token")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    for series in [
+        r#"http_requests_total{method="POST",route="/users/create",status="2xx"} 1"#,
+        r#"http_requests_total{method="POST",route="/users/login",status="4xx"} 1"#,
+        r#"http_requests_total{method="GET",route="/users/{id}",status="4xx"} 1"#,
+        r#"http_request_duration_seconds_count{method="POST",route="/users/login"} 2"#,
+        r#"logins_total{outcome="succeeded"} 1"#,
+        r#"logins_total{outcome="failed"} 1"#,
+        "users_created_total 1",
+        // The scrape itself.
+        "http_requests_in_flight 1",
+    ] {
+        assert!(body.lines().any(|line| line == series), "missing {} in\n{}", series, body);
+    }
+    assert!(body.lines().any(|line| line.starts_with("db_pool_connections ") && line != "db_pool_connections 0"));
+}
+
+#[tokio::test]
+async fn test_health() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = Request::get("/health/live").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::get("/health/ready").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let health: HealthResponse = read_json(response).await;
+    assert_eq!(health.database, "ok");
+    assert_eq!(health.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+}
+
+#[tokio::test]
+async fn test_health_database_down() {
+    // One address refuses connections, the other accepts them and then
+    // never says a word, like a hung server.
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let hung = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hung_addr = hung.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((socket, _)) = hung.accept().await {
+            connections.push(socket);
+        }
+    });
+
+    for addr in [closed, hung_addr] {
+        let pool = PgPool::connect_lazy(&format!("postgres://postgres@{}/tictoc", addr)).unwrap();
+        let app = app(test_state(pool));
+
+        let started = std::time::Instant::now();
+        let request = Request::get("/health/ready").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert!(started.elapsed() < READINESS_TIMEOUT + Duration::from_secs(1));
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(read_json::<HealthResponse>(response).await.database, "unreachable");
+
+        let request = Request::get("/health/live").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn slow(Path(millis): Path<u64>) -> &'static str {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        "done"
+    }
+
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let app = app(test_state(pool)).route("/slow/{millis}", get(slow));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(serve(listener, app, shutdown.clone(), Duration::from_secs(5)));
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /slow/300 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+
+    // Let the request reach the handler before pulling the plug.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.cancel();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("done"));
+
+    server.await.unwrap().unwrap();
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_request_timeout() {
+    async fn slow(Path(millis): Path<u64>) -> &'static str {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        "done"
+    }
+
+    let app = limits::apply(Router::new().route("/slow/{millis}", get(slow)), Duration::from_millis(100), 10);
+
+    let response = app.clone().oneshot(Request::get("/slow/10").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.oneshot(Request::get("/slow/1000").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    assert_json(&response);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "request_timeout".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_load_shedding() {
+    // Holds each request until told to let go.
+    let release = Arc::new(Notify::new());
+    let started = Arc::new(Notify::new());
+    let slow = {
+        let (release, started) = (release.clone(), started.clone());
+        move || async move {
+            // Registered before saying so, so a release can't slip past.
+            let released = release.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            started.notify_one();
+            released.await;
+            "done"
+        }
+    };
+
+    let app = limits::apply(Router::new().route("/slow", get(slow)), Duration::from_secs(5), 2);
+    let request = || Request::get("/slow").body(Body::empty()).unwrap();
+
+    let mut in_flight = Vec::new();
+    for _ in 0..2 {
+        in_flight.push(tokio::spawn(app.clone().oneshot(request())));
+        started.notified().await;
+    }
+
+    let response = app.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "overloaded".to_string() }
+    );
+
+    release.notify_waiters();
+    for response in in_flight {
+        assert_eq!(response.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    // Once they're done there is room again.
+    let response = tokio::spawn(app.oneshot(request()));
+    started.notified().await;
+    release.notify_waiters();
+    assert_eq!(response.await.unwrap().unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_compression() {
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use std::io::{Read, Write};
+
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash)
+        SELECT 'User ' || n, 'user' || n || '@gmail.com', 'not-a-hash' FROM generate_series(1, 300) n",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let request = with_token(
+        Request::get("/users?limit=200")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap(),
+        &admin_token(1),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut json = String::new();
+    GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+    let page: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(page["total"], 300);
+    assert_eq!(page["items"].as_array().unwrap().len(), 200);
+    assert_eq!(page["items"][0]["email"], "user1@gmail.com");
+
+    // Too small to bother with.
+    let request = with_token(
+        Request::get("/users/1")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap(),
+        &admin_token(1),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+    let request = Request::get("/metrics")
+        .header(header::AUTHORIZATION, "Bearer metrics-token")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+    // Bodies can come in gzipped too.
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let payload = json!({ "name": "Chad", "email": "chad@gmail.com", "password": "password" });
+    encoder.write_all(payload.to_string().as_bytes()).unwrap();
+
+    let request = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(encoder.finish().unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+/// A fresh self-signed certificate for `localhost`, written where
+/// [`TlsFiles`] points.
+fn write_self_signed(files: &TlsFiles) -> reqwest::Certificate {
+    let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(&files.cert, generated.cert.pem()).unwrap();
+    std::fs::write(&files.key, generated.signing_key.serialize_pem()).unwrap();
+
+    reqwest::Certificate::from_pem(generated.cert.pem().as_bytes()).unwrap()
+}
+
+fn trusting(cert: reqwest::Certificate) -> reqwest::Client {
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(cert)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_tls() {
+    let dir = env::temp_dir().join(format!("tictoc-tls-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir(&dir).unwrap();
+    let files = TlsFiles { cert: dir.join("cert.pem"), key: dir.join("key.pem") };
+
+    let first = write_self_signed(&files);
+    let config = tls::load(&files).unwrap();
+
+    let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(serve_tls(listener, app(test_state(pool)), config.clone(), shutdown.clone(), Duration::from_secs(1)));
+
+    let url = format!("https://localhost:{}/health/live", port);
+    let response = trusting(first.clone()).get(&url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // A renewed certificate is used for new connections without a restart.
+    let second = write_self_signed(&files);
+    tls::reload(&config, &files).unwrap();
+
+    let response = trusting(second).get(&url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(trusting(first).get(&url).send().await.is_err());
+
+    // A broken file leaves the current certificate in place.
+    std::fs::write(&files.key, "not a key").unwrap();
+    assert!(tls::reload(&config, &files).is_err());
+
+    shutdown.cancel();
+    server.await.unwrap().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_https_redirect() {
+    for (https_port, host, location) in [
+        (8443, "example.com:8080", "https://example.com:8443/users?limit=1"),
+        (443, "example.com", "https://example.com/users?limit=1"),
+        (443, "[::1]:80", "https://[::1]/users?limit=1"),
+    ] {
+        let request = Request::get("/users?limit=1")
+            .header(header::HOST, host)
+            .body(Body::empty())
+            .unwrap();
+        let response = tls::redirect_app(https_port).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()[header::LOCATION], location);
+    }
+
+    let response = tls::redirect_app(443)
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
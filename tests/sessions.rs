@@ -0,0 +1,110 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+use tictoc::app;
+use tictoc::models::{LoginSession, LoginUserResponse};
+
+use common::*;
+
+const CHROME_LINUX: &str =
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+const FIREFOX_MAC: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14.4; rv:125.0) Gecko/20100101 Firefox/125.0";
+
+async fn log_in(app: &axum::Router, email: &str, user_agent: &str, peer: &str) -> LoginUserResponse {
+    let mut request = json_request("POST", "/users/login", json!({ "email": email, "password": "password" }));
+    request.headers_mut().insert(header::USER_AGENT, user_agent.parse().unwrap());
+    let response = send(app, from_peer(request, peer)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    read_json(response).await
+}
+
+async fn refresh(app: &axum::Router, refresh_token: &str) -> axum::response::Response {
+    send(app, json_request("POST", "/token/refresh", json!({ "refresh_token": refresh_token }))).await
+}
+
+async fn sessions(app: &axum::Router, token: &str) -> Vec<LoginSession> {
+    let response = send(app, with_token(Request::get("/me/sessions").body(Body::empty()).unwrap(), token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    read_json(response).await
+}
+
+fn revoke(uri: &str, token: &str) -> Request<Body> {
+    with_token(Request::delete(uri).body(Body::empty()).unwrap(), token)
+}
+
+#[tokio::test]
+async fn test_sessions_are_listed_and_revoked() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    for email in ["chad226@gmail.com", "chad227@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": "password" }));
+        assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+    }
+
+    let laptop = log_in(&app, "chad226@gmail.com", CHROME_LINUX, "203.0.113.9").await;
+    let phone = log_in(&app, "chad226@gmail.com", FIREFOX_MAC, "198.51.100.4").await;
+
+    let listed = sessions(&app, &laptop.token).await;
+    assert_eq!(listed.len(), 2);
+    // The most recently used first.
+    let (phone_session, laptop_session) = (&listed[0], &listed[1]);
+    assert_eq!((phone_session.browser.as_deref(), phone_session.os.as_deref()), (Some("Firefox"), Some("macOS")));
+    assert_eq!((laptop_session.browser.as_deref(), laptop_session.os.as_deref()), (Some("Chrome"), Some("Linux")));
+    assert_eq!(laptop_session.ip.as_deref(), Some("203.0.113.9"));
+    assert_eq!(laptop_session.user_agent.as_deref(), Some(CHROME_LINUX));
+    assert!(laptop_session.current && !phone_session.current);
+
+    // Refreshing carries on the same session.
+    let response = refresh(&app, &laptop.refresh_token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let laptop: LoginUserResponse = read_json(response).await;
+    let listed = sessions(&app, &laptop.token).await;
+    assert_eq!(listed.iter().map(|session| session.id).collect::<Vec<_>>(), [laptop_session.id, phone_session.id]);
+    assert!(listed[0].current);
+
+    // Someone else's session is as good as none.
+    let other = login_token(&app, "chad227@gmail.com", "password").await;
+    let uri = format!("/me/sessions/{}", phone_session.id);
+    assert_eq!(send(&app, revoke(&uri, &other)).await.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(send(&app, revoke(&uri, &laptop.token)).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, revoke(&uri, &laptop.token)).await.status(), StatusCode::NOT_FOUND);
+
+    // The phone is signed out, refresh and access token both.
+    assert_eq!(refresh(&app, &phone.refresh_token).await.status(), StatusCode::UNAUTHORIZED);
+    let response = send(&app, with_token(Request::get("/me").body(Body::empty()).unwrap(), &phone.token)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The laptop keeps working.
+    let listed = sessions(&app, &laptop.token).await;
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].current);
+    assert_eq!(refresh(&app, &laptop.refresh_token).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_revoking_every_other_session() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": "chad228@gmail.com", "password": "password" }));
+    assert_eq!(send(&app, request).await.status(), StatusCode::CREATED);
+
+    let current = log_in(&app, "chad228@gmail.com", CHROME_LINUX, "203.0.113.9").await;
+    let others = [
+        log_in(&app, "chad228@gmail.com", FIREFOX_MAC, "198.51.100.4").await,
+        log_in(&app, "chad228@gmail.com", "curl/8.7.1", "198.51.100.5").await,
+    ];
+    assert_eq!(sessions(&app, &current.token).await.len(), 3);
+
+    assert_eq!(send(&app, revoke("/me/sessions", &current.token)).await.status(), StatusCode::NO_CONTENT);
+
+    let listed = sessions(&app, &current.token).await;
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].current);
+    for other in others {
+        assert_eq!(refresh(&app, &other.refresh_token).await.status(), StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(refresh(&app, &current.refresh_token).await.status(), StatusCode::OK);
+}
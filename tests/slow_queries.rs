@@ -0,0 +1,80 @@
+mod common;
+
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tictoc::db::{self, SlowQueries};
+use tictoc::metrics::Metrics;
+use tracing::Instrument;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Layer;
+
+use common::*;
+
+/// Whatever was logged, to look through afterwards.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Captured {
+    type Writer = Captured;
+
+    fn make_writer(&'a self) -> Captured {
+        self.clone()
+    }
+}
+
+fn slow_queries(metrics: &Metrics, pool: &PgPool, fingerprint: &str) -> u64 {
+    let prefix = format!("db_slow_queries_total{{fingerprint=\"{}\"}} ", fingerprint);
+    metrics
+        .render(pool)
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map_or(0, |count| count.parse().unwrap())
+}
+
+#[tokio::test]
+async fn test_slow_statements_are_logged_and_counted() {
+    let db = TestDb::new().await;
+    let options = db::log_statements((*db.pool.connect_options()).clone(), Duration::from_millis(100));
+    let pool = PgPoolOptions::new().max_connections(1).connect_with(options).await.unwrap();
+    let metrics = Metrics::new();
+
+    let captured = Captured::default();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(captured.clone()).with_ansi(false))
+        .with(SlowQueries.with_filter(Targets::new().with_target("sqlx::query", tracing::Level::WARN)));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let span = tracing::info_span!("request", request_id = "3f1c0b7e");
+    async {
+        sqlx::query("SELECT pg_sleep(0.2)").execute(&pool).await.unwrap();
+        sqlx::query("SELECT 1").execute(&pool).await.unwrap();
+    }
+    .instrument(span)
+    .await;
+
+    let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let warnings: Vec<&str> = logged.lines().filter(|line| line.contains("slow statement")).collect();
+    assert_eq!(warnings.len(), 1, "{}", logged);
+    assert!(warnings[0].contains("WARN"), "{}", warnings[0]);
+    assert!(warnings[0].contains("request_id=\"3f1c0b7e\""), "{}", warnings[0]);
+    assert!(warnings[0].contains("SELECT pg_sleep(0.2)"), "{}", warnings[0]);
+    assert!(warnings[0].contains("elapsed"), "{}", warnings[0]);
+
+    assert_eq!(slow_queries(&metrics, &pool, "SELECT pg_sleep(?)"), 1);
+    assert_eq!(slow_queries(&metrics, &pool, "SELECT ?"), 0);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use axum::body::{Body, HttpBody};
+use axum::http::{header, Request, StatusCode};
+use futures::StreamExt;
+use tictoc::app;
+use tictoc::models::UserResponse;
+
+use common::*;
+
+const SEEDED_USERS: i64 = 10_000;
+
+async fn seed_users(db: &TestDb, count: i64) {
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash, role)
+        SELECT 'User ' || i, 'user' || i || '@gmail.com', '', CASE WHEN i = 1 THEN 'admin' ELSE 'user' END
+        FROM generate_series(1, $1) i",
+    )
+    .bind(count)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+}
+
+fn export(format: &str) -> Request<Body> {
+    with_token(Request::get(format!("/users/export?format={}", format)).body(Body::empty()).unwrap(), &admin_token(1))
+}
+
+#[tokio::test]
+async fn test_json_export_is_streamed() {
+    let db = TestDb::new().await;
+    seed_users(&db, SEEDED_USERS).await;
+    let app = app(test_state(db.pool.clone()));
+
+    let response = send(&app, export("json")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+    assert_eq!(response.body().size_hint().exact(), None);
+
+    // Sent as it's read, a row or so at a time, not all at once.
+    let mut chunks = response.into_body().into_data_stream();
+    let mut body = Vec::new();
+    let mut count = 0;
+    while let Some(chunk) = chunks.next().await {
+        body.extend_from_slice(&chunk.unwrap());
+        count += 1;
+    }
+    assert!(count > 1000, "{} chunks", count);
+
+    let users: Vec<UserResponse> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(users.len() as i64, SEEDED_USERS);
+    assert_eq!((users[0].id, users.last().unwrap().name.as_str()), (1, "User 10000"));
+}
+
+#[tokio::test]
+async fn test_empty_json_export() {
+    let db = TestDb::new().await;
+    seed_users(&db, 1).await;
+    let app = app(test_state(db.pool.clone()));
+
+    let request = Request::get("/entries/export?format=json").body(Body::empty()).unwrap();
+    let response = send(&app, with_token(request, &test_token(1))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"[]");
+}
+
+#[tokio::test]
+async fn test_failure_mid_stream_cuts_the_body_short() {
+    let db = TestDb::new().await;
+    // More than the socket buffers hold, so the query is still going when
+    // it's killed.
+    seed_users(&db, 100_000).await;
+    let app = app(test_state(db.pool.clone()));
+
+    let response = send(&app, export("json")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut chunks = response.into_body().into_data_stream();
+    let mut body = chunks.next().await.unwrap().unwrap().to_vec();
+
+    sqlx::query(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+        WHERE query LIKE 'SELECT id, name, email, created_at, updated_at, version, last_login_at FROM users%'
+            AND pid <> pg_backend_pid()",
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let mut failed = false;
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) => body.extend_from_slice(&chunk),
+            Err(_) => {
+                failed = true;
+                break;
+            }
+        }
+    }
+
+    // An error, never a body that looks whole.
+    assert!(failed);
+    assert!(serde_json::from_slice::<Vec<UserResponse>>(&body).is_err());
+    assert_ne!(body.last(), Some(&b']'));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use tictoc::app;
+use tictoc::models::{Page, Tag, TimeEntry};
+use tictoc::validation::ValidationErrors;
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_tags() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    for email in ["chad90@gmail.com", "chad91@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let (alice, bob) = (test_token(1), test_token(2));
+
+    let create = |day: u32, tags: serde_json::Value| {
+        with_token(json_request("POST", "/entries", json!({
+            "started_at": format!("2025-04-{:02}T09:00:00Z", day),
+            "ended_at": format!("2025-04-{:02}T10:00:00Z", day),
+            "tags": tags
+        })), &alice)
+    };
+    let get = |uri: &str, token: &str| with_token(Request::get(uri).body(Body::empty()).unwrap(), token);
+
+    let response = app.clone().oneshot(create(1, json!(["meeting", "client-x", "MEETING"]))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let first: TimeEntry = read_json(response).await;
+    assert_eq!(first.tags, ["client-x", "meeting"]);
+
+    // The same tag in another case, which renames it.
+    let response = app.clone().oneshot(create(2, json!(["Meeting", " deep-work "]))).await.unwrap();
+    let second: TimeEntry = read_json(response).await;
+    assert_eq!(second.tags, ["deep-work", "Meeting"]);
+
+    let third: TimeEntry = read_json(app.clone().oneshot(create(3, json!(["client-x"]))).await.unwrap()).await;
+    app.clone().oneshot(create(4, json!([]))).await.unwrap();
+
+    let tags: Vec<Tag> = read_json(app.clone().oneshot(get("/tags", &alice)).await.unwrap()).await;
+    let counts: Vec<(&str, i64)> = tags.iter().map(|tag| (tag.name.as_str(), tag.entries)).collect();
+    assert_eq!(counts, [("client-x", 2), ("deep-work", 1), ("Meeting", 2)]);
+
+    let response = app.clone().oneshot(get(&format!("/entries/{}", first.id), &alice)).await.unwrap();
+    assert_eq!(read_json::<TimeEntry>(response).await.tags, ["client-x", "Meeting"]);
+
+    let ids = |page: Page<TimeEntry>| page.items.iter().map(|entry| entry.id).collect::<Vec<_>>();
+
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(get("/entries", &alice)).await.unwrap()).await;
+    assert_eq!(page.total, 4);
+    assert_eq!(page.items[1], third);
+
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(get("/entries?tag=meeting", &alice)).await.unwrap()).await;
+    assert_eq!(page.total, 2);
+    assert_eq!(ids(page), [second.id, first.id]);
+
+    let page: Page<TimeEntry> =
+        read_json(app.clone().oneshot(get("/entries?tag=MEETING&tag=client-x", &alice)).await.unwrap()).await;
+    assert_eq!((page.total, page.items[0].tags.len()), (1, 2));
+    assert_eq!(ids(page), [first.id]);
+
+    let page: Page<TimeEntry> =
+        read_json(app.clone().oneshot(get("/entries?tag=meeting&tag=nope", &alice)).await.unwrap()).await;
+    assert_eq!(page.total, 0);
+
+    // Updating replaces the tags.
+    let response = app.clone()
+        .oneshot(with_token(
+            json_request("PUT", &format!("/entries/{}", first.id), json!({
+                "started_at": "2025-04-01T09:00:00Z",
+                "ended_at": "2025-04-01T10:00:00Z",
+                "tags": ["deep-work"]
+            })),
+            &alice,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read_json::<TimeEntry>(response).await.tags, ["deep-work"]);
+
+    let tags: Vec<Tag> = read_json(app.clone().oneshot(get("/tags", &alice)).await.unwrap()).await;
+    let counts: Vec<(&str, i64)> = tags.iter().map(|tag| (tag.name.as_str(), tag.entries)).collect();
+    assert_eq!(counts, [("client-x", 1), ("deep-work", 2), ("Meeting", 1)]);
+
+    // Tags belong to whoever made them.
+    let tags: Vec<Tag> = read_json(app.clone().oneshot(get("/tags", &bob)).await.unwrap()).await;
+    assert!(tags.is_empty());
+    let page: Page<TimeEntry> = read_json(app.clone().oneshot(get("/entries?tag=meeting", &bob)).await.unwrap()).await;
+    assert_eq!(page.total, 0);
+
+    let response = app.clone().oneshot(create(5, json!(["ok", "  "]))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: ValidationErrors = read_json(response).await;
+    assert_eq!(errors.errors["tags"], ["must not contain empty tags"]);
+}
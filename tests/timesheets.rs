@@ -0,0 +1,218 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tictoc::app;
+use tictoc::error::{ErrorResponse, TimesheetLockedResponse};
+use tictoc::models::{Organization, TimeEntry, Timesheet, TimesheetState};
+use tower::ServiceExt;
+
+use common::*;
+
+fn get(uri: &str, token: &str) -> Request<Body> {
+    with_token(Request::get(uri).body(Body::empty()).unwrap(), token)
+}
+
+fn post(uri: &str, body: serde_json::Value, token: &str) -> Request<Body> {
+    with_token(json_request("POST", uri, body), token)
+}
+
+async fn error(response: axum::response::Response) -> (StatusCode, String) {
+    (response.status(), read_json::<ErrorResponse>(response).await.error)
+}
+
+/// Ann owns Acme, Bob is a member and Cat an admin; Dan is in no
+/// organization.
+async fn setup() -> (axum::Router, Arc<TestClock>, TestDb) {
+    let db = TestDb::new().await;
+    let mailer = Arc::new(TestMailer::default());
+    let mut state = test_state_with_mailer(db.pool.clone(), mailer.clone());
+    let clock = Arc::new(TestClock { now: Mutex::new("2025-04-20T11:00:00Z".parse().unwrap()) });
+    state.clock = clock.clone();
+    let app = app(state);
+
+    for (name, email) in [("Ann", "chad195@gmail.com"), ("Bob", "chad196@gmail.com"), ("Cat", "chad197@gmail.com"),
+        ("Dan", "chad198@gmail.com")]
+    {
+        let request = json_request("POST", "/users/create", json!({ "name": name, "email": email, "password": "password" }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let request = post("/organizations", json!({ "name": "Acme" }), &test_token(1));
+    let acme: Organization = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    for (email, role, id) in [("chad196@gmail.com", "member", 2), ("chad197@gmail.com", "admin", 3)] {
+        let request = post(
+            &format!("/organizations/{}/invitations", acme.id),
+            json!({ "email": email, "role": role }),
+            &test_token(1),
+        );
+        app.clone().oneshot(request).await.unwrap();
+        let invitation = mailer.last_token(email).unwrap();
+        let request = post("/invitations/accept", json!({ "token": invitation }), &test_token(id));
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    }
+
+    (app, clock, db)
+}
+
+async fn create_entries(app: &axum::Router, token: &str, spans: &[(&str, &str)]) -> Vec<TimeEntry> {
+    let mut entries = Vec::new();
+    for (started_at, ended_at) in spans {
+        let request = post("/entries", json!({ "started_at": started_at, "ended_at": ended_at }), token);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        entries.push(read_json(response).await);
+    }
+    entries
+}
+
+#[tokio::test]
+async fn test_timesheet_lifecycle() {
+    let (app, clock, _db) = setup().await;
+    let (bob, cat) = (test_token(2), test_token(3));
+    let entries = create_entries(&app, &bob, &[
+        ("2025-04-14T08:00:00Z", "2025-04-14T10:00:00Z"),
+        ("2025-04-18T09:00:00Z", "2025-04-18T09:30:00Z"),
+        // The week after.
+        ("2025-04-21T08:00:00Z", "2025-04-21T09:00:00Z"),
+    ])
+    .await;
+
+    let timesheet: Timesheet = read_json(app.clone().oneshot(get("/timesheets/2025-04-14", &bob)).await.unwrap()).await;
+    assert_eq!((timesheet.state, timesheet.entries, timesheet.tracked_secs), (TimesheetState::Draft, 2, 9000));
+
+    // Weeks start on the user's first day.
+    let response = app.clone().oneshot(get("/timesheets/2025-04-15", &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::BAD_REQUEST, "invalid_week".to_string()));
+
+    // Nothing to approve before it's submitted.
+    let response = app.clone().oneshot(post("/users/2/timesheets/2025-04-14/approve", json!({}), &cat)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "timesheet_not_submitted".to_string()));
+
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let timesheet: Timesheet = read_json(response).await;
+    assert_eq!((timesheet.state, timesheet.entries, timesheet.tracked_secs), (TimesheetState::Submitted, 2, 9000));
+    assert_eq!(timesheet.submitted_at, Some("2025-04-20T11:00:00Z".parse().unwrap()));
+
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "timesheet_submitted".to_string()));
+
+    // Rejecting takes a reason, and unlocks the week.
+    let response = app.clone().oneshot(post("/users/2/timesheets/2025-04-14/reject", json!({}), &cat)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    clock.advance(3600);
+    let request = post("/users/2/timesheets/2025-04-14/reject", json!({ "comment": "Friday looks short" }), &cat);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let timesheet: Timesheet = read_json(response).await;
+    assert_eq!(timesheet.state, TimesheetState::Rejected);
+    assert_eq!((timesheet.reviewed_by, timesheet.comment.as_deref()), (Some(3), Some("Friday looks short")));
+
+    let request = with_token(json_request("PUT", &format!("/entries/{}", entries[1].id), json!({
+        "started_at": "2025-04-18T09:00:00Z",
+        "ended_at": "2025-04-18T11:00:00Z"
+    })), &bob);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &bob)).await.unwrap();
+    let timesheet: Timesheet = read_json(response).await;
+    assert_eq!((timesheet.state, timesheet.tracked_secs, timesheet.comment), (TimesheetState::Submitted, 14400, None));
+
+    // The approver's queue.
+    let response = app.clone().oneshot(get("/timesheets?state=submitted", &cat)).await.unwrap();
+    let queue: Vec<Timesheet> = read_json(response).await;
+    assert_eq!(queue.iter().map(|t| (t.user_id, t.week)).collect::<Vec<_>>(), [(2, "2025-04-14".parse().unwrap())]);
+
+    let request = post("/users/2/timesheets/2025-04-14/approve", json!({ "comment": "Thanks" }), &cat);
+    let timesheet: Timesheet = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!((timesheet.state, timesheet.reviewed_by), (TimesheetState::Approved, Some(3)));
+
+    let response = app.clone().oneshot(get("/timesheets?state=submitted", &cat)).await.unwrap();
+    assert!(read_json::<Vec<Timesheet>>(response).await.is_empty());
+
+    // Approved is final.
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "timesheet_approved".to_string()));
+    let request = post("/users/2/timesheets/2025-04-14/reject", json!({ "comment": "Oops" }), &cat);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "timesheet_approved".to_string()));
+
+    // The next week was never part of it.
+    let request = with_token(Request::delete(format!("/entries/{}", entries[2].id)).body(Body::empty()).unwrap(), &bob);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_timesheet_locking() {
+    let (app, _clock, _db) = setup().await;
+    let bob = test_token(2);
+    let entries = create_entries(&app, &bob, &[
+        ("2025-04-15T08:00:00Z", "2025-04-15T09:00:00Z"),
+        ("2025-04-21T08:00:00Z", "2025-04-21T09:00:00Z"),
+    ])
+    .await;
+
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = with_token(json_request("PUT", &format!("/entries/{}", entries[0].id), json!({
+        "started_at": "2025-04-15T08:00:00Z",
+        "ended_at": "2025-04-15T12:00:00Z"
+    })), &bob);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::LOCKED);
+    let body: TimesheetLockedResponse = read_json(response).await;
+    assert_eq!((body.entry_ids, body.timesheet.as_str()), (vec![entries[0].id], "/timesheets/2025-04-14"));
+
+    let request = with_token(Request::delete(format!("/entries/{}", entries[0].id)).body(Body::empty()).unwrap(), &bob);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::LOCKED);
+
+    let request = post("/entries/bulk", json!({
+        "ids": [entries[0].id, entries[1].id],
+        "set": { "description": "Changed" }
+    }), &bob);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::LOCKED);
+    assert_eq!(read_json::<TimesheetLockedResponse>(response).await.entry_ids, [entries[0].id]);
+
+    let request = post("/entries/bulk-delete", json!({ "ids": [entries[0].id] }), &bob);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::LOCKED);
+
+    // A week with a timer still going can't be submitted.
+    let dan = test_token(4);
+    let request = post("/timer/start", json!({ "description": "Tic" }), &dan);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &dan)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::CONFLICT, "timer_running".to_string()));
+}
+
+#[tokio::test]
+async fn test_timesheet_reviewers() {
+    let (app, _clock, _db) = setup().await;
+    let (ann, bob, cat, dan) = (test_token(1), test_token(2), test_token(3), test_token(4));
+    create_entries(&app, &cat, &[("2025-04-15T08:00:00Z", "2025-04-15T09:00:00Z")]).await;
+    let response = app.clone().oneshot(post("/timesheets/2025-04-14/submit", json!({}), &cat)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let approve = |token: &str| post("/users/3/timesheets/2025-04-14/approve", json!({}), token);
+
+    let response = app.clone().oneshot(approve(&cat)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::FORBIDDEN, "own_timesheet".to_string()));
+    let response = app.clone().oneshot(approve(&bob)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::FORBIDDEN, "insufficient_role".to_string()));
+    let response = app.clone().oneshot(approve(&dan)).await.unwrap();
+    assert_eq!(error(response).await, (StatusCode::NOT_FOUND, "timesheet_not_found".to_string()));
+
+    // Only those who can review someone see their timesheets.
+    for (token, expected) in [(&bob, 0), (&dan, 0), (&ann, 1), (&cat, 1)] {
+        let timesheets: Vec<Timesheet> = read_json(app.clone().oneshot(get("/timesheets", token)).await.unwrap()).await;
+        assert_eq!(timesheets.len(), expected);
+    }
+
+    let response = app.clone().oneshot(approve(&ann)).await.unwrap();
+    assert_eq!(read_json::<Timesheet>(response).await.state, TimesheetState::Approved);
+}
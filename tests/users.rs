@@ -0,0 +1,1248 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::json;
+use tictoc::auth::jwt::JwtKeys;
+use tictoc::error::{BodyErrorResponse, ErrorResponse};
+use tictoc::models::{Claims, CreateUserResponse, ImportReport, Page, Role, UserResponse};
+use tictoc::{app, validation};
+use tower::ServiceExt;
+
+use common::*;
+
+#[tokio::test]
+async fn test_create_user() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad1@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_json(&response);
+    let location = response.headers()[header::LOCATION].to_str().unwrap().to_string();
+    let chad: UserSummary = read_json(response).await;
+    assert_eq!(location, format!("/users/{}", chad.id));
+    assert_eq!((chad.name.as_str(), chad.email.as_str()), ("Chad", "chad1@gmail.com"));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "User",
+        "email": "user@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let user: UserSummary = read_json(response).await;
+
+    promote(&pool, "chad1@gmail.com").await;
+    let token = login_token(&app, "chad1@gmail.com", "password").await;
+    let request = with_token(Request::get("/users").body(Body::empty()).unwrap(), &token);
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json(&response);
+    assert_eq!(
+        read_json::<Page<UserSummary>>(response).await.items,
+        vec![chad, user]
+    );
+}
+
+#[tokio::test]
+async fn test_create_user_duplicate_email() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad4@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Other Chad",
+        "email": "chad4@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "email_already_registered".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_read_user_by_id() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad5@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+
+    let request = with_token(Request::get("/users/1").body(Body::empty()).unwrap(), &admin_token(1));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        read_json::<UserSummary>(response).await,
+        UserSummary {
+            id: 1,
+            name: "Chad".to_string(),
+            email: "chad5@gmail.com".to_string(),
+        }
+    );
+
+    let request = with_token(Request::get("/users/42").body(Body::empty()).unwrap(), &admin_token(1));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "user_not_found".to_string() }
+    );
+
+    let token = login_token(&app, "chad5@gmail.com", "password").await;
+    let request = with_token(Request::get("/users/abc").body(Body::empty()).unwrap(), &token);
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "invalid_id".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_update_user() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    for email in ["chad6@gmail.com", "taken@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let update = |id: i32, name: &str, email: &str| {
+        if_match(
+            with_token(
+                json_request("PUT", &format!("/users/{}", id), json!({ "name": name, "email": email })),
+                &admin_token(1),
+            ),
+            "*",
+        )
+    };
+
+    let response = app.clone().oneshot(update(1, "Renamed", "renamed@gmail.com")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        read_json::<UserSummary>(response).await,
+        UserSummary {
+            id: 1,
+            name: "Renamed".to_string(),
+            email: "renamed@gmail.com".to_string(),
+        }
+    );
+
+    let response = app.clone().oneshot(update(1, "Renamed", "renamed@gmail.com")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(update(1, "Renamed", "taken@gmail.com")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "email_already_registered".to_string() }
+    );
+
+    let response = app.oneshot(update(42, "Ghost", "ghost@gmail.com")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_delete_user() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    for email in ["chad7@gmail.com", "kept@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let delete = || with_token(Request::delete("/users/1").body(Body::empty()).unwrap(), &admin_token(1));
+
+    let response = app.clone().oneshot(delete()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(delete()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = with_token(Request::get("/users").body(Body::empty()).unwrap(), &admin_token(1));
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(
+        read_json::<Page<UserSummary>>(response).await.items,
+        vec![UserSummary {
+            id: 2,
+            name: "Chad".to_string(),
+            email: "kept@gmail.com".to_string(),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_patch_user() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    for email in ["chad8@gmail.com", "taken@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    let patch =
+        |body: serde_json::Value| if_match(with_token(json_request("PATCH", "/users/1", body), &admin_token(1)), "*");
+
+    let response = app.clone().oneshot(patch(json!({ "name": "Renamed" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        read_json::<UserSummary>(response).await,
+        UserSummary {
+            id: 1,
+            name: "Renamed".to_string(),
+            email: "chad8@gmail.com".to_string(),
+        }
+    );
+
+    let response = app.clone().oneshot(patch(json!({ "email": "patched@gmail.com" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        read_json::<UserSummary>(response).await,
+        UserSummary {
+            id: 1,
+            name: "Renamed".to_string(),
+            email: "patched@gmail.com".to_string(),
+        }
+    );
+
+    let response = app.clone().oneshot(patch(json!({ "email": "taken@gmail.com" }))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = app.oneshot(patch(json!({}))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "no_fields_to_update".to_string() }
+    );
+}
+
+#[tokio::test]
+async fn test_protected_route() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad9@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let now = get_current_timestamp();
+    let expired = JwtKeys::from_secret(TEST_SECRET).encode(&Claims {
+        sub: "1".to_string(),
+        email: "chad9@gmail.com".to_string(),
+        jti: uuid::Uuid::new_v4().to_string(),
+        iat: now - 7200,
+        exp: now - 3600,
+        role: Role::User,
+        sid: None,
+    }).unwrap();
+
+    let cases = [
+        (None, "missing_token"),
+        (Some("Basic Y2hhZDpwYXNzd29yZA==".to_string()), "malformed_token"),
+        (Some("Bearer garbage".to_string()), "invalid_token"),
+        (Some(format!("Bearer {}", expired)), "token_expired"),
+    ];
+
+    for (authorization, error) in cases {
+        let mut request = Request::get("/users").body(Body::empty()).unwrap();
+        if let Some(authorization) = authorization {
+            request.headers_mut().insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        }
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            read_json::<ErrorResponse>(response).await,
+            ErrorResponse { error: error.to_string() }
+        );
+    }
+
+    let token = login_token(&app, "chad9@gmail.com", "password").await;
+    let request = with_token(Request::get("/users/1").body(Body::empty()).unwrap(), &token);
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Authenticated, but listing everyone is for admins.
+    let request = with_token(Request::get("/users").body(Body::empty()).unwrap(), &token);
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_read_me() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    // The rows are changed behind the app's back, which a cached `/me`
+    // wouldn't see.
+    let mut state = test_state(pool.clone());
+    state.cache_ttl = std::time::Duration::ZERO;
+    let app = app(state);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad10@gmail.com",
+        "password": "password"
+    }));
+
+    app.clone().oneshot(request).await.unwrap();
+
+    let token = login_token(&app, "chad10@gmail.com", "password").await;
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        read_json::<UserSummary>(response).await,
+        UserSummary {
+            id: 1,
+            name: "Chad".to_string(),
+            email: "chad10@gmail.com".to_string(),
+        }
+    );
+
+    sqlx::query("UPDATE users SET name = 'Renamed' WHERE id = 1")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(read_json::<UserSummary>(response).await.name, "Renamed");
+
+    sqlx::query("DELETE FROM users WHERE id = 1")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let request = with_token(Request::get("/me").body(Body::empty()).unwrap(), &token);
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_create_user_validation() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "   ",
+        "email": "not-an-email",
+        "password": "short"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        read_json::<serde_json::Value>(response).await,
+        json!({
+            "errors": {
+                "name": ["required"],
+                "email": ["invalid format"],
+                "password": ["too short"]
+            }
+        })
+    );
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": " Chad ",
+        "email": "Chad15@GMail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(
+        read_json::<UserSummary>(response).await,
+        UserSummary {
+            id: 1,
+            name: "Chad".to_string(),
+            email: "chad15@gmail.com".to_string(),
+        }
+    );
+
+    let request = with_token(
+        json_request("PATCH", "/users/1", json!({
+            "name": "x".repeat(validation::MAX_NAME_LEN + 1),
+            "version": 1
+        })),
+        &admin_token(1),
+    );
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        read_json::<serde_json::Value>(response).await,
+        json!({ "errors": { "name": ["too long"] } })
+    );
+
+    let request = json_request("POST", "/users/login", json!({
+        "email": "CHAD15@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_malformed_json_bodies() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let raw_request = |body: Body| {
+        Request::post("/users/create")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap()
+    };
+
+    let request = raw_request(Body::from(r#"{"name": "Chad", "email": "#));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: BodyErrorResponse = read_json(response).await;
+    assert_eq!(body.error, "malformed_json");
+    assert_eq!(body.field, None);
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad16@gmail.com",
+        "password": 12345678
+    }));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: BodyErrorResponse = read_json(response).await;
+    assert_eq!(body.error, "invalid_field");
+    assert_eq!(body.field.as_deref(), Some("password"));
+    assert!(body.detail.contains("invalid type"));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad16@gmail.com"
+    }));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: BodyErrorResponse = read_json(response).await;
+    assert_eq!(body.field.as_deref(), Some("password"));
+    assert!(body.detail.contains("missing field"));
+
+    let request = raw_request(Body::from(vec![b' '; 10 * 1024 * 1024]));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "payload_too_large".to_string() }
+    );
+
+    let request = Request::post("/users/create")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("{}"))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_read_user_pagination() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash)
+        SELECT 'User ' || n, 'user' || n || '@gmail.com', 'hash' FROM generate_series(1, 75) AS n",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let app = app(test_state(pool));
+    let token = admin_token(1);
+
+    let get = |uri: &str| with_token(Request::get(uri).body(Body::empty()).unwrap(), &token);
+
+    let response = app.clone().oneshot(get("/users")).await.unwrap();
+    let first: Page<UserSummary> = read_json(response).await;
+    assert_eq!((first.total, first.limit, first.offset), (75, 50, 0));
+    assert_eq!(first.items.len(), 50);
+
+    let response = app.clone().oneshot(get("/users?limit=50&offset=50")).await.unwrap();
+    let second: Page<UserSummary> = read_json(response).await;
+    assert_eq!(second.items.len(), 25);
+
+    let ids: Vec<i32> = first.items.iter().chain(&second.items).map(|user| user.id).collect();
+    assert_eq!(ids, (1..=75).collect::<Vec<_>>());
+    assert_eq!(first.next_cursor, Some(50));
+    assert_eq!(second.next_cursor, None);
+
+    for uri in ["/users?limit=0", "/users?limit=201", "/users?offset=-1", "/users?limit=ten", "/users?after_id=5&offset=5"] {
+        let response = app.clone().oneshot(get(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        assert_eq!(read_json::<BodyErrorResponse>(response).await.error, "invalid_query");
+    }
+}
+
+#[tokio::test]
+async fn test_read_user_cursor_pagination() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+
+    let insert_users = |from: i32, to: i32| {
+        sqlx::query(
+            "INSERT INTO users (name, email, password_hash)
+            SELECT 'User ' || n, 'user' || n || '@gmail.com', 'hash' FROM generate_series($1, $2) AS n",
+        )
+        .bind(from)
+        .bind(to)
+        .execute(&pool)
+    };
+
+    insert_users(1, 30).await.unwrap();
+
+    let app = app(test_state(pool.clone()));
+    let token = admin_token(1);
+
+    let mut seen = Vec::new();
+    let mut cursor = Some(0);
+    let mut inserted = 30;
+
+    while let Some(after_id) = cursor {
+        let uri = format!("/users?limit=7&after_id={}", after_id);
+        let request = with_token(Request::get(uri).body(Body::empty()).unwrap(), &token);
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        let page: Page<UserSummary> = read_json(response).await;
+        seen.extend(page.items.iter().map(|user| user.id));
+        cursor = page.next_cursor;
+
+        // Rows created mid-walk land after the cursor and must not shift
+        // the rows still to come.
+        if inserted < 45 {
+            insert_users(inserted + 1, inserted + 3).await.unwrap();
+            inserted += 3;
+        }
+    }
+
+    assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!((1..=30).all(|id| seen.contains(&id)));
+    assert_eq!(seen.len() as i32, inserted);
+}
+
+#[tokio::test]
+async fn test_read_user_search() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash) VALUES
+        ('Alice Smith', 'alice@gmail.com', 'hash'),
+        ('Bob', 'bob.smith@gmail.com', 'hash'),
+        ('Carol 100%', 'carol@gmail.com', 'hash'),
+        ('Dave', 'dave_1@gmail.com', 'hash'),
+        ('Jane 1', 'jane@gmail.com', 'hash')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let app = app(test_state(pool));
+    let token = admin_token(1);
+
+    let search = |q: &str| {
+        let request = with_token(
+            Request::get(format!("/users?q={}", q)).body(Body::empty()).unwrap(),
+            &token,
+        );
+        let app = app.clone();
+
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let page: Page<UserSummary> = read_json(response).await;
+            (page.total, page.items.into_iter().map(|user| user.name).collect::<Vec<_>>())
+        }
+    };
+
+    assert_eq!(search("ALICE").await, (1, vec!["Alice Smith".to_string()]));
+    assert_eq!(search("bob.smith").await, (1, vec!["Bob".to_string()]));
+    assert_eq!(search("smith").await.0, 2);
+
+    // %25 is a literal percent sign, which must not act as a wildcard.
+    assert_eq!(search("100%25").await, (1, vec!["Carol 100%".to_string()]));
+    assert_eq!(search("e_1").await, (1, vec!["Dave".to_string()]));
+    assert_eq!(search("").await.0, 5);
+}
+
+#[tokio::test]
+async fn test_read_user_sorting() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash) VALUES
+        ('Bob', 'bob@gmail.com', 'hash'),
+        ('Alice', 'alice@gmail.com', 'hash'),
+        ('Carol', 'carol@gmail.com', 'hash'),
+        ('Alan', 'alan@gmail.com', 'hash')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let app = app(test_state(pool));
+    let token = admin_token(1);
+
+    let get = |uri: &str| with_token(Request::get(uri).body(Body::empty()).unwrap(), &token);
+    let names = |page: Page<UserSummary>| {
+        page.items.into_iter().map(|user| user.name).collect::<Vec<_>>()
+    };
+
+    let response = app.clone().oneshot(get("/users?sort=name&order=desc")).await.unwrap();
+    assert_eq!(names(read_json(response).await), ["Carol", "Bob", "Alice", "Alan"]);
+
+    let response = app.clone().oneshot(get("/users?sort=name&q=al&limit=1&offset=1")).await.unwrap();
+    assert_eq!(names(read_json(response).await), ["Alice"]);
+
+    let response = app.clone().oneshot(get("/users?order=desc&limit=2")).await.unwrap();
+    let page: Page<UserSummary> = read_json(response).await;
+    assert_eq!(page.next_cursor, Some(3));
+
+    let response = app.clone().oneshot(get("/users?order=desc&after_id=3")).await.unwrap();
+    assert_eq!(names(read_json(response).await), ["Alice", "Bob"]);
+
+    let response = app.oneshot(get("/users?sort=password_hash")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        read_json::<BodyErrorResponse>(response).await,
+        BodyErrorResponse {
+            error: "invalid_query".to_string(),
+            field: Some("sort".to_string()),
+            detail: "must be one of id, name, email, created_at".to_string(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_user_timestamps() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad17@gmail.com",
+        "password": "password"
+    }));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body: serde_json::Value = read_json(response).await;
+    let created_at = DateTime::parse_from_rfc3339(body["created_at"].as_str().unwrap()).unwrap();
+    let updated_at = DateTime::parse_from_rfc3339(body["updated_at"].as_str().unwrap()).unwrap();
+    assert_eq!(created_at, updated_at);
+
+    let token = admin_token(1);
+    let request = with_token(json_request("PUT", "/users/1", json!({
+        "name": "Renamed",
+        "email": "chad17@gmail.com",
+        "version": 1
+    })), &token);
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let updated: CreateUserResponse = read_json(response).await;
+    assert_eq!(updated.created_at, created_at);
+    assert!(updated.updated_at > updated_at);
+
+    let request = with_token(
+        Request::get("/users?sort=created_at&order=desc").body(Body::empty()).unwrap(),
+        &token,
+    );
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_soft_delete_and_restore() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let create = |name: &str| json_request("POST", "/users/create", json!({
+        "name": name,
+        "email": "chad18@gmail.com",
+        "password": "password"
+    }));
+    let login = || json_request("POST", "/users/login", json!({
+        "email": "chad18@gmail.com",
+        "password": "password"
+    }));
+    let token = admin_token(1);
+    let authed = |method: &str, uri: &str| {
+        with_token(Request::builder().method(method).uri(uri).body(Body::empty()).unwrap(), &token)
+    };
+
+    app.clone().oneshot(create("Chad")).await.unwrap();
+
+    let response = app.clone().oneshot(authed("DELETE", "/users/1")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.clone().oneshot(authed("GET", "/users")).await.unwrap();
+    let page: Page<UserSummary> = read_json(response).await;
+    assert_eq!((page.total, page.items.len()), (0, 0));
+
+    let response = app.clone().oneshot(authed("GET", "/users/1")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app.clone().oneshot(authed("POST", "/users/1/restore")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(login()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(authed("POST", "/users/1/restore")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Once deleted, the address is free for a new account, and the old
+    // one can no longer be restored onto it.
+    app.clone().oneshot(authed("DELETE", "/users/1")).await.unwrap();
+
+    let response = app.clone().oneshot(create("New Chad")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app.clone().oneshot(authed("POST", "/users/1/restore")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    sqlx::query("UPDATE users SET deleted_at = now() - interval '31 days' WHERE id = 2")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = app.oneshot(authed("POST", "/users/2/restore")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_delete_user_requires_owner_or_admin() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    for (name, email) in [("Chad", "chad25@gmail.com"), ("Other", "other25@gmail.com"), ("Admin", "admin25@gmail.com")] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": name,
+            "email": email,
+            "password": "password"
+        }));
+
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    promote(&pool, "admin25@gmail.com").await;
+
+    let chad = login_token(&app, "chad25@gmail.com", "password").await;
+    let admin = login_token(&app, "admin25@gmail.com", "password").await;
+
+    let delete = |id: i32, token: &str| with_token(Request::builder()
+        .method("DELETE")
+        .uri(format!("/users/{}", id))
+        .body(Body::empty())
+        .unwrap(), token);
+
+    let response = app.clone().oneshot(delete(2, &chad)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "forbidden".to_string() }
+    );
+
+    let response = app.clone().oneshot(delete(2, &admin)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(delete(1, &chad)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Restoring is admin-only, even for your own account.
+    let restore = |id: i32, token: &str| with_token(Request::builder()
+        .method("POST")
+        .uri(format!("/users/{}/restore", id))
+        .body(Body::empty())
+        .unwrap(), token);
+
+    let response = app.clone().oneshot(restore(1, &test_token(1))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.oneshot(restore(1, &admin)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_last_login() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let mut ids = Vec::new();
+    for email in ["chad30@gmail.com", "chad31@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({
+            "name": "Chad",
+            "email": email,
+            "password": "password"
+        }));
+        let created: CreateUserResponse = read_json(app.clone().oneshot(request).await.unwrap()).await;
+        ids.push(created.id);
+    }
+
+    let before = Utc::now();
+    login_token(&app, "chad30@gmail.com", "password").await;
+    let request = json_request("POST", "/users/login", json!({
+        "email": "chad31@gmail.com",
+        "password": "wrong password"
+    }));
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let read = |id: i32| with_token(Request::get(format!("/users/{}", id)).body(Body::empty()).unwrap(), &admin_token(1));
+
+    let user: UserResponse = read_json(app.clone().oneshot(read(ids[0])).await.unwrap()).await;
+    let last_login_at = user.last_login_at.unwrap();
+    assert!(last_login_at >= before && last_login_at <= Utc::now());
+
+    let user: UserResponse = read_json(app.clone().oneshot(read(ids[1])).await.unwrap()).await;
+    assert_eq!(user.last_login_at, None);
+
+    // Dormant: one that last logged in long ago and one that never did and
+    // is just as old.
+    sqlx::query!("UPDATE users SET last_login_at = '2023-06-01T00:00:00Z' WHERE id = $1", ids[0])
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE users SET created_at = '2023-12-31T23:00:00Z' WHERE id = $1", ids[1])
+        .execute(&pool)
+        .await
+        .unwrap();
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad32@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+
+    let list = |query: &str| with_token(
+        Request::get(format!("/users?{}", query)).body(Body::empty()).unwrap(),
+        &admin_token(1),
+    );
+
+    let response = app.clone().oneshot(list("inactive_since=2024-01-01")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: Page<UserResponse> = read_json(response).await;
+    assert_eq!(page.items.iter().map(|user| user.id).collect::<Vec<_>>(), ids);
+    assert_eq!(page.total, 2);
+
+    let page: Page<UserResponse> = read_json(app.clone().oneshot(list("inactive_since=2023-01-01")).await.unwrap()).await;
+    assert_eq!(page.total, 0);
+
+    let response = app.oneshot(list("inactive_since=last-year")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+fn idempotent_create(key: &str, email: &str) -> Request<Body> {
+    let mut request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": email,
+        "password": "password"
+    }));
+    request.headers_mut().insert("idempotency-key", key.parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn test_create_user_idempotency() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let first = app.clone().oneshot(idempotent_create("signup-1", "chad50@gmail.com")).await.unwrap();
+    assert_eq!(first.status(), StatusCode::CREATED);
+    assert!(first.headers().get("idempotent-replay").is_none());
+    let location = first.headers()[header::LOCATION].clone();
+    let created: CreateUserResponse = read_json(first).await;
+
+    let retry = app.clone().oneshot(idempotent_create("signup-1", "chad50@gmail.com")).await.unwrap();
+    assert_eq!(retry.status(), StatusCode::CREATED);
+    assert_eq!(retry.headers()["idempotent-replay"], "true");
+    assert_eq!(retry.headers()[header::LOCATION], location);
+    assert_eq!(read_json::<CreateUserResponse>(retry).await, created);
+
+    let reused = app.clone().oneshot(idempotent_create("signup-1", "chad51@gmail.com")).await.unwrap();
+    assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorResponse = read_json(reused).await;
+    assert_eq!(body.error, "idempotency_key_reused");
+
+    // Without the key a repeat is a new signup, and fails as one.
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad50@gmail.com",
+        "password": "password"
+    }));
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CONFLICT);
+
+    // A request that failed leaves the key free for the next attempt.
+    let failed = app.clone().oneshot(idempotent_create("signup-2", "chad50@gmail.com")).await.unwrap();
+    assert_eq!(failed.status(), StatusCode::CONFLICT);
+    let retried = app.clone().oneshot(idempotent_create("signup-2", "chad52@gmail.com")).await.unwrap();
+    assert_eq!(retried.status(), StatusCode::CREATED);
+
+    let users = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM users"#)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 2);
+
+    let mut request = idempotent_create("", "chad53@gmail.com");
+    request.headers_mut().insert("idempotency-key", "".parse().unwrap());
+    assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_create_user_idempotency_race() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let (first, second) = tokio::join!(
+        app.clone().oneshot(idempotent_create("race", "chad54@gmail.com")),
+        app.clone().oneshot(idempotent_create("race", "chad54@gmail.com")),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    assert_eq!((first.status(), second.status()), (StatusCode::CREATED, StatusCode::CREATED));
+    let replays = [&first, &second]
+        .iter()
+        .filter(|response| response.headers().contains_key("idempotent-replay"))
+        .count();
+    assert_eq!(replays, 1);
+    assert_eq!(read_json::<UserSummary>(first).await, read_json::<UserSummary>(second).await);
+
+    let users = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM users"#)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 1);
+}
+
+#[tokio::test]
+async fn test_update_user_version() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad40@gmail.com",
+        "password": "password"
+    }));
+    let created: CreateUserResponse = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!(created.version, 1);
+
+    let token = admin_token(1);
+    let request = with_token(Request::get(format!("/users/{}", created.id)).body(Body::empty()).unwrap(), &token);
+    let response = app.clone().oneshot(request).await.unwrap();
+    let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+    assert_eq!(etag, "\"1\"");
+
+    let put = |body: serde_json::Value| with_token(json_request("PUT", &format!("/users/{}", created.id), body), &token);
+    let renamed = json!({ "name": "Renamed", "email": "chad40@gmail.com" });
+
+    let response = app.clone().oneshot(if_match(put(renamed.clone()), &etag)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ETAG], "\"2\"");
+    let updated: CreateUserResponse = read_json(response).await;
+    assert_eq!(updated.version, 2);
+
+    // Someone else's edit landed in between.
+    let stale = json!({ "name": "Stale", "email": "chad40@gmail.com" });
+    let response = app.clone().oneshot(if_match(put(stale), &etag)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(response.headers()[header::ETAG], "\"2\"");
+    let body: serde_json::Value = read_json(response).await;
+    assert_eq!(body["error"], "version_mismatch");
+    assert_eq!(body["current"]["name"], "Renamed");
+    assert_eq!(body["current"]["version"], 2);
+
+    let stale = json!({ "name": "Stale", "email": "chad40@gmail.com", "version": 1 });
+    let response = app.clone().oneshot(put(stale)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response = app.clone().oneshot(put(renamed.clone())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(
+        read_json::<ErrorResponse>(response).await,
+        ErrorResponse { error: "precondition_required".to_string() }
+    );
+
+    let response = app.clone().oneshot(if_match(put(renamed), "W/\"2\"")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let patch = json_request("PATCH", &format!("/users/{}", created.id), json!({ "name": "Patched", "version": 2 }));
+    let request = if_match(with_token(patch, &token), "\"2\"");
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ETAG], "\"3\"");
+
+    let request = if_match(
+        with_token(json_request("PUT", "/users/999", json!({ "name": "Nobody", "email": "nobody@gmail.com" })), &token),
+        "\"1\"",
+    );
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_conditional_get() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad41@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+    let token = admin_token(1);
+
+    let get = |uri: &str, etag: Option<&str>| {
+        let mut request = with_token(Request::get(uri).body(Body::empty()).unwrap(), &token);
+        if let Some(etag) = etag {
+            request.headers_mut().insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+        }
+        request
+    };
+
+    for uri in ["/users", "/me"] {
+        let response = app.clone().oneshot(get(uri, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""));
+
+        let response = app.clone().oneshot(get(uri, Some(&etag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+
+        let response = app.clone().oneshot(get(uri, Some("W/\"other\", \"another\""))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let response = app.clone().oneshot(get("/me", None)).await.unwrap();
+    let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+
+    let request = json_request("PATCH", "/users/1", json!({ "name": "Renamed", "version": 1 }));
+    let response = app.clone().oneshot(with_token(request, &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(get("/me", Some(&etag))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[header::ETAG], etag.as_str());
+    assert_eq!(read_json::<UserSummary>(response).await.name, "Renamed");
+
+    // Handlers that tag their own responses keep their tag.
+    let response = app.oneshot(get("/users/1", None)).await.unwrap();
+    assert_eq!(response.headers()[header::ETAG], "\"2\"");
+}
+
+#[tokio::test]
+async fn test_import_users() {
+    let db = TestDb::new().await;
+    let pool = db.pool.clone();
+    let app = app(test_state(pool.clone()));
+
+    let request = json_request("POST", "/users/create", json!({
+        "name": "Chad",
+        "email": "chad50@gmail.com",
+        "password": "password"
+    }));
+    app.clone().oneshot(request).await.unwrap();
+
+    let rows = json!([
+        { "name": "Ann", "email": "Ann@Example.com", "password": "password" },
+        { "name": "", "email": "not an email", "password": "short" },
+        { "name": "Ann again", "email": "ann@example.com", "password": "password" },
+        { "name": "Chad", "email": "chad50@gmail.com", "password": "password" },
+        { "name": 7, "email": "bob@example.com" },
+        { "name": "Bob", "email": "bob@example.com", "password": "password" },
+    ]);
+    let import = |uri: &str, body: serde_json::Value| with_token(json_request("POST", uri, body), &admin_token(1));
+
+    let response = app.clone().oneshot(import("/users/import?dry_run=true", rows.clone())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let dry_run: ImportReport = read_json(response).await;
+    assert!(dry_run.dry_run);
+    assert_eq!((dry_run.created, dry_run.failed), (2, 4));
+    assert!(dry_run.rows.iter().all(|row| row.id.is_none()));
+    let count = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM users"#).fetch_one(&pool).await.unwrap();
+    assert_eq!(count, 1);
+
+    let response = app.clone().oneshot(import("/users/import", rows)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: ImportReport = read_json(response).await;
+    assert!(!report.dry_run);
+    assert_eq!((report.created, report.failed), (2, 4));
+
+    let outcome: Vec<_> = report.rows.iter().map(|row| (row.row, row.id.is_some(), row.error.as_deref())).collect();
+    assert_eq!(outcome, [
+        (1, true, None),
+        (2, false, Some("invalid_fields")),
+        (3, false, Some("duplicate_in_import")),
+        (4, false, Some("email_already_registered")),
+        (5, false, Some("malformed_row")),
+        (6, true, None),
+    ]);
+    let fields = report.rows[1].fields.as_ref().unwrap();
+    assert_eq!(fields.keys().collect::<Vec<_>>(), ["email", "name", "password"]);
+    assert!(report.rows[4].detail.is_some());
+
+    let emails = sqlx::query_scalar!("SELECT email FROM users ORDER BY id").fetch_all(&pool).await.unwrap();
+    assert_eq!(emails, ["chad50@gmail.com", "ann@example.com", "bob@example.com"]);
+
+    // Imported users can log in with the password they came with.
+    login_token(&app, "bob@example.com", "password").await;
+
+    let csv = "email,name,password\ncarol@example.com,Carol,password\ndave@example.com,Dave\n";
+    let mut request = with_token(Request::post("/users/import").body(Body::from(csv)).unwrap(), &admin_token(1));
+    request.headers_mut().insert(header::CONTENT_TYPE, "text/csv".parse().unwrap());
+    let report: ImportReport = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!((report.created, report.failed), (1, 1));
+    assert_eq!(report.rows[1].error.as_deref(), Some("malformed_row"));
+
+    let response = app.clone().oneshot(with_token(
+        json_request("POST", "/users/import", json!([])),
+        &test_token(1),
+    )).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let mut state = test_state(pool);
+    state.import_max_rows = 2;
+    let rows = json!([{}, {}, {}]);
+    let response = tictoc::app(state).oneshot(import("/users/import", rows)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(read_json::<BodyErrorResponse>(response).await.error, "too_many_rows");
+}
+
+#[tokio::test]
+async fn test_export_users() {
+    let db = TestDb::new().await;
+    let app = app(test_state(db.pool.clone()));
+
+    let names = ["Doe, Jane", "Say \"hi\"", "Line\nbreak", "Plain"];
+    for (i, name) in names.iter().enumerate() {
+        let request = json_request("POST", "/users/create", json!({
+            "name": name,
+            "email": format!("export{}@gmail.com", i),
+            "password": "password"
+        }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+    app.clone()
+        .oneshot(with_token(Request::delete("/users/4").body(Body::empty()).unwrap(), &admin_token(1)))
+        .await
+        .unwrap();
+
+    let export = |query: &str| {
+        with_token(Request::get(format!("/users/export{}", query)).body(Body::empty()).unwrap(), &admin_token(1))
+    };
+
+    let response = app.clone().oneshot(export("?format=csv")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+    let disposition = response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().to_string();
+    assert!(disposition.starts_with("attachment; filename=\"users-") && disposition.ends_with(".csv\""));
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut reader = csv::Reader::from_reader(&body[..]);
+    let headers: Vec<String> = reader.headers().unwrap().iter().map(str::to_string).collect();
+    assert_eq!(headers, ["id", "name", "email", "created_at", "updated_at", "version", "last_login_at"]);
+    assert!(!headers.iter().any(|header| header.contains("password")));
+
+    let rows: Vec<UserResponse> = reader.deserialize().collect::<Result<_, _>>().unwrap();
+    assert_eq!(rows.iter().map(|user| user.name.as_str()).collect::<Vec<_>>(), &names[..3]);
+    assert_eq!(rows[1].email, "export1@gmail.com");
+
+    let response = app.clone().oneshot(export("?format=ndjson")).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[2]["name"], "Line\nbreak");
+    assert!(lines[0].get("password_hash").is_none());
+
+    let response = app.clone().oneshot(export("?format=xml")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = with_token(Request::get("/users/export").body(Body::empty()).unwrap(), &test_token(1));
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,239 @@
+mod common;
+
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, Request, StatusCode};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tictoc::app;
+use tictoc::models::{CreateWebhookResponse, TimeEntry, Webhook, WebhookDelivery, WebhookEvent};
+use tictoc::validation::ValidationErrors;
+use tictoc::webhooks::spawn_worker;
+use tower::ServiceExt;
+
+use common::*;
+
+/// A server standing in for the other end of a webhook. It answers with
+/// the statuses queued up, then 200s.
+#[derive(Clone, Default)]
+struct Endpoint {
+    requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+    statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+}
+
+impl Endpoint {
+    async fn start() -> (Endpoint, String) {
+        let endpoint = Endpoint::default();
+        let app = axum::Router::new().route("/hook", axum::routing::post({
+            let endpoint = endpoint.clone();
+            move |headers: HeaderMap, body: Bytes| async move {
+                endpoint.requests.lock().unwrap().push((headers, body));
+                endpoint.statuses.lock().unwrap().pop_front().unwrap_or(StatusCode::OK)
+            }
+        }));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (endpoint, url)
+    }
+
+    /// Waits for the `count`th request, and returns every one so far.
+    async fn wait_for(&self, count: usize) -> Vec<(HeaderMap, Bytes)> {
+        for _ in 0..500 {
+            if self.requests.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let requests = self.requests.lock().unwrap().clone();
+        assert!(requests.len() >= count, "got {} requests, waited for {}", requests.len(), count);
+        requests
+    }
+}
+
+fn get(uri: &str, token: &str) -> Request<Body> {
+    with_token(Request::get(uri).body(Body::empty()).unwrap(), token)
+}
+
+fn post(uri: &str, body: Value, token: &str) -> Request<Body> {
+    with_token(json_request("POST", uri, body), token)
+}
+
+async fn setup(db: &TestDb, max_attempts: u32) -> axum::Router {
+    let mut state = test_state(db.pool.clone());
+    state.webhooks.max_attempts = max_attempts;
+    state.webhooks.retry_base = Duration::from_millis(10);
+    spawn_worker(&state).unwrap();
+    app(state)
+}
+
+async fn create_user(app: &axum::Router, email: &str) {
+    let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": "password" }));
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+}
+
+async fn create_webhook(app: &axum::Router, url: &str, events: &[&str], token: &str) -> CreateWebhookResponse {
+    let response = app.clone().oneshot(post("/webhooks", json!({ "url": url, "events": events }), token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    read_json(response).await
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers.get(name).unwrap().to_str().unwrap()
+}
+
+#[tokio::test]
+async fn test_webhook_events() {
+    let db = TestDb::new().await;
+    let app = setup(&db, 1).await;
+    let (endpoint, url) = Endpoint::start().await;
+    // Inserted directly: signing up would emit a `user.created` the worker
+    // might only look at once the webhook exists.
+    sqlx::query("INSERT INTO users (name, email, password_hash, role) VALUES ('Chad', 'chad199@gmail.com', '', 'admin')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let token = test_token(1);
+
+    let webhook = create_webhook(&app, &url, &["user.created", "timer.started", "timer.stopped", "entry.deleted"], &token).await;
+    assert_eq!(webhook.secret.len(), 64);
+
+    // Admins hear about everyone who registers.
+    create_user(&app, "chad200@gmail.com").await;
+    let requests = endpoint.wait_for(1).await;
+    let (headers, body) = &requests[0];
+    assert_eq!(header(headers, "x-tictoc-event"), "user.created");
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(webhook.secret.as_bytes()).unwrap();
+    mac.update(body);
+    let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(header(headers, "x-tictoc-signature"), expected);
+
+    let payload: Value = serde_json::from_slice(body).unwrap();
+    assert_eq!((&payload["type"], &payload["data"]["email"]), (&json!("user.created"), &json!("chad200@gmail.com")));
+    assert_eq!(payload["id"], json!(header(headers, "x-tictoc-delivery")));
+
+    // Someone else's timer isn't the webhook's business.
+    let response = app.clone().oneshot(post("/timer/start", json!({}), &test_token(2))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app.clone().oneshot(post("/timer/start", json!({ "description": "Tic" }), &token)).await.unwrap();
+    let entry: TimeEntry = read_json(response).await;
+    app.clone().oneshot(post("/timer/stop", json!({}), &token)).await.unwrap();
+    let request = with_token(Request::delete(format!("/entries/{}", entry.id)).body(Body::empty()).unwrap(), &token);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+    let requests = endpoint.wait_for(4).await;
+    let mut events: Vec<&str> = requests[1..].iter().map(|(headers, _)| header(headers, "x-tictoc-event")).collect();
+    events.sort();
+    assert_eq!(events, ["entry.deleted", "timer.started", "timer.stopped"]);
+    for (_, body) in &requests[1..] {
+        let payload: Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload["data"]["id"], json!(entry.id));
+    }
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(endpoint.requests.lock().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_webhook_retries() {
+    let db = TestDb::new().await;
+    let app = setup(&db, 3).await;
+    let (endpoint, url) = Endpoint::start().await;
+    create_user(&app, "chad201@gmail.com").await;
+    let token = test_token(1);
+    let webhook = create_webhook(&app, &url, &["timer.started"], &token).await;
+
+    // The third attempt gets through.
+    endpoint.statuses.lock().unwrap().extend([StatusCode::INTERNAL_SERVER_ERROR, StatusCode::BAD_GATEWAY]);
+    let test = format!("/webhooks/{}/test", webhook.id);
+    assert_eq!(app.clone().oneshot(post(&test, json!({}), &token)).await.unwrap().status(), StatusCode::ACCEPTED);
+
+    let requests = endpoint.wait_for(3).await;
+    assert!(requests.iter().all(|(headers, _)| header(headers, "x-tictoc-event") == "ping"));
+    let delivery = header(&requests[0].0, "x-tictoc-delivery");
+    assert!(requests.iter().all(|(headers, _)| header(headers, "x-tictoc-delivery") == delivery));
+
+    let deliveries_uri = format!("/webhooks/{}/deliveries", webhook.id);
+    let mut deliveries: Vec<WebhookDelivery> = Vec::new();
+    for _ in 0..500 {
+        deliveries = read_json(app.clone().oneshot(get(&deliveries_uri, &token)).await.unwrap()).await;
+        if deliveries.len() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let attempts: Vec<(i32, Option<i32>)> = deliveries.iter().map(|d| (d.attempt, d.status_code)).collect();
+    assert_eq!(attempts, [(3, Some(200)), (2, Some(502)), (1, Some(500))]);
+    assert!(deliveries.iter().all(|d| d.event == WebhookEvent::Ping && d.event_id == delivery));
+    assert_eq!(deliveries[0].payload["data"]["webhook_id"], json!(webhook.id));
+
+    // Then it gives up.
+    endpoint.statuses.lock().unwrap().extend([StatusCode::INTERNAL_SERVER_ERROR; 4]);
+    app.clone().oneshot(post(&test, json!({}), &token)).await.unwrap();
+    endpoint.wait_for(6).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(endpoint.requests.lock().unwrap().len(), 6);
+}
+
+#[tokio::test]
+async fn test_webhook_crud() {
+    let db = TestDb::new().await;
+    let app = setup(&db, 1).await;
+    let (endpoint, url) = Endpoint::start().await;
+    create_user(&app, "chad202@gmail.com").await;
+    create_user(&app, "chad203@gmail.com").await;
+    let token = test_token(1);
+
+    for (body, field) in [
+        (json!({ "url": "ftp://example.com/hook", "events": ["timer.started"] }), "url"),
+        (json!({ "url": url, "events": [] }), "events"),
+        (json!({ "url": url, "events": ["ping"] }), "events"),
+    ] {
+        let response = app.clone().oneshot(post("/webhooks", body, &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(read_json::<ValidationErrors>(response).await.errors.contains_key(field));
+    }
+    let response = app.clone().oneshot(post("/webhooks", json!({ "url": url, "events": ["made.up"] }), &token)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let created = create_webhook(&app, &url, &["timer.started", "timer.started"], &token).await;
+    assert_eq!(created.events, [WebhookEvent::TimerStarted]);
+
+    let uri = format!("/webhooks/{}", created.id);
+    let request = with_token(json_request("PUT", &uri, json!({
+        "url": url,
+        "events": ["timer.started", "timer.stopped"],
+        "active": false
+    })), &token);
+    let webhook: Webhook = read_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!((webhook.events.len(), webhook.active), (2, false));
+
+    let webhooks: Vec<Webhook> = read_json(app.clone().oneshot(get("/webhooks", &token)).await.unwrap()).await;
+    assert_eq!(webhooks, [webhook]);
+
+    // Only its owner can see it.
+    for request in [get(&uri, &test_token(2)), get(&format!("{}/deliveries", uri), &test_token(2))] {
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    // Inactive, so nothing is sent but test pings.
+    app.clone().oneshot(post("/timer/start", json!({}), &token)).await.unwrap();
+    app.clone().oneshot(post(&format!("{}/test", uri), json!({}), &token)).await.unwrap();
+    endpoint.wait_for(1).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let requests = endpoint.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(header(&requests[0].0, "x-tictoc-event"), "ping");
+
+    let request = with_token(Request::delete(&uri).body(Body::empty()).unwrap(), &token);
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+    assert_eq!(app.clone().oneshot(get(&uri, &token)).await.unwrap().status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use futures::{SinkExt, StreamExt};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tictoc::auth::jwt::JwtKeys;
+use tictoc::models::{Claims, Role};
+use tictoc::routes::ws::{CLOSE_TOKEN_EXPIRED, CLOSE_UNAUTHORIZED};
+use tictoc::{app, serve};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+
+use common::*;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Serves the app on a port of its own, for as long as the test runs.
+async fn start(db: &TestDb) -> (axum::Router, String) {
+    let app = app(test_state(db.pool.clone()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(serve(listener, app.clone(), CancellationToken::new(), Duration::from_secs(1)));
+
+    for email in ["chad206@gmail.com", "chad207@gmail.com"] {
+        let request = json_request("POST", "/users/create", json!({ "name": "Chad", "email": email, "password": "password" }));
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    (app, url)
+}
+
+/// The next JSON message, skipping pings.
+async fn next(socket: &mut Socket) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no message in time")
+            .unwrap()
+            .unwrap();
+        match message {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Ping(_) | Message::Pong(_) => continue,
+            other => panic!("expected a text message, got {:?}", other),
+        }
+    }
+}
+
+async fn next_close(socket: &mut Socket) -> (CloseCode, String) {
+    let message = tokio::time::timeout(Duration::from_secs(10), socket.next()).await.expect("no close in time");
+    match message {
+        Some(Ok(Message::Close(Some(frame)))) => (frame.code, frame.reason.to_string()),
+        other => panic!("expected a close frame, got {:?}", other),
+    }
+}
+
+async fn send(socket: &mut Socket, message: Value) {
+    socket.send(Message::Text(message.to_string().into())).await.unwrap();
+}
+
+fn token_expiring_in(id: i32, secs: u64) -> String {
+    let now = get_current_timestamp();
+    JwtKeys::from_secret(TEST_SECRET).encode(&Claims {
+        sub: id.to_string(),
+        email: "test@gmail.com".to_string(),
+        jti: uuid::Uuid::new_v4().to_string(),
+        iat: now,
+        exp: now + secs,
+        role: Role::User,
+        sid: None,
+    }).unwrap()
+}
+
+#[tokio::test]
+async fn test_timer_over_websocket() {
+    let db = TestDb::new().await;
+    let (_app, url) = start(&db).await;
+    let token = test_token(1);
+
+    // One socket with the token in the URL, one that sends it first.
+    let (mut phone, _) = connect_async(format!("{}?token={}", url, token)).await.unwrap();
+    let (mut laptop, _) = connect_async(&url).await.unwrap();
+    send(&mut laptop, json!({ "action": "auth", "token": token })).await;
+    for socket in [&mut phone, &mut laptop] {
+        assert_eq!(next(socket).await, json!({ "event": "ready", "data": { "user_id": 1 } }));
+    }
+    let (mut other, _) = connect_async(format!("{}?token={}", url, test_token(2))).await.unwrap();
+    next(&mut other).await;
+
+    send(&mut phone, json!({ "action": "start", "description": "Tic", "billable": true })).await;
+    let started = next(&mut phone).await;
+    assert_eq!(started["event"], "timer.started");
+    assert_eq!((&started["data"]["description"], &started["data"]["billable"]), (&json!("Tic"), &json!(true)));
+    assert_eq!(next(&mut laptop).await, started);
+
+    send(&mut laptop, json!({ "action": "stop" })).await;
+    let stopped = next(&mut laptop).await;
+    assert_eq!((&stopped["event"], &stopped["data"]["id"]), (&json!("timer.stopped"), &started["data"]["id"]));
+    assert_eq!(next(&mut phone).await, stopped);
+
+    // Failures only go back to the socket that asked, as HTTP would say them.
+    send(&mut laptop, json!({ "action": "stop" })).await;
+    let error = next(&mut laptop).await;
+    assert_eq!(error, json!({ "event": "error", "status": 409, "data": { "error": "no_running_timer" } }));
+    send(&mut laptop, json!({ "action": "dance" })).await;
+    let error = next(&mut laptop).await;
+    assert_eq!((&error["status"], &error["data"]["error"]), (&json!(400), &json!("invalid_command")));
+
+    // The other user heard none of it.
+    send(&mut other, json!({ "action": "stop" })).await;
+    assert_eq!(next(&mut other).await["data"]["error"], "no_running_timer");
+}
+
+#[tokio::test]
+async fn test_websocket_auth() {
+    let db = TestDb::new().await;
+    let (_app, url) = start(&db).await;
+
+    match connect_async(format!("{}?token=nope", url)).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 401),
+        other => panic!("expected a 401, got {:?}", other.map(|(_, response)| response)),
+    }
+
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    send(&mut socket, json!({ "action": "start" })).await;
+    assert_eq!(next_close(&mut socket).await, (CloseCode::from(CLOSE_UNAUTHORIZED), "auth_required".to_string()));
+
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    send(&mut socket, json!({ "action": "auth", "token": "nope" })).await;
+    assert_eq!(next_close(&mut socket).await, (CloseCode::from(CLOSE_UNAUTHORIZED), "invalid_token".to_string()));
+
+    // A token that runs out mid-session closes the socket when it does.
+    let (mut socket, _) = connect_async(format!("{}?token={}", url, token_expiring_in(1, 2))).await.unwrap();
+    assert_eq!(next(&mut socket).await["event"], "ready");
+    assert_eq!(next_close(&mut socket).await, (CloseCode::from(CLOSE_TOKEN_EXPIRED), "token_expired".to_string()));
+}